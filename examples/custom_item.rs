@@ -23,7 +23,6 @@ pub fn main() {
     let options = SkimOptionsBuilder::default()
         .height(Some("50%"))
         .multi(true)
-        .preview(Some("")) // preview should be specified to enable preview window
         .build()
         .unwrap();
 
@@ -0,0 +1,59 @@
+extern crate skim;
+extern crate tuikit;
+use skim::prelude::*;
+use std::time::Duration;
+use tuikit::prelude::{Canvas, Draw, Rectangle, Result, Term};
+
+// draw skim in the right half of the screen while the host owns the terminal
+struct Host<'a> {
+    engine: &'a SkimEngine,
+}
+
+impl<'a> Host<'a> {
+    fn skim_rect(width: usize, height: usize) -> Rectangle {
+        Rectangle {
+            top: 0,
+            left: width / 2,
+            width: width - width / 2,
+            height,
+        }
+    }
+}
+
+impl<'a> Draw for Host<'a> {
+    fn draw(&self, canvas: &mut dyn Canvas) -> Result<()> {
+        let (width, height) = canvas.size()?;
+        canvas.clear()?;
+        canvas.print(0, 0, "host application, press ESC in the picker to quit")?;
+        self.engine.draw_in(canvas, Self::skim_rect(width, height))
+    }
+}
+
+pub fn main() {
+    let term: Term<()> = Term::new().unwrap();
+    let options = SkimOptionsBuilder::default().multi(true).build().unwrap();
+    let mut engine = SkimEngine::new(&options, None);
+
+    let output = loop {
+        if let Ok(event) = term.peek_event(Duration::from_millis(10)) {
+            let (width, height) = term.term_size().unwrap();
+            engine.feed_event_in(event, Host::skim_rect(width, height));
+        }
+
+        match engine.poll() {
+            SkimPoll::Idle => {}
+            SkimPoll::Redraw => {
+                let _ = term.draw(&Host { engine: &engine });
+                let _ = term.present();
+            }
+            SkimPoll::Done(output) => break output,
+        }
+    };
+
+    let _ = term.clear_on_exit(true);
+    drop(term);
+
+    for item in output.map(|out| out.selected_items).unwrap_or_default().iter() {
+        println!("{}", item.output());
+    }
+}
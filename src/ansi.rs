@@ -2,9 +2,9 @@
 use std::default::Default;
 use std::mem;
 
+use crate::attr::{Attr, Color, Effect, ExtAttr, ExtEffect, UnderlineStyle};
 use beef::lean::Cow;
-use std::cmp::max;
-use tuikit::prelude::*;
+use std::cmp::{max, min};
 use vte::Perform;
 
 /// An ANSI Parser, will parse one line at a time.
@@ -15,8 +15,11 @@ pub struct ANSIParser {
     partial_str: String,
     last_attr: Attr,
 
+    last_ext: ExtAttr,
+
     stripped: String,
     fragments: Vec<(Attr, (u32, u32))>,
+    extras: Vec<(ExtAttr, (u32, u32))>,
 }
 
 impl Default for ANSIParser {
@@ -24,9 +27,11 @@ impl Default for ANSIParser {
         ANSIParser {
             partial_str: String::new(),
             last_attr: Attr::default(),
+            last_ext: ExtAttr::default(),
 
             stripped: String::new(),
             fragments: Vec::new(),
+            extras: Vec::new(),
         }
     }
 }
@@ -74,107 +79,97 @@ impl Perform for ANSIParser {
             return;
         }
 
+        let params: Vec<&[i64]> = params.chunks(1).collect();
+        self.sgr(&params);
+    }
+
+    fn esc_dispatch(&mut self, _params: &[i64], _intermediates: &[u8], _ignore: bool, _byte: u8) {
+        // ESC characters are replaced with \[
+        self.partial_str.push('"');
+        self.partial_str.push('[');
+    }
+}
+
+impl ANSIParser {
+    /// apply the SGR params, each one with its `:` separated sub-params, e.g. `4:3` for curly underline
+    fn sgr(&mut self, params: &[&[i64]]) {
         // \[[m => means reset
-        let mut attr = if params.is_empty() {
-            Attr::default()
+        let (mut attr, mut ext) = if params.is_empty() {
+            (Attr::default(), ExtAttr::default())
         } else {
-            self.last_attr
+            (self.last_attr, self.last_ext)
         };
 
         let mut iter = params.iter();
-        while let Some(&code) = iter.next() {
-            match code {
-                0 => attr = Attr::default(),
-                1 => attr.effect |= Effect::BOLD,
-                2 => attr.effect |= !Effect::BOLD,
-                4 => attr.effect |= Effect::UNDERLINE,
-                5 => attr.effect |= Effect::BLINK,
-                7 => attr.effect |= Effect::REVERSE,
-                num if num >= 30 && num <= 37 => {
-                    attr.fg = Color::AnsiValue((num - 30) as u8);
+        while let Some(&param) = iter.next() {
+            match param[0] {
+                0 => {
+                    attr = Attr::default();
+                    ext = ExtAttr::default();
                 }
-                38 => match iter.next() {
-                    Some(2) => {
-                        // ESC[ 38;2;<r>;<g>;<b> m Select RGB foreground color
-                        let or = iter.next();
-                        let og = iter.next();
-                        let ob = iter.next();
-                        if ob.is_none() {
-                            trace!("ignore CSI {:?} m", params);
-                            continue;
+                1 => attr.effect |= Effect::BOLD,
+                2 => attr.effect |= Effect::DIM,
+                3 => ext.effect |= ExtEffect::ITALIC,
+                4 => {
+                    let style = match param.get(1) {
+                        None | Some(1) => Some(UnderlineStyle::Single),
+                        Some(2) => Some(UnderlineStyle::Double),
+                        Some(3) => Some(UnderlineStyle::Curly),
+                        Some(4) => Some(UnderlineStyle::Dotted),
+                        Some(5) => Some(UnderlineStyle::Dashed),
+                        _ => None,
+                    };
+                    // a styled underline is drawn as a plain one
+                    match style {
+                        Some(style) => {
+                            attr.effect |= Effect::UNDERLINE;
+                            ext.underline_style = style;
                         }
-
-                        let r = *or.unwrap() as u8;
-                        let g = *og.unwrap() as u8;
-                        let b = *ob.unwrap() as u8;
-
-                        attr.fg = Color::Rgb(r, g, b);
-                    }
-                    Some(5) => {
-                        // ESC[ 38;5;<n> m Select foreground color
-                        let color = iter.next();
-                        if color.is_none() {
-                            trace!("ignore CSI {:?} m", params);
-                            continue;
+                        None => {
+                            attr.effect.remove(Effect::UNDERLINE);
+                            ext.underline_style = UnderlineStyle::default();
                         }
-                        attr.fg = Color::AnsiValue(*color.unwrap() as u8);
-                    }
-                    _ => {
-                        trace!("error on parsing CSI {:?} m", params);
                     }
+                }
+                // rapid blink is drawn as the slow one
+                5 | 6 => attr.effect |= Effect::BLINK,
+                7 => attr.effect |= Effect::REVERSE,
+                9 => ext.effect |= ExtEffect::STRIKETHROUGH,
+                22 => attr.effect.remove(Effect::BOLD | Effect::DIM),
+                23 => ext.effect.remove(ExtEffect::ITALIC),
+                24 => {
+                    attr.effect.remove(Effect::UNDERLINE);
+                    ext.underline_style = UnderlineStyle::default();
+                }
+                25 => attr.effect.remove(Effect::BLINK),
+                27 => attr.effect.remove(Effect::REVERSE),
+                29 => ext.effect.remove(ExtEffect::STRIKETHROUGH),
+                num @ 30..=37 => attr.fg = Color::AnsiValue((num - 30) as u8),
+                38 => match extended_color(param, &mut iter) {
+                    Some(color) => attr.fg = color,
+                    None => trace!("ignore CSI {:?} m", params),
                 },
                 39 => attr.fg = Color::Default,
-                num if num >= 40 && num <= 47 => {
-                    attr.bg = Color::AnsiValue((num - 40) as u8);
-                }
-                48 => match iter.next() {
-                    Some(2) => {
-                        // ESC[ 48;2;<r>;<g>;<b> m Select RGB background color
-                        let or = iter.next();
-                        let og = iter.next();
-                        let ob = iter.next();
-                        if ob.is_none() {
-                            trace!("ignore CSI {:?} m", params);
-                            continue;
-                        }
-
-                        let r = *or.unwrap() as u8;
-                        let g = *og.unwrap() as u8;
-                        let b = *ob.unwrap() as u8;
-
-                        attr.bg = Color::Rgb(r, g, b);
-                    }
-                    Some(5) => {
-                        // ESC[ 48;5;<n> m Select background color
-                        let color = iter.next();
-                        if color.is_none() {
-                            trace!("ignore CSI {:?} m", params);
-                            continue;
-                        }
-                        attr.bg = Color::AnsiValue(*color.unwrap() as u8);
-                    }
-                    _ => {
-                        trace!("ignore CSI {:?} m", params);
-                    }
+                num @ 40..=47 => attr.bg = Color::AnsiValue((num - 40) as u8),
+                48 => match extended_color(param, &mut iter) {
+                    Some(color) => attr.bg = color,
+                    None => trace!("ignore CSI {:?} m", params),
                 },
                 49 => attr.bg = Color::Default,
+                58 => match extended_color(param, &mut iter) {
+                    Some(color) => ext.underline_color = color,
+                    None => trace!("ignore CSI {:?} m", params),
+                },
+                59 => ext.underline_color = Color::Default,
                 _ => {
                     trace!("ignore CSI {:?} m", params);
                 }
             }
         }
 
-        self.attr_change(attr);
-    }
-
-    fn esc_dispatch(&mut self, _params: &[i64], _intermediates: &[u8], _ignore: bool, _byte: u8) {
-        // ESC characters are replaced with \[
-        self.partial_str.push('"');
-        self.partial_str.push('[');
+        self.attr_change(attr, ext);
     }
-}
 
-impl ANSIParser {
     /// save the partial_str into fragments with current attr
     fn save_str(&mut self) {
         if self.partial_str.is_empty() {
@@ -182,45 +177,91 @@ impl ANSIParser {
         }
 
         let string = mem::replace(&mut self.partial_str, String::new());
-        self.fragments.push((
-            self.last_attr,
-            (self.stripped.len() as u32, (self.stripped.len() + string.len()) as u32),
-        ));
+        let range = (self.stripped.len() as u32, (self.stripped.len() + string.len()) as u32);
+        self.fragments.push((self.last_attr, range));
+        if self.last_ext != ExtAttr::default() {
+            self.extras.push((self.last_ext, range));
+        }
         self.stripped.push_str(&string);
     }
 
     // accept a new attr
-    fn attr_change(&mut self, new_attr: Attr) {
-        if new_attr == self.last_attr {
+    fn attr_change(&mut self, new_attr: Attr, new_ext: ExtAttr) {
+        if new_attr == self.last_attr && new_ext == self.last_ext {
             return;
         }
 
         self.save_str();
         self.last_attr = new_attr;
+        self.last_ext = new_ext;
     }
 
     pub fn parse_ansi(&mut self, text: &str) -> AnsiString<'static> {
         let mut statemachine = vte::Parser::new();
 
-        for byte in text.as_bytes() {
+        // vte drops the whole sequence on a `:`, so the SGR sequences with sub-params are parsed here
+        let mut rest = text;
+        while let Some(start) = rest.find("\x1B[") {
+            let params_len = rest[start + 2..]
+                .bytes()
+                .take_while(|&b| b.is_ascii_digit() || b == b':' || b == b';')
+                .count();
+            let (params, after) = rest[start + 2..].split_at(params_len);
+            let consumed = if params.contains(':') && after.starts_with('m') {
+                for byte in &rest.as_bytes()[..start] {
+                    statemachine.advance(self, *byte);
+                }
+                let params: Vec<Vec<i64>> = params
+                    .split(';')
+                    .map(|param| param.split(':').map(|sub| sub.parse().unwrap_or(0)).collect())
+                    .collect();
+                self.sgr(&params.iter().map(Vec::as_slice).collect::<Vec<_>>());
+                start + 2 + params_len + 1
+            } else {
+                for byte in &rest.as_bytes()[..start + 2] {
+                    statemachine.advance(self, *byte);
+                }
+                start + 2
+            };
+            rest = &rest[consumed..];
+        }
+        for byte in rest.as_bytes() {
             statemachine.advance(self, *byte);
         }
         self.save_str();
 
         let stripped = mem::replace(&mut self.stripped, String::new());
         let fragments = mem::replace(&mut self.fragments, Vec::new());
-        AnsiString::new_string(stripped, fragments)
+        let extras = mem::take(&mut self.extras);
+        let mut ret = AnsiString::new_string(stripped, fragments);
+        if !extras.is_empty() {
+            ret.extras = Some(extras);
+        }
+        ret
     }
 }
 
 /// A String that contains ANSI state (e.g. colors)
 ///
-/// It is internally represented as Vec<(attr, string)>
-#[derive(Clone, Debug)]
+/// It is internally represented as Vec<(attr, string)>, use `spans` to render it:
+///
+/// ```
+/// use skim::AnsiString;
+/// use skim::attr::{Attr, Color};
+///
+/// let text = AnsiString::parse("\x1B[31mred\x1B[0m plain");
+/// let spans: Vec<_> = text.spans().collect();
+/// assert_eq!(spans, vec![("red", Attr::default().fg(Color::RED)), (" plain", Attr::default())]);
+/// assert_eq!(text.stripped(), "red plain");
+/// assert_eq!(AnsiString::parse(&text.to_ansi()), text);
+/// ```
+#[derive(Clone, Debug, PartialEq)]
 pub struct AnsiString<'a> {
     stripped: Cow<'a, str>,
     // attr: start, end
     fragments: Option<Vec<(Attr, (u32, u32))>>,
+    // the attrs not drawn on screen, only set by the parser
+    extras: Option<Vec<(ExtAttr, (u32, u32))>>,
 }
 
 impl<'a> AnsiString<'a> {
@@ -228,6 +269,7 @@ impl<'a> AnsiString<'a> {
         Self {
             stripped: Cow::borrowed(""),
             fragments: None,
+            extras: None,
         }
     }
 
@@ -235,6 +277,7 @@ impl<'a> AnsiString<'a> {
         Self {
             stripped: Cow::owned(string),
             fragments: None,
+            extras: None,
         }
     }
 
@@ -242,6 +285,7 @@ impl<'a> AnsiString<'a> {
         Self {
             stripped: Cow::borrowed(str_ref),
             fragments: None,
+            extras: None,
         }
     }
 
@@ -251,6 +295,7 @@ impl<'a> AnsiString<'a> {
         Self {
             stripped: Cow::borrowed(stripped),
             fragments: if fragments_empty { None } else { Some(fragments) },
+            extras: None,
         }
     }
 
@@ -260,6 +305,7 @@ impl<'a> AnsiString<'a> {
         Self {
             stripped: Cow::owned(stripped),
             fragments: if fragments_empty { None } else { Some(fragments) },
+            extras: None,
         }
     }
 
@@ -288,6 +334,108 @@ impl<'a> AnsiString<'a> {
         ))
     }
 
+    /// The runs of characters sharing the same attr, in order, the unstyled text included
+    pub fn spans(&self) -> AnsiSpanIterator<'_> {
+        let fragments = self.fragments.as_deref().unwrap_or(&[]);
+        AnsiSpanIterator {
+            stripped: &self.stripped,
+            chars: AnsiStringIterator::new(&self.stripped, fragments).peekable(),
+            pos: 0,
+        }
+    }
+
+    /// The attrs beyond `Attr` set by the parser, by byte range, e.g. italic, strikethrough or the
+    /// style and the color of an underline. tuikit 0.4 has no way to draw them, so on screen
+    /// italic and strikethrough text is plain, a styled underline is a plain one and its color is
+    /// dropped, `to_ansi` writes them back.
+    ///
+    /// ```
+    /// use skim::AnsiString;
+    /// use skim::attr::{Color, ExtAttr, ExtEffect, UnderlineStyle};
+    ///
+    /// let text = AnsiString::parse("\x1B[4:3;58;5;1mtypo\x1B[0m \x1B[3;9mgone\x1B[0m");
+    /// let underline = ExtAttr {
+    ///     underline_style: UnderlineStyle::Curly,
+    ///     underline_color: Color::RED,
+    ///     ..ExtAttr::default()
+    /// };
+    /// let effect = ExtAttr {
+    ///     effect: ExtEffect::ITALIC | ExtEffect::STRIKETHROUGH,
+    ///     ..ExtAttr::default()
+    /// };
+    /// assert_eq!(text.ext_attrs(), &[(underline, (0, 4)), (effect, (5, 9))]);
+    /// ```
+    pub fn ext_attrs(&self) -> &[(ExtAttr, (u32, u32))] {
+        self.extras.as_deref().unwrap_or(&[])
+    }
+
+    /// The text with its attrs written back as SGR escape sequences
+    pub fn to_ansi(&self) -> String {
+        let fragments = self.fragments.as_deref().unwrap_or(&[]);
+        let extras = self.ext_attrs();
+        let mut extra_idx = 0;
+        let plain = (Attr::default(), ExtAttr::default());
+        let mut last = plain;
+
+        let mut ret = String::with_capacity(self.stripped.len());
+        let chars = self.stripped.char_indices();
+        for ((idx, ch), (_, attr)) in chars.zip(AnsiStringIterator::new(&self.stripped, fragments)) {
+            while extras.get(extra_idx).is_some_and(|&(_, (_, end))| end as usize <= idx) {
+                extra_idx += 1;
+            }
+            let ext = match extras.get(extra_idx) {
+                Some(&(ext, (start, _))) if start as usize <= idx => ext,
+                _ => ExtAttr::default(),
+            };
+
+            if (attr, ext) != last {
+                ret.push_str(&if (attr, ext) == plain {
+                    "\x1B[0m".to_string()
+                } else {
+                    sgr(attr, ext)
+                });
+                last = (attr, ext);
+            }
+            ret.push(ch);
+        }
+        if last != plain {
+            ret.push_str("\x1B[0m");
+        }
+        ret
+    }
+
+    /// The text of the byte ranges joined in order, with the attrs moved along,
+    /// e.g. to show some fields of a colored line
+    pub fn select_ranges(&self, ranges: &[(usize, usize)]) -> AnsiString<'static> {
+        fn select<A: Copy>(items: &[(A, (u32, u32))], ranges: &[(usize, usize)]) -> Vec<(A, (u32, u32))> {
+            let mut ret = vec![];
+            let mut offset = 0;
+            for &(begin, end) in ranges {
+                let (begin, end) = (begin as u32, end as u32);
+                for &(attr, (start, stop)) in items {
+                    if start < end && begin < stop {
+                        ret.push((
+                            attr,
+                            (max(start, begin) - begin + offset, min(stop, end) - begin + offset),
+                        ));
+                    }
+                }
+                offset += end - begin;
+            }
+            ret
+        }
+
+        let stripped: String = ranges.iter().map(|&(begin, end)| &self.stripped[begin..end]).collect();
+        let fragments = self.fragments.as_deref().map(|fragments| select(fragments, ranges));
+        let mut ret = AnsiString::new_string(stripped, fragments.unwrap_or_default());
+        ret.extras = self
+            .extras
+            .as_deref()
+            .map(|extras| select(extras, ranges))
+            .filter(|extras| !extras.is_empty());
+        ret
+    }
+
     pub fn has_attrs(&self) -> bool {
         self.fragments.is_some()
     }
@@ -387,6 +535,83 @@ impl<'a> Iterator for AnsiStringIterator<'a> {
     }
 }
 
+/// An iterator over the (text, attr) spans, see `AnsiString::spans`
+pub struct AnsiSpanIterator<'a> {
+    stripped: &'a str,
+    chars: std::iter::Peekable<AnsiStringIterator<'a>>,
+    pos: usize,
+}
+
+impl<'a> Iterator for AnsiSpanIterator<'a> {
+    type Item = (&'a str, Attr);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (ch, attr) = self.chars.next()?;
+        let start = self.pos;
+        self.pos += ch.len_utf8();
+        while let Some((ch, _)) = self.chars.next_if(|&(_, next_attr)| next_attr == attr) {
+            self.pos += ch.len_utf8();
+        }
+        Some((&self.stripped[start..self.pos], attr))
+    }
+}
+
+/// the extended color of `38`, `48` and `58`, either in the sub-params(`38:5:n`, `38:2::r:g:b`) or
+/// the following params(`38;5;n`, `38;2;r;g;b`)
+fn extended_color(param: &[i64], rest: &mut std::slice::Iter<&[i64]>) -> Option<Color> {
+    let mut next = || -> Option<u8> { rest.next().map(|param| param[0] as u8) };
+    if param.len() > 1 {
+        return match param[1..] {
+            [5, n] => Some(Color::AnsiValue(n as u8)),
+            [2, r, g, b] | [2, _, r, g, b] => Some(Color::Rgb(r as u8, g as u8, b as u8)),
+            _ => None,
+        };
+    }
+    match next()? {
+        5 => Some(Color::AnsiValue(next()?)),
+        2 => Some(Color::Rgb(next()?, next()?, next()?)),
+        _ => None,
+    }
+}
+
+/// the SGR sequence that sets exactly the attrs, starting from a reset
+fn sgr(attr: Attr, ext: ExtAttr) -> String {
+    let mut codes = vec!["0".to_string()];
+    for (effect, code) in [
+        (Effect::BOLD, "1"),
+        (Effect::DIM, "2"),
+        (Effect::UNDERLINE, "4"),
+        (Effect::BLINK, "5"),
+        (Effect::REVERSE, "7"),
+    ] {
+        if attr.effect.contains(effect) {
+            codes.push(match (effect, ext.underline_style) {
+                (Effect::UNDERLINE, UnderlineStyle::Double) => "4:2".to_string(),
+                (Effect::UNDERLINE, UnderlineStyle::Curly) => "4:3".to_string(),
+                (Effect::UNDERLINE, UnderlineStyle::Dotted) => "4:4".to_string(),
+                (Effect::UNDERLINE, UnderlineStyle::Dashed) => "4:5".to_string(),
+                _ => code.to_string(),
+            });
+        }
+    }
+    if ext.effect.contains(ExtEffect::ITALIC) {
+        codes.push("3".to_string());
+    }
+    if ext.effect.contains(ExtEffect::STRIKETHROUGH) {
+        codes.push("9".to_string());
+    }
+    let color = |color: Color, base: u8| match color {
+        Color::AnsiValue(n) if n < 8 && base != 50 => Some(format!("{}", base + n)),
+        Color::AnsiValue(n) => Some(format!("{};5;{}", base + 8, n)),
+        Color::Rgb(r, g, b) => Some(format!("{};2;{};{};{}", base + 8, r, g, b)),
+        _ => None,
+    };
+    codes.extend(color(attr.fg, 30));
+    codes.extend(color(attr.bg, 40));
+    codes.extend(color(ext.underline_color, 50));
+    format!("\x1B[{}m", codes.join(";"))
+}
+
 fn merge_fragments(old: &[(Attr, (u32, u32))], new: &[(Attr, (u32, u32))]) -> Vec<(Attr, (u32, u32))> {
     let mut ret = vec![];
     let mut i = 0;
@@ -427,7 +652,7 @@ fn merge_fragments(old: &[(Attr, (u32, u32))], new: &[(Attr, (u32, u32))]) -> Ve
         }
     }
     if j < new.len() {
-        ret.extend_from_slice(&new[i..]);
+        ret.extend_from_slice(&new[j..]);
     }
 
     ret
@@ -454,6 +679,128 @@ mod tests {
         assert_eq!(ansistring.stripped(), "hi");
     }
 
+    #[test]
+    fn test_spans() {
+        let input = "\x1B[1;31mhi\x1B[0m, \x1B[48;5;200mworld\x1B[38;2;1;2;3m!";
+        let ansistring = ANSIParser::default().parse_ansi(input);
+        let bg = Attr::default().bg(Color::AnsiValue(200));
+
+        assert_eq!(
+            ansistring.spans().collect::<Vec<_>>(),
+            vec![
+                ("hi", Attr::default().fg(Color::RED).effect(Effect::BOLD)),
+                (", ", Attr::default()),
+                ("world", bg),
+                ("!", bg.fg(Color::Rgb(1, 2, 3))),
+            ]
+        );
+        assert_eq!(ANSIParser::default().parse_ansi(&ansistring.to_ansi()), ansistring);
+
+        let plain = AnsiString::from("ab");
+        assert_eq!(plain.spans().collect::<Vec<_>>(), vec![("ab", Attr::default())]);
+        assert_eq!(plain.to_ansi(), "ab");
+        assert_eq!(AnsiString::new_empty().spans().next(), None);
+    }
+
+    #[test]
+    fn test_underline_style_and_color() {
+        let input = "\x1B[4:3;58;5;1mcurly\x1B[4:0m \x1B[4;58:2::1:2:3mred\x1B[59;24mplain";
+        let ansistring = ANSIParser::default().parse_ansi(input);
+        let underline = Attr::default().effect(Effect::UNDERLINE);
+
+        // drawn as plain underlines
+        assert_eq!(
+            ansistring.spans().collect::<Vec<_>>(),
+            vec![
+                ("curly", underline),
+                (" ", Attr::default()),
+                ("red", underline),
+                ("plain", Attr::default()),
+            ]
+        );
+        assert_eq!(
+            ansistring.extras,
+            Some(vec![
+                (
+                    ExtAttr {
+                        underline_style: UnderlineStyle::Curly,
+                        underline_color: Color::AnsiValue(1),
+                        ..ExtAttr::default()
+                    },
+                    (0, 5)
+                ),
+                (
+                    ExtAttr {
+                        underline_style: UnderlineStyle::Single,
+                        underline_color: Color::AnsiValue(1),
+                        ..ExtAttr::default()
+                    },
+                    (5, 6)
+                ),
+                (
+                    ExtAttr {
+                        underline_style: UnderlineStyle::Single,
+                        underline_color: Color::Rgb(1, 2, 3),
+                        ..ExtAttr::default()
+                    },
+                    (6, 9)
+                ),
+            ])
+        );
+        assert_eq!(ANSIParser::default().parse_ansi(&ansistring.to_ansi()), ansistring);
+    }
+
+    #[test]
+    fn test_effects() {
+        let input = "\x1B[1;2mbold dim\x1B[22;3;9mitalic strike\x1B[23;6mstrike blink\x1B[25;29mplain";
+        let ansistring = ANSIParser::default().parse_ansi(input);
+        let blink = Attr::default().effect(Effect::BLINK);
+
+        assert_eq!(
+            ansistring.spans().collect::<Vec<_>>(),
+            vec![
+                ("bold dim", Attr::default().effect(Effect::BOLD | Effect::DIM)),
+                ("italic strike", Attr::default()),
+                ("strike blink", blink),
+                ("plain", Attr::default()),
+            ]
+        );
+        let effects: Vec<_> = ansistring
+            .extras
+            .iter()
+            .flatten()
+            .map(|&(ext, range)| (ext.effect, range))
+            .collect();
+        assert_eq!(
+            effects,
+            vec![
+                (ExtEffect::ITALIC | ExtEffect::STRIKETHROUGH, (8, 21)),
+                (ExtEffect::STRIKETHROUGH, (21, 33)),
+            ]
+        );
+        assert_eq!(ANSIParser::default().parse_ansi(&ansistring.to_ansi()), ansistring);
+    }
+
+    #[test]
+    fn test_select_ranges() {
+        let input = "\x1B[31mred\x1B[0m \x1B[3;32mgreen\x1B[0m blue";
+        let ansistring = ANSIParser::default().parse_ansi(input);
+        let selected = ansistring.select_ranges(&[(4, 10), (2, 4)]);
+
+        assert_eq!(selected.stripped(), "green d ");
+        assert_eq!(
+            selected.spans().collect::<Vec<_>>(),
+            vec![
+                ("green", Attr::default().fg(Color::GREEN)),
+                (" ", Attr::default()),
+                ("d", Attr::default().fg(Color::RED)),
+                (" ", Attr::default()),
+            ]
+        );
+        assert_eq!(selected.to_ansi(), "\x1B[0;3;32mgreen\x1B[0m \x1B[0;31md\x1B[0m ");
+        assert_eq!(ansistring.select_ranges(&[]), AnsiString::new_empty());
+    }
+
     #[test]
     fn test_highlight_indices() {
         let text = "abc";
@@ -580,5 +927,10 @@ mod tests {
             merge_fragments(&[(ao, (1, 3)), (ao, (5, 7)), (ao, (9, 11))], &[(an, (2, 6))]),
             vec![(ao, (1, 2)), (an, (2, 6)), (ao, (6, 7)), (ao, (9, 11))]
         );
+
+        assert_eq!(
+            merge_fragments(&[(ao, (1, 3))], &[(an, (4, 5)), (an, (6, 7))]),
+            vec![(ao, (1, 3)), (an, (4, 5)), (an, (6, 7))]
+        );
     }
 }
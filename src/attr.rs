@@ -0,0 +1,134 @@
+//! The attributes(colors, effects) of the text displayed by skim.
+//!
+//! With the `tui` feature (default) these are the types of `tuikit`, otherwise a minimal
+//! implementation is provided so that items and matching engines could be used without tuikit.
+#[cfg(feature = "tui")]
+pub use tuikit::attr::{Attr, Color, Effect};
+
+#[cfg(not(feature = "tui"))]
+pub use self::standalone::{Attr, Color, Effect};
+
+#[cfg(not(feature = "tui"))]
+mod standalone {
+    use bitflags::bitflags;
+
+    /// Color of a character, could be 8 bit(256 color) or RGB color
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    pub enum Color {
+        Default,
+        AnsiValue(u8),
+        Rgb(u8, u8, u8),
+    }
+
+    impl Color {
+        pub const BLACK: Color = Color::AnsiValue(0);
+        pub const RED: Color = Color::AnsiValue(1);
+        pub const GREEN: Color = Color::AnsiValue(2);
+        pub const YELLOW: Color = Color::AnsiValue(3);
+        pub const BLUE: Color = Color::AnsiValue(4);
+        pub const MAGENTA: Color = Color::AnsiValue(5);
+        pub const CYAN: Color = Color::AnsiValue(6);
+        pub const WHITE: Color = Color::AnsiValue(7);
+    }
+
+    impl Default for Color {
+        fn default() -> Self {
+            Color::Default
+        }
+    }
+
+    bitflags! {
+        /// `Effect` is the effect of a text
+        pub struct Effect: u8 {
+            const BOLD = 0b00000001;
+            const DIM = 0b00000010;
+            const UNDERLINE = 0b00000100;
+            const BLINK = 0b00001000;
+            const REVERSE = 0b00010000;
+        }
+    }
+
+    /// `Attr` is a rendering attribute that contains fg color, bg color and text effect.
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    pub struct Attr {
+        pub fg: Color,
+        pub bg: Color,
+        pub effect: Effect,
+    }
+
+    impl Default for Attr {
+        fn default() -> Self {
+            Attr {
+                fg: Color::default(),
+                bg: Color::default(),
+                effect: Effect::empty(),
+            }
+        }
+    }
+
+    impl Attr {
+        /// extend the properties with the new attr's if the properties in new attr is not default.
+        pub fn extend(&self, new_attr: Self) -> Attr {
+            Attr {
+                fg: if new_attr.fg != Color::default() {
+                    new_attr.fg
+                } else {
+                    self.fg
+                },
+                bg: if new_attr.bg != Color::default() {
+                    new_attr.bg
+                } else {
+                    self.bg
+                },
+                effect: self.effect | new_attr.effect,
+            }
+        }
+
+        pub fn fg(mut self, fg: Color) -> Self {
+            self.fg = fg;
+            self
+        }
+
+        pub fn bg(mut self, bg: Color) -> Self {
+            self.bg = bg;
+            self
+        }
+
+        pub fn effect(mut self, effect: Effect) -> Self {
+            self.effect = effect;
+            self
+        }
+    }
+}
+
+/// The style of an underline, set by SGR `4:x`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum UnderlineStyle {
+    #[default]
+    Single,
+    Double,
+    Curly,
+    Dotted,
+    Dashed,
+}
+
+bitflags::bitflags! {
+    /// The text effects beyond `Effect`
+    #[derive(Default)]
+    pub struct ExtEffect: u8 {
+        const ITALIC = 0b00000001;
+        const STRIKETHROUGH = 0b00000010;
+    }
+}
+
+/// The attributes beyond `Attr` that the terminal backend couldn't draw.
+///
+/// They are kept in `AnsiString` (see `AnsiString::ext_attrs`) so that they survive to its output,
+/// and are drawn as close as possible on screen, e.g. a curly underline as a plain one and the
+/// underline color is dropped.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct ExtAttr {
+    pub effect: ExtEffect,
+    pub underline_style: UnderlineStyle,
+    pub underline_color: Color,
+}
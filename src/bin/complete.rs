@@ -0,0 +1,381 @@
+//! The fuzzy completion of the shells (`--complete-shell LINE`): the word before the cursor is
+//! completed with env vars, hosts, processes, directories or files, depending on the word and the
+//! command it's an argument of, and its replacement is printed for the shell to insert.
+use std::collections::BTreeSet;
+use std::env;
+
+use skim::prelude::*;
+
+use crate::read_file_lines;
+
+// the commands whose arguments are completed with hosts, processes or directories instead of files
+const HOST_COMMANDS: &[&str] = &["ssh", "scp", "sftp", "telnet", "mosh", "rsync"];
+const PROCESS_COMMANDS: &[&str] = &["kill"];
+const DIRECTORY_COMMANDS: &[&str] = &["cd", "pushd", "rmdir"];
+
+// turns a selected item into the replacement of the word
+type Replace = Box<dyn Fn(&str) -> String>;
+
+/// complete the word before the `cursor` (in chars) of the command `line`, and print the
+/// replacement of the word. The source is picked by the word and the command it's an argument of:
+/// env vars for `$VAR`, hosts for `ssh`, processes for `kill`, directories for `cd` or files.
+/// The word could end with the trigger (`$SKIM_COMPLETION_TRIGGER`, default: `**`).
+pub fn complete_shell(line: &str, cursor: Option<usize>, options: SkimOptions) -> Result<i32, std::io::Error> {
+    let trigger = env::var("SKIM_COMPLETION_TRIGGER").unwrap_or_else(|_| "**".to_string());
+    let (command, word) = completion_context(line, cursor, &trigger);
+    let (command, word) = (command.as_str(), word.as_str());
+
+    // the items (or the command that lists them), the query and how to turn a selected item back
+    // into the replacement
+    let mut items = None;
+    let mut cmd = String::new();
+    let mut header_lines = 0;
+    let (query, replace): (&str, Replace) = if let Some(name) = word.strip_prefix('$') {
+        let mut names: Vec<String> = env::vars().map(|(name, _)| name).collect();
+        names.sort();
+        items = Some(names);
+        (name, Box::new(|name| format!("${}", name)))
+    } else if HOST_COMMANDS.contains(&command) {
+        // keep the user of `user@host`
+        let (user, host) = match word.find('@') {
+            Some(idx) => word.split_at(idx + 1),
+            None => ("", word),
+        };
+        items = Some(known_hosts());
+        let user = user.to_string();
+        (host, Box::new(move |host| format!("{}{}", user, host)))
+    } else if PROCESS_COMMANDS.contains(&command) {
+        cmd = "ps -eo pid,user,args".to_string();
+        header_lines = 1;
+        let pid = |process: &str| process.split_whitespace().next().unwrap_or("").to_string();
+        (word, Box::new(pid))
+    } else {
+        // list the files under the directory part of the word, match the rest
+        let (dir, base) = match word.rfind('/') {
+            Some(idx) => word.split_at(idx + 1),
+            None => ("", word),
+        };
+        let types = if DIRECTORY_COMMANDS.contains(&command) {
+            "-type d -print"
+        } else {
+            "-type f -print -o -type d -print -o -type l -print"
+        };
+        // `~/` is expanded for find, and put back in the replacement for the shell to expand it
+        let home = env::var("HOME").ok().filter(|home| !home.is_empty());
+        let root = match home.as_deref() {
+            _ if dir.is_empty() => ".".to_string(),
+            Some(home) => expand_tilde(dir, home),
+            None => dir.to_string(),
+        };
+        let home = home.filter(|_| root != dir);
+        cmd = format!(
+            "command find -L {} -mindepth 1 \\( -path '*/\\.*' -o -fstype sysfs -o -fstype devfs -o -fstype devtmpfs \\) \
+             -prune -o {} 2> /dev/null | sed 's@^\\./@@'",
+            shlex::quote(&root),
+            types
+        );
+        (base, Box::new(move |path| quote_path(path, home.as_deref())))
+    };
+
+    let options = SkimOptions {
+        query: Some(query),
+        cmd: Some(&cmd),
+        multi: true,
+        header_lines,
+        ..options
+    };
+    let source = items.map(|items| {
+        let (tx_item, rx_item): (SkimItemSender, SkimItemReceiver) = unbounded();
+        for item in items {
+            let _ = tx_item.send(Arc::new(item));
+        }
+        rx_item
+    });
+
+    let output = match Skim::run_with(&options, source) {
+        Some(output) => output,
+        None => return Ok(130),
+    };
+    if output.selected_items.is_empty() {
+        return Ok(1);
+    }
+
+    let replacements: Vec<String> = output
+        .selected_items
+        .iter()
+        .map(|item| replace(&item.output()))
+        .collect();
+    println!("{}", replacements.join(" "));
+    Ok(0)
+}
+
+/// the command and the word (without the trigger) before the cursor (in chars) of the line, the
+/// command is empty if the word is the command itself
+fn completion_context(line: &str, cursor: Option<usize>, trigger: &str) -> (String, String) {
+    let left: String = line.chars().take(cursor.unwrap_or(usize::MAX)).collect();
+    let word = left.rsplit(char::is_whitespace).next().unwrap_or("");
+    let word = word.strip_suffix(trigger).unwrap_or(word);
+    let segment = left.rsplit(['|', ';', '&']).next().unwrap_or("");
+    let command = segment
+        .split_whitespace()
+        .next()
+        .filter(|&command| command != word)
+        .unwrap_or("");
+    (command.to_string(), word.to_string())
+}
+
+/// the hosts in the ssh config, the (unhashed) known hosts of ssh and /etc/hosts
+fn known_hosts() -> Vec<String> {
+    let home = env::var("HOME").unwrap_or_default();
+    let lines_of = |filename: &str| read_file_lines(filename).unwrap_or_default();
+    let mut hosts = BTreeSet::new();
+
+    let config = ssh_config_lines(&format!("{}/.ssh/config", home), &home, 0);
+    let (config_hosts, mut known_hosts_files) = hosts_of_ssh_config(&config, &home);
+    hosts.extend(config_hosts);
+
+    if known_hosts_files.is_empty() {
+        known_hosts_files.push(format!("{}/.ssh/known_hosts", home));
+    }
+    for filename in known_hosts_files {
+        hosts.extend(hosts_of_known_hosts(&lines_of(&filename)));
+    }
+
+    // address name aliases
+    for line in lines_of("/etc/hosts") {
+        let line = line.split('#').next().unwrap_or("");
+        hosts.extend(line.split_whitespace().skip(1).map(str::to_string));
+    }
+
+    hosts.into_iter().filter(|host| !host.is_empty()).collect()
+}
+
+// ssh stops at this depth of nested `Include`s too
+const SSH_INCLUDE_DEPTH: usize = 16;
+
+/// the lines of the ssh config file, with the files of its `Include`s in place
+fn ssh_config_lines(filename: &str, home: &str, depth: usize) -> Vec<String> {
+    let mut lines = vec![];
+    for line in read_file_lines(filename).unwrap_or_default() {
+        let mut words = line.split_whitespace();
+        if !words.next().is_some_and(|key| key.eq_ignore_ascii_case("include")) || depth >= SSH_INCLUDE_DEPTH {
+            lines.push(line);
+            continue;
+        }
+        for pattern in words {
+            // the relative paths are relative to ~/.ssh
+            let pattern = expand_tilde(pattern, home);
+            let pattern = if pattern.starts_with('/') {
+                pattern
+            } else {
+                format!("{}/.ssh/{}", home, pattern)
+            };
+            for included in glob_files(&pattern) {
+                lines.extend(ssh_config_lines(&included, home, depth + 1));
+            }
+        }
+    }
+    lines
+}
+
+/// the `Host` aliases of the ssh config, without the patterns, and its `UserKnownHostsFile`s
+fn hosts_of_ssh_config(lines: &[String], home: &str) -> (Vec<String>, Vec<String>) {
+    let mut hosts = vec![];
+    let mut known_hosts_files = vec![];
+    for line in lines {
+        let mut words = line.split_whitespace();
+        match words.next() {
+            Some(key) if key.eq_ignore_ascii_case("host") => {
+                hosts.extend(words.filter(|host| !host.contains(['*', '?', '!'])).map(str::to_string));
+            }
+            Some(key) if key.eq_ignore_ascii_case("userknownhostsfile") => {
+                known_hosts_files.extend(words.map(|filename| expand_tilde(filename, home)));
+            }
+            _ => {}
+        }
+    }
+    (hosts, known_hosts_files)
+}
+
+/// the hosts of the lines `host1,[host2]:port key-type key` of a known_hosts file, without the
+/// hashed hosts (`|1|...`), the comments and the lines with a marker (`@revoked`)
+fn hosts_of_known_hosts(lines: &[String]) -> Vec<String> {
+    let mut hosts = vec![];
+    for line in lines {
+        let names = line.split_whitespace().next().unwrap_or("");
+        if names.starts_with(['|', '#', '@']) {
+            continue;
+        }
+        for name in names
+            .split(',')
+            .filter(|name| !name.is_empty() && !name.contains(['*', '?', '!']))
+        {
+            let name = name
+                .strip_prefix('[')
+                .and_then(|name| name.split(']').next())
+                .unwrap_or(name);
+            hosts.push(name.to_string());
+        }
+    }
+    hosts
+}
+
+/// replace the leading `~` of a path with the home directory
+fn expand_tilde(path: &str, home: &str) -> String {
+    match path.strip_prefix('~') {
+        Some(rest) if rest.is_empty() || rest.starts_with('/') => format!("{}{}", home, rest),
+        _ => path.to_string(),
+    }
+}
+
+/// quote the path for the shell, with the leading home directory (if given) replaced by `~`
+fn quote_path(path: &str, home: Option<&str>) -> String {
+    match home.and_then(|home| path.strip_prefix(home)) {
+        Some(rest) if rest.starts_with('/') => format!("~{}", shlex::quote(rest)),
+        _ => shlex::quote(path).into_owned(),
+    }
+}
+
+/// the files matching the path, whose file name could have `*` and `?` wildcards
+fn glob_files(pattern: &str) -> Vec<String> {
+    let (dir, name) = match pattern.rfind('/') {
+        Some(idx) => (&pattern[..idx + 1], &pattern[idx + 1..]),
+        None => ("./", pattern),
+    };
+    if !name.contains(['*', '?']) {
+        return vec![pattern.to_string()];
+    }
+
+    let mut files: Vec<String> = std::fs::read_dir(dir)
+        .map(|entries| {
+            entries
+                .filter_map(|entry| entry.ok())
+                .filter_map(|entry| entry.file_name().into_string().ok())
+                .filter(|file_name| wildcard_match(name, file_name))
+                .map(|file_name| format!("{}{}", dir, file_name))
+                .collect()
+        })
+        .unwrap_or_default();
+    files.sort();
+    files
+}
+
+/// match the text with the pattern of `*` (any chars) and `?` (one char)
+fn wildcard_match(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+    // the position after the last `*` in the pattern and the text it's matched up to
+    let mut star = None;
+    let (mut p, mut t) = (0, 0);
+    while t < text.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                star = Some((p + 1, t));
+                p += 1;
+            }
+            Some(&ch) if ch == '?' || ch == text[t] => {
+                p += 1;
+                t += 1;
+            }
+            _ => match star {
+                Some((star_p, star_t)) => {
+                    p = star_p;
+                    t = star_t + 1;
+                    star = Some((star_p, star_t + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|&ch| ch == '*')
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_completion_context() {
+        let context = |line, cursor| completion_context(line, cursor, "**");
+        let pair = |command: &str, word: &str| (command.to_string(), word.to_string());
+        assert_eq!(context("vim src/ma**", None), pair("vim", "src/ma"));
+        assert_eq!(context("ssh user@ho", None), pair("ssh", "user@ho"));
+        assert_eq!(context("ls | kill 12", None), pair("kill", "12"));
+        assert_eq!(context("cd a; vi", None), pair("", "vi"));
+        assert_eq!(context("cd foo bar", Some(6)), pair("cd", "foo"));
+        assert_eq!(context("echo $HO", None), pair("echo", "$HO"));
+    }
+
+    #[test]
+    fn test_hosts_of_ssh_config() {
+        let lines: Vec<String> = [
+            "Host web db.local *.internal !bastion",
+            "  HostName 10.0.0.1",
+            "host backup",
+            "UserKnownHostsFile ~/.ssh/known_hosts ~/.ssh/known_hosts2 /etc/ssh/hosts",
+        ]
+        .iter()
+        .map(|line| line.to_string())
+        .collect();
+        let (hosts, files) = hosts_of_ssh_config(&lines, "/home/user");
+        assert_eq!(hosts, vec!["web", "db.local", "backup"]);
+        assert_eq!(
+            files,
+            vec![
+                "/home/user/.ssh/known_hosts",
+                "/home/user/.ssh/known_hosts2",
+                "/etc/ssh/hosts"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_hosts_of_known_hosts() {
+        let lines: Vec<String> = [
+            "github.com,140.82.121.4 ssh-ed25519 AAAA",
+            "[git.local]:2222 ssh-rsa AAAA",
+            "|1|JfKTdBh7rNbXkVAQCRp4OQoPfmI=|USECr3SWf1JUPsms5AqfD5QfxkM= ssh-rsa AAAA",
+            "# old.example.com ssh-rsa AAAA",
+            "@revoked bad.example.com ssh-rsa AAAA",
+            "*.example.com ssh-rsa AAAA",
+            "",
+        ]
+        .iter()
+        .map(|line| line.to_string())
+        .collect();
+        assert_eq!(
+            hosts_of_known_hosts(&lines),
+            vec!["github.com", "140.82.121.4", "git.local"]
+        );
+    }
+
+    #[test]
+    fn test_expand_tilde() {
+        assert_eq!(expand_tilde("~/.ssh/config", "/home/user"), "/home/user/.ssh/config");
+        assert_eq!(expand_tilde("~", "/home/user"), "/home/user");
+        assert_eq!(expand_tilde("~other/x", "/home/user"), "~other/x");
+        assert_eq!(expand_tilde("/etc/ssh", "/home/user"), "/etc/ssh");
+    }
+
+    #[test]
+    fn test_quote_path() {
+        assert_eq!(quote_path("src/main.rs", None), "src/main.rs");
+        assert_eq!(quote_path("/home/user/a b", Some("/home/user")), "~\"/a b\"");
+        assert_eq!(quote_path("/home/user/src", Some("/home/user")), "~/src");
+        assert_eq!(
+            quote_path("/home/username/src", Some("/home/user")),
+            "/home/username/src"
+        );
+        assert_eq!(quote_path("/home/user/src", None), "/home/user/src");
+    }
+
+    #[test]
+    fn test_wildcard_match() {
+        assert!(wildcard_match("*", "work"));
+        assert!(wildcard_match("*.conf", "work.conf"));
+        assert!(wildcard_match("w?rk*", "work.conf"));
+        assert!(wildcard_match("a*b*c", "aXbYbZc"));
+        assert!(!wildcard_match("*.conf", "work.conf.bak"));
+        assert!(!wildcard_match("w?rk", "wrk"));
+    }
+}
@@ -6,35 +6,72 @@ extern crate shlex;
 extern crate skim;
 extern crate time;
 
+mod complete;
+mod profile;
+mod shell_history;
+mod source;
+
 use derive_builder::Builder;
+use std::collections::VecDeque;
 use std::env;
 use std::fs::File;
-use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::io::{BufRead, BufReader, BufWriter, Read, Write};
+use std::net::Shutdown;
+use std::os::unix::fs::DirBuilderExt;
 use std::os::unix::io::AsRawFd;
+use std::os::unix::net::UnixStream;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
 
 use clap::{App, Arg, ArgMatches};
 use nix::unistd::isatty;
+use skim::field::{parse_delimiter, Delimiter, FieldRange};
 use skim::prelude::*;
+use skim::Json;
+
+use crate::complete::complete_shell;
+use crate::profile::profile_options;
+use crate::source::{source_items, source_options};
 
 const VERSION: &str = env!("CARGO_PKG_VERSION");
 
 const USAGE: &str = "
 Usage: sk [options]
+       sk ctl SOCKET COMMAND [ARG]
 
   Options
     -h, --help           print this help menu
     --version            print out the current version of skim
+    --shell=SHELL        print the key bindings and completion script for
+                         [bash|zsh|fish], e.g. eval \"$(sk --shell bash)\"
 
   Search
     --tac                reverse the order of search result
     --no-sort            Do not sort the result
+    --max-matches=N      Stop matching after N results are found
+    --max-items=N        Stop reading the input after N items
+    --tail=N             Keep only the last N items, dropping the older ones
+    --index              Index the chars and trigrams of a large input to skip
+                         the items that can't match the exact and regex terms
+    --max-bytes=SIZE     Stop reading the input after SIZE bytes, e.g. 100M
+    --read-buffer=N      Maximum number of items read but not matched yet
+    --read-buffer-policy=[block|drop]
+                         Block the input or drop the new items when the
+                         read buffer is full (default: block)
     -t, --tiebreak [score,begin,end,-score,length...]
 
                          comma seperated criteria
+    --sort-key FIELD[:num][:desc]
+                         sort by the value of the field, e.g. 3:num:desc
     -n, --nth 1,2..5     specify the fields to be matched
     --with-nth 1,2..5    specify the fields to be transformed
-    -d, --delimiter \\t  specify the delimiter(in REGEX) for fields
+    --accept-nth 1,2..5  specify the fields to be output on accept
+    -d, --delimiter \\t  specify the delimiter(in REGEX or literal) for fields
+                         or a preset: @csv, @tsv, @ws
     -e, --exact          start skim in exact mode
+    --literal            match the whole query as one exact string
+    --matcher-cmd=CMD    delegate the matching to a long-running command,
+                         see the man page for its protocol
     --regex              use regex instead of fuzzy match
     --algo=TYPE          Fuzzy matching algorithm:
                          [skim_v1|skim_v2|clangd] (default: skim_v2)
@@ -53,23 +90,57 @@ Usage: sk [options]
                          change color theme
     --no-hscroll         Disable horizontal scroll
     --keep-right         Keep the right end of the line visible on overflow
+    --track              Keep the cursor on the current item when the query
+                         changes
+    --cycle              Enable cyclic scroll
+    --grid               Lay out the items in columns, for many short items
+    --wrap               Wrap long items onto continuation lines
+    --multi-line         Display each line of multi-line items on its own row
+    --scroll-off=LINES   Number of screen lines to keep above or below when
+                         scrolling to the top or to the bottom (default: 0)
     --skip-to-pattern    Line starts with the start of matched pattern
+    --browse[=DIR]       Browse the directories from DIR (default: .),
+                         enter descends, backspace on empty query goes up
+    --clipboard-cmd=CMD  Command the yank-item and yank-query actions copy
+                         with, instead of the OSC 52 escape sequence
 
   Layout
     --layout=LAYOUT      Choose layout: [default|reverse|reverse-list]
     --height=HEIGHT      Height of skim's window (--height 40%)
     --no-height          Disable height feature
+    --tmux[=[center|top|bottom|left|right][,SIZE[%]][,SIZE[%]]]
+                         Start in a tmux popup when run inside tmux
+                         (default: center,50%)
     --min-height=HEIGHT  Minimum height when --height is given by percent
                          (default: 10)
     --margin=MARGIN      Screen Margin (TRBL / TB,RL / T,RL,B / T,R,B,L)
                          e.g. (sk --margin 1,10%)
-    -p, --prompt '> '    prompt string for query mode
-    --cmd-prompt '> '    prompt string for command mode
+    --border             Draw border around the finder
+    --border-label=LABEL Label shown in the top border
+    --border-label-pos=POS
+                         Alignment of the border label: [left|center|right]
+                         (default: left)
+    -p, --prompt '> '    prompt string for query mode, could contain ANSI colors
+    --cmd-prompt '> '    prompt string for command mode, could contain ANSI colors
+    --pointer '>'        pointer to the current line, could contain ANSI colors
+    --marker '>'         multi-select marker, could contain ANSI colors
 
   Display
     --ansi               parse ANSI color codes for input strings
     --tabstop=SPACES     Number of spaces for a tab character (default: 8)
+    --format-cmd=CMD     decorate the visible items with a long-running
+                         command answering each line with its display
+    --icons[=nerd|ascii] Show file-type icons before the items that look
+                         like paths (default: nerd)
+    --shorten-paths      Show $HOME as ~ and elide the middle of the paths
+                         too long for the screen
     --inline-info        Display info next to query
+    --info-format=FORMAT Template of the info line, e.g.
+                         '{matched}/{total} ({selected} selected) {spinner}'
+    --accessible         No animation and a plain info line for screen readers
+    --bell               Ring the bell when the number of matches changes
+    --no-unicode         Draw the borders and glyphs in ASCII, the default
+                         if the locale isn't UTF-8
     --header=STR         Display STR next to info
     --header-lines=N     The first N lines of the input are treated as header
 
@@ -84,6 +155,10 @@ Usage: sk [options]
                          We can specify the fields. e.g. ({1}, {..3}, {0..})
     --preview-window=OPT Preview window layout (default: right:50%)
                          [up|down|left|right][:SIZE[%]][:hidden]
+    --preview-label=LABEL
+                         Label shown in the border of the preview window,
+                         fields are expanded like --preview. e.g. ({1})
+    --preview-timeout=MS Kill the preview command if it runs longer than MS
 
   Scripting
     -q, --query \"\"       specify the initial query
@@ -94,12 +169,52 @@ Usage: sk [options]
     --no-clear           Do not clear screen on exit
     --print-query        Print query as the first line
     --print-cmd          Print command query as the first line (after --print-query)
-    --print-score        Print matching score in filter output (with --filter)
+    --print-score        Print the score before each output item
+    --print-ranges       Print the matched byte ranges before each output item
+                         in filter mode, e.g. 0-2,4-5
+    --print-json         Print every result of filter mode as a JSON object
+                         with its text, output, score and matched ranges
     -1, --select-1       Automatically select the only match
     -0, --exit-0         Exit immediately when there's no match
+    --bench=FILE         Match the input against the queries in FILE (one per
+                         line) without UI, report the time and top results
+    --filter-queries=FILE
+                         Filter the input with every query in FILE (one per
+                         line, or NUL delimited, '-' for stdin) in one pass,
+                         the results are grouped by query
+    --rpc[=json|msgpack] Run as a co-process speaking JSON-RPC over stdio
+                         (one message per line, or MessagePack values)
+                         instead of drawing the UI
+    --debug-perf         Log the match and render time to stderr
+                         (e.g. sk --debug-perf 2>perf.log)
+    --record=FILE        Record the keys and resizes of the session to FILE
+    --replay=FILE        Replay a recorded session without the terminal
+    --listen-socket=PATH Accept the commands of `sk ctl PATH` on a unix socket:
+                         reload [CMD], change-query QUERY and selection
+    --reload-on-signal   Reload the items on SIGUSR1
+    --refresh-interval=SECS
+                         Reload the items every SECS seconds, keeping the
+                         query, the cursor and the selections
+    --with-shell=SHELL   Shell and its flags to run the commands with, the
+                         command is appended (default: $SHELL -c)
+    --cmd-cwd=DIR        Working directory of the source and preview commands
+    --cmd-env=KEY=VALUE  Environment variable of the source and preview
+                         commands, could be given multiple times
+    --profile=NAME       Use the options of the [NAME] section of
+                         ~/.config/skim/profiles, before the ones given here
+    --source=NAME        Read the items of a built-in source, with its
+                         preview: [git-files|git-status|git-branches|
+                         shell-history]
+    --complete-shell=LINE
+                         Complete the word before the cursor of the command
+                         line with files, hosts, processes or env vars, and
+                         print the replacement of the word
+    --complete-cursor=N  The cursor position in LINE (default: the end)
 
   Environment variables
-    SKIM_DEFAULT_COMMAND Default command to use when input is tty
+    SKIM_DEFAULT_COMMAND Default command to use when input is tty, before the
+                         first installed one in ~/.config/skim/default_command
+                         and `find .`
     SKIM_DEFAULT_OPTIONS Default options (e.g. '--ansi --regex')
                          You should not include other environment variables
                          (e.g. '-c \"$HOME/bin/ag\"')
@@ -109,12 +224,9 @@ Usage: sk [options]
 
   Reserved (not used for now)
     --extended
-    --literal
-    --cycle
     --hscroll-off=COL
     --filepath-word
     --jump-labels=CHARS
-    --border
     --no-bold
     --history=FILE
     --history-size=N
@@ -122,6 +234,13 @@ Usage: sk [options]
     --exit-0
 ";
 
+// the shell integration, shipped with the binary so that they are always of the same version
+const KEY_BINDINGS_BASH: &str = include_str!("../../shell/key-bindings.bash");
+const COMPLETION_BASH: &str = include_str!("../../shell/completion.bash");
+const KEY_BINDINGS_ZSH: &str = include_str!("../../shell/key-bindings.zsh");
+const COMPLETION_ZSH: &str = include_str!("../../shell/completion.zsh");
+const KEY_BINDINGS_FISH: &str = include_str!("../../shell/key-bindings.fish");
+
 const DEFAULT_HISTORY_SIZE: usize = 1000;
 
 //------------------------------------------------------------------------------
@@ -144,6 +263,11 @@ fn main() {
 fn real_main() -> Result<i32, std::io::Error> {
     let mut stdout = std::io::stdout();
 
+    // control a running instance, e.g. `sk ctl /tmp/sk.sock reload`
+    if env::args().nth(1).as_deref() == Some("ctl") {
+        return control(&env::args().skip(2).collect::<Vec<_>>());
+    }
+
     let mut args = Vec::new();
 
     args.push(env::args().next().expect("there should be at least one arg: the application name"));
@@ -151,10 +275,38 @@ fn real_main() -> Result<i32, std::io::Error> {
         .ok()
         .and_then(|val| shlex::split(&val))
         .unwrap_or_default());
+    let num_default_args = args.len();
     for arg in env::args().skip(1) {
         args.push(arg);
     }
 
+    // the options of the profile go after the default ones, the command line overrides both
+    if let Some(name) = last_value_of(&args[1..], "--profile") {
+        match profile_options(&name) {
+            Ok(options) => {
+                args.splice(num_default_args..num_default_args, options);
+            }
+            Err(err) => {
+                eprintln!("sk: {}", err);
+                return Ok(2);
+            }
+        }
+    }
+
+    // the options of the source go first, any of them could be overridden
+    let source = last_value_of(&args[1..], "--source");
+    if let Some(name) = source.as_ref() {
+        match source_options(name) {
+            Ok(options) => {
+                args.splice(1..1, options);
+            }
+            Err(err) => {
+                eprintln!("sk: {}", err);
+                return Ok(2);
+            }
+        }
+    }
+
 
     //------------------------------------------------------------------------------
     // parse options
@@ -162,14 +314,22 @@ fn real_main() -> Result<i32, std::io::Error> {
         .author("Jinzhou Zhang<lotabout@gmail.com>")
         .arg(Arg::with_name("help").long("help").short("h"))
         .arg(Arg::with_name("version").long("version").short("v"))
+        .arg(Arg::with_name("shell").long("shell").multiple(true).takes_value(true))
+        .arg(Arg::with_name("profile").long("profile").multiple(true).takes_value(true))
+        .arg(Arg::with_name("source").long("source").multiple(true).takes_value(true))
+        .arg(Arg::with_name("complete-shell").long("complete-shell").multiple(true).takes_value(true))
+        .arg(Arg::with_name("complete-cursor").long("complete-cursor").multiple(true).takes_value(true))
         .arg(Arg::with_name("bind").long("bind").short("b").multiple(true).takes_value(true))
         .arg(Arg::with_name("multi").long("multi").short("m").multiple(true))
         .arg(Arg::with_name("no-multi").long("no-multi").multiple(true))
         .arg(Arg::with_name("prompt").long("prompt").short("p").multiple(true).takes_value(true).default_value("> "))
         .arg(Arg::with_name("cmd-prompt").long("cmd-prompt").multiple(true).takes_value(true).default_value("c> "))
+        .arg(Arg::with_name("pointer").long("pointer").multiple(true).takes_value(true).default_value(">"))
+        .arg(Arg::with_name("marker").long("marker").multiple(true).takes_value(true).default_value(">"))
         .arg(Arg::with_name("expect").long("expect").multiple(true).takes_value(true))
         .arg(Arg::with_name("tac").long("tac").multiple(true))
         .arg(Arg::with_name("tiebreak").long("tiebreak").short("t").multiple(true).takes_value(true))
+        .arg(Arg::with_name("sort-key").long("sort-key").multiple(true).takes_value(true))
         .arg(Arg::with_name("ansi").long("ansi").multiple(true))
         .arg(Arg::with_name("exact").long("exact").short("e").multiple(true))
         .arg(Arg::with_name("cmd").long("cmd").short("c").multiple(true).takes_value(true))
@@ -177,19 +337,24 @@ fn real_main() -> Result<i32, std::io::Error> {
         .arg(Arg::with_name("query").long("query").short("q").multiple(true).takes_value(true))
         .arg(Arg::with_name("cmd-query").long("cmd-query").multiple(true).takes_value(true))
         .arg(Arg::with_name("regex").long("regex").multiple(true))
+        .arg(Arg::with_name("matcher-cmd").long("matcher-cmd").multiple(true).takes_value(true))
         .arg(Arg::with_name("delimiter").long("delimiter").short("d").multiple(true).takes_value(true))
         .arg(Arg::with_name("nth").long("nth").short("n").multiple(true).takes_value(true))
         .arg(Arg::with_name("with-nth").long("with-nth").multiple(true).takes_value(true))
+        .arg(Arg::with_name("accept-nth").long("accept-nth").multiple(true).takes_value(true))
         .arg(Arg::with_name("replstr").short("I").multiple(true).takes_value(true))
         .arg(Arg::with_name("color").long("color").multiple(true).takes_value(true))
         .arg(Arg::with_name("margin").long("margin").multiple(true).takes_value(true).default_value("0,0,0,0"))
         .arg(Arg::with_name("min-height").long("min-height").multiple(true).takes_value(true).default_value("10"))
         .arg(Arg::with_name("height").long("height").multiple(true).takes_value(true).default_value("100%"))
         .arg(Arg::with_name("no-height").long("no-height").multiple(true))
+        .arg(Arg::with_name("tmux").long("tmux").multiple(true).takes_value(true).min_values(0))
         .arg(Arg::with_name("no-clear").long("no-clear").multiple(true))
         .arg(Arg::with_name("no-mouse").long("no-mouse").multiple(true))
         .arg(Arg::with_name("preview").long("preview").multiple(true).takes_value(true))
         .arg(Arg::with_name("preview-window").long("preview-window").multiple(true).takes_value(true).default_value("right:50%"))
+        .arg(Arg::with_name("preview-label").long("preview-label").multiple(true).takes_value(true))
+        .arg(Arg::with_name("preview-timeout").long("preview-timeout").multiple(true).takes_value(true))
         .arg(Arg::with_name("reverse").long("reverse").multiple(true))
 
         .arg(Arg::with_name("algorithm").long("algo").multiple(true).takes_value(true).default_value("skim_v2"))
@@ -201,9 +366,36 @@ fn real_main() -> Result<i32, std::io::Error> {
         .arg(Arg::with_name("filepath-word").long("filepath-word").multiple(true))
         .arg(Arg::with_name("jump-labels").long("jump-labels").multiple(true).takes_value(true).default_value("abcdefghijklmnopqrstuvwxyz"))
         .arg(Arg::with_name("border").long("border").multiple(true))
+        .arg(Arg::with_name("border-label").long("border-label").multiple(true).takes_value(true))
+        .arg(Arg::with_name("border-label-pos").long("border-label-pos").multiple(true).takes_value(true).default_value("left"))
         .arg(Arg::with_name("inline-info").long("inline-info").multiple(true))
+        .arg(Arg::with_name("accessible").long("accessible").multiple(true))
+        .arg(Arg::with_name("bell").long("bell").multiple(true))
+        .arg(Arg::with_name("no-unicode").long("no-unicode").multiple(true))
+        .arg(Arg::with_name("info-format").long("info-format").multiple(true).takes_value(true))
+        .arg(Arg::with_name("debug-perf").long("debug-perf").multiple(true))
+        .arg(Arg::with_name("record").long("record").multiple(true).takes_value(true))
+        .arg(Arg::with_name("replay").long("replay").multiple(true).takes_value(true))
+        .arg(Arg::with_name("listen-socket").long("listen-socket").multiple(true).takes_value(true))
+        .arg(Arg::with_name("reload-on-signal").long("reload-on-signal").multiple(true))
+        .arg(Arg::with_name("with-shell").long("with-shell").multiple(true).takes_value(true))
+        .arg(Arg::with_name("cmd-cwd").long("cmd-cwd").multiple(true).takes_value(true))
+        .arg(Arg::with_name("cmd-env").long("cmd-env").multiple(true).takes_value(true))
+        .arg(Arg::with_name("refresh-interval").long("refresh-interval").multiple(true).takes_value(true))
         .arg(Arg::with_name("header").long("header").multiple(true).takes_value(true).default_value(""))
         .arg(Arg::with_name("header-lines").long("header-lines").multiple(true).takes_value(true).default_value("0"))
+        .arg(Arg::with_name("format-cmd").long("format-cmd").multiple(true).takes_value(true))
+        .arg(
+            Arg::with_name("icons")
+                .long("icons")
+                .multiple(true)
+                .takes_value(true)
+                .min_values(0)
+                .possible_values(&["nerd", "ascii"]),
+        )
+        .arg(Arg::with_name("browse").long("browse").multiple(true).takes_value(true).min_values(0))
+        .arg(Arg::with_name("clipboard-cmd").long("clipboard-cmd").multiple(true).takes_value(true))
+        .arg(Arg::with_name("shorten-paths").long("shorten-paths").multiple(true))
         .arg(Arg::with_name("tabstop").long("tabstop").multiple(true).takes_value(true).default_value("8"))
         .arg(Arg::with_name("no-bold").long("no-bold").multiple(true))
         .arg(Arg::with_name("history").long("history").multiple(true).takes_value(true))
@@ -213,18 +405,35 @@ fn real_main() -> Result<i32, std::io::Error> {
         .arg(Arg::with_name("print-query").long("print-query").multiple(true))
         .arg(Arg::with_name("print-cmd").long("print-cmd").multiple(true))
         .arg(Arg::with_name("print-score").long("print-score").multiple(true))
+        .arg(Arg::with_name("print-ranges").long("print-ranges").multiple(true))
+        .arg(Arg::with_name("print-json").long("print-json").multiple(true))
         .arg(Arg::with_name("read0").long("read0").multiple(true))
         .arg(Arg::with_name("print0").long("print0").multiple(true))
         .arg(Arg::with_name("sync").long("sync").multiple(true))
         .arg(Arg::with_name("extended").long("extended").short("x").multiple(true))
         .arg(Arg::with_name("no-sort").long("no-sort").multiple(true))
+        .arg(Arg::with_name("max-matches").long("max-matches").multiple(true).takes_value(true))
+        .arg(Arg::with_name("max-items").long("max-items").multiple(true).takes_value(true))
+        .arg(Arg::with_name("tail").long("tail").multiple(true).takes_value(true))
+        .arg(Arg::with_name("index").long("index").multiple(true))
+        .arg(Arg::with_name("max-bytes").long("max-bytes").multiple(true).takes_value(true))
+        .arg(Arg::with_name("read-buffer").long("read-buffer").multiple(true).takes_value(true))
+        .arg(Arg::with_name("read-buffer-policy").long("read-buffer-policy").multiple(true).takes_value(true))
         .arg(Arg::with_name("select-1").long("select-1").short("1").multiple(true))
         .arg(Arg::with_name("exit-0").long("exit-0").short("0").multiple(true))
         .arg(Arg::with_name("filter").long("filter").short("f").takes_value(true).multiple(true))
+        .arg(Arg::with_name("filter-queries").long("filter-queries").takes_value(true).multiple(true))
+        .arg(Arg::with_name("bench").long("bench").takes_value(true).multiple(true))
+        .arg(Arg::with_name("rpc").long("rpc").multiple(true).takes_value(true).min_values(0))
         .arg(Arg::with_name("layout").long("layout").multiple(true).takes_value(true).default_value("default"))
         .arg(Arg::with_name("keep-right").long("keep-right").multiple(true))
+        .arg(Arg::with_name("track").long("track").multiple(true))
+        .arg(Arg::with_name("grid").long("grid").multiple(true))
+        .arg(Arg::with_name("wrap").long("wrap").multiple(true))
+        .arg(Arg::with_name("multi-line").long("multi-line").multiple(true))
+        .arg(Arg::with_name("scroll-off").long("scroll-off").multiple(true).takes_value(true).default_value("0"))
         .arg(Arg::with_name("skip-to-pattern").long("skip-to-pattern").multiple(true).takes_value(true).default_value(""))
-        .get_matches_from(args);
+        .get_matches_from(&args);
 
     if opts.is_present("help") {
         write!(stdout, "{}", USAGE)?;
@@ -235,15 +444,59 @@ fn real_main() -> Result<i32, std::io::Error> {
         writeln!(stdout, "{}", VERSION)?;
         return Ok(0);
     }
+
+    if let Some(shell) = opts.values_of("shell").and_then(|vals| vals.last()) {
+        return print_shell_script(shell);
+    }
+
+    //------------------------------------------------------------------------------
+    // run in a tmux popup, the filter, bench and rpc modes don't need the terminal
+    let in_tmux = env::var_os("TMUX").map(|tmux| !tmux.is_empty()).unwrap_or(false);
+    let headless = ["filter", "filter-queries", "bench", "rpc"].iter().any(|&name| opts.is_present(name));
+    if opts.is_present("tmux") && in_tmux && !headless && tmux_has_popup() {
+        let geometry = opts.values_of("tmux").and_then(|vals| vals.last()).unwrap_or("");
+        return run_in_tmux(geometry, &args[1..]);
+    }
+
+    let delimiter = opts.values_of("delimiter").and_then(|vals| vals.last()).unwrap_or("");
+    if let Err(err) = parse_delimiter(delimiter) {
+        eprintln!("sk: invalid --delimiter '{}': {}", delimiter, err);
+        return Ok(2);
+    }
+
+    let with_shell = opts.values_of("with-shell").and_then(|vals| vals.last()).unwrap_or("");
+    if !with_shell.is_empty() && shlex::split(with_shell).map(|words| words.is_empty()).unwrap_or(true) {
+        eprintln!("sk: invalid --with-shell '{}'", with_shell);
+        return Ok(2);
+    }
+
+    let cmd_cwd = opts.values_of("cmd-cwd").and_then(|vals| vals.last()).unwrap_or("");
+    if !cmd_cwd.is_empty() && !std::path::Path::new(cmd_cwd).is_dir() {
+        eprintln!("sk: --cmd-cwd '{}' is not a directory", cmd_cwd);
+        return Ok(2);
+    }
+
+    let cmd_env: Vec<&str> = opts.values_of("cmd-env").map(|vals| vals.collect()).unwrap_or_default();
+    let is_assignment = |var: &&str| var.split_once('=').is_some_and(|(key, _)| !key.is_empty());
+    if let Some(var) = cmd_env.iter().find(|var| !is_assignment(var)) {
+        eprintln!("sk: invalid --cmd-env '{}', expect KEY=VALUE", var);
+        return Ok(2);
+    }
+
     //------------------------------------------------------------------------------
     // initialize collector
     let item_reader_option = SkimItemReaderOption::default()
+        .shell(with_shell)
+        .cmd_cwd(cmd_cwd)
+        .cmd_env(&cmd_env)
         .ansi(opts.is_present("ansi"))
-        .delimiter(opts.values_of("delimiter").and_then(|vals| vals.last()).unwrap_or(""))
+        .delimiter(delimiter)
         .with_nth(opts.values_of("with-nth").and_then(|vals| vals.last()).unwrap_or(""))
         .nth(opts.values_of("nth").and_then(|vals| vals.last()).unwrap_or(""))
         .read0(opts.is_present("read0"))
         .build();
+    // `--accept-nth` splits the fields the same way as `--nth`
+    let delimiter_regex = item_reader_option.get_delimiter().clone();
 
     let cmd_collector = Rc::new(RefCell::new(SkimItemReader::new(item_reader_option)));
 
@@ -254,7 +507,9 @@ fn real_main() -> Result<i32, std::io::Error> {
     let query_history = fz_query_histories.and_then(|filename| read_file_lines(filename).ok()).unwrap_or_else(|| vec![]);
     let cmd_history = cmd_query_histories.and_then(|filename| read_file_lines(filename).ok()).unwrap_or_else(|| vec![]);
 
+    let default_command = default_command();
     let mut options = parse_options(&opts);
+    options.cmd = options.cmd.or(Some(&default_command));
     if fz_query_histories.is_some() || cmd_query_histories.is_some() {
         options.query_history = &query_history;
         options.cmd_history = &cmd_history;
@@ -269,29 +524,82 @@ fn real_main() -> Result<i32, std::io::Error> {
     //------------------------------------------------------------------------------
     let bin_options = BinOptionsBuilder::default()
         .filter(opts.values_of("filter").and_then(|vals| vals.last()))
+        .filter_queries(opts.values_of("filter-queries").and_then(|vals| vals.last()))
+        .bench(opts.values_of("bench").and_then(|vals| vals.last()))
         .print_query(opts.is_present("print-query"))
         .print_cmd(opts.is_present("print-cmd"))
+        .print_score(opts.is_present("print-score"))
+        .print_ranges(opts.is_present("print-ranges"))
+        .print_json(opts.is_present("print-json"))
         .output_ending(if opts.is_present("print0") { "\0" } else { "\n" })
+        .accept_nth(
+            opts.values_of("accept-nth")
+                .and_then(|vals| vals.last())
+                .map(|nth| nth.split(',').filter_map(FieldRange::from_str).collect())
+                .unwrap_or_default(),
+        )
+        .delimiter(delimiter_regex)
         .build()
         .expect("");
 
     //------------------------------------------------------------------------------
-    // read from pipe or command
+    // shell completion mode
+    if let Some(line) = opts.values_of("complete-shell").and_then(|vals| vals.last()) {
+        let cursor = opts.values_of("complete-cursor").and_then(|vals| vals.last()).and_then(|pos| pos.parse().ok());
+        return complete_shell(line, cursor, options);
+    }
+
+    //------------------------------------------------------------------------------
+    // rpc mode, the items are pushed by the requests on stdin
+    if opts.is_present("rpc") {
+        let framing = match opts.values_of("rpc").and_then(|vals| vals.last()).unwrap_or("json") {
+            "json" => Framing::JsonLines,
+            "msgpack" => Framing::MessagePack,
+            framing => {
+                eprintln!("sk: invalid --rpc '{}', expected json or msgpack", framing);
+                return Ok(2);
+            }
+        };
+        let engine_factory = engine_factory(&options)?;
+        let item_reader = cmd_collector.borrow();
+        let format = OutputFormat {
+            accept_nth: bin_options.accept_nth(),
+            ..Default::default()
+        };
+        let stdin = std::io::stdin();
+        return RpcServer::new(engine_factory.as_ref(), &item_reader)
+            .case(options.case)
+            .format(format)
+            .framing(framing)
+            .serve(stdin.lock(), stdout.lock());
+    }
+
+    //------------------------------------------------------------------------------
+    // read from pipe or command, unless the queries of `--filter-queries -` are read from stdin
     let stdin = std::io::stdin();
+    let queries_on_stdin = bin_options.filter_queries == Some("-");
     let rx_item = match isatty(stdin.as_raw_fd()) {
-        Ok(false) | Err(nix::Error::Sys(nix::errno::Errno::EINVAL)) => {
+        Ok(false) | Err(nix::Error::Sys(nix::errno::Errno::EINVAL)) if !queries_on_stdin && options.browse.is_none() && source.is_none() => {
             let rx_item = cmd_collector.borrow().of_bufread(BufReader::new(stdin));
             Some(rx_item)
         }
-        Ok(true) | Err(_) => None,
+        _ => None,
     };
+    // or from the built-in source, if it's not read from a command
+    let rx_item = rx_item.or_else(|| source.as_deref().and_then(source_items));
 
     //------------------------------------------------------------------------------
     // filter mode
-    if opts.is_present("filter") {
+    if opts.is_present("filter") || opts.is_present("filter-queries") {
         return filter(&bin_options, &options, rx_item);
     }
 
+    //------------------------------------------------------------------------------
+    // bench mode
+    if opts.is_present("bench") {
+        return bench(&bin_options, &options, rx_item);
+    }
+
     //------------------------------------------------------------------------------
     let output = Skim::run_with(&options, rx_item);
     if output.is_none() {
@@ -302,22 +610,22 @@ fn real_main() -> Result<i32, std::io::Error> {
     // output
     let output = output.unwrap();
 
-    // output query
-    if bin_options.print_query {
-        write!(stdout, "{}{}", output.query, bin_options.output_ending)?;
-    }
-
-    if bin_options.print_cmd {
-        write!(stdout, "{}{}", output.cmd, bin_options.output_ending)?;
-    }
-
-    if let Some(key) = output.accept_key {
-        write!(stdout, "{}{}", key, bin_options.output_ending)?;
-    }
+    // score the selected items against the final query, they might be selected with another one
+    let scorer = if bin_options.print_score {
+        Some(engine_factory(&options)?.create_engine_with_case(&output.query, options.case))
+    } else {
+        None
+    };
 
-    for item in output.selected_items.iter() {
-        write!(stdout, "{}{}", item.output(), bin_options.output_ending)?;
-    }
+    let format = OutputFormat {
+        print_query: bin_options.print_query,
+        print_cmd: bin_options.print_cmd,
+        ending: bin_options.output_ending,
+        accept_nth: bin_options.accept_nth(),
+        scorer: scorer.as_deref(),
+        print_ranges: false,
+    };
+    output.write_to(&mut stdout, &format)?;
 
     //------------------------------------------------------------------------------
     // write the history with latest item
@@ -345,14 +653,31 @@ fn parse_options<'a>(options: &'a ArgMatches) -> SkimOptions<'a> {
         .no_height(options.is_present("no-height"))
         .height(options.values_of("height").and_then(|vals| vals.last()))
         .margin(options.values_of("margin").and_then(|vals| vals.last()))
+        .border(options.is_present("border"))
+        .border_label(options.values_of("border-label").and_then(|vals| vals.last()))
+        .border_label_pos(
+            options
+                .values_of("border-label-pos")
+                .and_then(|vals| vals.last())
+                .unwrap(),
+        )
         .preview(options.values_of("preview").and_then(|vals| vals.last()))
         .preview_window(options.values_of("preview-window").and_then(|vals| vals.last()))
+        .preview_label(options.values_of("preview-label").and_then(|vals| vals.last()))
+        .preview_timeout(
+            options
+                .values_of("preview-timeout")
+                .and_then(|vals| vals.last())
+                .and_then(|s| s.parse::<u64>().ok()),
+        )
         .cmd(options.values_of("cmd").and_then(|vals| vals.last()))
         .query(options.values_of("query").and_then(|vals| vals.last()))
         .cmd_query(options.values_of("cmd-query").and_then(|vals| vals.last()))
         .interactive(options.is_present("interactive"))
         .prompt(options.values_of("prompt").and_then(|vals| vals.last()))
         .cmd_prompt(options.values_of("cmd-prompt").and_then(|vals| vals.last()))
+        .pointer(options.values_of("pointer").and_then(|vals| vals.last()))
+        .marker(options.values_of("marker").and_then(|vals| vals.last()))
         .bind(
             options
                 .values_of("bind")
@@ -371,13 +696,92 @@ fn parse_options<'a>(options: &'a ArgMatches) -> SkimOptions<'a> {
         .no_mouse(options.is_present("no-mouse"))
         .no_clear(options.is_present("no-clear"))
         .tabstop(options.values_of("tabstop").and_then(|vals| vals.last()))
+        .shorten_paths(options.is_present("shorten-paths"))
+        .browse(if options.is_present("browse") {
+            Some(options.values_of("browse").and_then(|vals| vals.last()).unwrap_or(""))
+        } else {
+            None
+        })
+        .browse_prompt(options.occurrences_of("prompt") == 0)
+        .clipboard_cmd(options.values_of("clipboard-cmd").and_then(|vals| vals.last()))
+        .accept_nth(options.values_of("accept-nth").and_then(|vals| vals.last()))
+        .format_cmd(options.values_of("format-cmd").and_then(|vals| vals.last()))
+        .icons(if options.is_present("icons") {
+            Some(options.values_of("icons").and_then(|vals| vals.last()).unwrap_or(""))
+        } else {
+            None
+        })
         .tiebreak(options.values_of("tiebreak").map(|x| x.collect::<Vec<_>>().join(",")))
+        .sort_key(options.values_of("sort-key").and_then(|vals| vals.last()))
         .tac(options.is_present("tac"))
         .nosort(options.is_present("no-sort"))
+        .max_matches(
+            options
+                .values_of("max-matches")
+                .and_then(|vals| vals.last())
+                .and_then(|s| s.parse::<usize>().ok()),
+        )
+        .max_items(
+            options
+                .values_of("max-items")
+                .and_then(|vals| vals.last())
+                .and_then(|s| s.parse::<usize>().ok()),
+        )
+        .tail(
+            options
+                .values_of("tail")
+                .and_then(|vals| vals.last())
+                .and_then(|s| s.parse::<usize>().ok()),
+        )
+        .index(options.is_present("index"))
+        .max_bytes(
+            options
+                .values_of("max-bytes")
+                .and_then(|vals| vals.last())
+                .and_then(parse_size),
+        )
+        .read_buffer(
+            options
+                .values_of("read-buffer")
+                .and_then(|vals| vals.last())
+                .and_then(|s| s.parse::<usize>().ok()),
+        )
+        .read_buffer_policy(
+            options
+                .values_of("read-buffer-policy")
+                .and_then(|vals| vals.last())
+                .unwrap_or("block"),
+        )
         .exact(options.is_present("exact"))
+        .literal(options.is_present("literal"))
+        .matcher_cmd(options.values_of("matcher-cmd").and_then(|vals| vals.last()))
+        .read0(options.is_present("read0"))
         .regex(options.is_present("regex"))
         .delimiter(options.values_of("delimiter").and_then(|vals| vals.last()))
         .inline_info(options.is_present("inline-info"))
+        .info_format(options.values_of("info-format").and_then(|vals| vals.last()))
+        .accessible(options.is_present("accessible"))
+        .bell(options.is_present("bell"))
+        .no_unicode(options.is_present("no-unicode") || !locale_is_utf8())
+        .debug_perf(options.is_present("debug-perf"))
+        .record(options.values_of("record").and_then(|vals| vals.last()))
+        .replay(options.values_of("replay").and_then(|vals| vals.last()))
+        .listen_socket(options.values_of("listen-socket").and_then(|vals| vals.last()))
+        .reload_on_signal(options.is_present("reload-on-signal"))
+        .shell(options.values_of("with-shell").and_then(|vals| vals.last()))
+        .cmd_cwd(options.values_of("cmd-cwd").and_then(|vals| vals.last()))
+        .cmd_env(
+            options
+                .values_of("cmd-env")
+                .map(|vals| vals.collect())
+                .unwrap_or_default(),
+        )
+        .refresh_interval(
+            options
+                .values_of("refresh-interval")
+                .and_then(|vals| vals.last())
+                .and_then(|s| s.parse::<f64>().ok()),
+        )
         .header(options.values_of("header").and_then(|vals| vals.last()))
         .header_lines(
             options
@@ -396,6 +800,18 @@ fn parse_options<'a>(options: &'a ArgMatches) -> SkimOptions<'a> {
             _ => CaseMatching::Respect,
         })
         .keep_right(options.is_present("keep-right"))
+        .cycle(options.is_present("cycle"))
+        .track(options.is_present("track"))
+        .grid(options.is_present("grid"))
+        .wrap(options.is_present("wrap"))
+        .multi_line(options.is_present("multi-line"))
+        .scroll_off(
+            options
+                .values_of("scroll-off")
+                .and_then(|vals| vals.last())
+                .map(|s| s.parse::<usize>().unwrap_or(0))
+                .unwrap_or(0),
+        )
         .skip_to_pattern(
             options
                 .values_of("skip-to-pattern")
@@ -408,6 +824,188 @@ fn parse_options<'a>(options: &'a ArgMatches) -> SkimOptions<'a> {
         .unwrap()
 }
 
+/// the value of the last `OPTION VALUE` or `OPTION=VALUE` in the args, e.g. `--profile NAME`
+fn last_value_of(args: &[String], option: &str) -> Option<String> {
+    let mut value = None;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        if arg == option {
+            value = args.next().cloned();
+        } else if let Some(rest) = arg.strip_prefix(option).and_then(|rest| rest.strip_prefix('=')) {
+            value = Some(rest.to_string());
+        }
+    }
+    value
+}
+
+/// the directory of the config files of skim, `$XDG_CONFIG_HOME/skim` (`~/.config/skim` by default)
+fn config_dir() -> Option<PathBuf> {
+    let config_home = env::var_os("XDG_CONFIG_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".config")))?;
+    Some(config_home.join("skim"))
+}
+
+/// print the key bindings (ctrl-t, ctrl-r, alt-c) and the fuzzy completion of the shell
+fn print_shell_script(shell: &str) -> Result<i32, std::io::Error> {
+    let scripts: &[&str] = match shell {
+        "bash" => &[KEY_BINDINGS_BASH, COMPLETION_BASH],
+        "zsh" => &[KEY_BINDINGS_ZSH, COMPLETION_ZSH],
+        // the fish script only defines the function for the key bindings
+        "fish" => &[KEY_BINDINGS_FISH, "skim_key_bindings\n"],
+        _ => {
+            eprintln!("sk: unsupported shell '{}', expected one of bash, zsh, fish", shell);
+            return Ok(2);
+        }
+    };
+
+    let mut stdout = std::io::stdout();
+    for script in scripts {
+        stdout.write_all(script.as_bytes())?;
+    }
+    Ok(0)
+}
+
+/// the arguments of `tmux display-popup` for the `--tmux` geometry:
+/// `[center|top|bottom|left|right][,SIZE[%]][,SIZE[%]]`
+fn tmux_popup_args(geometry: &str) -> Vec<String> {
+    let mut parts = geometry.split(',').filter(|part| !part.is_empty()).peekable();
+    let position = match parts.peek() {
+        Some(&position @ "center")
+        | Some(&position @ "top")
+        | Some(&position @ "bottom")
+        | Some(&position @ "left")
+        | Some(&position @ "right") => {
+            parts.next();
+            position
+        }
+        _ => "center",
+    };
+
+    // a single size is the width of the side popups and the height of the top/bottom ones
+    let sizes: Vec<&str> = parts.collect();
+    let (width, height) = match (position, sizes.as_slice()) {
+        (_, [width, height, ..]) => (*width, *height),
+        ("top", [height]) | ("bottom", [height]) => ("100%", *height),
+        ("left", [width]) | ("right", [width]) => (*width, "100%"),
+        (_, [size]) => (*size, *size),
+        (_, []) => ("50%", "50%"),
+    };
+
+    let (x, y) = match position {
+        "top" => ("C", "0"),
+        "bottom" => ("C", "9999"),
+        "left" => ("0", "C"),
+        "right" => ("R", "C"),
+        _ => ("C", "C"),
+    };
+
+    ["-w", width, "-h", height, "-x", x, "-y", y]
+        .iter()
+        .map(|arg| arg.to_string())
+        .collect()
+}
+
+/// whether the running tmux has `display-popup`, i.e. it's 3.2 or above
+fn tmux_has_popup() -> bool {
+    std::process::Command::new("tmux")
+        .arg("-V")
+        .output()
+        .map(|output| tmux_version_has_popup(&String::from_utf8_lossy(&output.stdout)))
+        .unwrap_or(false)
+}
+
+/// parse the output of `tmux -V`, e.g. `tmux 3.2a`, `tmux next-3.4` or `tmux master`
+fn tmux_version_has_popup(version: &str) -> bool {
+    let version = version.trim().trim_start_matches("tmux").trim();
+    if version == "master" {
+        return true;
+    }
+    let version = version.trim_start_matches(|ch: char| !ch.is_ascii_digit());
+    let mut numbers = version
+        .split(|ch: char| !ch.is_ascii_digit())
+        .map(|number| number.parse::<u32>().ok());
+    match (numbers.next().flatten(), numbers.next().flatten()) {
+        (Some(major), minor) => (major, minor.unwrap_or(0)) >= (3, 2),
+        (None, _) => false,
+    }
+}
+
+/// run sk with the same arguments (except `--tmux`) in a tmux popup, the input is piped through
+/// and the output and exit code are passed back.
+fn run_in_tmux(geometry: &str, args: &[String]) -> Result<i32, std::io::Error> {
+    // a fresh directory only the user can access, so that the FIFO and the output can't be
+    // replaced by other users of the shared temp directory
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default();
+    let dir = env::temp_dir().join(format!("sk-tmux-{}-{}", std::process::id(), now.subsec_nanos()));
+    std::fs::DirBuilder::new().mode(0o700).create(&dir)?;
+    let input = dir.join("input");
+    let output = dir.join("output");
+    let exit_code = dir.join("exit");
+    let quote = |path: &std::path::Path| shlex::quote(&path.to_string_lossy()).into_owned();
+
+    // drop `--tmux` and its value
+    let mut sk_args = vec![];
+    let mut iter = args.iter().peekable();
+    while let Some(arg) = iter.next() {
+        if arg == "--tmux" {
+            iter.next_if(|value| !value.starts_with('-'));
+        } else if !arg.starts_with("--tmux=") {
+            sk_args.push(shlex::quote(arg).into_owned());
+        }
+    }
+
+    let sk = env::current_exe()?;
+    let mut command = format!(
+        "cd {} && SKIM_DEFAULT_OPTIONS= {} {}",
+        quote(&env::current_dir()?),
+        quote(&sk),
+        sk_args.join(" ")
+    );
+    if let Ok(default_command) = env::var("SKIM_DEFAULT_COMMAND") {
+        command = format!("SKIM_DEFAULT_COMMAND={} {}", shlex::quote(&default_command), command);
+    }
+
+    // the popup doesn't share the stdin, pipe it through a FIFO
+    let stdin = std::io::stdin();
+    if !isatty(stdin.as_raw_fd()).unwrap_or(true) {
+        nix::unistd::mkfifo(&input, nix::sys::stat::Mode::S_IRWXU)
+            .map_err(|err| std::io::Error::new(std::io::ErrorKind::Other, format!("failed to create FIFO: {}", err)))?;
+        command = format!("{} < {}", command, quote(&input));
+        let input = input.clone();
+        std::thread::spawn(move || {
+            // blocks until the popup opens the FIFO
+            if let Ok(mut fifo) = std::fs::OpenOptions::new().write(true).open(input) {
+                let _ = std::io::copy(&mut std::io::stdin(), &mut fifo);
+            }
+        });
+    }
+    command = format!("{} > {}; echo $? > {}", command, quote(&output), quote(&exit_code));
+
+    let status = std::process::Command::new("tmux")
+        .arg("display-popup")
+        .arg("-E")
+        .args(tmux_popup_args(geometry))
+        .arg(command)
+        .status();
+
+    let result = status.and_then(|_| {
+        let code = std::fs::read_to_string(&exit_code)
+            .ok()
+            .and_then(|code| code.trim().parse::<i32>().ok())
+            .unwrap_or(2);
+        if let Ok(mut output) = File::open(&output) {
+            std::io::copy(&mut output, &mut std::io::stdout())?;
+        }
+        Ok(code)
+    });
+    let _ = std::fs::remove_dir_all(&dir);
+    result
+}
+
 fn read_file_lines(filename: &str) -> Result<Vec<String>, std::io::Error> {
     let file = File::open(filename)?;
     let ret = BufReader::new(file).lines().collect();
@@ -444,9 +1042,87 @@ fn write_history_to_file(
 #[derive(Builder)]
 pub struct BinOptions<'a> {
     filter: Option<&'a str>,
+    filter_queries: Option<&'a str>,
+    bench: Option<&'a str>,
     output_ending: &'a str,
     print_query: bool,
     print_cmd: bool,
+    print_score: bool,
+    print_ranges: bool,
+    print_json: bool,
+    accept_nth: Vec<FieldRange>,
+    delimiter: Delimiter,
+}
+
+impl BinOptions<'_> {
+    fn accept_nth(&self) -> Option<(&Delimiter, &[FieldRange])> {
+        if self.accept_nth.is_empty() {
+            None
+        } else {
+            Some((&self.delimiter, &self.accept_nth))
+        }
+    }
+}
+
+/// send a command to the instance listening on the socket of `--listen-socket` and print the reply
+fn control(args: &[String]) -> Result<i32, std::io::Error> {
+    if args.len() < 2 {
+        eprintln!("usage: sk ctl SOCKET COMMAND [ARG]");
+        return Ok(2);
+    }
+
+    let mut stream = match UnixStream::connect(&args[0]) {
+        Ok(stream) => stream,
+        Err(err) => {
+            eprintln!("sk: failed to connect to {}: {}", args[0], err);
+            return Ok(2);
+        }
+    };
+    writeln!(stream, "{}", args[1..].join(" "))?;
+    stream.shutdown(Shutdown::Write)?;
+
+    let mut reply = String::new();
+    stream.read_to_string(&mut reply)?;
+    write!(std::io::stdout(), "{}", reply)?;
+    Ok(if reply.starts_with("error:") { 1 } else { 0 })
+}
+
+/// whether the locale of the environment is UTF-8, assume it is if no locale is set
+fn locale_is_utf8() -> bool {
+    ["LC_ALL", "LC_CTYPE", "LANG"]
+        .iter()
+        .filter_map(|name| env::var(name).ok())
+        .find(|locale| !locale.is_empty())
+        .map(|locale| {
+            let locale = locale.to_lowercase();
+            locale.contains("utf-8") || locale.contains("utf8")
+        })
+        .unwrap_or(true)
+}
+
+fn engine_factory(options: &SkimOptions) -> Result<Box<dyn MatchEngineFactory>, std::io::Error> {
+    Ok(if options.regex {
+        Box::new(RegexEngineFactory::builder())
+    } else if let Some(cmd) = options.matcher_cmd {
+        let factory = CommandEngineFactory::spawn(options.shell_command(cmd), options.read0).map_err(|err| {
+            eprintln!("sk: failed to start the matcher command {:?}: {}", cmd, err);
+            err
+        })?;
+        Box::new(factory)
+    } else if options.literal {
+        Box::new(LiteralEngineFactory::builder())
+    } else {
+        let fuzzy_engine_factory = ExactOrFuzzyEngineFactory::builder()
+            .fuzzy_algorithm(options.algorithm)
+            .exact_mode(options.exact)
+            .build();
+        Box::new(AndOrEngineFactory::new(fuzzy_engine_factory))
+    })
+}
+
+/// whether the engines of the options match the items in a batch, which needs all of them first
+fn matches_in_batch(options: &SkimOptions) -> bool {
+    options.matcher_cmd.is_some() && !options.regex
 }
 
 pub fn filter(
@@ -456,15 +1132,17 @@ pub fn filter(
 ) -> Result<i32, std::io::Error> {
     let mut stdout = std::io::stdout();
 
-    let default_command = match env::var("SKIM_DEFAULT_COMMAND").as_ref().map(String::as_ref) {
-        Ok("") | Err(_) => "find .".to_owned(),
-        Ok(val) => val.to_owned(),
-    };
     let query = bin_option.filter.unwrap_or(&"");
-    let cmd = options.cmd.unwrap_or(&default_command);
+    let cmd = options.cmd.unwrap_or_default();
+
+    // with `--filter-queries`, every query prints its own group instead
+    let queries = match bin_option.filter_queries {
+        Some(filename) => Some(read_queries(filename)?),
+        None => None,
+    };
 
     // output query
-    if bin_option.print_query {
+    if bin_option.print_query && queries.is_none() {
         write!(stdout, "{}{}", query, bin_option.output_ending)?;
     }
 
@@ -474,16 +1152,7 @@ pub fn filter(
 
     //------------------------------------------------------------------------------
     // matcher
-    let engine_factory: Box<dyn MatchEngineFactory> = if options.regex {
-        Box::new(RegexEngineFactory::builder())
-    } else {
-        let fuzzy_engine_factory = ExactOrFuzzyEngineFactory::builder()
-            .fuzzy_algorithm(options.algorithm)
-            .exact_mode(options.exact)
-            .build();
-        Box::new(AndOrEngineFactory::new(fuzzy_engine_factory))
-    };
-
+    let engine_factory = engine_factory(options)?;
     let engine = engine_factory.create_engine_with_case(query, options.case);
 
     //------------------------------------------------------------------------------
@@ -496,14 +1165,356 @@ pub fn filter(
         ret
     });
 
+    let format = OutputFormat {
+        ending: bin_option.output_ending,
+        accept_nth: bin_option.accept_nth(),
+        scorer: if bin_option.print_score {
+            Some(engine.as_ref())
+        } else {
+            None
+        },
+        print_ranges: bin_option.print_ranges,
+        ..Default::default()
+    };
+
+    // stop reading before the input exceeds `--max-items` or `--max-bytes`
+    let max_items = options.max_items.unwrap_or(usize::MAX);
+    let max_bytes = options.max_bytes.unwrap_or(usize::MAX);
+    let (mut num_read, mut num_bytes) = (0, 0);
+    let mut truncated = false;
+
     let mut num_matched = 0;
-    stream_of_item
-        .into_iter()
-        .filter_map(|item| engine.match_item(item))
-        .try_for_each(|matched| {
-            num_matched += 1;
-            write!(stdout, "{}{}", matched.item.output(), bin_option.output_ending)
-        })?;
+    let items = stream_of_item.into_iter().take_while(|item| {
+        num_read += 1;
+        num_bytes += item.text().len() + 1;
+        truncated = num_read > max_items || num_bytes > max_bytes;
+        !truncated
+    });
+    // only the last N items are matched with `--tail`, once the input ends
+    let items: Box<dyn Iterator<Item = Arc<dyn SkimItem>>> = match options.tail {
+        Some(tail) => Box::new(last_items(items, tail).into_iter()),
+        None => Box::new(items),
+    };
+    let engines: Vec<_> = queries
+        .iter()
+        .flatten()
+        .map(|query| engine_factory.create_engine_with_case(query, options.case))
+        .collect();
+    // the engines that match in a batch are given all the items, once the input ends
+    let items: Box<dyn Iterator<Item = Arc<dyn SkimItem>>> = if matches_in_batch(options) {
+        let items: Vec<_> = items.collect();
+        if queries.is_some() {
+            engines.iter().for_each(|engine| engine.prepare(&mut items.iter()));
+        } else {
+            engine.prepare(&mut items.iter());
+        }
+        Box::new(items.into_iter())
+    } else {
+        items
+    };
+    let max_matches = options.max_matches.unwrap_or(usize::MAX);
+    match queries {
+        Some(queries) => {
+            let groups = match_grouped(items, &engines, max_matches);
+            for ((query, engine), group) in queries.iter().zip(engines.iter()).zip(groups) {
+                let format = OutputFormat {
+                    scorer: format.scorer.map(|_| engine.as_ref()),
+                    ..format
+                };
+                // every JSON object tells its query instead of the groups
+                if bin_option.print_json {
+                    for matched in group.iter() {
+                        num_matched += 1;
+                        write_json(&mut stdout, &format, Some(query), matched)?;
+                    }
+                    continue;
+                }
+                write!(stdout, "{}{}", query, bin_option.output_ending)?;
+                for matched in group.iter() {
+                    num_matched += 1;
+                    format.write_matched(&mut stdout, matched)?;
+                }
+                write!(stdout, "{}", bin_option.output_ending)?;
+            }
+        }
+        None => items
+            .filter_map(|item| engine.match_item(item))
+            .take(max_matches)
+            .try_for_each(|matched| {
+                num_matched += 1;
+                if bin_option.print_json {
+                    write_json(&mut stdout, &format, None, &matched)
+                } else {
+                    format.write_matched(&mut stdout, &matched)
+                }
+            })?,
+    }
+
+    if truncated {
+        eprintln!("sk: the input is truncated after {} items", num_read - 1);
+    }
 
     Ok(if num_matched == 0 { 1 } else { 0 })
 }
+
+/// write a result of filter mode as a JSON object, e.g.
+/// `{"text":"abc","output":"abc","score":31,"ranges":[[0,1],[2,3]]}`, the ranges are the byte
+/// ranges of the text matched
+fn write_json(
+    writer: &mut impl Write,
+    format: &OutputFormat,
+    query: Option<&str>,
+    matched: &MatchedItem,
+) -> Result<(), std::io::Error> {
+    let text = matched.item.text();
+    let ranges = matched
+        .matched_range
+        .as_ref()
+        .map(|range| range.byte_ranges(&text))
+        .unwrap_or_default();
+
+    let mut fields = Vec::new();
+    if let Some(query) = query {
+        fields.push(("query", query.into()));
+    }
+    fields.push(("text", text.as_ref().into()));
+    fields.push(("output", format.output_of(matched.item.as_ref()).as_ref().into()));
+    fields.push(("score", matched.score.into()));
+    let ranges: Vec<Vec<usize>> = ranges.into_iter().map(|(start, end)| vec![start, end]).collect();
+    fields.push(("ranges", ranges.into()));
+    write!(writer, "{}{}", Json::object(fields), format.ending)
+}
+
+/// match every item against all the engines in one pass, keep the first `max_matches` matches of
+/// each engine in the order of the input
+fn match_grouped(
+    items: impl Iterator<Item = Arc<dyn SkimItem>>,
+    engines: &[Box<dyn MatchEngine>],
+    max_matches: usize,
+) -> Vec<Vec<MatchedItem>> {
+    let mut groups = vec![Vec::new(); engines.len()];
+    for item in items {
+        for (engine, group) in engines.iter().zip(groups.iter_mut()) {
+            if group.len() < max_matches {
+                group.extend(engine.match_item(item.clone()));
+            }
+        }
+    }
+    groups
+}
+
+/// the queries of `--filter-queries`, NUL delimited if there's a NUL, otherwise one per line
+fn read_queries(filename: &str) -> Result<Vec<String>, std::io::Error> {
+    let mut content = String::new();
+    if filename == "-" {
+        std::io::stdin().read_to_string(&mut content)?;
+    } else {
+        File::open(filename)?.read_to_string(&mut content)?;
+    }
+    Ok(split_queries(&content))
+}
+
+fn split_queries(content: &str) -> Vec<String> {
+    if content.contains('\0') {
+        content.split_terminator('\0').map(str::to_string).collect()
+    } else {
+        content.lines().map(str::to_string).collect()
+    }
+}
+
+/// the last `n` items of the iterator
+fn last_items<T>(items: impl Iterator<Item = T>, n: usize) -> VecDeque<T> {
+    let mut last = VecDeque::new();
+    for item in items {
+        if last.len() == n {
+            last.pop_front();
+        }
+        if n > 0 {
+            last.push_back(item);
+        }
+    }
+    last
+}
+
+/// parse the size of `--max-bytes`, e.g. `4096`, `64K` or `100M`
+fn parse_size(size: &str) -> Option<usize> {
+    let (number, unit) = match size.char_indices().find(|(_, ch)| !ch.is_ascii_digit()) {
+        Some((idx, _)) => size.split_at(idx),
+        None => (size, ""),
+    };
+    let scale: usize = match unit {
+        "" | "B" => 1,
+        "K" => 1 << 10,
+        "M" => 1 << 20,
+        "G" => 1 << 30,
+        _ => return None,
+    };
+    number.parse::<usize>().ok()?.checked_mul(scale)
+}
+
+/// the number of top results shown for each query in bench mode
+const BENCH_TOP: usize = 3;
+
+/// match the whole input against every query of the bench file and report the matching time, the
+/// throughput and the top results, e.g. to compare `--algo` on your own data.
+pub fn bench(
+    bin_option: &BinOptions,
+    options: &SkimOptions,
+    source: Option<SkimItemReceiver>,
+) -> Result<i32, std::io::Error> {
+    let mut stdout = std::io::stdout();
+
+    let queries = read_file_lines(bin_option.bench.unwrap_or(""))?;
+    let cmd = options.cmd.unwrap_or_default();
+
+    let stream_of_item = source.unwrap_or_else(|| {
+        let cmd_collector = options.cmd_collector.clone();
+        let (ret, _control) = cmd_collector.borrow_mut().invoke(cmd, Arc::new(AtomicUsize::new(0)));
+        ret
+    });
+    let items: Vec<Arc<dyn SkimItem>> = stream_of_item.into_iter().collect();
+
+    let engine_factory = engine_factory(options)?;
+    let mut total_time = Duration::from_secs(0);
+    for query in queries.iter() {
+        let engine = engine_factory.create_engine_with_case(query, options.case);
+
+        let start = Instant::now();
+        engine.prepare(&mut items.iter());
+        let mut matched: Vec<MatchedItem> = items
+            .iter()
+            .filter_map(|item| engine.match_item(item.clone()))
+            .collect();
+        matched.sort();
+        let elapsed = start.elapsed();
+        total_time += elapsed;
+
+        let items_per_sec = items.len() as u128 * 1_000_000 / std::cmp::max(elapsed.as_micros(), 1);
+        writeln!(
+            stdout,
+            "query={:?} matched={}/{} time_ms={:.3} items_per_sec={}",
+            query,
+            matched.len(),
+            items.len(),
+            elapsed.as_secs_f64() * 1000.0,
+            items_per_sec
+        )?;
+        for (rank, matched_item) in matched.iter().take(BENCH_TOP).enumerate() {
+            writeln!(stdout, "  {}. {}", rank + 1, matched_item.item.output())?;
+        }
+    }
+
+    writeln!(
+        stdout,
+        "queries={} items={} total_time_ms={:.3}",
+        queries.len(),
+        items.len(),
+        total_time.as_secs_f64() * 1000.0
+    )?;
+
+    Ok(0)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_tmux_popup_args() {
+        let args = |geometry| tmux_popup_args(geometry).join(" ");
+        assert_eq!(args(""), "-w 50% -h 50% -x C -y C");
+        assert_eq!(args("center,70%"), "-w 70% -h 70% -x C -y C");
+        assert_eq!(args("bottom,40%"), "-w 100% -h 40% -x C -y 9999");
+        assert_eq!(args("left,30"), "-w 30 -h 100% -x 0 -y C");
+        assert_eq!(args("right,30,20"), "-w 30 -h 20 -x R -y C");
+        assert_eq!(args("80%,60%"), "-w 80% -h 60% -x C -y C");
+    }
+
+    #[test]
+    fn test_tmux_version_has_popup() {
+        assert!(tmux_version_has_popup("tmux 3.2\n"));
+        assert!(tmux_version_has_popup("tmux 3.2a"));
+        assert!(tmux_version_has_popup("tmux 3.10"));
+        assert!(tmux_version_has_popup("tmux next-3.4"));
+        assert!(tmux_version_has_popup("tmux master"));
+        assert!(!tmux_version_has_popup("tmux 3.1c"));
+        assert!(!tmux_version_has_popup("tmux 2.9"));
+        assert!(!tmux_version_has_popup(""));
+    }
+
+    fn items(texts: &[&str]) -> Vec<Arc<dyn SkimItem>> {
+        texts
+            .iter()
+            .map(|text| Arc::new(text.to_string()) as Arc<dyn SkimItem>)
+            .collect()
+    }
+
+    #[test]
+    fn test_match_grouped() {
+        let factory = ExactOrFuzzyEngineFactory::builder().exact_mode(true).build();
+        let engines: Vec<_> = ["a", "b", "z"]
+            .iter()
+            .map(|query| factory.create_engine_with_case(query, CaseMatching::Smart))
+            .collect();
+        let texts = |group: &[MatchedItem]| {
+            group
+                .iter()
+                .map(|matched| matched.item.output().into_owned())
+                .collect::<Vec<_>>()
+        };
+
+        let groups = match_grouped(items(&["ab", "b", "ca", "ba"]).into_iter(), &engines, usize::MAX);
+        assert_eq!(groups.len(), 3);
+        assert_eq!(texts(&groups[0]), vec!["ab", "ca", "ba"]);
+        assert_eq!(texts(&groups[1]), vec!["ab", "b", "ba"]);
+        assert!(groups[2].is_empty());
+
+        let groups = match_grouped(items(&["ab", "b", "ca", "ba"]).into_iter(), &engines, 2);
+        assert_eq!(texts(&groups[0]), vec!["ab", "ca"]);
+        assert_eq!(texts(&groups[1]), vec!["ab", "b"]);
+    }
+
+    #[test]
+    fn test_write_json() {
+        let factory = ExactOrFuzzyEngineFactory::builder().exact_mode(true).build();
+        let engine = factory.create_engine_with_case("bc", CaseMatching::Smart);
+        let matched = engine.match_item(Arc::new("a\"bc".to_string())).unwrap();
+        let format = OutputFormat::default();
+
+        let mut written = vec![];
+        write_json(&mut written, &format, Some("bc"), &matched).unwrap();
+        let written = String::from_utf8(written).unwrap();
+        assert!(written.starts_with(r#"{"query":"bc","text":"a\"bc","output":"a\"bc","score":"#));
+        assert!(written.ends_with(",\"ranges\":[[2,4]]}\n"));
+
+        let mut written = vec![];
+        write_json(&mut written, &format, None, &matched).unwrap();
+        assert!(String::from_utf8(written).unwrap().starts_with(r#"{"text":"#));
+    }
+
+    #[test]
+    fn test_split_queries() {
+        assert_eq!(split_queries("a b\nc\n"), vec!["a b", "c"]);
+        assert_eq!(split_queries("a\nb\0c\0"), vec!["a\nb", "c"]);
+        assert!(split_queries("").is_empty());
+    }
+
+    #[test]
+    fn test_last_items() {
+        assert_eq!(last_items(1..=5, 2), vec![4, 5]);
+        assert_eq!(last_items(1..=2, 5), vec![1, 2]);
+        assert!(last_items(1..=5, 0).is_empty());
+    }
+
+    #[test]
+    fn test_parse_size() {
+        assert_eq!(parse_size("4096"), Some(4096));
+        assert_eq!(parse_size("100B"), Some(100));
+        assert_eq!(parse_size("64K"), Some(64 << 10));
+        assert_eq!(parse_size("100M"), Some(100 << 20));
+        assert_eq!(parse_size("2G"), Some(2 << 30));
+        assert_eq!(parse_size("1T"), None);
+        assert_eq!(parse_size("K"), None);
+        assert_eq!(parse_size(&format!("{}G", usize::MAX)), None);
+    }
+}
@@ -0,0 +1,92 @@
+//! Named sets of options selected with `--profile NAME`, read from `$XDG_CONFIG_HOME/skim/profiles`
+//! (`~/.config/skim/profiles` by default). Every profile is a `[name]` section of options, written
+//! the same way as on the command line, e.g.
+//!
+//! ```text
+//! # sk --profile files
+//! [files]
+//! --cmd 'fd --type f'
+//! --preview 'bat --color=always {}'
+//! --bind 'ctrl-y:execute-silent(echo {} | pbcopy)'
+//!
+//! [history]
+//! --tac --no-sort --color light
+//! ```
+use std::fs;
+
+use crate::config_dir;
+
+const PROFILES_FILE: &str = "profiles";
+
+/// the options of the profile `name` in the profiles file
+pub fn profile_options(name: &str) -> Result<Vec<String>, String> {
+    let path = config_dir()
+        .map(|dir| dir.join(PROFILES_FILE))
+        .ok_or_else(|| "no config directory, neither $XDG_CONFIG_HOME nor $HOME is set".to_string())?;
+    let content = fs::read_to_string(&path).map_err(|err| format!("{}: {}", path.display(), err))?;
+    parse_profile(&content, name)
+        .map_err(|err| format!("{}: {}", path.display(), err))?
+        .ok_or_else(|| format!("{}: no profile named {:?}", path.display(), name))
+}
+
+/// the options of the profile `name` in `content`, `None` if there's no such profile. The options
+/// before the first section belong to no profile and are ignored, blank lines and `#` comments
+/// are skipped.
+fn parse_profile(content: &str, name: &str) -> Result<Option<Vec<String>>, String> {
+    let mut options: Option<Vec<String>> = None;
+    let mut in_profile = false;
+    for (line_num, line) in content.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        if let Some(section) = line.strip_prefix('[').and_then(|rest| rest.strip_suffix(']')) {
+            in_profile = section.trim() == name;
+            if in_profile {
+                options.get_or_insert_with(Vec::new);
+            }
+            continue;
+        }
+
+        if in_profile {
+            let words = shlex::split(line).ok_or_else(|| format!("line {}: unbalanced quotes", line_num + 1))?;
+            options.get_or_insert_with(Vec::new).extend(words);
+        }
+    }
+    Ok(options)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_profile() {
+        let content = "
+            --ignored
+            # sk --profile files
+            [files]
+            --cmd 'fd --type f'
+            --preview 'bat {}'
+
+            [history]
+            --tac --no-sort
+            [files]
+            --color=dark
+        ";
+        let words = |words: &[&str]| Ok(Some(words.iter().map(|word| word.to_string()).collect()));
+
+        assert_eq!(
+            parse_profile(content, "files"),
+            words(&["--cmd", "fd --type f", "--preview", "bat {}", "--color=dark"])
+        );
+        assert_eq!(parse_profile(content, "history"), words(&["--tac", "--no-sort"]));
+        assert_eq!(parse_profile("[empty]", "empty"), words(&[]));
+        assert_eq!(parse_profile(content, "grep"), Ok(None));
+        assert_eq!(
+            parse_profile("[bad]\n--cmd 'fd", "bad"),
+            Err("line 2: unbalanced quotes".to_string())
+        );
+    }
+}
@@ -0,0 +1,336 @@
+//! The items of `--source shell-history`: the commands of the history file of bash, zsh or fish,
+//! the most recent first and each only once, with the time they were run in a dim column when the
+//! file records it (`HISTTIMEFORMAT` of bash, `EXTENDED_HISTORY` of zsh, always for fish).
+use std::borrow::Cow;
+use std::collections::HashSet;
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::thread;
+
+use chrono::{Local, TimeZone};
+use crossbeam::channel::unbounded;
+use skim::attr::{Attr, Effect};
+use skim::{AnsiString, DisplayContext, DisplayText, SkimItem, SkimItemReceiver};
+
+const TIME_FORMAT: &str = "%Y-%m-%d %H:%M";
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+enum HistoryFormat {
+    Bash,
+    Zsh,
+    Fish,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+struct Command {
+    text: String,
+    /// seconds since the epoch
+    time: Option<i64>,
+}
+
+/// A command of the history, matched and output as it is
+struct HistoryEntry {
+    command: String,
+    /// the time column, e.g. `2020-01-31 13:05  `
+    time: Option<String>,
+}
+
+impl SkimItem for HistoryEntry {
+    fn text(&self) -> Cow<'_, str> {
+        Cow::Borrowed(&self.command)
+    }
+
+    fn display<'a>(&'a self, context: DisplayContext<'a>) -> AnsiString<'a> {
+        let time = match self.time.as_ref() {
+            Some(time) => time,
+            None => return AnsiString::from(context),
+        };
+        let display_text = DisplayText {
+            prefix: Cow::Borrowed(time),
+            range: (0, self.command.len()),
+        };
+        let mut displayed = display_text.render(context);
+        let dim = Attr::default().effect(Effect::DIM);
+        displayed.override_attrs(vec![(dim, (0, time.len() as u32))]);
+        displayed
+    }
+}
+
+/// read the history file of the shell: `$HISTFILE`, or the default one of `$SHELL`
+pub fn read_shell_history() -> SkimItemReceiver {
+    let (tx_item, rx_item) = unbounded();
+    thread::spawn(move || {
+        let shell = shell_name();
+        let file = history_file(&shell);
+        let commands = match fs::read(&file) {
+            Ok(content) => parse_history(history_format(&shell, &content), &content),
+            Err(err) => {
+                debug!("shell history: failed to read {}: {}", file.display(), err);
+                Vec::new()
+            }
+        };
+        for command in commands {
+            let entry = HistoryEntry {
+                time: command.time.and_then(format_time),
+                command: command.text,
+            };
+            if tx_item.send(Arc::new(entry) as Arc<dyn SkimItem>).is_err() {
+                break;
+            }
+        }
+    });
+    rx_item
+}
+
+/// the name of `$SHELL`, e.g. `zsh` of `/usr/bin/zsh`
+fn shell_name() -> String {
+    let shell = env::var("SHELL").unwrap_or_default();
+    shell.rsplit('/').next().unwrap_or_default().to_string()
+}
+
+fn history_file(shell: &str) -> PathBuf {
+    if let Some(file) = env::var_os("HISTFILE").filter(|file| !file.is_empty()) {
+        return PathBuf::from(file);
+    }
+
+    let home = PathBuf::from(env::var_os("HOME").unwrap_or_default());
+    match shell {
+        "zsh" => home.join(".zsh_history"),
+        "fish" => env::var_os("XDG_DATA_HOME")
+            .filter(|dir| !dir.is_empty())
+            .map_or_else(|| home.join(".local/share"), PathBuf::from)
+            .join("fish/fish_history"),
+        _ => home.join(".bash_history"),
+    }
+}
+
+fn format_time(time: i64) -> Option<String> {
+    Local
+        .timestamp_opt(time, 0)
+        .single()
+        .map(|time| format!("{}  ", time.format(TIME_FORMAT)))
+}
+
+/// the format of the history, by the content if it tells (the entries of fish, the extended
+/// history of zsh), otherwise by the shell: the plain history of zsh only differs from the one of
+/// bash in its bytes >= 0x80
+fn history_format(shell: &str, content: &[u8]) -> HistoryFormat {
+    let content = String::from_utf8_lossy(content);
+    let mut lines = content.lines().filter(|line| !line.is_empty());
+    if lines.next().is_some_and(|line| line.starts_with("- cmd: ")) {
+        return HistoryFormat::Fish;
+    }
+    if content.lines().any(|line| parse_zsh_extended(line).is_some()) {
+        return HistoryFormat::Zsh;
+    }
+    match shell {
+        "zsh" => HistoryFormat::Zsh,
+        "fish" => HistoryFormat::Fish,
+        _ => HistoryFormat::Bash,
+    }
+}
+
+/// the commands of the history file, the most recent first, without the older duplicates
+fn parse_history(format: HistoryFormat, content: &[u8]) -> Vec<Command> {
+    let commands = match format {
+        HistoryFormat::Fish => parse_fish(&String::from_utf8_lossy(content)),
+        HistoryFormat::Zsh => parse_lines(&String::from_utf8_lossy(&unmetafy(content))),
+        HistoryFormat::Bash => parse_lines(&String::from_utf8_lossy(content)),
+    };
+
+    let mut seen = HashSet::new();
+    commands
+        .into_iter()
+        .rev()
+        .filter(|command| !command.text.trim().is_empty() && seen.insert(command.text.clone()))
+        .collect()
+}
+
+/// zsh writes the bytes >= 0x83 of the commands as 0x83 followed by the byte xor 32
+fn unmetafy(content: &[u8]) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(content.len());
+    let mut iter = content.iter();
+    while let Some(&byte) = iter.next() {
+        match byte {
+            0x83 => bytes.extend(iter.next().map(|&next| next ^ 32)),
+            _ => bytes.push(byte),
+        }
+    }
+    bytes
+}
+
+/// the history of bash or zsh, a command per line with:
+/// - `#1580000000` before it if bash records the time, then the lines until the next time are
+///   the lines of the command
+/// - `: 1580000000:0;` before it in the extended history of zsh
+/// - the lines ending with `\` continued on the next line in zsh
+fn parse_lines(content: &str) -> Vec<Command> {
+    let mut commands: Vec<Command> = Vec::new();
+    // the time of bash for the next command
+    let mut next_time = None;
+    // whether the next line continues the last command
+    let mut continued = false;
+    // whether the last command had a time of bash, then it has the lines until the next time
+    let mut timed = false;
+
+    for line in content.lines() {
+        if let Some(time) = line.strip_prefix('#').and_then(|time| time.parse().ok()) {
+            next_time = Some(time);
+            continued = false;
+            timed = false;
+            continue;
+        }
+
+        let bash_time = next_time.take();
+        let has_bash_time = bash_time.is_some();
+        let (line, time) = match parse_zsh_extended(line) {
+            Some((time, line)) if !continued && !has_bash_time => (line, Some(time)),
+            _ => (line, bash_time),
+        };
+        let (line, continues) = match line.strip_suffix('\\') {
+            Some(line) if !line.ends_with('\\') => (line, true),
+            _ => (line, false),
+        };
+
+        match commands.last_mut() {
+            Some(last) if continued || (timed && !has_bash_time) => {
+                last.text.push('\n');
+                last.text.push_str(line);
+            }
+            _ => {
+                timed = has_bash_time;
+                commands.push(Command {
+                    text: line.to_string(),
+                    time,
+                });
+            }
+        }
+        continued = continues;
+    }
+    commands
+}
+
+/// `: <start>:<elapsed>;<command>` -> (start, command)
+fn parse_zsh_extended(line: &str) -> Option<(i64, &str)> {
+    let rest = line.strip_prefix(": ")?;
+    let (header, command) = rest.split_once(';')?;
+    let (start, elapsed) = header.split_once(':')?;
+    elapsed.parse::<u64>().ok()?;
+    Some((start.trim().parse().ok()?, command))
+}
+
+/// the history of fish:
+/// ```text
+/// - cmd: echo hello\nworld
+///   when: 1580000000
+///   paths:
+///     - hello
+/// ```
+fn parse_fish(content: &str) -> Vec<Command> {
+    let mut commands: Vec<Command> = Vec::new();
+    for line in content.lines() {
+        if let Some(cmd) = line.strip_prefix("- cmd: ") {
+            commands.push(Command {
+                text: unescape_fish(cmd),
+                time: None,
+            });
+        } else if let (Some(time), Some(last)) = (line.strip_prefix("  when: "), commands.last_mut()) {
+            last.time = time.trim().parse().ok();
+        }
+    }
+    commands
+}
+
+fn unescape_fish(cmd: &str) -> String {
+    let mut text = String::with_capacity(cmd.len());
+    let mut chars = cmd.chars();
+    while let Some(ch) = chars.next() {
+        match ch {
+            '\\' => match chars.next() {
+                Some('n') => text.push('\n'),
+                Some('\\') => text.push('\\'),
+                Some(other) => {
+                    text.push('\\');
+                    text.push(other);
+                }
+                None => text.push('\\'),
+            },
+            _ => text.push(ch),
+        }
+    }
+    text
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn parse(shell: &str, content: &[u8]) -> Vec<(String, Option<i64>)> {
+        parse_history(history_format(shell, content), content)
+            .into_iter()
+            .map(|command| (command.text, command.time))
+            .collect()
+    }
+
+    fn command(text: &str, time: Option<i64>) -> (String, Option<i64>) {
+        (text.to_string(), time)
+    }
+
+    #[test]
+    fn test_parse_bash() {
+        let history = b"ls\ncd /tmp\nls\n";
+        assert_eq!(
+            parse("bash", history),
+            vec![command("ls", None), command("cd /tmp", None)]
+        );
+
+        let history = b"#1600000000\nls\n#1600000100\nfor i in 1 2; do\n  echo $i\ndone\n#1600000200\nls\n";
+        assert_eq!(
+            parse("bash", history),
+            vec![
+                command("ls", Some(1600000200)),
+                command("for i in 1 2; do\n  echo $i\ndone", Some(1600000100)),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_zsh() {
+        let history = b": 1600000000:0;git status\n: 1600000100:3;echo a\\\nb\n: 1600000200:0;git status\n";
+        assert_eq!(
+            parse("zsh", history),
+            vec![
+                command("git status", Some(1600000200)),
+                command("echo a\nb", Some(1600000100)),
+            ]
+        );
+
+        // "é" is 0xc3 0xa9, metafied as 0xc3 0x83 0x89
+        let history = b"echo caf\xc3\x83\x89\n";
+        assert_eq!(parse("zsh", history), vec![command("echo café", None)]);
+    }
+
+    #[test]
+    fn test_history_format() {
+        // told by the content, whatever the shell
+        assert_eq!(history_format("bash", b": 1600000000:0;ls\n"), HistoryFormat::Zsh);
+        assert_eq!(
+            history_format("zsh", b"- cmd: ls\n  when: 1600000000\n"),
+            HistoryFormat::Fish
+        );
+        // otherwise by the shell
+        assert_eq!(history_format("zsh", b"ls\n"), HistoryFormat::Zsh);
+        assert_eq!(history_format("sh", b"ls\n"), HistoryFormat::Bash);
+    }
+
+    #[test]
+    fn test_parse_fish() {
+        let history = b"- cmd: echo a\\nb\n  when: 1600000000\n- cmd: ls\n  when: 1600000100\n  paths:\n    - src\n- cmd: echo a\\nb\n  when: 1600000200\n";
+        assert_eq!(
+            parse("fish", history),
+            vec![command("echo a\nb", Some(1600000200)), command("ls", Some(1600000100))]
+        );
+    }
+}
@@ -0,0 +1,107 @@
+//! The built-in sources selected with `--source NAME`: the command to read the items from, with
+//! the preview and the fields that suit them. They are options like the ones of a profile, given
+//! before all the others, so that any of them could be overridden on the command line.
+//!
+//! The listings of git are NUL separated (`-z`), so that the paths are never quoted. The sources
+//! not read from a command have their items from `source_items`.
+use skim::SkimItemReceiver;
+
+use crate::shell_history::read_shell_history;
+
+// (name, options)
+const SOURCES: &[(&str, &[&str])] = &[
+    (
+        "git-files",
+        &[
+            "--cmd",
+            "git ls-files --cached --others --exclude-standard -z",
+            "--read0",
+            "--preview",
+            "cat -- {}",
+        ],
+    ),
+    (
+        // `XY PATH`, the path is the 2nd field and the output
+        "git-status",
+        &[
+            "--cmd",
+            "git status --short --no-renames --untracked-files=all -z",
+            "--read0",
+            "--delimiter",
+            "^...",
+            "--accept-nth",
+            "2",
+            "--preview",
+            "if git ls-files --error-unmatch -- {2} >/dev/null 2>&1; \
+             then git diff --color=always HEAD -- {2}; else cat -- {2}; fi",
+        ],
+    ),
+    (
+        // the local and remote branches, the recent ones first
+        "git-branches",
+        &[
+            "--cmd",
+            "git for-each-ref --sort=-committerdate --format='%(refname:short)' refs/heads refs/remotes",
+            "--tiebreak",
+            "score,index",
+            "--preview",
+            "git log --oneline --graph --color=always -n 50 {} --",
+        ],
+    ),
+    (
+        // the commands from `read_shell_history`, the recent ones first
+        "shell-history",
+        &["--tiebreak", "score,index"],
+    ),
+];
+
+/// the options of the built-in source `name`
+pub fn source_options(name: &str) -> Result<Vec<String>, String> {
+    SOURCES
+        .iter()
+        .find(|(source, _)| *source == name)
+        .map(|(_, options)| options.iter().map(|option| option.to_string()).collect())
+        .ok_or_else(|| {
+            let names: Vec<&str> = SOURCES.iter().map(|(source, _)| *source).collect();
+            format!("unknown source {:?}, expect one of: {}", name, names.join(", "))
+        })
+}
+
+/// the items of the built-in source `name`, `None` if they are read from its `--cmd`
+pub fn source_items(name: &str) -> Option<SkimItemReceiver> {
+    match name {
+        "shell-history" => Some(read_shell_history()),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use skim::{parse_criteria, RankCriteria};
+
+    #[test]
+    fn test_source_options() {
+        let options = source_options("git-status").unwrap();
+        assert_eq!(options[0], "--cmd");
+        assert!(options.windows(2).any(|pair| pair == ["--accept-nth", "2"]));
+        assert_eq!(
+            source_options("svn-files"),
+            Err(
+                "unknown source \"svn-files\", expect one of: git-files, git-status, git-branches, shell-history"
+                    .to_string()
+            )
+        );
+    }
+
+    #[test]
+    fn test_source_tiebreak() {
+        // the unknown criteria are dropped, the matches are then left unranked
+        for name in &["git-branches", "shell-history"] {
+            let options = source_options(name).unwrap();
+            let tiebreak = options.windows(2).find(|pair| pair[0] == "--tiebreak").unwrap();
+            let criterion: Vec<_> = tiebreak[1].split(',').map(parse_criteria).collect();
+            assert_eq!(criterion, vec![Some(RankCriteria::Score), Some(RankCriteria::Index)]);
+        }
+    }
+}
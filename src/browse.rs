@@ -0,0 +1,166 @@
+//! The directory browser of `--browse`: the items are the entries of a directory, accepting a
+//! directory lists it instead, `parent-dir` goes up a level, and accepting a file outputs its full
+//! path.
+use std::borrow::Cow;
+use std::env;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::thread;
+
+use crossbeam::channel::unbounded;
+
+use crate::{SkimItem, SkimItemReceiver};
+
+/// An entry of the browsed directory, shown by its name (with a `/` after the directories) and
+/// output as its full path
+struct DirEntry {
+    name: String,
+    path: PathBuf,
+    is_dir: bool,
+}
+
+impl SkimItem for DirEntry {
+    fn text(&self) -> Cow<'_, str> {
+        Cow::Borrowed(&self.name)
+    }
+
+    fn output(&self) -> Cow<'_, str> {
+        self.path.to_string_lossy()
+    }
+}
+
+pub struct Browser {
+    dir: PathBuf,
+}
+
+impl Browser {
+    /// browse `dir`, the current directory if it's empty
+    pub fn new(dir: &str) -> io::Result<Self> {
+        let dir = if dir.is_empty() { "." } else { dir };
+        let dir = fs::canonicalize(dir)?;
+        if !dir.is_dir() {
+            return Err(io::Error::new(io::ErrorKind::Other, "not a directory"));
+        }
+        Ok(Self { dir })
+    }
+
+    /// the prompt showing the directory, with the home as `~`
+    pub fn prompt(&self) -> String {
+        let dir = self.dir.to_string_lossy();
+        let home = env::var("HOME").ok().filter(|home| !home.is_empty() && home != "/");
+        let dir = match home {
+            Some(home) if dir == home => "~".to_string(),
+            Some(home) if dir.starts_with(&format!("{}/", home)) => format!("~{}", &dir[home.len()..]),
+            _ => dir.into_owned(),
+        };
+        if dir.ends_with('/') {
+            format!("{} > ", dir)
+        } else {
+            format!("{}/ > ", dir)
+        }
+    }
+
+    /// the entries of the directory: `../` unless it's the root, the directories, then the files,
+    /// each sorted by name
+    pub fn list(&self) -> SkimItemReceiver {
+        let (tx_item, rx_item) = unbounded();
+        let dir = self.dir.clone();
+        thread::spawn(move || {
+            for entry in list_dir(&dir) {
+                if tx_item.send(Arc::new(entry) as Arc<dyn SkimItem>).is_err() {
+                    break;
+                }
+            }
+        });
+        rx_item
+    }
+
+    /// browse the directory `item` if it's one, return whether it is
+    pub fn enter(&mut self, item: &Arc<dyn SkimItem>) -> bool {
+        match (**item).as_any().downcast_ref::<DirEntry>() {
+            Some(entry) if entry.is_dir => {
+                self.dir = entry.path.clone();
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// browse the parent directory, return whether there is one
+    pub fn parent(&mut self) -> bool {
+        match self.dir.parent() {
+            Some(parent) => {
+                self.dir = parent.to_path_buf();
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+fn list_dir(dir: &Path) -> Vec<DirEntry> {
+    let mut entries: Vec<DirEntry> = match fs::read_dir(dir) {
+        Ok(read_dir) => read_dir
+            .filter_map(Result::ok)
+            .map(|entry| {
+                let path = entry.path();
+                // follow the symlinks to the directories
+                let is_dir = path.is_dir();
+                let name = entry.file_name().to_string_lossy().into_owned();
+                DirEntry {
+                    name: if is_dir { format!("{}/", name) } else { name },
+                    path,
+                    is_dir,
+                }
+            })
+            .collect(),
+        Err(err) => {
+            debug!("browse: failed to read {}: {}", dir.display(), err);
+            Vec::new()
+        }
+    };
+    entries.sort_by(|a, b| b.is_dir.cmp(&a.is_dir).then_with(|| a.name.cmp(&b.name)));
+
+    if let Some(parent) = dir.parent() {
+        let parent = DirEntry {
+            name: "../".to_string(),
+            path: parent.to_path_buf(),
+            is_dir: true,
+        };
+        entries.insert(0, parent);
+    }
+    entries
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_browse() {
+        let root = std::env::temp_dir().join(format!("skim-browse-{}", std::process::id()));
+        fs::create_dir_all(root.join("sub")).unwrap();
+        fs::write(root.join("b.txt"), "").unwrap();
+        fs::write(root.join("a.txt"), "").unwrap();
+        fs::write(root.join("sub/c.txt"), "").unwrap();
+
+        let mut browser = Browser::new(root.to_str().unwrap()).unwrap();
+        let items: Vec<Arc<dyn SkimItem>> = browser.list().iter().collect();
+        let names: Vec<String> = items.iter().map(|item| item.text().to_string()).collect();
+        assert_eq!(names, vec!["../", "sub/", "a.txt", "b.txt"]);
+        assert_eq!(items[2].output(), browser.dir.join("a.txt").to_string_lossy());
+
+        // files are not entered
+        assert!(!browser.enter(&items[2]));
+        assert!(browser.enter(&items[1]));
+        let names: Vec<String> = browser.list().iter().map(|item| item.text().to_string()).collect();
+        assert_eq!(names, vec!["../", "c.txt"]);
+
+        assert!(browser.parent());
+        assert_eq!(browser.dir, fs::canonicalize(&root).unwrap());
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
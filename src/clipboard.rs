@@ -0,0 +1,94 @@
+//! Copy to the system clipboard, e.g. the `yank-item` and `yank-query` actions: by an external
+//! command (`--clipboard-cmd`), or by the OSC 52 escape sequence written to the terminal, which
+//! works over ssh too, without the command or when it fails.
+use std::fs::OpenOptions;
+use std::io::{self, Write};
+use std::process::{Command, Stdio};
+
+const BASE64_CHARS: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// copy `text` by running `command` with it on its stdin, or by OSC 52 without a command
+pub fn copy(text: &str, command: Option<Command>) -> io::Result<()> {
+    match command {
+        Some(mut command) => {
+            let mut child = command
+                .stdin(Stdio::piped())
+                .stdout(Stdio::null())
+                .stderr(Stdio::null())
+                .spawn()?;
+            let written = child
+                .stdin
+                .take()
+                .expect("stdin should be piped")
+                .write_all(text.as_bytes());
+            let status = child.wait()?;
+            written?;
+            if status.success() {
+                Ok(())
+            } else {
+                Err(io::Error::new(
+                    io::ErrorKind::Other,
+                    format!("the command failed: {}", status),
+                ))
+            }
+        }
+        None => {
+            // the terminal is /dev/tty, the stdout might be redirected
+            let mut tty = OpenOptions::new().write(true).open("/dev/tty")?;
+            tty.write_all(osc52(text).as_bytes())?;
+            tty.flush()
+        }
+    }
+}
+
+/// the sequence setting the clipboard to `text`
+fn osc52(text: &str) -> String {
+    format!("\x1b]52;c;{}\x07", base64(text.as_bytes()))
+}
+
+fn base64(bytes: &[u8]) -> String {
+    let mut encoded = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let group = chunk
+            .iter()
+            .enumerate()
+            .fold(0u32, |group, (idx, &byte)| group | (byte as u32) << (16 - 8 * idx));
+        for idx in 0..4 {
+            if idx <= chunk.len() {
+                encoded.push(BASE64_CHARS[(group >> (18 - 6 * idx) & 0x3f) as usize] as char);
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+    encoded
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_osc52() {
+        assert_eq!(base64(b""), "");
+        assert_eq!(base64(b"f"), "Zg==");
+        assert_eq!(base64(b"fo"), "Zm8=");
+        assert_eq!(base64(b"foo"), "Zm9v");
+        assert_eq!(base64("héllo\n".as_bytes()), "aMOpbGxvCg==");
+        assert_eq!(osc52("foo"), "\x1b]52;c;Zm9v\x07");
+    }
+
+    #[test]
+    fn test_copy_with_command() {
+        let path = std::env::temp_dir().join(format!("skim-clipboard-{}", std::process::id()));
+        let mut command = Command::new("sh");
+        command.arg("-c").arg(format!("cat > '{}'", path.display()));
+        copy("a\nb", Some(command)).unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "a\nb");
+        std::fs::remove_file(&path).unwrap();
+
+        let mut command = Command::new("sh");
+        command.arg("-c").arg("exit 1");
+        assert!(copy("a", Some(command)).is_err());
+    }
+}
@@ -0,0 +1,275 @@
+//! Decorate the displayed items with an external command, e.g. `--format-cmd`. The command is
+//! started once and keeps running, it reads the text of an item per line and answers every line
+//! with the decorated one (icons, colors in ANSI codes, annotations), in order. Only the visible
+//! items are sent, by a thread of its own, so the matching never waits for it: the items are shown
+//! as they are until their decoration arrives, and for good if the command stops answering.
+use std::collections::{HashMap, HashSet};
+use std::io::{self, BufRead, BufReader, Write};
+use std::process::{Command, Stdio};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError, Sender};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use crate::ansi::{ANSIParser, AnsiString};
+use crate::attr::Attr;
+use crate::event::{Event, EventSender};
+use crate::spinlock::SpinLock;
+
+// the decorations kept, cleared all at once when full, a screen shows much less
+const DECORATION_CACHE_SIZE: usize = 4096;
+// the command is given up if a line isn't answered in time, e.g. its output is buffered
+const ANSWER_TIMEOUT: Duration = Duration::from_secs(5);
+
+pub struct Decorator {
+    decorations: Arc<SpinLock<HashMap<String, AnsiString<'static>>>>,
+    // the texts sent to the command and not answered yet
+    pending: Arc<SpinLock<HashSet<String>>>,
+    tx_text: Sender<String>,
+    stopped: Arc<AtomicBool>,
+    // the number of answers received, the rows drawn before it changed may be out of date
+    answers: Arc<AtomicUsize>,
+}
+
+impl Decorator {
+    /// start the command, the screen is redrawn through `tx_redraw` when decorations arrive
+    pub fn spawn(mut command: Command, tx_redraw: EventSender) -> io::Result<Self> {
+        let mut child = command.stdin(Stdio::piped()).stdout(Stdio::piped()).spawn()?;
+        let stdin = child.stdin.take().expect("stdin should be piped");
+        let mut stdout = BufReader::new(child.stdout.take().expect("stdout should be piped"));
+
+        let decorations = Arc::new(SpinLock::new(HashMap::new()));
+        let pending = Arc::new(SpinLock::new(HashSet::new()));
+        let stopped = Arc::new(AtomicBool::new(false));
+        let answers = Arc::new(AtomicUsize::new(0));
+        let (tx_text, rx_text) = channel();
+        let (tx_line, rx_line) = channel();
+
+        // the answers are read by a thread of their own so that the worker could time out on them,
+        // it ends once the command is killed
+        thread::spawn(move || loop {
+            let mut line = String::new();
+            match stdout.read_line(&mut line) {
+                Ok(0) | Err(_) => break,
+                Ok(_) => {
+                    if tx_line.send(line).is_err() {
+                        break;
+                    }
+                }
+            }
+        });
+
+        let worker = Worker {
+            decorations: decorations.clone(),
+            pending: pending.clone(),
+            stopped: stopped.clone(),
+            answers: answers.clone(),
+            tx_redraw,
+            timeout: ANSWER_TIMEOUT,
+        };
+        thread::spawn(move || {
+            if let Err(err) = worker.run(rx_text, stdin, rx_line) {
+                error!("format command: {}", err);
+            }
+            worker.stopped.store(true, Ordering::SeqCst);
+            let _ = child.kill();
+            let _ = child.wait();
+        });
+
+        Ok(Self {
+            decorations,
+            pending,
+            tx_text,
+            stopped,
+            answers,
+        })
+    }
+
+    /// changes whenever decorations arrive
+    pub fn version(&self) -> usize {
+        self.answers.load(Ordering::SeqCst)
+    }
+
+    /// the decoration of `text` with the attrs of its `display` (colors of `--ansi`, highlighted
+    /// matches), `None` if it's not there yet, then it's asked to the command
+    pub fn decorate(&self, text: &str, display: &AnsiString) -> Option<AnsiString<'static>> {
+        if let Some(decorated) = self.decorations.lock().get(text) {
+            return Some(overlay(decorated.clone(), display));
+        }
+
+        if !self.stopped.load(Ordering::SeqCst) && self.pending.lock().insert(text.to_string()) {
+            let _ = self.tx_text.send(text.to_string());
+        }
+        None
+    }
+}
+
+impl Drop for Decorator {
+    fn drop(&mut self) {
+        self.stopped.store(true, Ordering::SeqCst);
+    }
+}
+
+struct Worker {
+    decorations: Arc<SpinLock<HashMap<String, AnsiString<'static>>>>,
+    pending: Arc<SpinLock<HashSet<String>>>,
+    stopped: Arc<AtomicBool>,
+    answers: Arc<AtomicUsize>,
+    tx_redraw: EventSender,
+    timeout: Duration,
+}
+
+impl Worker {
+    fn run(&self, rx_text: Receiver<String>, mut stdin: impl Write, rx_line: Receiver<String>) -> io::Result<()> {
+        let mut ansi_parser = ANSIParser::default();
+        // the texts asked for while drawing one screen are sent together
+        while let Ok(text) = rx_text.recv() {
+            let texts: Vec<String> = Some(text).into_iter().chain(rx_text.try_iter()).collect();
+            let mut request = String::new();
+            for text in texts.iter() {
+                request.push_str(&text.replace('\n', " "));
+                request.push('\n');
+            }
+            stdin.write_all(request.as_bytes())?;
+            stdin.flush()?;
+
+            for text in texts {
+                let line = match rx_line.recv_timeout(self.timeout) {
+                    Ok(line) => line,
+                    Err(RecvTimeoutError::Timeout) => {
+                        let msg = "no answer in time, the output of the command might be buffered";
+                        return Err(io::Error::new(io::ErrorKind::TimedOut, msg));
+                    }
+                    Err(RecvTimeoutError::Disconnected) => {
+                        return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "the command exited"));
+                    }
+                };
+                let decorated = ansi_parser.parse_ansi(line.trim_end_matches(['\r', '\n']));
+                let mut decorations = self.decorations.lock();
+                if decorations.len() >= DECORATION_CACHE_SIZE {
+                    decorations.clear();
+                }
+                self.pending.lock().remove(&text);
+                decorations.insert(text, decorated);
+            }
+            self.answers.fetch_add(1, Ordering::SeqCst);
+
+            if self.stopped.load(Ordering::SeqCst) || self.tx_redraw.send(Event::EvHeartBeat).is_err() {
+                break;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// lay the attrs of `display` on its text in the decoration, if the decoration still shows it
+fn overlay(mut decorated: AnsiString<'static>, display: &AnsiString) -> AnsiString<'static> {
+    let mut start = match decorated.stripped().find(display.stripped()) {
+        Some(byte) if !display.is_empty() => byte,
+        _ => return decorated,
+    };
+
+    let mut fragments = vec![];
+    for (span, attr) in display.spans() {
+        let end = start + span.len();
+        if attr != Attr::default() {
+            fragments.push((attr, (start as u32, end as u32)));
+        }
+        start = end;
+    }
+    decorated.override_attrs(fragments);
+    decorated
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::time::Duration;
+    use tuikit::attr::{Color, Effect};
+
+    #[test]
+    fn test_overlay() {
+        let attr = Attr::default().effect(Effect::BOLD);
+        let red = Attr::default().fg(Color::RED);
+        let spans = |decorated: AnsiString<'static>| -> Vec<(String, Attr)> {
+            decorated
+                .spans()
+                .map(|(text, span_attr)| (text.to_string(), span_attr))
+                .collect()
+        };
+
+        // the highlighted matches
+        let display = AnsiString::new_str("abc", vec![(attr, (0, 1)), (attr, (2, 3))]);
+        let decorated = overlay(AnsiString::parse("> abc <"), &display);
+        assert_eq!(decorated.stripped(), "> abc <");
+        let plain = Attr::default();
+        assert_eq!(
+            spans(decorated),
+            vec![
+                ("> ".to_string(), plain),
+                ("a".to_string(), attr),
+                ("b".to_string(), plain),
+                ("c".to_string(), attr),
+                (" <".to_string(), plain)
+            ]
+        );
+
+        // the colors of --ansi are kept along with the ones of the decoration
+        let display = AnsiString::parse("\x1b[31mab\x1b[mc");
+        let decorated = overlay(AnsiString::parse("\x1b[1m*\x1b[m abc"), &display);
+        assert_eq!(
+            spans(decorated),
+            vec![
+                ("*".to_string(), attr),
+                (" ".to_string(), plain),
+                ("ab".to_string(), red),
+                ("c".to_string(), plain)
+            ]
+        );
+
+        // the text is not shown as is, nothing to lay on
+        let decorated = overlay(AnsiString::parse("ABC"), &AnsiString::parse("\x1b[31mabc"));
+        assert_eq!(spans(decorated), vec![("ABC".to_string(), plain)]);
+    }
+
+    #[test]
+    fn test_timeout() {
+        let (tx_redraw, _rx_redraw) = channel();
+        let worker = Worker {
+            decorations: Arc::new(SpinLock::new(HashMap::new())),
+            pending: Arc::new(SpinLock::new(HashSet::new())),
+            stopped: Arc::new(AtomicBool::new(false)),
+            answers: Arc::new(AtomicUsize::new(0)),
+            tx_redraw,
+            timeout: Duration::from_millis(10),
+        };
+        let (tx_text, rx_text) = channel();
+        // a command that buffers its output never answers
+        let (_tx_line, rx_line) = channel();
+        tx_text.send("abc".to_string()).unwrap();
+
+        let err = worker.run(rx_text, io::sink(), rx_line).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+        assert!(worker.decorations.lock().is_empty());
+    }
+
+    #[test]
+    fn test_decorate() {
+        let (tx, rx) = channel();
+        let mut command = Command::new("sh");
+        command
+            .arg("-c")
+            .arg("while IFS= read -r line; do echo \"[$line]\"; done");
+        let decorator = Decorator::spawn(command, tx).unwrap();
+
+        let display = AnsiString::from("abc");
+        assert!(decorator.decorate("abc", &display).is_none());
+        assert!(matches!(
+            rx.recv_timeout(Duration::from_secs(5)),
+            Ok(Event::EvHeartBeat)
+        ));
+        let decorated = decorator.decorate("abc", &display).unwrap();
+        assert_eq!(decorated.stripped(), "[abc]");
+    }
+}
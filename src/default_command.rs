@@ -0,0 +1,116 @@
+//! The command to read the items from when the input is a tty. The first one found is used:
+//! 1. `$SKIM_DEFAULT_COMMAND`
+//! 2. the first command of the config file whose program is installed, the file is
+//!    `$XDG_CONFIG_HOME/skim/default_command` (`~/.config/skim/default_command` by default) with one
+//!    command per line, so the same file could prefer `fd` or `rg --files` on different machines
+//! 3. `find .`
+//!
+//! `{cq}` in the command is replaced by the command query of interactive mode (`-i`), as in the
+//! other commands, e.g. `rg --files --glob '*{cq}*'`.
+use std::env;
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+
+const BUILTIN_COMMAND: &str = "find .";
+
+// the default `--interactive` replacement string
+const REPLSTR: &str = "{}";
+
+pub fn default_command() -> String {
+    let command = env::var("SKIM_DEFAULT_COMMAND")
+        .ok()
+        .filter(|cmd| !cmd.is_empty())
+        .or_else(|| {
+            config_file()
+                .and_then(|path| fs::read_to_string(path).ok())
+                .and_then(|content| first_available(&content, is_installed))
+        })
+        .unwrap_or_else(|| BUILTIN_COMMAND.to_string());
+    expand_placeholders(&command)
+}
+
+/// `{cq}` becomes the replacement string of the command query, `\{cq}` is kept as `{cq}`
+fn expand_placeholders(command: &str) -> String {
+    command
+        .split("\\{cq}")
+        .map(|part| part.replace("{cq}", REPLSTR))
+        .collect::<Vec<_>>()
+        .join("{cq}")
+}
+
+fn config_file() -> Option<PathBuf> {
+    let config_home = env::var_os("XDG_CONFIG_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".config")))?;
+    Some(config_home.join("skim").join("default_command"))
+}
+
+/// the first command whose program is installed, blank lines and `#` comments are skipped
+fn first_available(content: &str, is_installed: impl Fn(&str) -> bool) -> Option<String> {
+    content
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .find(|line| {
+            shlex::split(line)
+                .and_then(|words| words.into_iter().next())
+                .is_some_and(|program| is_installed(&program))
+        })
+        .map(str::to_string)
+}
+
+/// whether the program is an executable file, searched in `$PATH` unless it's a path
+fn is_installed(program: &str) -> bool {
+    let is_executable = |path: &Path| {
+        fs::metadata(path)
+            .map(|meta| meta.is_file() && meta.permissions().mode() & 0o111 != 0)
+            .unwrap_or(false)
+    };
+
+    if program.contains('/') {
+        return is_executable(Path::new(program));
+    }
+    env::var_os("PATH")
+        .map(|paths| env::split_paths(&paths).any(|dir| is_executable(&dir.join(program))))
+        .unwrap_or(false)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_first_available() {
+        let content = "
+            # prefer fd, then rg
+            fd --type f
+            rg --files
+
+            find . -type f
+        ";
+        let installed = |programs: &'static [&'static str]| move |program: &str| programs.contains(&program);
+
+        assert_eq!(
+            first_available(content, installed(&["fd", "rg", "find"])),
+            Some("fd --type f".to_string())
+        );
+        assert_eq!(
+            first_available(content, installed(&["rg", "find"])),
+            Some("rg --files".to_string())
+        );
+        assert_eq!(first_available(content, installed(&[])), None);
+        assert_eq!(first_available("# fd", installed(&["fd", "#"])), None);
+    }
+
+    #[test]
+    fn test_expand_placeholders() {
+        assert_eq!(expand_placeholders("fd --type f"), "fd --type f");
+        assert_eq!(
+            expand_placeholders("rg --files --glob '*{cq}*'"),
+            "rg --files --glob '*{}*'"
+        );
+        assert_eq!(expand_placeholders("echo {cq} \\{cq}"), "echo {} {cq}");
+    }
+}
@@ -0,0 +1,249 @@
+//! Run skim inside an event loop owned by the caller
+use std::sync::mpsc::channel;
+
+use tuikit::canvas::BoundedCanvas;
+use tuikit::prelude::{Canvas, Draw, Event as TermEvent, Key, Rectangle, Result, Widget};
+
+use crate::event::{Event, EventSender};
+use crate::input::{parse_keys, Input};
+use crate::model::{Model, ModelEnv, Step};
+use crate::options::SkimOptions;
+use crate::output::{MatchedOutput, SkimOutput};
+use crate::reader::Reader;
+use crate::SkimItemReceiver;
+
+/// The result of `SkimEngine::poll`
+pub enum SkimPoll {
+    /// nothing changed since last poll
+    Idle,
+    /// the state changed, call `SkimEngine::draw` to render a new frame
+    Redraw,
+    /// skim finished, `None` means aborted
+    Done(Option<SkimOutput>),
+}
+
+/// A non-blocking version of `Skim`, used to embed skim into an existing TUI application.
+///
+/// Unlike `Skim::run_with`, the engine never touches the terminal. The caller feeds terminal
+/// events with `feed_event`, calls `poll` regularly (e.g. on every tick of its own event loop) to
+/// let skim process them, and calls `draw` with a canvas whenever `poll` asks for a redraw.
+///
+/// ```no_run
+/// use skim::prelude::*;
+///
+/// let options = SkimOptions::default();
+/// let mut engine = SkimEngine::new(&options, None);
+/// loop {
+///     // engine.feed_event(event_from_host);
+///     match engine.poll() {
+///         SkimPoll::Idle => {}
+///         SkimPoll::Redraw => { /* engine.draw(&mut canvas) */ }
+///         SkimPoll::Done(_output) => break,
+///     }
+/// }
+/// ```
+pub struct SkimEngine {
+    model: Model,
+    env: ModelEnv,
+    input: Input,
+    tx: EventSender,
+    next_event: Option<Event>,
+    done: bool,
+}
+
+impl SkimEngine {
+    pub fn new(options: &SkimOptions, source: Option<SkimItemReceiver>) -> Self {
+        let (tx, rx) = channel();
+        let input = Input::with_options(options);
+        let reader = Reader::with_options(options).source(source);
+        let mut model = Model::new(rx, tx.clone(), reader, None, options);
+        let env = model.prepare();
+
+        Self {
+            model,
+            env,
+            input,
+            tx,
+            next_event: Some(Event::EvHeartBeat),
+            done: false,
+        }
+    }
+
+    /// feed a terminal event (key, mouse, resize, etc.) to skim, it will be processed on next `poll`
+    pub fn feed_event(&mut self, event: TermEvent) {
+        for ev in self.input.translate_event(event).into_iter() {
+            let _ = self.tx.send(ev);
+        }
+    }
+
+    /// feed the keys named as in `--bind` and separated by spaces, e.g. "ctrl-k down enter", to
+    /// drive skim from the host or from a test. Nothing is fed if any key name is unknown.
+    ///
+    /// ```
+    /// use skim::prelude::*;
+    /// use std::time::{Duration, Instant};
+    ///
+    /// let (tx, rx): (SkimItemSender, SkimItemReceiver) = unbounded();
+    /// for text in ["a", "b", "c"] {
+    ///     let _ = tx.send(Arc::new(text));
+    /// }
+    /// drop(tx);
+    ///
+    /// // accepted once the query typed has the only match
+    /// let options = SkimOptions { select1: true, ..Default::default() };
+    /// let mut engine = SkimEngine::new(&options, Some(rx));
+    /// assert!(engine.feed_keys("b"));
+    /// assert!(!engine.feed_keys("no-such-key"));
+    ///
+    /// let start = Instant::now();
+    /// let output = loop {
+    ///     assert!(start.elapsed() < Duration::from_secs(5), "skim didn't finish");
+    ///     if let SkimPoll::Done(output) = engine.poll() {
+    ///         break output.unwrap();
+    ///     }
+    ///     std::thread::sleep(Duration::from_millis(10));
+    /// };
+    /// assert_eq!("b", output.query);
+    /// let selected: Vec<_> = output.selected_items.iter().map(|item| item.text()).collect();
+    /// assert_eq!(vec!["b"], selected);
+    /// assert!(matches!(engine.poll(), SkimPoll::Idle));
+    /// ```
+    pub fn feed_keys(&mut self, keys: &str) -> bool {
+        match parse_keys(keys) {
+            Some(keys) => {
+                keys.into_iter().for_each(|key| self.feed_event(TermEvent::Key(key)));
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// feed a terminal event to a skim instance that is drawn into `rect` (see `draw_in`).
+    /// Mouse events are translated to be relative to `rect`, the ones outside of it are dropped.
+    pub fn feed_event_in(&mut self, event: TermEvent, rect: Rectangle) {
+        let event = match event {
+            TermEvent::Key(key) => match relative_mouse_key(key, rect) {
+                Some(key) => TermEvent::Key(key),
+                None => return,
+            },
+            event => event,
+        };
+        self.feed_event(event);
+    }
+
+    /// process all the pending events without blocking.
+    /// Once `SkimPoll::Done` is returned, all the following calls will return `SkimPoll::Idle`.
+    pub fn poll(&mut self) -> SkimPoll {
+        if self.done {
+            return SkimPoll::Idle;
+        }
+
+        let mut redraw = false;
+        while let Some(ev) = self.next_event.take().or_else(|| self.model.try_recv()) {
+            match self.model.handle_event(ev, &mut self.env) {
+                Step::Chain(ev) => self.next_event = ev,
+                Step::Redraw(ev) => {
+                    self.next_event = ev;
+                    redraw = true;
+                }
+                Step::Exit(output) => {
+                    self.done = true;
+                    return SkimPoll::Done(output);
+                }
+            }
+        }
+
+        if redraw {
+            SkimPoll::Redraw
+        } else {
+            SkimPoll::Idle
+        }
+    }
+
+    /// the output of `SkimPoll::Done` with the ranges of its selected items matched by the final
+    /// query, to highlight them the way skim did
+    pub fn matched_output(&self, output: SkimOutput) -> MatchedOutput {
+        self.model.matched_output(output)
+    }
+
+    /// render the current state of skim to `canvas`
+    pub fn draw(&self, canvas: &mut dyn Canvas) -> Result<()> {
+        self.model.draw(canvas)
+    }
+
+    /// render the current state of skim into the sub-region `rect` of `canvas`, leaving the rest
+    /// of the canvas untouched. Useful for side-pane pickers inside a larger TUI.
+    pub fn draw_in(&self, canvas: &mut dyn Canvas, rect: Rectangle) -> Result<()> {
+        let Rectangle {
+            top,
+            left,
+            width,
+            height,
+        } = rect;
+        let mut canvas = BoundedCanvas::new(top, left, width, height, canvas);
+        self.model.draw(&mut canvas)
+    }
+}
+
+impl Draw for SkimEngine {
+    fn draw(&self, canvas: &mut dyn Canvas) -> Result<()> {
+        self.model.draw(canvas)
+    }
+}
+
+/// `SkimEngine` could be composed with other tuikit widgets (e.g. in `HSplit`), events should
+/// still be fed through `feed_event` or `feed_event_in`.
+impl<Message> Widget<Message> for SkimEngine {}
+
+/// translate the position of mouse keys to be relative to `rect`, `None` if outside of `rect`
+fn relative_mouse_key(key: Key, rect: Rectangle) -> Option<Key> {
+    let relative = |row: u16, col: u16| {
+        let (row, col) = (row as usize, col as usize);
+        if rect.contains(row, col) {
+            let (row, col) = rect.relative_to_origin(row, col);
+            Some((row as u16, col as u16))
+        } else {
+            None
+        }
+    };
+
+    let key = match key {
+        Key::MousePress(button, row, col) => relative(row, col).map(|(r, c)| Key::MousePress(button, r, c))?,
+        Key::MouseRelease(row, col) => relative(row, col).map(|(r, c)| Key::MouseRelease(r, c))?,
+        Key::MouseHold(row, col) => relative(row, col).map(|(r, c)| Key::MouseHold(r, c))?,
+        Key::SingleClick(button, row, col) => relative(row, col).map(|(r, c)| Key::SingleClick(button, r, c))?,
+        Key::DoubleClick(button, row, col) => relative(row, col).map(|(r, c)| Key::DoubleClick(button, r, c))?,
+        Key::WheelUp(row, col, count) => relative(row, col).map(|(r, c)| Key::WheelUp(r, c, count))?,
+        Key::WheelDown(row, col, count) => relative(row, col).map(|(r, c)| Key::WheelDown(r, c, count))?,
+        key => key,
+    };
+    Some(key)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use tuikit::prelude::MouseButton;
+
+    #[test]
+    fn test_relative_mouse_key() {
+        let rect = Rectangle {
+            top: 2,
+            left: 10,
+            width: 20,
+            height: 5,
+        };
+
+        assert_eq!(
+            Some(Key::WheelUp(1, 3, 1)),
+            relative_mouse_key(Key::WheelUp(3, 13, 1), rect)
+        );
+        assert_eq!(
+            Some(Key::SingleClick(MouseButton::Left, 0, 0)),
+            relative_mouse_key(Key::SingleClick(MouseButton::Left, 2, 10), rect)
+        );
+        assert_eq!(None, relative_mouse_key(Key::WheelDown(0, 0, 1), rect));
+        assert_eq!(None, relative_mouse_key(Key::MouseHold(7, 10), rect));
+        assert_eq!(Some(Key::Char('a')), relative_mouse_key(Key::Char('a'), rect));
+    }
+}
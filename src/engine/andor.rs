@@ -1,7 +1,9 @@
 use std::fmt::{Display, Error, Formatter};
 use std::sync::Arc;
 
-use crate::item::{MatchedItem, MatchedRange};
+use crate::engine::index::Required;
+use crate::engine::mask::CharMask;
+use crate::item::{MatchedItem, MatchedRange, ScoreDetail};
 use crate::{MatchEngine, SkimItem};
 
 //------------------------------------------------------------------------------
@@ -36,6 +38,18 @@ impl MatchEngine for OrEngine {
 
         None
     }
+
+    fn required_chars(&self) -> CharMask {
+        self.engines
+            .iter()
+            .map(|engine| engine.required_chars())
+            .reduce(CharMask::intersection)
+            .unwrap_or_default()
+    }
+
+    fn required_substrings(&self) -> Required {
+        Required::Any(self.engines.iter().map(|engine| engine.required_substrings()).collect())
+    }
 }
 
 impl Display for OrEngine {
@@ -74,7 +88,9 @@ impl AndEngine {
 
     fn merge_matched_items(&self, items: Vec<MatchedItem>) -> MatchedItem {
         let rank = items[0].rank;
+        let score = items[0].score;
         let item = Arc::clone(&items[0].item);
+        let terms = items.iter().map(|item| item.score).collect();
         let mut ranges = vec![];
         for item in items {
             match item.matched_range {
@@ -91,6 +107,8 @@ impl AndEngine {
         ranges.sort();
         ranges.dedup();
         MatchedItem::builder(item)
+            .score(score)
+            .score_detail(ScoreDetail { value: None, terms })
             .rank(rank)
             .matched_range(MatchedRange::Chars(ranges))
             .build()
@@ -112,6 +130,17 @@ impl MatchEngine for AndEngine {
             Some(self.merge_matched_items(results))
         }
     }
+
+    fn required_chars(&self) -> CharMask {
+        self.engines
+            .iter()
+            .map(|engine| engine.required_chars())
+            .fold(CharMask::default(), CharMask::union)
+    }
+
+    fn required_substrings(&self) -> Required {
+        Required::All(self.engines.iter().map(|engine| engine.required_substrings()).collect())
+    }
 }
 
 impl Display for AndEngine {
@@ -0,0 +1,506 @@
+//! Delegate the matching to an external long-running process, e.g. `--matcher-cmd`. Every item is
+//! sent to the process once, by its index in the item pool, and then only the queries. skim writes
+//! to the stdin of the process the records:
+//!
+//! ```text
+//! add<TAB>ID<TAB>TEXT                 (the item ID, sent again only if its text changed)
+//! match<TAB>START<TAB>END<TAB>QUERY   (match the items from START to END, END excluded)
+//! ```
+//!
+//! and reads from its stdout the answer to every `match`, the matched items in any order, then an
+//! empty record:
+//!
+//! ```text
+//! ID<TAB>SCORE[<TAB>RANGES]
+//! ```
+//!
+//! where SCORE is an integer, the greater the better, and the optional RANGES are the matched
+//! byte ranges of the text, e.g. `0-2,4-5`. The TEXT is the fields of `--nth` of the item if set.
+//! The case matching is up to the process, `--case` doesn't apply.
+//! The records end with a newline, the newlines in the texts and in the query are sent as spaces,
+//! or with a NUL with `--read0`. The answer to a request abandoned for a new query is skipped.
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::fmt::{Display, Error, Formatter};
+use std::hash::{Hash, Hasher};
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::process::{Child, ChildStdin, ChildStdout, Command, Stdio};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError, Sender};
+use std::sync::{Arc, Mutex, RwLock};
+use std::thread;
+use std::time::Duration;
+
+use crate::item::{ItemWithFields, MatchedItem, MatchedRange, RankBuilder};
+use crate::{CaseMatching, MatchEngine, MatchEngineFactory, MatchingRanges, SkimItem};
+
+// how often a request waiting for its answer checks whether it's abandoned
+const STOP_POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+type Ranges = Vec<(usize, usize)>;
+type Answer = Vec<(usize, i32, Ranges)>;
+
+/// the key of the results of an item, the address of the item in the pool, so that the items of
+/// the same text are told apart. The item is unwrapped if it's matched on the fields of `change-nth`.
+fn key_of(item: &Arc<dyn SkimItem>) -> usize {
+    let item = (**item)
+        .as_any()
+        .downcast_ref::<ItemWithFields>()
+        .map_or(item, |wrapped| &wrapped.item);
+    Arc::as_ptr(item) as *const () as usize
+}
+
+struct MatcherProcess {
+    child: Child,
+    stdin: BufWriter<ChildStdin>,
+    // the answers, read by a thread of their own so that waiting for one could be abandoned
+    answers: Receiver<Answer>,
+    // the answers of the abandoned requests still to come, they are skipped
+    abandoned: usize,
+    // the hash of the text sent for every ID, `None` if it's not sent
+    sent: Vec<Option<u64>>,
+    ending: u8,
+}
+
+impl MatcherProcess {
+    /// send the items from the ID `offset` on that the process doesn't have yet and the query,
+    /// return the score and the byte ranges of the matched ones by their IDs. Interrupted if
+    /// `stopped` is set before the answer arrives.
+    fn request(
+        &mut self,
+        query: &str,
+        offset: usize,
+        texts: impl Iterator<Item = String>,
+        stopped: &AtomicBool,
+    ) -> io::Result<Answer> {
+        let interrupted = || io::Error::new(io::ErrorKind::Interrupted, "the matching is stopped");
+        let ending = self.ending as char;
+        let mut end = offset;
+        for (id, text) in (offset..).zip(texts) {
+            if stopped.load(Ordering::Relaxed) {
+                return Err(interrupted());
+            }
+            let text = self.escape(text);
+            let hash = hash_of(&text);
+            if self.sent.len() <= id {
+                self.sent.resize(id + 1, None);
+            }
+            if self.sent[id] != Some(hash) {
+                write!(self.stdin, "add\t{}\t{}{}", id, text, ending)?;
+                self.sent[id] = Some(hash);
+            }
+            end = id + 1;
+        }
+        let query = self.escape(query.to_string());
+        write!(self.stdin, "match\t{}\t{}\t{}{}", offset, end, query, ending)?;
+        self.stdin.flush()?;
+
+        loop {
+            match self.answers.recv_timeout(STOP_POLL_INTERVAL) {
+                Ok(_) if self.abandoned > 0 => self.abandoned -= 1,
+                Ok(mut matched) => {
+                    matched.retain(|&(id, _, _)| (offset..end).contains(&id));
+                    return Ok(matched);
+                }
+                Err(RecvTimeoutError::Timeout) if stopped.load(Ordering::Relaxed) => {
+                    self.abandoned += 1;
+                    return Err(interrupted());
+                }
+                Err(RecvTimeoutError::Timeout) => {}
+                Err(RecvTimeoutError::Disconnected) => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::UnexpectedEof,
+                        "the matcher command exited",
+                    ))
+                }
+            }
+        }
+    }
+
+    /// the newlines end the records unless they are NUL terminated
+    fn escape(&self, text: String) -> String {
+        if self.ending == b'\n' && text.contains('\n') {
+            text.replace('\n', " ")
+        } else {
+            text
+        }
+    }
+}
+
+impl Drop for MatcherProcess {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+/// read the answers of the process until it exits
+fn read_answers(mut stdout: BufReader<ChildStdout>, ending: u8, tx_answer: Sender<Answer>) {
+    let mut matched = Vec::new();
+    let mut record = Vec::new();
+    loop {
+        record.clear();
+        match stdout.read_until(ending, &mut record) {
+            Ok(0) | Err(_) => return,
+            Ok(_) => {}
+        }
+        let record = String::from_utf8_lossy(&record);
+        let record = record.trim_end_matches(['\r', '\n', '\0']);
+        if record.is_empty() {
+            if tx_answer.send(std::mem::take(&mut matched)).is_err() {
+                return;
+            }
+            continue;
+        }
+        match parse_result(record) {
+            Some(result) => matched.push(result),
+            None => debug!("matcher command: invalid result {:?}", record),
+        }
+    }
+}
+
+fn hash_of(text: &str) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    text.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// parse `ID<TAB>SCORE[<TAB>RANGES]`
+fn parse_result(line: &str) -> Option<(usize, i32, Ranges)> {
+    let mut parts = line.split('\t');
+    let id = parts.next()?.trim().parse().ok()?;
+    let score = parts.next()?.trim().parse().ok()?;
+    let ranges = match parts.next().map(str::trim) {
+        None | Some("") => Vec::new(),
+        Some(ranges) => ranges
+            .split(',')
+            .map(|range| {
+                let (start, end) = range.split_once('-')?;
+                Some((start.trim().parse().ok()?, end.trim().parse().ok()?))
+            })
+            .collect::<Option<Vec<_>>>()?,
+    };
+    Some((id, score, ranges))
+}
+
+/// the text sent for an item, its `fields` joined, or the whole text without them
+fn text_of_fields(text: &str, fields: Option<&[(usize, usize)]>) -> String {
+    match fields {
+        Some(fields) => fields.iter().filter_map(|&(start, end)| text.get(start..end)).collect(),
+        None => text.to_string(),
+    }
+}
+
+/// the matched `ranges` of the text sent, the `fields` joined, as the ranges of the item's text
+fn ranges_in_item(ranges: &[(usize, usize)], fields: Option<&[(usize, usize)]>) -> Ranges {
+    let fields = match fields {
+        Some(fields) => fields,
+        None => return ranges.to_vec(),
+    };
+    let mut item_ranges = Vec::new();
+    for &(start, end) in ranges {
+        // a range could span several fields
+        let mut sent = 0;
+        for &(field_start, field_end) in fields {
+            let len = field_end - field_start;
+            let (from, to) = (start.max(sent), end.min(sent + len));
+            if from < to {
+                item_ranges.push((field_start + from - sent, field_start + to - sent));
+            }
+            sent += len;
+        }
+    }
+    item_ranges
+}
+
+//------------------------------------------------------------------------------
+/// Create the engines that ask the same external process, started once by `spawn`
+pub struct CommandEngineFactory {
+    process: Arc<Mutex<MatcherProcess>>,
+    rank_builder: Arc<RankBuilder>,
+}
+
+impl CommandEngineFactory {
+    /// start the command, with its stdin and stdout piped to skim, the records end with a NUL
+    /// instead of a newline if `read0`
+    pub fn spawn(mut command: Command, read0: bool) -> io::Result<Self> {
+        let mut child = command.stdin(Stdio::piped()).stdout(Stdio::piped()).spawn()?;
+        let stdin = BufWriter::new(child.stdin.take().expect("stdin should be piped"));
+        let stdout = BufReader::new(child.stdout.take().expect("stdout should be piped"));
+        let ending = if read0 { b'\0' } else { b'\n' };
+        let (tx_answer, answers) = channel();
+        thread::spawn(move || read_answers(stdout, ending, tx_answer));
+        Ok(Self {
+            process: Arc::new(Mutex::new(MatcherProcess {
+                child,
+                stdin,
+                answers,
+                abandoned: 0,
+                sent: Vec::new(),
+                ending,
+            })),
+            rank_builder: Default::default(),
+        })
+    }
+
+    pub fn rank_builder(mut self, rank_builder: Arc<RankBuilder>) -> Self {
+        self.rank_builder = rank_builder;
+        self
+    }
+
+    pub fn build(self) -> Self {
+        self
+    }
+}
+
+impl MatchEngineFactory for CommandEngineFactory {
+    /// the case is matched the way the process does, it's not told the `case`
+    fn create_engine_with_case(&self, query: &str, _case: CaseMatching) -> Box<dyn MatchEngine> {
+        Box::new(CommandEngine {
+            query: query.to_string(),
+            process: self.process.clone(),
+            rank_builder: self.rank_builder.clone(),
+            results: RwLock::new(HashMap::new()),
+        })
+    }
+}
+
+//------------------------------------------------------------------------------
+/// The items are matched by the process in `prepare_pool`, `match_item` only looks up the results
+/// of the item, by `key_of` it
+pub struct CommandEngine {
+    query: String,
+    process: Arc<Mutex<MatcherProcess>>,
+    rank_builder: Arc<RankBuilder>,
+    results: RwLock<HashMap<usize, (i32, Ranges)>>,
+}
+
+impl MatchEngine for CommandEngine {
+    fn prepare(&self, items: &mut dyn Iterator<Item = &Arc<dyn SkimItem>>) {
+        self.prepare_pool(0, items, None, &AtomicBool::new(false));
+    }
+
+    fn prepare_pool(
+        &self,
+        offset: usize,
+        items: &mut dyn Iterator<Item = &Arc<dyn SkimItem>>,
+        matching_ranges: Option<MatchingRanges>,
+        stopped: &AtomicBool,
+    ) {
+        let items: Vec<Arc<dyn SkimItem>> = items.cloned().collect();
+        // the fields of `change-nth`, or of `--nth` kept by the item
+        let fields_of = |item: &Arc<dyn SkimItem>| match matching_ranges {
+            Some(ranges_of) => Some(ranges_of(&item.text())),
+            None => item.get_matching_ranges().map(<[_]>::to_vec),
+        };
+        let texts = items
+            .iter()
+            .map(|item| text_of_fields(&item.text(), fields_of(item).as_deref()));
+        let matched = match self.process.lock() {
+            Ok(mut process) => process.request(&self.query, offset, texts, stopped),
+            Err(_) => return,
+        };
+        let matched = match matched {
+            Ok(matched) => matched,
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => return,
+            Err(err) => {
+                error!("matcher command: {}", err);
+                return;
+            }
+        };
+
+        let mut results = self.results.write().unwrap_or_else(|err| err.into_inner());
+        results.clear();
+        for (id, score, ranges) in matched {
+            let item = &items[id - offset];
+            let ranges = ranges_in_item(&ranges, fields_of(item).as_deref());
+            results.insert(key_of(item), (score, ranges));
+        }
+    }
+
+    fn match_item(&self, item: Arc<dyn SkimItem>) -> Option<MatchedItem> {
+        let results = self.results.read().unwrap_or_else(|err| err.into_inner());
+        let (score, ranges) = results.get(&key_of(&item))?;
+        let text = item.text();
+
+        let begin = ranges.first().map_or(0, |range| range.0);
+        let end = ranges.last().map_or(0, |range| range.1);
+        let indices = text
+            .char_indices()
+            .enumerate()
+            .filter(|(_, (byte, _))| ranges.iter().any(|&(start, end)| start <= *byte && *byte < end))
+            .map(|(idx, _)| idx)
+            .collect();
+        let rank = self.rank_builder.build_rank(*score, begin, end, text.len());
+        Some(
+            MatchedItem::builder(item.clone())
+                .score(*score)
+                .rank(rank)
+                .matched_range(MatchedRange::Chars(indices))
+                .build(),
+        )
+    }
+}
+
+impl Display for CommandEngine {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), Error> {
+        write!(f, "(Command: {})", self.query)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    // match the items containing the query, the score is the number of items sent so far
+    const SCRIPT: &str = r#"
+        added=0
+        while IFS= read -r line; do
+            case "$line" in
+            add*)
+                rest=${line#add	}; id=${rest%%	*}
+                eval "item_$id=\${rest#*	}"; added=$((added + 1));;
+            match*)
+                rest=${line#match	}; id=${rest%%	*}; rest=${rest#*	}; end=${rest%%	*}; query=${rest#*	}
+                while [ "$id" -lt "$end" ]; do
+                    eval "text=\$item_$id"
+                    case "$text" in *"$query"*) printf '%s\t%s\t0-2\n' "$id" "$added";; esac
+                    id=$((id + 1))
+                done
+                echo;;
+            esac
+        done
+    "#;
+
+    fn matched(engine: &dyn MatchEngine, items: &[Arc<dyn SkimItem>]) -> Vec<(String, i32, Option<MatchedRange>)> {
+        items
+            .iter()
+            .filter_map(|item| engine.match_item(item.clone()))
+            .map(|matched| (matched.item.text().to_string(), matched.score, matched.matched_range))
+            .collect()
+    }
+
+    #[test]
+    fn test_command_engine() {
+        let mut command = Command::new("sh");
+        command.arg("-c").arg(SCRIPT);
+        let factory = CommandEngineFactory::spawn(command, false).unwrap();
+        let stopped = AtomicBool::new(false);
+
+        let mut items: Vec<Arc<dyn SkimItem>> = vec![Arc::new("abc"), Arc::new("xbcd"), Arc::new("xyz")];
+        let engine = factory.create_engine("bc");
+        engine.prepare_pool(0, &mut items.iter(), None, &stopped);
+        assert_eq!(
+            matched(engine.as_ref(), &items),
+            vec![
+                ("abc".to_string(), 3, Some(MatchedRange::Chars(vec![0, 1]))),
+                ("xbcd".to_string(), 3, Some(MatchedRange::Chars(vec![0, 1]))),
+            ]
+        );
+
+        // the items are not sent again for the next query, only the new ones
+        let engine = factory.create_engine("yz");
+        engine.prepare_pool(0, &mut items.iter(), None, &stopped);
+        assert_eq!(matched(engine.as_ref(), &items[2..])[0].1, 3);
+        items.push(Arc::new("wxyz"));
+        let engine = factory.create_engine("yz");
+        engine.prepare_pool(3, &mut items[3..].iter(), None, &stopped);
+        assert_eq!(matched(engine.as_ref(), &items[3..])[0].1, 4);
+        // only the items of the request are matched
+        assert!(engine.match_item(items[2].clone()).is_none());
+
+        // the fields are sent instead, the ranges are of the fields
+        let engine = factory.create_engine("z");
+        let fields = |text: &str| vec![(2, text.len())];
+        engine.prepare_pool(0, &mut items.iter(), Some(&fields), &stopped);
+        assert_eq!(
+            matched(engine.as_ref(), &items),
+            vec![
+                ("xyz".to_string(), 8, Some(MatchedRange::Chars(vec![2]))),
+                ("wxyz".to_string(), 8, Some(MatchedRange::Chars(vec![2, 3]))),
+            ]
+        );
+        // the item wrapped for `change-nth` is looked up as the item itself
+        let wrapped: Arc<dyn SkimItem> = Arc::new(ItemWithFields {
+            item: items[2].clone(),
+            ranges: vec![(2, 3)],
+        });
+        assert!(engine.match_item(wrapped).is_some());
+
+        // the items of the same text are told apart
+        items.push(Arc::new("wxyz"));
+        let engine = factory.create_engine("yz");
+        engine.prepare_pool(4, &mut items[4..].iter(), None, &stopped);
+        assert!(engine.match_item(items[3].clone()).is_none());
+        assert!(engine.match_item(items[4].clone()).is_some());
+    }
+
+    #[test]
+    fn test_read0() {
+        let script = r#"
+            while IFS= read -r -d '' record; do
+                case "$record" in match*) printf '0\t1\0\0';; esac
+            done
+        "#;
+        let mut command = Command::new("bash");
+        command.arg("-c").arg(script);
+        let factory = CommandEngineFactory::spawn(command, true).unwrap();
+
+        let items: Vec<Arc<dyn SkimItem>> = vec![Arc::new("a\nb")];
+        let engine = factory.create_engine("a");
+        engine.prepare(&mut items.iter());
+        assert_eq!(
+            matched(engine.as_ref(), &items),
+            vec![("a\nb".to_string(), 1, Some(MatchedRange::Chars(vec![])))]
+        );
+    }
+
+    #[test]
+    fn test_stop() {
+        // answers every query with the query as the score, slowly
+        let script = r#"
+            while IFS= read -r line; do
+                case "$line" in match*) sleep 0.2; printf '0\t%s\n\n' "${line##*	}";; esac
+            done
+        "#;
+        let mut command = Command::new("sh");
+        command.arg("-c").arg(script);
+        let factory = CommandEngineFactory::spawn(command, false).unwrap();
+
+        let items: Vec<Arc<dyn SkimItem>> = vec![Arc::new("abc")];
+        let engine = factory.create_engine("1");
+        let stopped = Arc::new(AtomicBool::new(false));
+        let stop = stopped.clone();
+        thread::spawn(move || {
+            thread::sleep(Duration::from_millis(50));
+            stop.store(true, Ordering::Relaxed);
+        });
+        engine.prepare_pool(0, &mut items.iter(), None, &stopped);
+        assert!(engine.match_item(items[0].clone()).is_none());
+        assert_eq!(factory.process.lock().unwrap().abandoned, 1);
+
+        // the answer of the abandoned request is skipped
+        let engine = factory.create_engine("2");
+        engine.prepare_pool(0, &mut items.iter(), None, &AtomicBool::new(false));
+        assert_eq!(engine.match_item(items[0].clone()).unwrap().score, 2);
+        assert_eq!(factory.process.lock().unwrap().abandoned, 0);
+    }
+
+    #[test]
+    fn test_ranges_in_item() {
+        assert_eq!(ranges_in_item(&[(0, 2)], None), vec![(0, 2)]);
+        // "ab" of "ab cd ef" and "ef" are sent as "abef"
+        let fields = [(0, 2), (6, 8)];
+        assert_eq!(text_of_fields("ab cd ef", Some(&fields)), "abef");
+        assert_eq!(ranges_in_item(&[(1, 3)], Some(&fields)), vec![(1, 2), (6, 7)]);
+        assert_eq!(ranges_in_item(&[(2, 4), (0, 1)], Some(&fields)), vec![(6, 8), (0, 1)]);
+    }
+
+    #[test]
+    fn test_parse_result() {
+        assert_eq!(parse_result("3\t10"), Some((3, 10, vec![])));
+        assert_eq!(parse_result("3\t-1\t0-2,4-5"), Some((3, -1, vec![(0, 2), (4, 5)])));
+        assert_eq!(parse_result("x\t10"), None);
+        assert_eq!(parse_result("3\t10\t0-"), None);
+    }
+}
@@ -1,3 +1,5 @@
+use crate::engine::index::Required;
+use crate::engine::mask::CharMask;
 use crate::engine::util::{contains_upper, regex_match};
 use crate::item::{MatchedItem, MatchedRange, RankBuilder};
 use crate::{CaseMatching, MatchEngine, SkimItem};
@@ -70,6 +72,43 @@ impl ExactEngine {
     pub fn build(self) -> Self {
         self
     }
+
+    /// match `choice` against the query, return the byte range that matched.
+    /// An empty query matches everything with range `(0, 0)`, so does an inverse query that
+    /// does not match.
+    ///
+    /// ```
+    /// use skim::{ExactEngine, ExactMatchingParam};
+    ///
+    /// let mut param = ExactMatchingParam::default();
+    /// param.prefix = true;
+    /// let engine = ExactEngine::builder("src", param).build();
+    /// assert_eq!(Some((0, 3)), engine.match_text("src/lib.rs"));
+    /// assert_eq!(None, engine.match_text("tests/src.rs"));
+    /// ```
+    pub fn match_text(&self, choice: &str) -> Option<(usize, usize)> {
+        if self.query_regex.is_none() {
+            return Some((0, 0));
+        }
+
+        let matched = regex_match(choice, &self.query_regex);
+        if self.inverse {
+            matched.xor(Some((0, 0)))
+        } else {
+            matched
+        }
+    }
+}
+
+/// Exact match `choice` against `pattern`, return the byte range of `choice` that matched
+///
+/// ```
+/// use skim::{exact_match, ExactMatchingParam};
+///
+/// assert_eq!(Some((4, 10)), exact_match("src/engine/exact.rs", "engine", ExactMatchingParam::default()));
+/// ```
+pub fn exact_match(choice: &str, pattern: &str, param: ExactMatchingParam) -> Option<(usize, usize)> {
+    ExactEngine::builder(pattern, param).build().match_text(choice)
 }
 
 impl MatchEngine for ExactEngine {
@@ -102,11 +141,28 @@ impl MatchEngine for ExactEngine {
         let item_len = item_text.len();
         Some(
             MatchedItem::builder(item)
+                .score(score)
                 .rank(self.rank_builder.build_rank(score, begin, end, item_len))
                 .matched_range(MatchedRange::ByteRange(begin, end))
                 .build(),
         )
     }
+
+    fn required_chars(&self) -> CharMask {
+        if self.inverse {
+            CharMask::default()
+        } else {
+            CharMask::of_query(&self.query)
+        }
+    }
+
+    fn required_substrings(&self) -> Required {
+        if self.inverse {
+            Required::Nothing
+        } else {
+            Required::Substring(self.query.clone())
+        }
+    }
 }
 
 impl Display for ExactEngine {
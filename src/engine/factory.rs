@@ -5,13 +5,7 @@ use crate::engine::fuzzy::{FuzzyAlgorithm, FuzzyEngine};
 use crate::engine::regexp::RegexEngine;
 use crate::item::RankBuilder;
 use crate::{CaseMatching, MatchEngine, MatchEngineFactory};
-use regex::Regex;
 use std::sync::Arc;
-
-lazy_static! {
-    static ref RE_AND: Regex = Regex::new(r"([^ |]+( +\| +[^ |]*)+)|( +)").unwrap();
-    static ref RE_OR: Regex = Regex::new(r" +\| +").unwrap();
-}
 //------------------------------------------------------------------------------
 // Exact engine factory
 pub struct ExactOrFuzzyEngineFactory {
@@ -58,8 +52,12 @@ impl MatchEngineFactory for ExactOrFuzzyEngineFactory {
         // !^abc => items not starting with "abc"
         // !abc$ => items not ending with "abc"
         // !^abc$ => not "abc"
+        // =Abc => match "Abc" case sensitively, could be followed by the above, e.g. ='Abc
 
-        let mut query = query;
+        let (mut query, case) = match query.strip_prefix('=') {
+            Some(rest) if !rest.is_empty() => (rest, CaseMatching::Respect),
+            _ => (query, case),
+        };
         let mut exact = false;
         let mut param = ExactMatchingParam::default();
         param.case = case;
@@ -144,38 +142,67 @@ impl AndOrEngineFactory {
 
     fn parse_or(&self, query: &str, case: CaseMatching) -> Box<dyn MatchEngine> {
         if query.trim().is_empty() {
-            self.inner.create_engine_with_case(query, case)
-        } else {
-            Box::new(
-                OrEngine::builder()
-                    .engines(RE_OR.split(query).map(|q| self.parse_and(q, case)).collect())
-                    .build(),
-            )
+            return self.inner.create_engine_with_case(query, case);
         }
+
+        let terms = split_terms(query);
+        let mut alternatives: Vec<&[Option<String>]> =
+            terms.split(Option::is_none).filter(|and| !and.is_empty()).collect();
+        if alternatives.is_empty() {
+            alternatives.push(&[]);
+        }
+        Box::new(
+            OrEngine::builder()
+                .engines(alternatives.into_iter().map(|and| self.parse_and(and, case)).collect())
+                .build(),
+        )
     }
 
-    fn parse_and(&self, query: &str, case: CaseMatching) -> Box<dyn MatchEngine> {
-        let query_trim = query.trim_matches(|c| c == ' ' || c == '|');
-        let mut engines = vec![];
-        let mut last = 0;
-        for mat in RE_AND.find_iter(query_trim) {
-            let (start, end) = (mat.start(), mat.end());
-            let term = query_trim[last..start].trim_matches(|c| c == ' ' || c == '|');
-            if !term.is_empty() {
-                engines.push(self.inner.create_engine_with_case(term, case));
-            }
+    fn parse_and(&self, terms: &[Option<String>], case: CaseMatching) -> Box<dyn MatchEngine> {
+        let engines = terms
+            .iter()
+            .flatten()
+            .map(|term| self.inner.create_engine_with_case(term, case))
+            .collect();
+        Box::new(AndEngine::builder().engines(engines).build())
+    }
+}
 
-            if !mat.as_str().trim().is_empty() {
-                engines.push(self.parse_or(mat.as_str().trim(), case));
+/// Split the query by the spaces into terms, except the escaped spaces (`foo\ bar`) and the ones of
+/// a term quoted as a whole (`"foo bar"`, the closing quote could be omitted), `\"` is a quote. A
+/// bare `|` separates the alternatives, it's `None`.
+fn split_terms(query: &str) -> Vec<Option<String>> {
+    let mut terms = vec![];
+    let mut chars = query.chars().peekable();
+    loop {
+        while chars.next_if_eq(&' ').is_some() {}
+        let quoted = chars.next_if_eq(&'"').is_some();
+        if !quoted && chars.peek().is_none() {
+            return terms;
+        }
+
+        let mut term = String::new();
+        while let Some(ch) = chars.next() {
+            match ch {
+                '\\' if matches!(chars.peek(), Some(' ') | Some('"')) => term.extend(chars.next()),
+                '"' if quoted => break,
+                ' ' if !quoted => break,
+                ch => term.push(ch),
             }
-            last = end;
         }
 
-        let term = query_trim[last..].trim_matches(|c| c == ' ' || c == '|');
-        if !term.is_empty() {
-            engines.push(self.inner.create_engine_with_case(term, case));
+        if quoted {
+            if !term.is_empty() {
+                terms.push(Some(term));
+            }
+        } else if term == "|" {
+            terms.push(None);
+        } else {
+            let term = term.trim_matches('|');
+            if !term.is_empty() {
+                terms.push(Some(term.to_string()));
+            }
         }
-        Box::new(AndEngine::builder().engines(engines).build())
     }
 }
 
@@ -186,6 +213,70 @@ impl MatchEngineFactory for AndOrEngineFactory {
 }
 
 //------------------------------------------------------------------------------
+/// The whole query is one case-aware exact substring, no term splitting and no prefixes, for
+/// pasting IDs and URLs that contain the characters of the extended search syntax
+pub struct LiteralEngineFactory {
+    rank_builder: Arc<RankBuilder>,
+}
+
+impl LiteralEngineFactory {
+    pub fn builder() -> Self {
+        Self {
+            rank_builder: Default::default(),
+        }
+    }
+
+    pub fn rank_builder(mut self, rank_builder: Arc<RankBuilder>) -> Self {
+        self.rank_builder = rank_builder;
+        self
+    }
+
+    pub fn build(self) -> Self {
+        self
+    }
+}
+
+impl MatchEngineFactory for LiteralEngineFactory {
+    fn create_engine_with_case(&self, query: &str, case: CaseMatching) -> Box<dyn MatchEngine> {
+        if query.is_empty() {
+            return Box::new(
+                MatchAllEngine::builder()
+                    .rank_builder(self.rank_builder.clone())
+                    .build(),
+            );
+        }
+
+        let mut param = ExactMatchingParam::default();
+        param.case = case;
+        Box::new(
+            ExactEngine::builder(query, param)
+                .rank_builder(self.rank_builder.clone())
+                .build(),
+        )
+    }
+}
+
+//------------------------------------------------------------------------------
+/// `\ ` is not a valid escape of the regex syntax, accept it as the space it means in the other
+/// modes, e.g. `foo\ bar` => `foo bar`, but `foo\\ bar` is left as is
+fn unescape_spaces(query: &str) -> String {
+    let mut unescaped = String::with_capacity(query.len());
+    let mut chars = query.chars();
+    while let Some(ch) = chars.next() {
+        match ch {
+            '\\' => match chars.next() {
+                Some(' ') => unescaped.push(' '),
+                next => {
+                    unescaped.push('\\');
+                    unescaped.extend(next);
+                }
+            },
+            ch => unescaped.push(ch),
+        }
+    }
+    unescaped
+}
+
 pub struct RegexEngineFactory {
     rank_builder: Arc<RankBuilder>,
 }
@@ -210,7 +301,7 @@ impl RegexEngineFactory {
 impl MatchEngineFactory for RegexEngineFactory {
     fn create_engine_with_case(&self, query: &str, case: CaseMatching) -> Box<dyn MatchEngine> {
         Box::new(
-            RegexEngine::builder(query, case)
+            RegexEngine::builder(&unescape_spaces(query), case)
                 .rank_builder(self.rank_builder.clone())
                 .build(),
         )
@@ -246,6 +337,21 @@ mod test {
         let x = exact_or_fuzzy.create_engine("!^abc$");
         assert_eq!(format!("{}", x), "(Exact|!(?i)^abc$)");
 
+        let x = exact_or_fuzzy.create_engine("='Abc");
+        assert_eq!(format!("{}", x), "(Exact|Abc)");
+
+        let x = exact_or_fuzzy.create_engine("=!^abc");
+        assert_eq!(format!("{}", x), "(Exact|!^abc)");
+
+        let x = exact_or_fuzzy.create_engine("=");
+        assert_eq!(format!("{}", x), "(Fuzzy: =)");
+
+        let x = exact_or_fuzzy.create_engine("'=abc");
+        assert_eq!(format!("{}", x), "(Exact|(?i)=abc)");
+
+        let x = exact_or_fuzzy.create_engine("==abc");
+        assert_eq!(format!("{}", x), "(Fuzzy: =abc)");
+
         let regex_factory = RegexEngineFactory::builder();
         let and_or_factory = AndOrEngineFactory::new(exact_or_fuzzy);
 
@@ -255,7 +361,65 @@ mod test {
             "(Or: (And: (Exact|(?i)abc)), (And: (Fuzzy: def), (Exact|(?i)^gh), (Fuzzy: ij)), (And: (Fuzzy: kl), (Fuzzy: mn)))"
         );
 
+        let x = and_or_factory.create_engine_with_case("='Abc ^gh", CaseMatching::Ignore);
+        assert_eq!(format!("{}", x), "(Or: (And: (Exact|Abc), (Exact|(?i)^gh)))");
+
+        let x = and_or_factory.create_engine(r#"'abc\ def "^gh | ij" kl\"mn"#);
+        assert_eq!(
+            format!("{}", x),
+            r#"(Or: (And: (Exact|(?i)abc def), (Exact|(?i)^gh \| ij), (Fuzzy: kl"mn)))"#
+        );
+
+        let x = and_or_factory.create_engine(r#"a\  | "b c"#);
+        assert_eq!(format!("{}", x), "(Or: (And: (Fuzzy: a )), (And: (Fuzzy: b c)))");
+
+        let literal_factory = LiteralEngineFactory::builder();
+        let x = literal_factory.create_engine("!^a 'b | c$");
+        assert_eq!(format!("{}", x), r"(Exact|(?i)!\^a 'b \| c\$)");
+
+        let x = regex_factory.create_engine(r"a\ b\\ c\d");
+        assert_eq!(format!("{}", x), r"(Regex: a b\\ c\d)");
+
         let x = regex_factory.create_engine("'abc | def ^gh ij | kl mn");
         assert_eq!(format!("{}", x), "(Regex: 'abc | def ^gh ij | kl mn)");
     }
+    #[test]
+    fn test_literal_engine_match() {
+        use super::*;
+        use crate::item::MatchedRange;
+        use crate::SkimItem;
+
+        let literal_factory = LiteralEngineFactory::builder();
+        let range_of = |query: &str, case: CaseMatching, text: &str| {
+            let item: Arc<dyn SkimItem> = Arc::new(text.to_string());
+            literal_factory
+                .create_engine_with_case(query, case)
+                .match_item(item)
+                .map(|matched| matched.matched_range)
+        };
+        let byte_range = |start, end| Some(Some(MatchedRange::ByteRange(start, end)));
+
+        // the special chars of the other modes are matched as they are
+        let url = "https://example.com/a?b=1&c=$d|e";
+        assert_eq!(range_of("?b=1&c=$d|e", CaseMatching::Smart, url), byte_range(21, 32));
+        assert_eq!(range_of("^https", CaseMatching::Smart, url), None);
+        assert_eq!(range_of("!abc", CaseMatching::Smart, "x!abc"), byte_range(1, 5));
+        assert_eq!(range_of("'a\\b", CaseMatching::Smart, "'a\\b"), byte_range(0, 4));
+        assert_eq!(range_of("a.c", CaseMatching::Smart, "abc"), None);
+
+        // the spaces are part of the query, not term separators
+        assert_eq!(range_of("b c", CaseMatching::Smart, "a b c"), byte_range(2, 5));
+        assert_eq!(range_of("b c", CaseMatching::Smart, "c b"), None);
+        assert_eq!(range_of(" ", CaseMatching::Smart, "a b"), byte_range(1, 2));
+
+        // the case modes
+        assert_eq!(range_of("abc", CaseMatching::Smart, "xABC"), byte_range(1, 4));
+        assert_eq!(range_of("Abc", CaseMatching::Smart, "xabc"), None);
+        assert_eq!(range_of("Abc", CaseMatching::Smart, "xAbc"), byte_range(1, 4));
+        assert_eq!(range_of("abc", CaseMatching::Respect, "xABC"), None);
+        assert_eq!(range_of("ABC", CaseMatching::Ignore, "xabc"), byte_range(1, 4));
+
+        // the empty query matches everything
+        assert!(range_of("", CaseMatching::Smart, "abc").is_some());
+    }
 }
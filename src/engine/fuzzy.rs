@@ -5,6 +5,7 @@ use fuzzy_matcher::clangd::ClangdMatcher;
 use fuzzy_matcher::skim::SkimMatcherV2;
 use fuzzy_matcher::FuzzyMatcher;
 
+use crate::engine::mask::CharMask;
 use crate::item::{MatchedItem, MatchedRange, RankBuilder};
 use crate::SkimItem;
 use crate::{CaseMatching, MatchEngine};
@@ -112,6 +113,20 @@ impl FuzzyEngine {
         FuzzyEngineBuilder::default()
     }
 
+    /// match `choice` against the query, return the score and the char indices that matched
+    ///
+    /// ```
+    /// use skim::FuzzyEngine;
+    ///
+    /// let engine = FuzzyEngine::builder().query("lbr").build();
+    /// let (_score, indices) = engine.match_text("src/lib.rs").unwrap();
+    /// assert_eq!(indices, vec![4, 6, 8]);
+    /// assert_eq!(None, engine.match_text("main.rs"));
+    /// ```
+    pub fn match_text(&self, choice: &str) -> Option<(i64, Vec<usize>)> {
+        self.fuzzy_match(choice, &self.query)
+    }
+
     fn fuzzy_match(&self, choice: &str, pattern: &str) -> Option<(i64, Vec<usize>)> {
         if pattern.is_empty() {
             return Some((0, Vec::new()));
@@ -123,6 +138,42 @@ impl FuzzyEngine {
     }
 }
 
+/// Fuzzy match `choice` against `pattern` with the default algorithm and smart case.
+/// Return the score (higher is better) and the char indices of `choice` that matched.
+///
+/// ```
+/// use skim::fuzzy_match;
+///
+/// let (_score, indices) = fuzzy_match("src/engine/fuzzy.rs", "efz").unwrap();
+/// assert_eq!(indices, vec![4, 11, 13]);
+/// assert_eq!(None, fuzzy_match("abc", "abd"));
+/// ```
+pub fn fuzzy_match(choice: &str, pattern: &str) -> Option<(i64, Vec<usize>)> {
+    fuzzy_match_with(choice, pattern, FuzzyAlgorithm::default(), CaseMatching::default())
+}
+
+/// Same as `fuzzy_match`, with the given algorithm and case matching
+///
+/// ```
+/// use skim::{fuzzy_match_with, CaseMatching, FuzzyAlgorithm};
+///
+/// assert!(fuzzy_match_with("Makefile", "make", FuzzyAlgorithm::Clangd, CaseMatching::Ignore).is_some());
+/// assert_eq!(None, fuzzy_match_with("Makefile", "make", FuzzyAlgorithm::Clangd, CaseMatching::Respect));
+/// ```
+pub fn fuzzy_match_with(
+    choice: &str,
+    pattern: &str,
+    algorithm: FuzzyAlgorithm,
+    case: CaseMatching,
+) -> Option<(i64, Vec<usize>)> {
+    FuzzyEngine::builder()
+        .query(pattern)
+        .algorithm(algorithm)
+        .case(case)
+        .build()
+        .match_text(choice)
+}
+
 impl MatchEngine for FuzzyEngine {
     fn match_item(&self, item: Arc<dyn SkimItem>) -> Option<MatchedItem> {
         // iterate over all matching fields:
@@ -155,14 +206,21 @@ impl MatchEngine for FuzzyEngine {
         let begin = *matched_range.get(0).unwrap_or(&0);
         let end = *matched_range.last().unwrap_or(&0);
 
+        // the score of a long item could overflow i32, clamp it instead of wrapping
+        let score = score.clamp(i32::MIN as i64, i32::MAX as i64) as i32;
         let item_len = item_text.len();
         Some(
             MatchedItem::builder(item)
-                .rank(self.rank_builder.build_rank(score as i32, begin, end, item_len))
+                .score(score)
+                .rank(self.rank_builder.build_rank(score, begin, end, item_len))
                 .matched_range(MatchedRange::Chars(matched_range))
                 .build(),
         )
     }
+
+    fn required_chars(&self) -> CharMask {
+        CharMask::of_query(&self.query)
+    }
 }
 
 impl Display for FuzzyEngine {
@@ -0,0 +1,238 @@
+//! A trigram index of the items, used to skip the items that can't match the exact and regex
+//! terms of a query without running the matching algorithm.
+//!
+//! A term that requires a substring could only match the items that contain all the trigrams
+//! (the 3 consecutive chars) of it. The index keeps the items containing each trigram, so the
+//! candidates of a term are the intersection of the items of its trigrams. Case is folded like
+//! `CharMask`, the non-ASCII chars that don't fold to ASCII ones split the trigrams, so that the
+//! index works for all the case matching modes.
+//!
+//! Fuzzy terms don't require their chars to be consecutive, they are left to `CharMask`.
+#[cfg(feature = "tui")]
+use std::collections::HashMap;
+
+use regex_syntax::hir::{Hir, HirKind, Literal, RepetitionKind, RepetitionRange};
+use regex_syntax::Parser;
+
+#[cfg(feature = "tui")]
+use crate::engine::mask::fold_to_ascii;
+
+/// The substrings that every item matched by an engine contains
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Required {
+    /// nothing is known, every item could match, e.g. a fuzzy or an inverse term
+    Nothing,
+    Substring(String),
+    /// all of them, e.g. the terms of an AND query
+    All(Vec<Required>),
+    /// either of them, e.g. the terms of an OR query
+    Any(Vec<Required>),
+}
+
+impl Required {
+    /// the substrings of the literals in a regex, nothing for an invalid one
+    pub fn of_regex(pattern: &str) -> Self {
+        match Parser::new().parse(pattern) {
+            Ok(hir) => of_hir(&hir),
+            Err(_) => Required::Nothing,
+        }
+    }
+}
+
+fn of_hir(hir: &Hir) -> Required {
+    match hir.kind() {
+        HirKind::Literal(Literal::Unicode(ch)) => Required::Substring(ch.to_string()),
+        HirKind::Group(group) => of_hir(&group.hir),
+        HirKind::Repetition(repetition) => {
+            let at_least_once = match repetition.kind {
+                RepetitionKind::ZeroOrOne | RepetitionKind::ZeroOrMore => false,
+                RepetitionKind::OneOrMore => true,
+                RepetitionKind::Range(RepetitionRange::Exactly(min))
+                | RepetitionKind::Range(RepetitionRange::AtLeast(min))
+                | RepetitionKind::Range(RepetitionRange::Bounded(min, _)) => min > 0,
+            };
+            if at_least_once {
+                of_hir(&repetition.hir)
+            } else {
+                Required::Nothing
+            }
+        }
+        HirKind::Concat(hirs) => {
+            // the consecutive literals make a substring, the rest are required on their own
+            let mut required = Vec::new();
+            let mut substring = String::new();
+            for hir in hirs {
+                match hir.kind() {
+                    HirKind::Literal(Literal::Unicode(ch)) => substring.push(*ch),
+                    _ => {
+                        required.push(Required::Substring(std::mem::take(&mut substring)));
+                        required.push(of_hir(hir));
+                    }
+                }
+            }
+            required.push(Required::Substring(substring));
+            Required::All(required)
+        }
+        HirKind::Alternation(hirs) => Required::Any(hirs.iter().map(of_hir).collect()),
+        _ => Required::Nothing,
+    }
+}
+
+/// The items containing each trigram, for at most 65536 items
+#[cfg(feature = "tui")]
+#[derive(Debug, Default)]
+pub(crate) struct TrigramIndex {
+    len: usize,
+    /// the trigram => the indices of the items, in ascending order
+    postings: HashMap<u32, Vec<u16>>,
+}
+
+#[cfg(feature = "tui")]
+impl TrigramIndex {
+    pub fn of_texts<T: AsRef<str>>(texts: impl Iterator<Item = T>) -> Self {
+        let mut index = TrigramIndex::default();
+        for (idx, text) in texts.enumerate() {
+            assert!(idx <= u16::MAX as usize, "too many items to index");
+            for trigram in trigrams(text.as_ref()) {
+                let items = index.postings.entry(trigram).or_default();
+                // the trigrams repeated in an item are added once
+                if items.last() != Some(&(idx as u16)) {
+                    items.push(idx as u16);
+                }
+            }
+            index.len = idx + 1;
+        }
+        index
+    }
+
+    /// the items that could match as bits by their indices, `None` if all of them could
+    pub fn candidates(&self, required: &Required) -> Option<Vec<u64>> {
+        match required {
+            Required::Nothing => None,
+            Required::Substring(substring) => {
+                let mut trigrams = trigrams(substring);
+                let mut bits = self.bits_of(self.items_of(trigrams.next()?));
+                for trigram in trigrams {
+                    intersect(&mut bits, &self.bits_of(self.items_of(trigram)));
+                }
+                Some(bits)
+            }
+            Required::All(all) => {
+                all.iter()
+                    .filter_map(|required| self.candidates(required))
+                    .reduce(|mut bits, other| {
+                        intersect(&mut bits, &other);
+                        bits
+                    })
+            }
+            Required::Any(any) => {
+                let mut bits = self.bits_of(&[]);
+                for required in any {
+                    let other = self.candidates(required)?;
+                    bits.iter_mut().zip(other).for_each(|(word, other)| *word |= other);
+                }
+                Some(bits)
+            }
+        }
+    }
+
+    fn items_of(&self, trigram: u32) -> &[u16] {
+        self.postings.get(&trigram).map_or(&[], Vec::as_slice)
+    }
+
+    fn bits_of(&self, items: &[u16]) -> Vec<u64> {
+        let mut bits = vec![0; self.len.div_ceil(64)];
+        for &idx in items {
+            bits[idx as usize / 64] |= 1 << (idx % 64);
+        }
+        bits
+    }
+}
+
+#[cfg(feature = "tui")]
+fn intersect(bits: &mut [u64], other: &[u64]) {
+    bits.iter_mut().zip(other).for_each(|(word, other)| *word &= other);
+}
+
+/// the trigrams of the case folded text, the chars that don't fold to ASCII split them
+#[cfg(feature = "tui")]
+fn trigrams(text: &str) -> impl Iterator<Item = u32> + '_ {
+    let mut window = 0u32;
+    let mut run = 0;
+    text.chars().filter_map(move |ch| match fold(ch) {
+        Some(byte) => {
+            window = (window << 8 | byte as u32) & 0xff_ffff;
+            run += 1;
+            Some(window).filter(|_| run >= 3)
+        }
+        None => {
+            run = 0;
+            None
+        }
+    })
+}
+
+#[cfg(feature = "tui")]
+fn fold(ch: char) -> Option<u8> {
+    if ch.is_ascii() {
+        return Some(ch.to_ascii_lowercase() as u8);
+    }
+    let mut lower = ch.to_lowercase();
+    match (lower.next(), lower.next()) {
+        (Some(lower), None) if lower.is_ascii() => Some(lower as u8),
+        _ => fold_to_ascii(ch).map(|ch| ch as u8),
+    }
+}
+
+// the tests go through the index
+#[cfg(all(test, feature = "tui"))]
+mod test {
+    use super::*;
+
+    fn candidates(texts: &[&str], required: &Required) -> Option<Vec<usize>> {
+        let bits = TrigramIndex::of_texts(texts.iter()).candidates(required)?;
+        Some(
+            (0..texts.len())
+                .filter(|&idx| bits[idx / 64] & (1 << (idx % 64)) != 0)
+                .collect(),
+        )
+    }
+
+    fn substring(text: &str) -> Required {
+        Required::Substring(text.to_string())
+    }
+
+    #[test]
+    fn test_candidates() {
+        let texts = ["src/Main.rs", "src/lib.rs", "README.md", "\u{212A}elvin"];
+        assert_eq!(candidates(&texts, &substring("main")), Some(vec![0]));
+        assert_eq!(candidates(&texts, &substring("SRC/")), Some(vec![0, 1]));
+        assert_eq!(candidates(&texts, &substring("kelvin")), Some(vec![3]));
+        assert_eq!(candidates(&texts, &substring("mainx")), Some(vec![]));
+        // too short to have a trigram
+        assert_eq!(candidates(&texts, &substring("rs")), None);
+        // the non-ASCII chars split the trigrams
+        assert_eq!(candidates(&texts, &substring("aé")), None);
+        assert_eq!(candidates(&texts, &Required::Nothing), None);
+
+        let all = Required::All(vec![substring("src"), substring("lib"), Required::Nothing]);
+        assert_eq!(candidates(&texts, &all), Some(vec![1]));
+        let any = Required::Any(vec![substring("lib"), substring("readme")]);
+        assert_eq!(candidates(&texts, &any), Some(vec![1, 2]));
+        let any = Required::Any(vec![substring("lib"), substring("rs")]);
+        assert_eq!(candidates(&texts, &any), None);
+    }
+
+    #[test]
+    fn test_required_of_regex() {
+        let texts = ["src/main.rs", "src/lib.rs", "README.md", "Cargo.toml"];
+        let of_regex = |pattern: &str| candidates(&texts, &Required::of_regex(pattern));
+        assert_eq!(of_regex(r"main\.rs$"), Some(vec![0]));
+        assert_eq!(of_regex(r"^src/(main|lib)"), Some(vec![0, 1]));
+        assert_eq!(of_regex(r"(?:read)+me"), Some(vec![2]));
+        assert_eq!(of_regex(r"README|toml"), Some(vec![2, 3]));
+        assert_eq!(of_regex(r"mai?n"), None);
+        assert_eq!(of_regex(r"(cargo)?[.]md"), None);
+        assert_eq!(of_regex(r"x(y"), None);
+    }
+}
@@ -0,0 +1,107 @@
+//! A bitmask of the chars a text contains, used to skip the items that can't match a query
+//! without running the matching algorithm.
+//!
+//! Each item gets a mask when it's added to the item pool with `--index`. A query term requires
+//! all of its chars to be in the item, whether it's matched fuzzily or exactly, so an item whose
+//! mask doesn't contain the mask of the term could be skipped. Case is folded, so the masks work
+//! for all the case matching modes.
+
+// 'a'..='z' => 0..26, '0'..='9' => 26..36, other ASCII => 36..62, non-ASCII => 63
+const DIGIT_BIT: u32 = 26;
+const OTHER_BIT: u32 = 36;
+const NUM_OTHER_BITS: u32 = 26;
+const NON_ASCII_BIT: u32 = 63;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct CharMask(u64);
+
+impl CharMask {
+    /// the mask of all the chars in an item's text
+    pub fn of_text(text: &str) -> Self {
+        let mut mask = 0;
+        for ch in text.chars() {
+            if ch.is_ascii() {
+                mask |= ascii_bit(ch);
+            } else {
+                mask |= 1 << NON_ASCII_BIT;
+                // the non-ASCII chars that are matched by ASCII ones when ignoring case, e.g. the
+                // Kelvin sign and 'k', long s and 's'
+                for lower in ch.to_lowercase().chain(fold_to_ascii(ch)).filter(char::is_ascii) {
+                    mask |= ascii_bit(lower);
+                }
+            }
+        }
+        CharMask(mask)
+    }
+
+    /// the mask of the chars an item must contain to match the query term
+    pub fn of_query(query: &str) -> Self {
+        let mut mask = 0;
+        for ch in query.chars() {
+            if ch.is_ascii() {
+                mask |= ascii_bit(ch);
+            } else if !ch.to_lowercase().any(|lower| lower.is_ascii()) && fold_to_ascii(ch).is_none() {
+                mask |= 1 << NON_ASCII_BIT;
+            }
+        }
+        CharMask(mask)
+    }
+
+    /// an item requires both
+    pub fn union(self, other: Self) -> Self {
+        CharMask(self.0 | other.0)
+    }
+
+    /// an item requires either
+    pub fn intersection(self, other: Self) -> Self {
+        CharMask(self.0 & other.0)
+    }
+
+    pub fn contains(self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+}
+
+fn ascii_bit(ch: char) -> u64 {
+    let bit = match ch.to_ascii_lowercase() {
+        ch @ 'a'..='z' => ch as u32 - 'a' as u32,
+        ch @ '0'..='9' => DIGIT_BIT + (ch as u32 - '0' as u32),
+        ch => OTHER_BIT + (ch as u32 % NUM_OTHER_BITS),
+    };
+    1 << bit
+}
+
+// the non-ASCII chars whose case folding is ASCII but lowercase isn't
+pub(crate) fn fold_to_ascii(ch: char) -> Option<char> {
+    match ch {
+        '\u{017F}' => Some('s'),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_char_mask() {
+        let item = CharMask::of_text("src/Main.rs");
+        assert!(item.contains(CharMask::of_query("smr")));
+        assert!(item.contains(CharMask::of_query("MAIN")));
+        assert!(item.contains(CharMask::of_query("")));
+        assert!(!item.contains(CharMask::of_query("mainx")));
+        assert!(!item.contains(CharMask::of_query("main2")));
+        assert!(!item.contains(CharMask::of_query("é")));
+
+        // case folding of non-ASCII chars
+        assert!(CharMask::of_text("\u{212A}elvin").contains(CharMask::of_query("kelvin")));
+        assert!(CharMask::of_text("\u{017F}").contains(CharMask::of_query("s")));
+        assert!(CharMask::of_text("s").contains(CharMask::of_query("\u{017F}")));
+        assert!(CharMask::of_text("É").contains(CharMask::of_query("é")));
+
+        let either = CharMask::of_query("ab").intersection(CharMask::of_query("bc"));
+        assert!(CharMask::of_text("b").contains(either));
+        let both = CharMask::of_query("ab").union(CharMask::of_query("bc"));
+        assert!(!CharMask::of_text("ab").contains(both));
+    }
+}
@@ -1,7 +1,10 @@
 pub mod all;
 pub mod andor;
+pub mod command;
 pub mod exact;
 pub mod factory;
 pub mod fuzzy;
+pub mod index;
+pub mod mask;
 pub mod regexp;
 mod util;
@@ -3,6 +3,7 @@ use std::sync::Arc;
 
 use regex::Regex;
 
+use crate::engine::index::Required;
 use crate::engine::util::regex_match;
 use crate::item::{MatchedItem, MatchedRange, RankBuilder};
 use crate::SkimItem;
@@ -14,6 +15,7 @@ use std::cmp::min;
 #[derive(Debug)]
 pub struct RegexEngine {
     query_regex: Option<Regex>,
+    required: Required,
     rank_builder: Arc<RankBuilder>,
 }
 
@@ -28,9 +30,16 @@ impl RegexEngine {
         }
 
         query_builder.push_str(query);
+        let query_regex = Regex::new(&query_builder).ok();
+        let required = if query_regex.is_some() {
+            Required::of_regex(query)
+        } else {
+            Required::Nothing
+        };
 
         RegexEngine {
-            query_regex: Regex::new(&query_builder).ok(),
+            query_regex,
+            required,
             rank_builder: Default::default(),
         }
     }
@@ -43,6 +52,24 @@ impl RegexEngine {
     pub fn build(self) -> Self {
         self
     }
+
+    /// match `choice` against the regex, return the byte range that matched.
+    /// An invalid regex matches everything with range `(0, 0)`.
+    ///
+    /// ```
+    /// use skim::{CaseMatching, RegexEngine};
+    ///
+    /// let engine = RegexEngine::builder(r"\d+$", CaseMatching::Smart).build();
+    /// assert_eq!(Some((3, 6)), engine.match_text("v0.842"));
+    /// assert_eq!(None, engine.match_text("main"));
+    /// ```
+    pub fn match_text(&self, choice: &str) -> Option<(usize, usize)> {
+        if self.query_regex.is_none() {
+            return Some((0, 0));
+        }
+
+        regex_match(choice, &self.query_regex)
+    }
 }
 
 impl MatchEngine for RegexEngine {
@@ -72,11 +99,16 @@ impl MatchEngine for RegexEngine {
 
         Some(
             MatchedItem::builder(item)
+                .score(score)
                 .rank(self.rank_builder.build_rank(score, begin, end, item_len))
                 .matched_range(MatchedRange::ByteRange(begin, end))
                 .build(),
         )
     }
+
+    fn required_substrings(&self) -> Required {
+        self.required.clone()
+    }
 }
 
 impl Display for RegexEngine {
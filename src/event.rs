@@ -12,6 +12,12 @@ pub enum Event {
     EvInputKey(Key),
     EvInputInvalid,
     EvHeartBeat,
+    // report the selection to the handle of the host application
+    EvReportSelection,
+    // a command of `execute-bg` exited with the code, none if it's killed or failed to start
+    EvExecuteBgDone(Option<i32>),
+    // the command of `--clipboard-cmd` failed to copy the text, copy it by OSC 52 instead
+    EvClipboardCmdFailed(String),
 
     // user bind actions
     EvActAbort,
@@ -24,6 +30,8 @@ pub enum Event {
     EvActBackwardWord,
     EvActBeginningOfLine,
     EvActCancel,
+    EvActChangeNth(String),
+    EvActChangeQuery(String),
     EvActClearScreen,
     EvActDeleteChar,
     EvActDeleteCharEOF,
@@ -31,7 +39,9 @@ pub enum Event {
     EvActDown(i32),
     EvActEndOfLine,
     EvActExecute(String),
+    EvActExecuteBg(String),
     EvActExecuteSilent(String),
+    EvActFirst,
     EvActForwardChar,
     EvActForwardWord,
     EvActIfQueryEmpty(String),
@@ -40,11 +50,15 @@ pub enum Event {
     EvActIgnore,
     EvActKillLine,
     EvActKillWord,
+    EvActLast,
+    EvActLeft(i32),
     EvActNextHistory,
     EvActHalfPageDown(i32),
     EvActHalfPageUp(i32),
     EvActPageDown(i32),
     EvActPageUp(i32),
+    EvActParentDir,
+    EvActPreview(String),
     EvActPreviewUp(i32),
     EvActPreviewDown(i32),
     EvActPreviewLeft(i32),
@@ -52,24 +66,34 @@ pub enum Event {
     EvActPreviewPageUp(i32),
     EvActPreviewPageDown(i32),
     EvActPreviousHistory,
+    EvActPut(String),
     EvActRedraw,
+    EvActRefreshPreview,
+    EvActReload(Option<String>),
+    EvActRight(i32),
     EvActRotateMode,
     EvActScrollLeft(i32),
     EvActScrollRight(i32),
     EvActSelectAll,
     EvActSelectRow(usize),
+    EvActSetQueryCursor(usize),
     EvActToggle,
     EvActToggleAll,
+    EvActToggleRows(usize, usize),
     EvActToggleIn,
     EvActToggleInteractive,
+    EvActToggleNth(String),
     EvActToggleOut,
     EvActTogglePreview,
     EvActTogglePreviewWrap,
     EvActToggleSort,
+    EvActTransformQuery(String),
     EvActUnixLineDiscard,
     EvActUnixWordRubout,
     EvActUp(i32),
     EvActYank,
+    EvActYankItem,
+    EvActYankQuery,
 }
 
 bitflags! {
@@ -97,6 +121,8 @@ pub fn parse_event(action: &str, arg: Option<String>) -> Option<Event> {
         "backward-word"        =>   Some(Event::EvActBackwardWord),
         "beginning-of-line"    =>   Some(Event::EvActBeginningOfLine),
         "cancel"               =>   Some(Event::EvActCancel),
+        "change-nth"           =>   Some(Event::EvActChangeNth(arg.unwrap_or_default())),
+        "change-query"         =>   Some(Event::EvActChangeQuery(arg.unwrap_or_default())),
         "clear-screen"         =>   Some(Event::EvActClearScreen),
         "delete-char"          =>   Some(Event::EvActDeleteChar),
         "delete-charEOF"       =>   Some(Event::EvActDeleteCharEOF),
@@ -104,7 +130,9 @@ pub fn parse_event(action: &str, arg: Option<String>) -> Option<Event> {
         "down"                 =>   Some(Event::EvActDown(arg.and_then(|s|s.parse().ok()).unwrap_or(1))),
         "end-of-line"          =>   Some(Event::EvActEndOfLine),
         "execute"              =>   Some(Event::EvActExecute(arg.expect("execute event should have argument"))),
+        "execute-bg"           =>   Some(Event::EvActExecuteBg(arg.expect("execute-bg event should have argument"))),
         "execute-silent"       =>   Some(Event::EvActExecuteSilent(arg.expect("execute-silent event should have argument"))),
+        "first"                =>   Some(Event::EvActFirst),
         "forward-char"         =>   Some(Event::EvActForwardChar),
         "forward-word"         =>   Some(Event::EvActForwardWord),
         "if-non-matched"       =>   Some(Event::EvActIfNonMatched(arg.expect("no arg specified for event if-non-matched"))),
@@ -113,11 +141,15 @@ pub fn parse_event(action: &str, arg: Option<String>) -> Option<Event> {
         "ignore"               =>   Some(Event::EvActIgnore),
         "kill-line"            =>   Some(Event::EvActKillLine),
         "kill-word"            =>   Some(Event::EvActKillWord),
+        "last"                 =>   Some(Event::EvActLast),
+        "left"                 =>   Some(Event::EvActLeft(arg.and_then(|s|s.parse().ok()).unwrap_or(1))),
         "next-history"         =>   Some(Event::EvActNextHistory),
         "half-page-down"       =>   Some(Event::EvActHalfPageDown(arg.and_then(|s|s.parse().ok()).unwrap_or(1))),
         "half-page-up"         =>   Some(Event::EvActHalfPageUp(arg.and_then(|s|s.parse().ok()).unwrap_or(1))),
         "page-down"            =>   Some(Event::EvActPageDown(arg.and_then(|s|s.parse().ok()).unwrap_or(1))),
         "page-up"              =>   Some(Event::EvActPageUp(arg.and_then(|s|s.parse().ok()).unwrap_or(1))),
+        "parent-dir"           =>   Some(Event::EvActParentDir),
+        "preview"              =>   Some(Event::EvActPreview(arg.expect("preview event should have argument"))),
         "preview-up"           =>   Some(Event::EvActPreviewUp(arg.and_then(|s|s.parse().ok()).unwrap_or(1))),
         "preview-down"         =>   Some(Event::EvActPreviewDown(arg.and_then(|s|s.parse().ok()).unwrap_or(1))),
         "preview-left"         =>   Some(Event::EvActPreviewLeft(arg.and_then(|s|s.parse().ok()).unwrap_or(1))),
@@ -125,6 +157,10 @@ pub fn parse_event(action: &str, arg: Option<String>) -> Option<Event> {
         "preview-page-up"      =>   Some(Event::EvActPreviewPageUp(arg.and_then(|s|s.parse().ok()).unwrap_or(1))),
         "preview-page-down"    =>   Some(Event::EvActPreviewPageDown(arg.and_then(|s|s.parse().ok()).unwrap_or(1))),
         "previous-history"     =>   Some(Event::EvActPreviousHistory),
+        "put"                  =>   Some(Event::EvActPut(arg.unwrap_or_default())),
+        "refresh-preview"      =>   Some(Event::EvActRefreshPreview),
+        "reload"               =>   Some(Event::EvActReload(arg)),
+        "right"                =>   Some(Event::EvActRight(arg.and_then(|s|s.parse().ok()).unwrap_or(1))),
         "scroll-left"          =>   Some(Event::EvActScrollLeft(arg.and_then(|s|s.parse().ok()).unwrap_or(1))),
         "scroll-right"         =>   Some(Event::EvActScrollRight(arg.and_then(|s|s.parse().ok()).unwrap_or(1))),
         "select-all"           =>   Some(Event::EvActSelectAll),
@@ -132,14 +168,18 @@ pub fn parse_event(action: &str, arg: Option<String>) -> Option<Event> {
         "toggle-all"           =>   Some(Event::EvActToggleAll),
         "toggle-in"            =>   Some(Event::EvActToggleIn),
         "toggle-interactive"   =>   Some(Event::EvActToggleInteractive),
+        "toggle-nth"           =>   Some(Event::EvActToggleNth(arg.unwrap_or_default())),
         "toggle-out"           =>   Some(Event::EvActToggleOut),
         "toggle-preview"       =>   Some(Event::EvActTogglePreview),
         "toggle-preview-wrap"  =>   Some(Event::EvActTogglePreviewWrap),
         "toggle-sort"          =>   Some(Event::EvActToggleSort),
+        "transform-query"      =>   Some(Event::EvActTransformQuery(arg.expect("transform-query event should have argument"))),
         "unix-line-discard"    =>   Some(Event::EvActUnixLineDiscard),
         "unix-word-rubout"     =>   Some(Event::EvActUnixWordRubout),
         "up"                   =>   Some(Event::EvActUp(arg.and_then(|s|s.parse().ok()).unwrap_or(1))),
         "yank"                 =>   Some(Event::EvActYank),
+        "yank-item"            =>   Some(Event::EvActYankItem),
+        "yank-query"           =>   Some(Event::EvActYankQuery),
         _ => None
     }
 }
@@ -3,6 +3,7 @@ use std::cmp::{max, min};
 
 lazy_static! {
     static ref FIELD_RANGE: Regex = Regex::new(r"^(?P<left>-?\d+)?(?P<sep>\.\.)?(?P<right>-?\d+)?$").unwrap();
+    static ref CAPTURE_GROUP: Regex = Regex::new(r"^\$(?:(?P<group>\d+)|\{(?P<braced>\d+)\})$").unwrap();
 }
 
 #[derive(PartialEq, Eq, Clone, Debug)]
@@ -11,12 +12,20 @@ pub enum FieldRange {
     LeftInf(i32),
     RightInf(i32),
     Both(i32, i32),
+    /// the capture group of the first match of the delimiter, e.g. `$2` or `${2}`
+    Capture(usize),
 }
 
 impl FieldRange {
     pub fn from_str(range: &str) -> Option<FieldRange> {
         use self::FieldRange::*;
 
+        // "$1", "${1}"
+        if let Some(caps) = CAPTURE_GROUP.captures(range) {
+            let group = caps.name("group").or_else(|| caps.name("braced"))?;
+            return group.as_str().parse().ok().map(Capture);
+        }
+
         // "1", "1..", "..10", "1..10", etc.
         let opt_caps = FIELD_RANGE.captures(range);
         if let Some(caps) = opt_caps {
@@ -86,6 +95,7 @@ impl FieldRange {
                     Some((max(left, 1) - 1, min(right, length)))
                 }
             }
+            Capture(_) => None,
         }
     }
 
@@ -96,22 +106,160 @@ impl FieldRange {
     }
 }
 
+// matches the quoted fields as well as the commas, so that the commas in the quotes are skipped
+const CSV_DELIMITER: &str = r#""(?:[^"]|"")*"|,"#;
+const WS_DELIMITER: &str = r"\s+";
+
+/// The delimiter of the fields, see `parse_delimiter`. The presets split the text their own way
+/// on top of their regex.
+#[derive(Debug, Clone)]
+pub enum Delimiter {
+    Regex(Regex),
+    /// `@csv`, only the commas are delimiters, not the quoted fields matched with them
+    Csv(Regex),
+    /// `@ws`, the leading and trailing matches are skipped
+    Whitespace(Regex),
+}
+
+impl Delimiter {
+    /// the regex the delimiters are found by, its capture groups are the fields of `$N`
+    pub fn regex(&self) -> &Regex {
+        match self {
+            Delimiter::Regex(regex) | Delimiter::Csv(regex) | Delimiter::Whitespace(regex) => regex,
+        }
+    }
+}
+
+impl From<Regex> for Delimiter {
+    fn from(regex: Regex) -> Self {
+        Delimiter::Regex(regex)
+    }
+}
+
+/// Parse the delimiter of `--delimiter`.
+///
+/// `\t`, `\n`, `\r`, `\0` and `\\` are accepted as escape sequences. A delimiter without
+/// any other regex syntax is a literal (e.g. `::` or `\0`) and is matched by substring search
+/// instead of the regex engine, otherwise it is compiled as a regex, `Err` if it is invalid.
+///
+/// There are presets for the common formats:
+/// - `@csv` splits on the commas outside of the double quoted fields, `""` being an escaped
+///   quote.
+/// - `@tsv` splits on every tab, so empty fields are kept.
+/// - `@ws` splits on the runs of whitespace like awk, the leading and trailing whitespace is
+///   trimmed.
+pub fn parse_delimiter(delimiter: &str) -> Result<Delimiter, regex::Error> {
+    match delimiter {
+        "@csv" => return Regex::new(CSV_DELIMITER).map(Delimiter::Csv),
+        "@tsv" => return Regex::new(r"\t").map(Delimiter::Regex),
+        "@ws" => return Regex::new(WS_DELIMITER).map(Delimiter::Whitespace),
+        _ => {}
+    }
+    let regex = match unescape_literal(delimiter) {
+        Some(literal) => Regex::new(&regex::escape(&literal)),
+        None => Regex::new(&escape_nul(delimiter)),
+    };
+    regex.map(Delimiter::Regex)
+}
+
+// the regex engine knows all the escapes but `\0`: r"a\0" -> r"a\x00", r"a\\0" is kept
+fn escape_nul(delimiter: &str) -> String {
+    let mut escaped = String::with_capacity(delimiter.len());
+    let mut chars = delimiter.chars();
+    while let Some(ch) = chars.next() {
+        if ch != '\\' {
+            escaped.push(ch);
+            continue;
+        }
+        match chars.next() {
+            Some('0') => escaped.push_str(r"\x00"),
+            Some(next) => {
+                escaped.push('\\');
+                escaped.push(next);
+            }
+            None => escaped.push('\\'),
+        }
+    }
+    escaped
+}
+
+// "\t::" -> Some("\t::"), "a|b" -> None
+fn unescape_literal(delimiter: &str) -> Option<String> {
+    let mut literal = String::new();
+    let mut chars = delimiter.chars();
+    while let Some(ch) = chars.next() {
+        let ch = match ch {
+            '\\' => match chars.next()? {
+                't' => '\t',
+                'n' => '\n',
+                'r' => '\r',
+                '0' => '\0',
+                '\\' => '\\',
+                _ => return None,
+            },
+            '.' | '+' | '*' | '?' | '(' | ')' | '|' | '[' | ']' | '{' | '}' | '^' | '$' => return None,
+            ch => ch,
+        };
+        literal.push(ch);
+    }
+    Some(literal)
+}
+
 // ("|", "a|b||c") -> [(0, 2), (2, 4), (4, 5), (5, 6)]
 // explain: split to ["a|", "b|", "|", "c"]
-fn get_ranges_by_delimiter(delimiter: &Regex, text: &str) -> Vec<(usize, usize)> {
+fn get_ranges_by_delimiter(delimiter: &Delimiter, text: &str) -> Vec<(usize, usize)> {
+    let is_ws = matches!(delimiter, Delimiter::Whitespace(_));
     let mut ranges = Vec::new();
     let mut last = 0;
-    for mat in delimiter.find_iter(text) {
+    let mut end = text.len();
+    for mat in find_delimiters(delimiter, text) {
+        // like awk, the leading and trailing whitespace isn't part of the fields
+        if is_ws && mat.start() == 0 {
+            last = mat.end();
+            continue;
+        }
+        if is_ws && mat.end() == text.len() {
+            end = mat.start();
+            continue;
+        }
         ranges.push((last, mat.start()));
         last = mat.end();
     }
-    ranges.push((last, text.len()));
+    ranges.push((last, end.max(last)));
     ranges
 }
 
+fn find_delimiters<'t>(delimiter: &'t Delimiter, text: &'t str) -> impl Iterator<Item = regex::Match<'t>> {
+    let is_csv = matches!(delimiter, Delimiter::Csv(_));
+    delimiter
+        .regex()
+        .find_iter(text)
+        .filter(move |mat| !is_csv || mat.as_str() == ",")
+}
+
+// the byte range of a capture group of the first match of the delimiter
+// ("(\d+):(.*)", "12:ab", 2) -> Some((3, 5))
+fn get_capture_range(delimiter: &Delimiter, text: &str, group: usize) -> Option<(usize, usize)> {
+    let mat = delimiter.regex().captures(text)?.get(group)?;
+    Some((mat.start(), mat.end()))
+}
+
 // e.g. delimiter = Regex::new(",").unwrap()
 // Note that this is differnt with `to_index_pair`, it uses delimiters like ".*?,"
 pub fn get_string_by_field<'a>(delimiter: &Regex, text: &'a str, field: &FieldRange) -> Option<&'a str> {
+    get_string_by_field_with_delimiter(&delimiter.clone().into(), text, field)
+}
+
+/// same as `get_string_by_field`, with a delimiter of `parse_delimiter`, e.g. the presets
+pub fn get_string_by_field_with_delimiter<'a>(
+    delimiter: &Delimiter,
+    text: &'a str,
+    field: &FieldRange,
+) -> Option<&'a str> {
+    if let FieldRange::Capture(group) = *field {
+        return get_capture_range(delimiter, text, group).map(|(begin, end)| &text[begin..end]);
+    }
+
     let ranges = get_ranges_by_delimiter(delimiter, text);
 
     if let Some((start, stop)) = field.to_index_pair(ranges.len()) {
@@ -124,18 +272,34 @@ pub fn get_string_by_field<'a>(delimiter: &Regex, text: &'a str, field: &FieldRa
 }
 
 pub fn get_string_by_range<'a>(delimiter: &Regex, text: &'a str, range: &str) -> Option<&'a str> {
-    FieldRange::from_str(range).and_then(|field| get_string_by_field(delimiter, text, &field))
+    get_string_by_range_with_delimiter(&delimiter.clone().into(), text, range)
+}
+
+/// same as `get_string_by_range`, with a delimiter of `parse_delimiter`, e.g. the presets
+pub fn get_string_by_range_with_delimiter<'a>(delimiter: &Delimiter, text: &'a str, range: &str) -> Option<&'a str> {
+    FieldRange::from_str(range).and_then(|field| get_string_by_field_with_delimiter(delimiter, text, &field))
 }
 
 // -> a vector of the matching fields (byte wise).
 // Given delimiter `,`, text: "a,b,c"
 // &[Single(2), LeftInf(2)] => [(2, 4), (0, 4)]
 pub fn parse_matching_fields(delimiter: &Regex, text: &str, fields: &[FieldRange]) -> Vec<(usize, usize)> {
+    parse_matching_fields_with_delimiter(&delimiter.clone().into(), text, fields)
+}
+
+/// same as `parse_matching_fields`, with a delimiter of `parse_delimiter`, e.g. the presets
+pub fn parse_matching_fields_with_delimiter(
+    delimiter: &Delimiter,
+    text: &str,
+    fields: &[FieldRange],
+) -> Vec<(usize, usize)> {
     let ranges = get_ranges_by_delimiter(delimiter, text);
 
     let mut ret = Vec::new();
     for field in fields {
-        if let Some((start, stop)) = field.to_index_pair(ranges.len()) {
+        if let FieldRange::Capture(group) = *field {
+            ret.extend(get_capture_range(delimiter, text, group));
+        } else if let Some((start, stop)) = field.to_index_pair(ranges.len()) {
             let &(begin, _) = &ranges[start];
             let &(end, _) = ranges.get(stop).unwrap_or(&(text.len(), 0));
             ret.push((begin, end));
@@ -145,19 +309,47 @@ pub fn parse_matching_fields(delimiter: &Regex, text: &str, fields: &[FieldRange
 }
 
 pub fn parse_transform_fields(delimiter: &Regex, text: &str, fields: &[FieldRange]) -> String {
+    parse_transform_fields_with_delimiter(&delimiter.clone().into(), text, fields)
+}
+
+/// same as `parse_transform_fields`, with a delimiter of `parse_delimiter`, e.g. the presets
+pub fn parse_transform_fields_with_delimiter(delimiter: &Delimiter, text: &str, fields: &[FieldRange]) -> String {
+    get_transform_ranges(delimiter, text, fields)
+        .into_iter()
+        .map(|(begin, end)| &text[begin..end])
+        .collect()
+}
+
+/// the byte ranges of the fields, in the order of the fields
+pub fn get_transform_ranges(delimiter: &Delimiter, text: &str, fields: &[FieldRange]) -> Vec<(usize, usize)> {
     let ranges = get_ranges_by_delimiter(delimiter, text);
 
-    let mut ret = String::new();
+    let mut ret = Vec::new();
     for field in fields {
-        if let Some((start, stop)) = field.to_index_pair(ranges.len()) {
+        if let FieldRange::Capture(group) = *field {
+            ret.extend(get_capture_range(delimiter, text, group));
+        } else if let Some((start, stop)) = field.to_index_pair(ranges.len()) {
             let &(begin, _) = &ranges[start];
             let &(end, _) = ranges.get(stop).unwrap_or(&(text.len(), 0));
-            ret.push_str(&text[begin..end]);
+            ret.push((begin, end));
         }
     }
     ret
 }
 
+/// the fields to output, without the delimiter that follows the last field
+/// (" ", "a b c", 1..2) -> "a b"
+pub fn parse_output_fields(delimiter: &Delimiter, text: &str, fields: &[FieldRange]) -> String {
+    let mut ret = parse_transform_fields_with_delimiter(delimiter, text, fields);
+    if let Some(mat) = find_delimiters(delimiter, &ret)
+        .last()
+        .filter(|mat| mat.end() == ret.len())
+    {
+        ret.truncate(mat.start());
+    }
+    ret
+}
+
 #[cfg(test)]
 mod test {
     use super::FieldRange::*;
@@ -179,6 +371,11 @@ mod test {
         assert_eq!(FieldRange::from_str("a.."), None);
         assert_eq!(FieldRange::from_str("..b"), None);
         assert_eq!(FieldRange::from_str("a..b"), None);
+
+        assert_eq!(FieldRange::from_str("$2"), Some(Capture(2)));
+        assert_eq!(FieldRange::from_str("${10}"), Some(Capture(10)));
+        assert_eq!(FieldRange::from_str("$"), None);
+        assert_eq!(FieldRange::from_str("${1"), None);
     }
 
     use regex::Regex;
@@ -267,6 +464,115 @@ mod test {
         );
     }
 
+    #[test]
+    fn test_parse_open_ranges_from_end() {
+        let re = Regex::new(",").unwrap();
+        let fields = |range: &str| parse_transform_fields(&re, "A,B,C,D", &[FieldRange::from_str(range).unwrap()]);
+
+        // all but the first field
+        assert_eq!(fields("2.."), "B,C,D");
+        // the last two fields
+        assert_eq!(fields("-2.."), "C,D");
+        // all but the last field
+        assert_eq!(fields("..-2"), "A,B,C,");
+        assert_eq!(fields("..-1"), "A,B,C,D");
+        // out of range counted from the end is clamped
+        assert_eq!(fields("-9.."), "A,B,C,D");
+        assert_eq!(fields("..-9"), "");
+    }
+
+    #[test]
+    fn test_parse_delimiter() {
+        let split = |delimiter: &str, text: &str| {
+            let re = parse_delimiter(delimiter).unwrap();
+            get_ranges_by_delimiter(&re, text)
+                .into_iter()
+                .map(|(begin, end)| text[begin..end].to_string())
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(split(r"\t", "a\tb c"), vec!["a", "b c"]);
+        assert_eq!(split(r"\0", "a\0b"), vec!["a", "b"]);
+        assert_eq!(split("::", "a::b:c"), vec!["a", "b:c"]);
+        assert_eq!(split(r"\\", r"a\b"), vec!["a", "b"]);
+        assert_eq!(split(r"[,;]\0?", "a,b;\0c"), vec!["a", "b", "c"]);
+        // an escaped backslash followed by `0` is not a NUL
+        assert_eq!(split(r"[;]\\0", r"a;\0b"), vec!["a", "b"]);
+        assert_eq!(split(r"[;]\\0", "a;\0b"), vec!["a;\0b"]);
+        assert!(parse_delimiter("a(b").is_err());
+    }
+
+    #[test]
+    fn test_csv_delimiter() {
+        let re = parse_delimiter("@csv").unwrap();
+        let text = r#"a,"b,""c""",,"d""#;
+        let fields: Vec<_> = get_ranges_by_delimiter(&re, text)
+            .into_iter()
+            .map(|(begin, end)| &text[begin..end])
+            .collect();
+        assert_eq!(fields, vec!["a", r#""b,""c""""#, "", r#""d""#]);
+
+        assert_eq!(
+            parse_transform_fields_with_delimiter(&re, text, &[Single(2)]),
+            r#""b,""c""","#
+        );
+        assert_eq!(parse_output_fields(&re, text, &[Single(4)]), r#""d""#);
+        assert_eq!(
+            get_string_by_field_with_delimiter(&re, text, &Single(2)),
+            Some(r#""b,""c""""#)
+        );
+        // an unbalanced quote is not a quoted field
+        assert_eq!(get_ranges_by_delimiter(&re, r#"a,"b,c"#).len(), 3);
+    }
+
+    #[test]
+    fn test_delimiter_presets() {
+        let split = |delimiter: &str, text: &str| {
+            let re = parse_delimiter(delimiter).unwrap();
+            get_ranges_by_delimiter(&re, text)
+                .into_iter()
+                .map(|(begin, end)| text[begin..end].to_string())
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(split("@csv", r#"a,"b,c""#), vec!["a", r#""b,c""#]);
+        // a plain word is a delimiter, not a preset
+        assert_eq!(split("csv", "acsvb,c"), vec!["a", "b,c"]);
+        assert_eq!(split("@tsv", "a\t\tb c"), vec!["a", "", "b c"]);
+        assert_eq!(split("@ws", "  a \t b  "), vec!["a", "b"]);
+        assert_eq!(split("@ws", "  a b"), vec!["a", "b"]);
+        assert_eq!(split("@ws", "   "), vec![""]);
+        assert_eq!(split("@ws", "a"), vec!["a"]);
+        // a regex is never a preset, even with the same source
+        assert_eq!(split(r"\s+", " a"), vec!["", "a"]);
+        assert_eq!(split(CSV_DELIMITER, r#"a,"b,c""#), vec!["a", "", ""]);
+
+        let re = parse_delimiter("@ws").unwrap();
+        assert_eq!(
+            parse_transform_fields_with_delimiter(&re, " a  b c", &[Single(2)]),
+            "b "
+        );
+        assert_eq!(parse_output_fields(&re, " a  b c", &[Both(2, 3)]), "b c");
+    }
+
+    #[test]
+    fn test_parse_output_fields() {
+        let re = Delimiter::from(Regex::new(" +").unwrap());
+        assert_eq!(parse_output_fields(&re, "a b  c", &[Both(1, 2)]), "a b");
+        assert_eq!(parse_output_fields(&re, "a b  c", &[Single(3), Single(1)]), "ca");
+        assert_eq!(parse_output_fields(&re, "a b  c", &[Single(4)]), "");
+    }
+
+    #[test]
+    fn test_parse_capture_fields() {
+        let re = Regex::new(r"(\d+):(.*)").unwrap();
+        assert_eq!(parse_transform_fields(&re, "12:ab", &[Capture(2), Capture(1)]), "ab12");
+        assert_eq!(parse_transform_fields(&re, "12:ab", &[Capture(3)]), "");
+        assert_eq!(parse_transform_fields(&re, "ab", &[Capture(1)]), "");
+        assert_eq!(parse_matching_fields(&re, "12:ab", &[Capture(2)]), vec![(3, 5)]);
+        assert_eq!(get_string_by_field(&re, "12:ab", &Capture(1)), Some("12"));
+    }
+
     #[test]
     fn test_parse_matching_fields() {
         // delimiter is ","
@@ -0,0 +1,205 @@
+//! A handle for the host application to control a running skim instance
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{channel, Sender};
+use std::sync::Arc;
+use std::time::Duration;
+
+use tuikit::prelude::{Event as TermEvent, Term};
+
+use crate::event::{Event, EventSender};
+use crate::input::parse_keys;
+use crate::spinlock::SpinLock;
+
+/// `SkimHandle` lets the caller of skim drive the UI from another thread.
+///
+/// Create a handle, clone it to wherever the updates come from, and start skim with
+/// `Skim::run_with_handle`. The handle is only connected while skim is running, all the requests
+/// made before skim starts or after it returns are dropped (and the methods return `false`).
+///
+/// ```no_run
+/// use skim::prelude::*;
+///
+/// let handle = SkimHandle::new();
+/// let handle_clone = handle.clone();
+/// std::thread::spawn(move || {
+///     handle_clone.set_query("initial");
+///     handle_clone.set_cursor(0);
+/// });
+/// let _output = Skim::run_with_handle(&SkimOptions::default(), None, &handle);
+/// ```
+///
+/// `abort` is the exception: it also cancels a run that hasn't started yet, e.g. to give up after
+/// a timeout.
+///
+/// ```no_run
+/// use skim::prelude::*;
+/// use std::time::Duration;
+///
+/// let handle = SkimHandle::new();
+/// let handle_clone = handle.clone();
+/// std::thread::spawn(move || {
+///     std::thread::sleep(Duration::from_secs(30));
+///     handle_clone.abort();
+/// });
+/// // `None` if aborted, the terminal is restored either way
+/// let output = Skim::run_with_handle(&SkimOptions::default(), None, &handle);
+/// ```
+#[derive(Clone)]
+pub struct SkimHandle {
+    tx: Arc<SpinLock<Option<EventSender>>>,
+    // the terminal events are injected here to go through the key bindings like the real ones
+    term: Arc<SpinLock<Option<Arc<Term>>>>,
+    // the requests of `selection` waiting for skim to report
+    pending_selections: Arc<SpinLock<Vec<Sender<Vec<String>>>>>,
+    // `abort` was called before skim started, abort as soon as it does
+    abort_requested: Arc<AtomicBool>,
+}
+
+impl Default for SkimHandle {
+    fn default() -> Self {
+        Self {
+            tx: Arc::new(SpinLock::new(None)),
+            term: Arc::new(SpinLock::new(None)),
+            pending_selections: Arc::new(SpinLock::new(Vec::new())),
+            abort_requested: Arc::new(AtomicBool::new(false)),
+        }
+    }
+}
+
+impl SkimHandle {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Replace the query of current mode (query or command query) and move the cursor to the end.
+    pub fn set_query(&self, query: &str) -> bool {
+        self.send(Event::EvActChangeQuery(query.to_string()))
+    }
+
+    /// Move the cursor of current query to the given position (in chars).
+    pub fn set_cursor(&self, position: usize) -> bool {
+        self.send(Event::EvActSetQueryCursor(position))
+    }
+
+    /// Read the items again from `cmd`, or from the current command if it's `None`.
+    pub fn reload(&self, cmd: Option<&str>) -> bool {
+        self.send(Event::EvActReload(cmd.map(str::to_string)))
+    }
+
+    /// The output of the items that would be accepted now: the selected ones, or the current one
+    /// if none is selected. `None` if skim isn't running or doesn't report within `timeout`.
+    pub fn selection(&self, timeout: Duration) -> Option<Vec<String>> {
+        let (tx, rx) = channel();
+        {
+            // keep the lock so skim can't report before the request is queued
+            let mut pending = self.pending_selections.lock();
+            if !self.send(Event::EvReportSelection) {
+                return None;
+            }
+            pending.push(tx);
+        }
+        rx.recv_timeout(timeout).ok()
+    }
+
+    /// Close the running skim as if the user aborted it, the terminal is restored and the run
+    /// returns `None`. If skim hasn't started yet, the next run is aborted as soon as it starts.
+    /// Returns whether a running instance was told to abort.
+    pub fn abort(&self) -> bool {
+        self.abort_requested.store(true, Ordering::SeqCst);
+        self.send(Event::EvActAbort)
+    }
+
+    /// Push a terminal event (key, mouse, resize, etc.) as if it came from the terminal, it's
+    /// translated by the key bindings (and recorded by `--record`) like the real ones.
+    pub fn send_event(&self, event: TermEvent) -> bool {
+        self.term
+            .lock()
+            .as_ref()
+            .map(|term| term.send_event(event).is_ok())
+            .unwrap_or(false)
+    }
+
+    /// Push the keys named as in `--bind` and separated by spaces, e.g. "ctrl-k down enter", see
+    /// `send_event`. Nothing is sent if any key name is unknown.
+    pub fn send_keys(&self, keys: &str) -> bool {
+        match parse_keys(keys) {
+            Some(keys) if self.is_running() => keys.into_iter().all(|key| self.send_event(TermEvent::Key(key))),
+            _ => false,
+        }
+    }
+
+    /// whether the handle is connected to a running skim instance.
+    pub fn is_running(&self) -> bool {
+        self.tx.lock().is_some()
+    }
+
+    pub(crate) fn attach(&self, tx: EventSender) {
+        let mut current = self.tx.lock();
+        if self.abort_requested.load(Ordering::SeqCst) {
+            let _ = tx.send(Event::EvActAbort);
+        }
+        current.replace(tx);
+    }
+
+    pub(crate) fn attach_term(&self, term: Arc<Term>) {
+        self.term.lock().replace(term);
+    }
+
+    pub(crate) fn detach(&self) {
+        self.tx.lock().take();
+        self.term.lock().take();
+        self.pending_selections.lock().clear();
+        self.abort_requested.store(false, Ordering::SeqCst);
+    }
+
+    pub(crate) fn report_selection(&self, selection: Vec<String>) {
+        for tx in self.pending_selections.lock().drain(..) {
+            let _ = tx.send(selection.clone());
+        }
+    }
+
+    pub(crate) fn send(&self, event: Event) -> bool {
+        self.tx
+            .lock()
+            .as_ref()
+            .map(|tx| tx.send(event).is_ok())
+            .unwrap_or(false)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_abort() {
+        let handle = SkimHandle::new();
+        assert!(!handle.abort());
+
+        // the abort requested before skim starts is delivered once it does
+        let (tx, rx) = channel();
+        handle.attach(tx);
+        assert!(matches!(rx.try_recv(), Ok(Event::EvActAbort)));
+        handle.detach();
+
+        // but not to the next run
+        let (tx, rx) = channel();
+        handle.attach(tx);
+        assert!(rx.try_recv().is_err());
+        assert!(handle.abort());
+        assert!(matches!(rx.try_recv(), Ok(Event::EvActAbort)));
+    }
+
+    #[test]
+    fn test_selection_not_running() {
+        let handle = SkimHandle::new();
+        assert_eq!(handle.selection(Duration::from_millis(1)), None);
+        assert!(handle.pending_selections.lock().is_empty());
+
+        let (tx, rx) = channel();
+        handle.attach(tx);
+        drop(rx);
+        assert_eq!(handle.selection(Duration::from_millis(1)), None);
+        assert!(handle.pending_selections.lock().is_empty());
+    }
+}
@@ -17,6 +17,7 @@ pub struct Header {
     tabstop: usize,
     reverse: bool,
     theme: Arc<ColorTheme>,
+    no_unicode: bool,
 
     // for reserved header items
     item_pool: Arc<DeferDrop<ItemPool>>,
@@ -29,6 +30,7 @@ impl Header {
             tabstop: 8,
             reverse: false,
             theme: Arc::new(*DEFAULT_THEME),
+            no_unicode: false,
             item_pool: Arc::new(DeferDrop::new(ItemPool::new())),
         }
     }
@@ -52,6 +54,7 @@ impl Header {
         if options.layout.starts_with("reverse") {
             self.reverse = true;
         }
+        self.no_unicode = options.no_unicode;
 
         match options.header {
             None => {}
@@ -117,6 +120,7 @@ impl Draw for Header {
                 .container_width(screen_width - 2)
                 .shift(0)
                 .text_width(screen_width - 2)
+                .no_unicode(self.no_unicode)
                 .build();
 
             let context = DisplayContext {
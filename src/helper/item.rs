@@ -1,9 +1,18 @@
+#[cfg(feature = "tui")]
 use crate::ansi::ANSIParser;
-use crate::field::{parse_matching_fields, parse_transform_fields, FieldRange};
-use crate::{AnsiString, DisplayContext, Matches, SkimItem};
-use regex::Regex;
+#[cfg(feature = "tui")]
+use crate::attr::Attr;
+#[cfg(feature = "tui")]
+use crate::field::{
+    get_transform_ranges, parse_matching_fields_with_delimiter, parse_transform_fields_with_delimiter, Delimiter,
+    FieldRange,
+};
+#[cfg(feature = "tui")]
+use crate::Matches;
+use crate::{AnsiString, DisplayContext, DisplayText, ItemPreview, PreviewContext, SkimItem};
+use std::any::Any;
 use std::borrow::Cow;
-use tuikit::prelude::Attr;
+use std::sync::Arc;
 
 //------------------------------------------------------------------------------
 /// An item will store everything that one line input will need to be operated and displayed.
@@ -16,6 +25,7 @@ use tuikit::prelude::Attr;
 ///
 /// About the ANSI, we made assumption that it is linewise, that means no ANSI codes will affect
 /// more than one line.
+#[cfg(feature = "tui")]
 #[derive(Debug)]
 pub struct DefaultSkimItem {
     /// The text that will be output when user press `enter`
@@ -31,19 +41,20 @@ pub struct DefaultSkimItem {
     matching_ranges: Option<Box<Vec<(usize, usize)>>>,
 }
 
+#[cfg(feature = "tui")]
 impl<'a> DefaultSkimItem {
     pub fn new(
         orig_text: String,
         ansi_enabled: bool,
         trans_fields: &[FieldRange],
         matching_fields: &[FieldRange],
-        delimiter: &Regex,
+        delimiter: &Delimiter,
     ) -> Self {
         let using_transform_fields = !trans_fields.is_empty();
 
         //        transformed | ANSI             | output
         //------------------------------------------------------
-        //                    +- T -> trans+ANSI | ANSI
+        //                    +- T -> ANSI+trans | stripped orig
         //                    |                  |
         //      +- T -> trans +- F -> trans      | orig
         // orig |                                |
@@ -54,12 +65,15 @@ impl<'a> DefaultSkimItem {
         let mut ansi_parser: ANSIParser = Default::default();
 
         let (orig_text, text) = if using_transform_fields && ansi_enabled {
-            // ansi and transform
-            let transformed = ansi_parser.parse_ansi(&parse_transform_fields(delimiter, &orig_text, trans_fields));
-            (Some(orig_text), transformed)
+            // ansi and transform, the fields are split on the stripped text so that the escape codes
+            // are never cut and the attrs of the selected fields are kept
+            let parsed = ansi_parser.parse_ansi(&orig_text);
+            let ranges = get_transform_ranges(delimiter, parsed.stripped(), trans_fields);
+            let transformed = parsed.select_ranges(&ranges);
+            (Some(parsed.into_inner().into_owned()), transformed)
         } else if using_transform_fields {
             // transformed, not ansi
-            let transformed = parse_transform_fields(delimiter, &orig_text, trans_fields).into();
+            let transformed = parse_transform_fields_with_delimiter(delimiter, &orig_text, trans_fields).into();
             (Some(orig_text), transformed)
         } else if ansi_enabled {
             // not transformed, ansi
@@ -70,7 +84,7 @@ impl<'a> DefaultSkimItem {
         };
 
         let matching_ranges = if !matching_fields.is_empty() {
-            Some(Box::new(parse_matching_fields(
+            Some(Box::new(parse_matching_fields_with_delimiter(
                 delimiter,
                 text.stripped(),
                 matching_fields,
@@ -87,6 +101,7 @@ impl<'a> DefaultSkimItem {
     }
 }
 
+#[cfg(feature = "tui")]
 impl SkimItem for DefaultSkimItem {
     #[inline]
     fn text(&self) -> Cow<str> {
@@ -94,16 +109,9 @@ impl SkimItem for DefaultSkimItem {
     }
 
     fn output(&self) -> Cow<str> {
-        if self.orig_text.is_some() {
-            if self.text.has_attrs() {
-                let mut ansi_parser: ANSIParser = Default::default();
-                let text = ansi_parser.parse_ansi(self.orig_text.as_ref().unwrap());
-                text.into_inner()
-            } else {
-                Cow::Borrowed(self.orig_text.as_ref().unwrap())
-            }
-        } else {
-            Cow::Borrowed(self.text.stripped())
+        match self.orig_text {
+            Some(ref orig_text) => Cow::Borrowed(orig_text),
+            None => Cow::Borrowed(self.text.stripped()),
         }
     }
 
@@ -130,3 +138,63 @@ impl SkimItem for DefaultSkimItem {
         ret
     }
 }
+
+//------------------------------------------------------------------------------
+/// Attach a payload to an item, the item behaves the same otherwise.
+///
+/// ```rust
+/// use skim::prelude::*;
+///
+/// struct Record {
+///     id: u64,
+/// }
+///
+/// let item = ItemWithPayload::new("first record", Arc::new(Record { id: 1 }));
+/// let output = SkimOutput::new(vec![Arc::new(item)]);
+/// let records: Vec<Arc<Record>> = output.payloads().collect();
+/// assert_eq!(records[0].id, 1);
+/// ```
+pub struct ItemWithPayload<T: SkimItem> {
+    item: T,
+    payload: Arc<dyn Any + Send + Sync>,
+}
+
+impl<T: SkimItem> ItemWithPayload<T> {
+    pub fn new(item: T, payload: Arc<dyn Any + Send + Sync>) -> Self {
+        Self { item, payload }
+    }
+
+    pub fn item(&self) -> &T {
+        &self.item
+    }
+}
+
+impl<T: SkimItem> SkimItem for ItemWithPayload<T> {
+    fn display<'a>(&'a self, context: DisplayContext<'a>) -> AnsiString<'a> {
+        self.item.display(context)
+    }
+
+    fn display_text(&self) -> Option<DisplayText<'_>> {
+        self.item.display_text()
+    }
+
+    fn text(&self) -> Cow<'_, str> {
+        self.item.text()
+    }
+
+    fn preview(&self, context: PreviewContext) -> ItemPreview {
+        self.item.preview(context)
+    }
+
+    fn output(&self) -> Cow<'_, str> {
+        self.item.output()
+    }
+
+    fn get_matching_ranges(&self) -> Option<&[(usize, usize)]> {
+        self.item.get_matching_ranges()
+    }
+
+    fn payload(&self) -> Option<Arc<dyn Any + Send + Sync>> {
+        Some(self.payload.clone())
+    }
+}
@@ -1,22 +1,29 @@
 /// helper for turn a BufRead into a skim stream
-use crate::field::FieldRange;
+use crate::field::{parse_delimiter, Delimiter, FieldRange};
 use crate::helper::item::DefaultSkimItem;
 use crate::reader::CommandCollector;
+use crate::spinlock::SpinLock;
+use crate::util::{valid_env_vars, Shell};
 use crate::{SkimItem, SkimItemReceiver, SkimItemSender};
 use crossbeam::channel::{bounded, Receiver, Sender};
 use regex::Regex;
-use std::env;
 use std::error::Error;
 use std::io::{BufRead, BufReader};
-use std::process::{Child, Command, Stdio};
+use std::path::PathBuf;
+use std::process::{Child, Stdio};
 use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 use std::sync::Arc;
 use std::thread;
+use std::time::Duration;
 
 const CMD_CHANNEL_SIZE: usize = 1024;
 const ITEM_CHANNEL_SIZE: usize = 10240;
 const DELIMITER_STR: &str = r"[\t\n ]+";
 const READ_BUFFER_SIZE: usize = 1024;
+// sent to the command killer once the output reaches EOF
+const EOF: i32 = 0;
+// rounds(10ms each) to wait for the command to exit after EOF before killing it
+const EXIT_WAIT_ROUNDS: usize = 10;
 
 pub enum CollectorInput {
     Pipe(Box<dyn BufRead + Send>),
@@ -29,8 +36,11 @@ pub struct SkimItemReaderOption {
     use_ansi_color: bool,
     transform_fields: Vec<FieldRange>,
     matching_fields: Vec<FieldRange>,
-    delimiter: Regex,
+    delimiter: Delimiter,
     line_ending: u8,
+    shell: Shell,
+    cmd_cwd: Option<PathBuf>,
+    cmd_env: Vec<(String, String)>,
 }
 
 impl Default for SkimItemReaderOption {
@@ -41,7 +51,10 @@ impl Default for SkimItemReaderOption {
             use_ansi_color: false,
             transform_fields: Vec::new(),
             matching_fields: Vec::new(),
-            delimiter: Regex::new(DELIMITER_STR).unwrap(),
+            delimiter: Regex::new(DELIMITER_STR).unwrap().into(),
+            shell: Shell::default(),
+            cmd_cwd: None,
+            cmd_env: Vec::new(),
         }
     }
 }
@@ -64,7 +77,7 @@ impl SkimItemReaderOption {
 
     pub fn delimiter(mut self, delimiter: &str) -> Self {
         if !delimiter.is_empty() {
-            self.delimiter = Regex::new(delimiter).unwrap_or_else(|_| Regex::new(DELIMITER_STR).unwrap());
+            self.delimiter = parse_delimiter(delimiter).unwrap_or_else(|_| Regex::new(DELIMITER_STR).unwrap().into());
         }
         self
     }
@@ -108,10 +121,36 @@ impl SkimItemReaderOption {
         self
     }
 
+    /// the shell to run the source command with, e.g. `bash -eo pipefail -c`
+    pub fn shell(mut self, shell: &str) -> Self {
+        if let Some(shell) = Shell::parse(shell) {
+            self.shell = shell;
+        }
+        self
+    }
+
+    /// the working directory of the source command
+    pub fn cmd_cwd(mut self, dir: &str) -> Self {
+        self.cmd_cwd = Some(dir).filter(|dir| !dir.is_empty()).map(PathBuf::from);
+        self
+    }
+
+    /// the extra environment variables of the source command, as `KEY=VALUE`, invalid ones are
+    /// ignored
+    pub fn cmd_env(mut self, vars: &[&str]) -> Self {
+        self.cmd_env = valid_env_vars(vars);
+        self
+    }
+
     pub fn build(self) -> Self {
         self
     }
 
+    /// the delimiter the fields of `--nth` and `--with-nth` are split by
+    pub fn get_delimiter(&self) -> &Delimiter {
+        &self.delimiter
+    }
+
     pub fn is_simple(&self) -> bool {
         !self.use_ansi_color && self.matching_fields.is_empty() && self.transform_fields.is_empty()
     }
@@ -119,12 +158,15 @@ impl SkimItemReaderOption {
 
 pub struct SkimItemReader {
     option: Arc<SkimItemReaderOption>,
+    // exit code of the command of last `invoke`
+    exit_code: Arc<SpinLock<Option<i32>>>,
 }
 
 impl Default for SkimItemReader {
     fn default() -> Self {
         Self {
             option: Arc::new(Default::default()),
+            exit_code: Arc::new(SpinLock::new(None)),
         }
     }
 }
@@ -133,6 +175,7 @@ impl SkimItemReader {
     pub fn new(option: SkimItemReaderOption) -> Self {
         Self {
             option: Arc::new(option),
+            exit_code: Arc::new(SpinLock::new(None)),
         }
     }
 
@@ -143,12 +186,31 @@ impl SkimItemReader {
 }
 
 impl SkimItemReader {
+    /// an item of the whole `text`, even if it has line endings, split and transformed the same
+    /// way as the lines read
+    pub fn of_text(&self, text: &str) -> Arc<dyn SkimItem> {
+        if self.option.is_simple() {
+            return Arc::new(text.to_string());
+        }
+        Arc::new(DefaultSkimItem::new(
+            text.to_string(),
+            self.option.use_ansi_color,
+            &self.option.transform_fields,
+            &self.option.matching_fields,
+            &self.option.delimiter,
+        ))
+    }
+
     pub fn of_bufread(&self, source: impl BufRead + Send + 'static) -> SkimItemReceiver {
         if self.option.is_simple() {
             self.raw_bufread(source)
         } else {
-            self.read_and_collect_from_command(Arc::new(AtomicUsize::new(0)), CollectorInput::Pipe(Box::new(source)))
-                .0
+            self.read_and_collect_from_command(
+                Arc::new(AtomicUsize::new(0)),
+                CollectorInput::Pipe(Box::new(source)),
+                Arc::new(SpinLock::new(None)),
+            )
+            .0
         }
     }
 
@@ -193,10 +255,19 @@ impl SkimItemReader {
         &self,
         components_to_stop: Arc<AtomicUsize>,
         input: CollectorInput,
+        exit_code: Arc<SpinLock<Option<i32>>>,
     ) -> (Receiver<Arc<dyn SkimItem>>, Sender<i32>) {
         let (command, mut source) = match input {
             CollectorInput::Pipe(pipe) => (None, pipe),
-            CollectorInput::Command(cmd) => get_command_output(&cmd).expect("command not found"),
+            CollectorInput::Command(cmd) => {
+                let shell = self
+                    .option
+                    .shell
+                    .clone()
+                    .cwd(self.option.cmd_cwd.clone())
+                    .envs(self.option.cmd_env.clone());
+                get_command_output(&shell, &cmd).expect("command not found")
+            }
         };
 
         let (tx_interrupt, rx_interrupt) = bounded(CMD_CHANNEL_SIZE);
@@ -211,9 +282,19 @@ impl SkimItemReader {
             components_to_stop_clone.fetch_add(1, Ordering::SeqCst);
             started_clone.store(true, Ordering::SeqCst); // notify parent that it is started
 
-            let _ = rx_interrupt.recv(); // block waiting
-                                         // clean up resources
+            let msg = rx_interrupt.recv(); // block waiting
+                                           // clean up resources
             if let Some(mut x) = command {
+                if msg == Ok(EOF) {
+                    // the output is closed, give the command a moment to exit on its own
+                    for _ in 0..EXIT_WAIT_ROUNDS {
+                        if let Ok(Some(status)) = x.try_wait() {
+                            *exit_code.lock() = status.code();
+                            break;
+                        }
+                        thread::sleep(Duration::from_millis(10));
+                    }
+                }
                 let _ = x.kill();
                 let _ = x.wait();
             }
@@ -275,7 +356,7 @@ impl SkimItemReader {
                 }
             }
 
-            let _ = tx_interrupt_clone.send(1); // ensure the waiting thread will exit
+            let _ = tx_interrupt_clone.send(EOF); // ensure the waiting thread will exit
             components_to_stop.fetch_sub(1, Ordering::SeqCst);
             debug!("collector: command collector stop");
         });
@@ -290,16 +371,23 @@ impl SkimItemReader {
 
 impl CommandCollector for SkimItemReader {
     fn invoke(&mut self, cmd: &str, components_to_stop: Arc<AtomicUsize>) -> (SkimItemReceiver, Sender<i32>) {
-        self.read_and_collect_from_command(components_to_stop, CollectorInput::Command(cmd.to_string()))
+        self.exit_code = Arc::new(SpinLock::new(None));
+        self.read_and_collect_from_command(
+            components_to_stop,
+            CollectorInput::Command(cmd.to_string()),
+            self.exit_code.clone(),
+        )
+    }
+
+    fn exit_code(&self) -> Option<i32> {
+        *self.exit_code.lock()
     }
 }
 
 type CommandOutput = (Option<Child>, Box<dyn BufRead + Send>);
-fn get_command_output(cmd: &str) -> Result<CommandOutput, Box<dyn Error>> {
-    let shell = env::var("SHELL").unwrap_or_else(|_| "sh".to_string());
-    let mut command: Child = Command::new(shell)
-        .arg("-c")
-        .arg(cmd)
+fn get_command_output(shell: &Shell, cmd: &str) -> Result<CommandOutput, Box<dyn Error>> {
+    let mut command: Child = shell
+        .command(cmd)
         .stdout(Stdio::piped())
         .stderr(Stdio::null())
         .spawn()?;
@@ -1,2 +1,3 @@
 pub mod item;
+#[cfg(feature = "tui")]
 pub mod item_reader;
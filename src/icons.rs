@@ -0,0 +1,205 @@
+//! The file-type icons shown before the items that look like paths, e.g. `--icons`. The icon is
+//! drawn in a column of its own, it's never part of the matched text nor of the output.
+use std::collections::HashMap;
+use std::path::Path;
+
+use crate::spinlock::SpinLock;
+
+// the number of texts whose directory-ness is kept, a few screens of items
+const DIR_CACHE_SIZE: usize = 256;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IconStyle {
+    /// the glyphs of a Nerd Font
+    Nerd,
+    /// a plain ASCII char by the kind of file
+    Ascii,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum FileKind {
+    Directory,
+    Code,
+    Config,
+    Document,
+    Image,
+    Archive,
+    Media,
+    File,
+}
+
+// (extensions or file names, nerd font icon, kind)
+const ICONS: &[(&[&str], char, FileKind)] = &[
+    (&["rs"], '\u{e7a8}', FileKind::Code),
+    (&["py"], '\u{e73c}', FileKind::Code),
+    (&["js", "mjs", "cjs", "jsx"], '\u{e74e}', FileKind::Code),
+    (&["ts", "tsx"], '\u{e628}', FileKind::Code),
+    (&["go"], '\u{e626}', FileKind::Code),
+    (&["c", "h"], '\u{e61e}', FileKind::Code),
+    (&["cpp", "cc", "cxx", "hpp", "hh"], '\u{e61d}', FileKind::Code),
+    (&["java"], '\u{e738}', FileKind::Code),
+    (&["rb"], '\u{e739}', FileKind::Code),
+    (&["lua"], '\u{e620}', FileKind::Code),
+    (&["vim"], '\u{e62b}', FileKind::Code),
+    (&["html", "htm"], '\u{e736}', FileKind::Code),
+    (&["css", "scss"], '\u{e749}', FileKind::Code),
+    (&["sh", "bash", "zsh", "fish"], '\u{f489}', FileKind::Code),
+    (&["json"], '\u{e60b}', FileKind::Config),
+    (
+        &["toml", "yaml", "yml", "ini", "conf", "cfg"],
+        '\u{e615}',
+        FileKind::Config,
+    ),
+    (&["lock"], '\u{f023}', FileKind::Config),
+    (
+        &[".gitignore", ".gitmodules", ".gitattributes"],
+        '\u{f1d3}',
+        FileKind::Config,
+    ),
+    (&["Dockerfile"], '\u{f308}', FileKind::Config),
+    (&["md", "markdown", "rst"], '\u{e73e}', FileKind::Document),
+    (&["txt", "log"], '\u{f15c}', FileKind::Document),
+    (&["pdf"], '\u{f1c1}', FileKind::Document),
+    (
+        &["png", "jpg", "jpeg", "gif", "svg", "webp", "bmp", "ico"],
+        '\u{f1c5}',
+        FileKind::Image,
+    ),
+    (
+        &["zip", "tar", "gz", "xz", "bz2", "zst", "7z", "rar"],
+        '\u{f410}',
+        FileKind::Archive,
+    ),
+    (&["mp3", "flac", "wav", "ogg"], '\u{f1c7}', FileKind::Media),
+    (&["mp4", "mkv", "webm", "avi", "mov"], '\u{f1c8}', FileKind::Media),
+];
+
+const DIRECTORY_ICON: char = '\u{f07b}';
+const FILE_ICON: char = '\u{f15b}';
+
+impl IconStyle {
+    fn icon(self, nerd: char, kind: FileKind) -> char {
+        match self {
+            IconStyle::Nerd => nerd,
+            IconStyle::Ascii => match kind {
+                FileKind::Directory => '/',
+                FileKind::Code => '#',
+                FileKind::Config => '~',
+                FileKind::Document => '=',
+                FileKind::Image => '%',
+                FileKind::Archive => '@',
+                FileKind::Media => '&',
+                FileKind::File => '-',
+            },
+        }
+    }
+}
+
+/// Whether the texts of the drawn items name directories, the file system is checked once for
+/// every text, not on every draw
+pub struct DirCache {
+    dirs: SpinLock<HashMap<String, bool>>,
+}
+
+impl Default for DirCache {
+    fn default() -> Self {
+        Self {
+            dirs: SpinLock::new(HashMap::new()),
+        }
+    }
+}
+
+impl DirCache {
+    pub fn is_dir(&self, text: &str) -> bool {
+        let mut dirs = self.dirs.lock();
+        if let Some(&is_dir) = dirs.get(text) {
+            return is_dir;
+        }
+
+        if dirs.len() >= DIR_CACHE_SIZE {
+            dirs.clear();
+        }
+        let is_dir = Path::new(text).is_dir();
+        dirs.insert(text.to_string(), is_dir);
+        is_dir
+    }
+}
+
+/// the icon of `text` if it looks like a path: it ends with `/` or is a directory by `is_dir`,
+/// contains a `/` or has an extension, `None` otherwise
+pub fn icon_of(text: &str, style: IconStyle, is_dir: impl FnOnce(&str) -> bool) -> Option<char> {
+    if text.is_empty() || text.trim() != text || text.chars().any(char::is_control) {
+        return None;
+    }
+
+    if text.ends_with('/') || is_dir(text) {
+        return Some(style.icon(DIRECTORY_ICON, FileKind::Directory));
+    }
+
+    let name = text.rsplit('/').next().unwrap_or(text);
+    let extension = match name.rfind('.') {
+        Some(dot) if dot > 0 => Some(&name[dot + 1..]),
+        _ => None,
+    }
+    .filter(|ext| !ext.is_empty() && ext.len() <= 10 && ext.chars().all(|ch| ch.is_ascii_alphanumeric()));
+
+    if extension.is_none() && !text.contains('/') && !is_known_name(name) {
+        return None;
+    }
+
+    let extension = extension.map(str::to_ascii_lowercase);
+    let (nerd, kind) = ICONS
+        .iter()
+        .find(|(keys, _, _)| keys.iter().any(|&key| key == name || Some(key) == extension.as_deref()))
+        .map_or((FILE_ICON, FileKind::File), |&(_, nerd, kind)| (nerd, kind));
+    Some(style.icon(nerd, kind))
+}
+
+fn is_known_name(name: &str) -> bool {
+    ICONS.iter().any(|(keys, _, _)| keys.contains(&name))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn no_dir(_: &str) -> bool {
+        false
+    }
+
+    #[test]
+    fn test_icon_of() {
+        assert_eq!(icon_of("src/main.rs", IconStyle::Nerd, no_dir), Some('\u{e7a8}'));
+        assert_eq!(icon_of("README.MD", IconStyle::Ascii, no_dir), Some('='));
+        assert_eq!(icon_of("photos/cat.JPG", IconStyle::Ascii, no_dir), Some('%'));
+        assert_eq!(icon_of(".gitignore", IconStyle::Ascii, no_dir), Some('~'));
+        assert_eq!(icon_of("docs/", IconStyle::Nerd, no_dir), Some(DIRECTORY_ICON));
+        assert_eq!(icon_of("/", IconStyle::Ascii, no_dir), Some('/'));
+        assert_eq!(icon_of("bin/skim", IconStyle::Ascii, no_dir), Some('-'));
+        assert_eq!(icon_of("data.unknown", IconStyle::Nerd, no_dir), Some(FILE_ICON));
+
+        // not paths
+        assert_eq!(icon_of("hello world", IconStyle::Ascii, no_dir), None);
+        assert_eq!(icon_of("The end.", IconStyle::Ascii, no_dir), None);
+        assert_eq!(icon_of("Hello. World", IconStyle::Ascii, no_dir), None);
+        assert_eq!(icon_of(" padded.rs", IconStyle::Ascii, no_dir), None);
+        assert_eq!(icon_of("", IconStyle::Ascii, no_dir), None);
+
+        // the directories without a trailing `/`, e.g. of `find` and `fd`
+        let is_dir = |text: &str| text == "src" || text == "a.d";
+        assert_eq!(icon_of("src", IconStyle::Ascii, is_dir), Some('/'));
+        assert_eq!(icon_of("a.d", IconStyle::Ascii, is_dir), Some('/'));
+        assert_eq!(icon_of("lib", IconStyle::Ascii, is_dir), None);
+    }
+
+    #[test]
+    fn test_dir_cache() {
+        let cache = DirCache::default();
+        let dir = std::env::temp_dir();
+        let dir = dir.to_str().unwrap();
+        assert!(cache.is_dir(dir));
+        assert!(!cache.is_dir("surely/not/a/dir"));
+        assert_eq!(cache.dirs.lock().len(), 2);
+        assert!(cache.is_dir(dir));
+    }
+}
@@ -1,6 +1,7 @@
 ///! Input will listens to user input, modify the query string, send special
 ///! keystrokes(such as Enter, Ctrl-p, Ctrl-n, etc) to the controller.
 use crate::event::{parse_event, Event};
+use crate::options::SkimOptions;
 use regex::Regex;
 use std::collections::HashMap;
 use tuikit::event::Event as TermEvent;
@@ -19,6 +20,27 @@ impl Input {
         }
     }
 
+    pub fn with_options(options: &SkimOptions) -> Self {
+        let mut input = Self::new();
+        if options.grid {
+            // move across the columns of the grid instead of the query cursor
+            input.bind("left", vec![Event::EvActLeft(1)]);
+            input.bind("right", vec![Event::EvActRight(1)]);
+        }
+        if options.browse.is_some() {
+            // go up a level once the query is erased
+            let parent_dir = vec![
+                Event::EvActIfQueryEmpty("parent-dir".to_string()),
+                Event::EvActBackwardDeleteChar,
+            ];
+            input.bind("bspace", parent_dir.clone());
+            input.bind("ctrl-h", parent_dir);
+        }
+        input.parse_keymaps(&options.bind);
+        input.parse_expect_keys(options.expect.as_deref());
+        input
+    }
+
     pub fn translate_event(&self, event: TermEvent) -> ActionChain {
         match event {
             // search event from keymap
@@ -76,6 +98,55 @@ impl Input {
     }
 }
 
+/// The events of skim itself that could be bound like the keys, e.g. `focus:execute-silent(...)`
+#[derive(Debug, Clone, Copy, Hash, PartialEq, Eq)]
+pub enum Trigger {
+    /// the current item changes
+    Focus,
+    /// the items are all read and matched, after the start and after the reloads
+    Load,
+    /// the query (or the command query in interactive mode) changes
+    Change,
+    /// the number of matches drops to zero
+    Zero,
+    /// the items are all matched against the query
+    Result,
+}
+
+impl Trigger {
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "focus" => Some(Trigger::Focus),
+            "load" => Some(Trigger::Load),
+            "change" => Some(Trigger::Change),
+            "zero" => Some(Trigger::Zero),
+            "result" => Some(Trigger::Result),
+            _ => None,
+        }
+    }
+}
+
+/// the actions bound to the triggers by `--bind`, the bindings of the keys are skipped
+pub fn parse_triggers(maps: &[&str]) -> HashMap<Trigger, ActionChain> {
+    let mut triggers = HashMap::new();
+    for (name, action_chain) in maps.iter().flat_map(|map| parse_key_action(map)) {
+        if let Some(trigger) = Trigger::from_name(name) {
+            let action_chain: ActionChain = action_chain
+                .into_iter()
+                .filter_map(|(action, arg)| parse_event(action, arg))
+                .collect();
+            triggers.insert(trigger, action_chain);
+        }
+    }
+    triggers
+}
+
+/// parse the keys named as in `--bind` and separated by spaces, e.g. "ctrl-k down enter", `None`
+/// if any of the names is unknown
+pub fn parse_keys(keys: &str) -> Option<Vec<Key>> {
+    keys.split_whitespace().map(from_keyname).collect()
+}
+
 type KeyActions<'a> = (&'a str, Vec<(&'a str, Option<String>)>);
 
 /// parse key action string to `(key, action, argument)` tuple
@@ -186,6 +257,16 @@ fn get_default_key_map() -> HashMap<Key, ActionChain> {
 mod test {
     use super::*;
 
+    #[test]
+    fn test_parse_keys() {
+        assert_eq!(
+            parse_keys("ctrl-k  a enter"),
+            Some(vec![Key::Ctrl('k'), Key::Char('a'), Key::Enter])
+        );
+        assert_eq!(parse_keys(""), Some(vec![]));
+        assert_eq!(parse_keys("a no-such-key"), None);
+    }
+
     #[test]
     fn execute_should_be_parsed_correctly() {
         // example from https://github.com/lotabout/skim/issues/73
@@ -238,4 +319,40 @@ mod test {
             key_action[1]
         );
     }
+
+    #[test]
+    fn preview_action_should_be_parsed() {
+        let key_action = parse_key_action("ctrl-/:preview(git log --oneline -- {})");
+        assert_eq!(
+            ("ctrl-/", vec![("preview", Some("git log --oneline -- {}".to_string()))]),
+            key_action[0]
+        );
+
+        assert_eq!(
+            parse_action_arg("preview(cat {})"),
+            Some(Event::EvActPreview("cat {}".to_string()))
+        );
+    }
+
+    #[test]
+    fn triggers_should_be_parsed() {
+        let triggers = parse_triggers(&[
+            "ctrl-a:select-all,focus:execute-silent(echo {} > /tmp/current)",
+            "change:first,load:select-all+first",
+            "zero:reload(find ~),result:refresh-preview",
+        ]);
+        assert_eq!(triggers.len(), 5);
+        assert_eq!(
+            triggers.get(&Trigger::Focus),
+            Some(&vec![Event::EvActExecuteSilent("echo {} > /tmp/current".to_string())])
+        );
+        assert_eq!(
+            triggers.get(&Trigger::Load),
+            Some(&vec![Event::EvActSelectAll, Event::EvActFirst])
+        );
+        assert_eq!(
+            triggers.get(&Trigger::Zero),
+            Some(&vec![Event::EvActReload(Some("find ~".to_string()))])
+        );
+    }
 }
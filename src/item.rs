@@ -1,62 +1,297 @@
 ///! An item is line of text that read from `find` command or stdin together with
 ///! the internal states, such as selected or not
+use std::borrow::Cow;
+#[cfg(feature = "tui")]
 use std::cmp::min;
+use std::cmp::Ordering as CmpOrd;
 use std::default::Default;
+#[cfg(feature = "tui")]
 use std::ops::Deref;
+#[cfg(feature = "tui")]
 use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::Arc;
 
+#[cfg(feature = "tui")]
+use crate::engine::index::{Required, TrigramIndex};
+#[cfg(feature = "tui")]
+use crate::engine::mask::CharMask;
+#[cfg(feature = "tui")]
+use crate::field::{get_string_by_field_with_delimiter, Delimiter, FieldRange};
+#[cfg(feature = "tui")]
 use crate::spinlock::{SpinLock, SpinLockGuard};
 use crate::SkimItem;
 
 //------------------------------------------------------------------------------
+#[cfg(feature = "tui")]
 pub type ItemIndex = (u32, u32);
 
 //------------------------------------------------------------------------------
-pub type Rank = [i32; 4];
+/// The values an item is ranked by, compared in the order of the criteria (`--tiebreak`), the
+/// smaller rank comes first. A new criteria only needs a value here and a case in `cmp_with_keys`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Rank {
+    /// the score by the engine, the greater the better
+    pub score: i32,
+    pub begin: i32,
+    pub end: i32,
+    pub length: i32,
+    criterion: Criterion,
+}
+
+impl PartialEq for Rank {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == CmpOrd::Equal
+    }
+}
+
+impl Eq for Rank {}
+
+impl PartialOrd for Rank {
+    fn partial_cmp(&self, other: &Self) -> Option<CmpOrd> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Rank {
+    /// the ranks built by the same `RankBuilder` share the criteria, the keys of `--sort-key` are
+    /// compared by `MatchedItem`
+    fn cmp(&self, other: &Self) -> CmpOrd {
+        self.cmp_with_keys(&SortKey::None, None, other, &SortKey::None, None)
+    }
+}
+
+impl Rank {
+    /// compare with the keys of `--sort-key` and the `ScoreDetail::value` of the items, they are
+    /// cached with the items instead of being carried by the ranks
+    pub fn cmp_with_keys(
+        &self,
+        key: &SortKey,
+        value: Option<f64>,
+        other: &Self,
+        other_key: &SortKey,
+        other_value: Option<f64>,
+    ) -> CmpOrd {
+        // the value breaks the ties of the score, the items without one come after the others
+        let (value, other_value) = (
+            value.unwrap_or(f64::NEG_INFINITY),
+            other_value.unwrap_or(f64::NEG_INFINITY),
+        );
+        for criteria in self.criterion.iter() {
+            let ord = match criteria {
+                RankCriteria::Score => other.score.cmp(&self.score).then(other_value.total_cmp(&value)),
+                RankCriteria::Begin => self.begin.cmp(&other.begin),
+                RankCriteria::End => self.end.cmp(&other.end),
+                RankCriteria::NegScore => self.score.cmp(&other.score).then(value.total_cmp(&other_value)),
+                RankCriteria::NegBegin => other.begin.cmp(&self.begin),
+                RankCriteria::NegEnd => other.end.cmp(&self.end),
+                RankCriteria::Length => self.length.cmp(&other.length),
+                RankCriteria::NegLength => other.length.cmp(&self.length),
+                RankCriteria::Key => key.cmp(other_key, false),
+                RankCriteria::NegKey => key.cmp(other_key, true),
+                // the ties keep the order they are read in
+                RankCriteria::Index => break,
+            };
+            if ord != CmpOrd::Equal {
+                return ord;
+            }
+        }
+        CmpOrd::Equal
+    }
+
+    /// the value of the first criteria, the smaller the better, so it agrees with the order
+    pub fn primary_key(&self, key: &SortKey) -> i64 {
+        let (score, begin, end, length) = (
+            i64::from(self.score),
+            i64::from(self.begin),
+            i64::from(self.end),
+            i64::from(self.length),
+        );
+        match self.criterion.iter().next() {
+            Some(RankCriteria::Score) => -score,
+            Some(RankCriteria::Begin) => begin,
+            Some(RankCriteria::End) => end,
+            Some(RankCriteria::NegScore) => score,
+            Some(RankCriteria::NegBegin) => -begin,
+            Some(RankCriteria::NegEnd) => -end,
+            Some(RankCriteria::Length) => length,
+            Some(RankCriteria::NegLength) => -length,
+            Some(RankCriteria::Key) => key.primary_key(false),
+            Some(RankCriteria::NegKey) => key.primary_key(true),
+            Some(RankCriteria::Index) | None => 0,
+        }
+    }
+}
+
+/// The value of the field of `--sort-key` of an item, the items without one come last in either
+/// order
+#[derive(Debug, Clone, Default, PartialEq)]
+pub enum SortKey {
+    #[default]
+    None,
+    Num(f64),
+    Str(Arc<str>),
+}
+
+impl SortKey {
+    fn cmp(&self, other: &Self, descending: bool) -> CmpOrd {
+        let ord = match (self, other) {
+            (SortKey::None, SortKey::None) => return CmpOrd::Equal,
+            (SortKey::None, _) => return CmpOrd::Greater,
+            (_, SortKey::None) => return CmpOrd::Less,
+            (SortKey::Num(a), SortKey::Num(b)) => a.total_cmp(b),
+            (SortKey::Str(a), SortKey::Str(b)) => a.cmp(b),
+            (SortKey::Num(_), SortKey::Str(_)) => CmpOrd::Less,
+            (SortKey::Str(_), SortKey::Num(_)) => CmpOrd::Greater,
+        };
+        if descending {
+            ord.reverse()
+        } else {
+            ord
+        }
+    }
+
+    /// agrees with `cmp`, the strings by their first 7 bytes
+    fn primary_key(&self, descending: bool) -> i64 {
+        let key = match self {
+            SortKey::None => return i64::MAX,
+            SortKey::Num(value) => value.floor() as i64,
+            SortKey::Str(text) => {
+                let prefix = text.bytes().chain(std::iter::repeat(0)).take(7);
+                prefix.fold(0, |key, byte| key << 8 | i64::from(byte))
+            }
+        };
+        if descending {
+            key.saturating_neg()
+        } else {
+            key
+        }
+    }
+}
+
+/// The field of `--sort-key` and whether its values are compared as numbers
+#[cfg(feature = "tui")]
+#[derive(Debug, Clone)]
+pub struct SortField {
+    delimiter: Delimiter,
+    field: FieldRange,
+    numeric: bool,
+    /// the descending order, swaps `key` and `-key` of the criteria
+    pub descending: bool,
+}
+
+#[cfg(feature = "tui")]
+impl SortField {
+    /// parse `FIELD[:num|:str][:desc]`, e.g. `3:num:desc`
+    pub fn parse(spec: &str, delimiter: Delimiter) -> Option<Self> {
+        let mut parts = spec.split(':');
+        let field = FieldRange::from_str(parts.next()?)?;
+        let mut sort_field = Self {
+            delimiter,
+            field,
+            numeric: false,
+            descending: false,
+        };
+        for flag in parts {
+            match flag {
+                "num" => sort_field.numeric = true,
+                "str" => sort_field.numeric = false,
+                "desc" => sort_field.descending = true,
+                "asc" => sort_field.descending = false,
+                _ => return None,
+            }
+        }
+        Some(sort_field)
+    }
+
+    /// the key of the text, a number is read from the start of the field, e.g. `12` of `12ms`
+    pub fn key_of(&self, text: &str) -> SortKey {
+        let value = match get_string_by_field_with_delimiter(&self.delimiter, text, &self.field) {
+            Some(value) => value.trim(),
+            None => return SortKey::None,
+        };
+        if !self.numeric {
+            return SortKey::Str(value.into());
+        }
+
+        let number_len = value
+            .char_indices()
+            .find(|&(idx, ch)| !(ch.is_ascii_digit() || ch == '.' || (idx == 0 && (ch == '-' || ch == '+'))))
+            .map(|(idx, _)| idx)
+            .unwrap_or(value.len());
+        value[..number_len]
+            .parse::<f64>()
+            .map(SortKey::Num)
+            .unwrap_or(SortKey::None)
+    }
+}
+
+/// The criteria packed 4 bits each, so that every `Rank` could carry them at no cost
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+struct Criterion(u32);
+
+impl Criterion {
+    const ALL: [RankCriteria; 11] = [
+        RankCriteria::Score,
+        RankCriteria::Begin,
+        RankCriteria::End,
+        RankCriteria::NegScore,
+        RankCriteria::NegBegin,
+        RankCriteria::NegEnd,
+        RankCriteria::Length,
+        RankCriteria::NegLength,
+        RankCriteria::Key,
+        RankCriteria::NegKey,
+        RankCriteria::Index,
+    ];
+
+    fn new(criterion: &[RankCriteria]) -> Self {
+        let codes = criterion
+            .iter()
+            .map(|criteria| 1 + Self::ALL.iter().position(|c| c == criteria).unwrap() as u32);
+        Self(
+            codes
+                .take(8)
+                .enumerate()
+                .fold(0, |packed, (i, code)| packed | code << (4 * i)),
+        )
+    }
+
+    fn iter(self) -> impl Iterator<Item = RankCriteria> {
+        (0..8)
+            .map(move |i| (self.0 >> (4 * i)) & 0xf)
+            .take_while(|&code| code != 0)
+            .map(|code| Self::ALL[code as usize - 1])
+    }
+}
 
 #[derive(Debug)]
 pub struct RankBuilder {
-    criterion: Vec<RankCriteria>,
+    criterion: Criterion,
 }
 
 impl Default for RankBuilder {
     fn default() -> Self {
-        Self {
-            criterion: vec![RankCriteria::Score, RankCriteria::Begin, RankCriteria::End],
-        }
+        Self::new(vec![RankCriteria::Score, RankCriteria::Begin, RankCriteria::End])
     }
 }
 
 impl RankBuilder {
     pub fn new(mut criterion: Vec<RankCriteria>) -> Self {
         criterion.dedup();
-        Self { criterion }
+        Self {
+            criterion: Criterion::new(&criterion),
+        }
     }
 
     /// score: the greater the better
     pub fn build_rank(&self, score: i32, begin: usize, end: usize, length: usize) -> Rank {
-        let mut rank = [0; 4];
-        let begin = begin as i32;
-        let end = end as i32;
-        let length = length as i32;
-
-        for (index, criteria) in self.criterion.iter().take(4).enumerate() {
-            let value = match criteria {
-                RankCriteria::Score => -score,
-                RankCriteria::Begin => begin,
-                RankCriteria::End => end,
-                RankCriteria::NegScore => score,
-                RankCriteria::NegBegin => -begin,
-                RankCriteria::NegEnd => -end,
-                RankCriteria::Length => length,
-                RankCriteria::NegLength => -length,
-            };
-
-            rank[index] = value;
+        Rank {
+            score,
+            begin: begin as i32,
+            end: end as i32,
+            length: length as i32,
+            criterion: self.criterion,
         }
-
-        rank
     }
 }
 
@@ -69,10 +304,66 @@ pub enum MatchedRange {
     Chars(Vec<usize>), // individual character indices matched
 }
 
+impl MatchedRange {
+    /// the indices of the chars of `text` matched
+    pub fn char_indices(&self, text: &str) -> Vec<usize> {
+        match self {
+            MatchedRange::Chars(indices) => indices.clone(),
+            MatchedRange::ByteRange(start, end) => text
+                .char_indices()
+                .enumerate()
+                .filter(|(_, (byte, _))| byte >= start && byte < end)
+                .map(|(idx, _)| idx)
+                .collect(),
+        }
+    }
+
+    /// the byte ranges of `text` matched, the adjacent chars are merged into one range, e.g. the
+    /// chars `[0, 1, 3]` of "abcd" => `[(0, 2), (3, 4)]`
+    pub fn byte_ranges(&self, text: &str) -> Vec<(usize, usize)> {
+        match self {
+            MatchedRange::ByteRange(start, end) => vec![(*start, *end)],
+            MatchedRange::Chars(indices) => {
+                let mut ranges: Vec<(usize, usize)> = Vec::new();
+                let mut indices = indices.iter().peekable();
+                for (idx, (byte, ch)) in text.char_indices().enumerate() {
+                    while indices.next_if(|&&matched| matched < idx).is_some() {}
+                    if indices.next_if_eq(&&idx).is_none() {
+                        continue;
+                    }
+                    let end = byte + ch.len_utf8();
+                    match ranges.last_mut() {
+                        Some(last) if last.1 == byte => last.1 = end,
+                        _ => ranges.push((byte, end)),
+                    }
+                }
+                ranges
+            }
+        }
+    }
+}
+
+/// The score of an engine beyond the `i32` one, for the rankers that need more than it, e.g. a
+/// floating point score or the scores of every term of the query
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ScoreDetail {
+    /// a finer score, it orders the items of the same `score` by the `score` criteria, the
+    /// greater the better
+    pub value: Option<f64>,
+    /// the scores of the terms of the query, in order
+    pub terms: Vec<i32>,
+}
+
 #[derive(Clone)]
 pub struct MatchedItem {
     pub item: Arc<dyn SkimItem>,
     pub rank: Rank,
+    /// the key of `--sort-key` of the item, computed once when it's added to the pool
+    pub key: SortKey,
+    /// the score by the engine, the greater the better, `rank` is built from it
+    pub score: i32,
+    /// set by the engines that have more to tell than `score`, shared by the clones
+    pub score_detail: Option<Arc<ScoreDetail>>,
     pub matched_range: Option<MatchedRange>, // range of chars that matched the pattern
 }
 
@@ -81,6 +372,9 @@ impl MatchedItem {
         MatchedItem {
             item,
             rank: Default::default(),
+            key: SortKey::None,
+            score: 0,
+            score_detail: None,
             matched_range: None,
         }
     }
@@ -90,15 +384,35 @@ impl MatchedItem {
         self
     }
 
+    pub fn score(mut self, score: i32) -> Self {
+        self.score = score;
+        self
+    }
+
+    pub fn score_detail(mut self, score_detail: ScoreDetail) -> Self {
+        self.score_detail = Some(Arc::new(score_detail));
+        self
+    }
+
     pub fn rank(mut self, rank: Rank) -> Self {
         self.rank = rank;
         self
     }
 
+    pub fn key(mut self, key: SortKey) -> Self {
+        self.key = key;
+        self
+    }
+
     pub fn build(self) -> Self {
         self
     }
 
+    /// the `ScoreDetail::value` of the engine, if any
+    pub fn score_value(&self) -> Option<f64> {
+        self.score_detail.as_ref().and_then(|detail| detail.value)
+    }
+
     pub fn range_char_indices(&self) -> Option<Vec<usize>> {
         self.matched_range.as_ref().map(|r| match r {
             MatchedRange::ByteRange(start, end) => {
@@ -111,11 +425,9 @@ impl MatchedItem {
     }
 }
 
-use std::cmp::Ordering as CmpOrd;
-
 impl PartialEq for MatchedItem {
     fn eq(&self, other: &Self) -> bool {
-        self.rank.eq(&other.rank)
+        self.cmp(other) == CmpOrd::Equal
     }
 }
 
@@ -123,38 +435,117 @@ impl std::cmp::Eq for MatchedItem {}
 
 impl PartialOrd for MatchedItem {
     fn partial_cmp(&self, other: &Self) -> Option<CmpOrd> {
-        self.rank.partial_cmp(&other.rank)
+        Some(self.cmp(other))
     }
 }
 
 impl Ord for MatchedItem {
     fn cmp(&self, other: &Self) -> CmpOrd {
-        self.rank.cmp(&other.rank)
+        self.rank.cmp_with_keys(
+            &self.key,
+            self.score_value(),
+            &other.rank,
+            &other.key,
+            other.score_value(),
+        )
+    }
+}
+
+/// An item matched on the fields of `change-nth` instead of its own ones, it stands for `item`
+pub(crate) struct ItemWithFields {
+    pub item: Arc<dyn SkimItem>,
+    pub ranges: Vec<(usize, usize)>,
+}
+
+impl SkimItem for ItemWithFields {
+    fn text(&self) -> Cow<'_, str> {
+        self.item.text()
+    }
+
+    fn get_matching_ranges(&self) -> Option<&[(usize, usize)]> {
+        Some(&self.ranges)
     }
 }
 
 //------------------------------------------------------------------------------
-const ITEM_POOL_CAPACITY: usize = 1024;
+/// the number of items in a chunk, a large batch is split into chunks instead of growing a vector
+#[cfg(feature = "tui")]
+const CHUNK_SIZE: usize = 4096;
 
+/// The items with their chars, only the last chunk of the pool is not full. A chunk read by a
+/// snapshot is never changed, it's copied on the next append instead.
+#[cfg(feature = "tui")]
+struct Chunk {
+    items: Vec<Arc<dyn SkimItem>>,
+    /// the chars of the items, empty without `--index`
+    masks: Vec<CharMask>,
+    /// the keys of `--sort-key`, empty without it
+    keys: Vec<SortKey>,
+    /// the trigram index of a full chunk, built by the first query looking it up
+    index: SpinLock<Option<Arc<TrigramIndex>>>,
+}
+
+#[cfg(feature = "tui")]
+impl Chunk {
+    fn new() -> Self {
+        Self {
+            items: Vec::with_capacity(CHUNK_SIZE),
+            masks: Vec::with_capacity(CHUNK_SIZE),
+            keys: Vec::new(),
+            index: SpinLock::new(None),
+        }
+    }
+}
+
+#[cfg(feature = "tui")]
+impl Clone for Chunk {
+    // keeps the capacity, so that the copy doesn't grow either
+    fn clone(&self) -> Self {
+        let mut chunk = Chunk::new();
+        chunk.items.extend(self.items.iter().cloned());
+        chunk.masks.extend_from_slice(&self.masks);
+        chunk.keys.extend(self.keys.iter().cloned());
+        chunk
+    }
+}
+
+#[cfg(feature = "tui")]
 pub struct ItemPool {
     length: AtomicUsize,
-    pool: SpinLock<Vec<Arc<dyn SkimItem>>>,
+    chunks: SpinLock<Vec<Arc<Chunk>>>,
     /// number of items that was `take`n
     taken: AtomicUsize,
+    /// increased on `clear` and when the oldest items are dropped, so that the indices of items
+    /// are only comparable in a generation
+    generation: AtomicUsize,
+    /// the number of dropped items at the start of the first chunk
+    head: AtomicUsize,
 
     /// reverse first N lines as header
     reserved_items: SpinLock<Vec<Arc<dyn SkimItem>>>,
     lines_to_reserve: usize,
+    /// keep only the newest N items (`--tail`)
+    tail: Option<usize>,
+    /// look up the full chunks in their trigram indices (`--index`)
+    index: bool,
+    /// the field the items are sorted by (`--sort-key`)
+    sort_field: Option<SortField>,
 }
 
+#[cfg(feature = "tui")]
 impl ItemPool {
     pub fn new() -> Self {
         Self {
             length: AtomicUsize::new(0),
-            pool: SpinLock::new(Vec::with_capacity(ITEM_POOL_CAPACITY)),
+            chunks: SpinLock::new(Vec::new()),
             taken: AtomicUsize::new(0),
+            generation: AtomicUsize::new(0),
+            head: AtomicUsize::new(0),
             reserved_items: SpinLock::new(Vec::new()),
             lines_to_reserve: 0,
+            tail: None,
+            index: false,
+            sort_field: None,
         }
     }
 
@@ -163,6 +554,21 @@ impl ItemPool {
         self
     }
 
+    pub fn tail(mut self, tail: Option<usize>) -> Self {
+        self.tail = tail;
+        self
+    }
+
+    pub fn index(mut self, index: bool) -> Self {
+        self.index = index;
+        self
+    }
+
+    pub fn sort_field(mut self, sort_field: Option<SortField>) -> Self {
+        self.sort_field = sort_field;
+        self
+    }
+
     pub fn len(&self) -> usize {
         self.length.load(Ordering::SeqCst)
     }
@@ -172,42 +578,112 @@ impl ItemPool {
     }
 
     pub fn clear(&self) {
-        let mut items = self.pool.lock();
-        items.clear();
+        let mut chunks = self.chunks.lock();
+        chunks.clear();
         let mut header_items = self.reserved_items.lock();
         header_items.clear();
         self.taken.store(0, Ordering::SeqCst);
         self.length.store(0, Ordering::SeqCst);
+        self.head.store(0, Ordering::SeqCst);
+        self.generation.fetch_add(1, Ordering::SeqCst);
+    }
+
+    pub fn generation(&self) -> usize {
+        self.generation.load(Ordering::SeqCst)
     }
 
     pub fn reset(&self) {
         // lock to ensure consistency
-        let _items = self.pool.lock();
+        let _chunks = self.chunks.lock();
         self.taken.store(0, Ordering::SeqCst);
     }
 
-    pub fn append(&self, mut items: Vec<Arc<dyn SkimItem>>) {
+    /// append the items, returns the oldest ones dropped for `--tail`
+    pub fn append(&self, mut items: Vec<Arc<dyn SkimItem>>) -> Vec<Arc<dyn SkimItem>> {
         let len = items.len();
         trace!("item pool, append {} items", len);
-        let mut pool = self.pool.lock();
+        // outside of the lock, the matcher might be waiting for it
+        let mut masks: Vec<CharMask> = if self.index {
+            items.iter().map(|item| CharMask::of_text(&item.text())).collect()
+        } else {
+            Vec::new()
+        };
+        let mut keys: Vec<SortKey> = match self.sort_field.as_ref() {
+            Some(sort_field) => items.iter().map(|item| sort_field.key_of(&item.text())).collect(),
+            None => Vec::new(),
+        };
+
+        let mut chunks = self.chunks.lock();
         let mut header_items = self.reserved_items.lock();
 
-        let to_reserve = self.lines_to_reserve - header_items.len();
+        let to_reserve = min(self.lines_to_reserve - header_items.len(), items.len());
         if to_reserve > 0 {
-            let to_reserve = min(to_reserve, items.len());
-            header_items.extend_from_slice(&items[..to_reserve]);
-            pool.extend_from_slice(&items[to_reserve..]);
-        } else {
-            pool.append(&mut items);
+            header_items.extend(items.drain(..to_reserve));
+            masks.drain(..to_reserve.min(masks.len()));
+            keys.drain(..to_reserve.min(keys.len()));
+        }
+
+        let mut masks = masks.into_iter();
+        let mut keys = keys.into_iter();
+        let mut new_items = items.into_iter().peekable();
+        while new_items.peek().is_some() {
+            let last_full = match chunks.last() {
+                Some(chunk) => chunk.items.len() == CHUNK_SIZE,
+                None => true,
+            };
+            if last_full {
+                chunks.push(Arc::new(Chunk::new()));
+            }
+            let chunk = Arc::make_mut(chunks.last_mut().unwrap());
+            for item in new_items.by_ref().take(CHUNK_SIZE - chunk.items.len()) {
+                chunk.items.push(item);
+                chunk.masks.extend(masks.next());
+                chunk.keys.extend(keys.next());
+            }
+        }
+        let length = self.length.fetch_add(len - to_reserve, Ordering::SeqCst) + len - to_reserve;
+
+        // drop the oldest items, the rest are indexed from 0 again, the taken ones stay taken
+        let mut dropped = Vec::new();
+        if let Some(tail) = self.tail.filter(|&tail| length > tail) {
+            let old_head = self.head.load(Ordering::SeqCst);
+            let num_dropped = length - tail;
+            dropped.extend(
+                chunks
+                    .iter()
+                    .flat_map(|chunk| chunk.items.iter())
+                    .skip(old_head)
+                    .take(num_dropped)
+                    .cloned(),
+            );
+            let head = old_head + num_dropped;
+            chunks.drain(..head / CHUNK_SIZE);
+            self.head.store(head % CHUNK_SIZE, Ordering::SeqCst);
+            self.length.store(tail, Ordering::SeqCst);
+            let taken = self.taken.load(Ordering::SeqCst);
+            self.taken.store(taken.saturating_sub(num_dropped), Ordering::SeqCst);
+            // the indices of the results of the previous runs are stale
+            self.generation.fetch_add(1, Ordering::SeqCst);
         }
-        self.length.store(pool.len(), Ordering::SeqCst);
         trace!("item pool, done append {} items", len);
+        dropped
     }
 
-    pub fn take(&self) -> ItemPoolGuard<Arc<dyn SkimItem>> {
-        let guard = self.pool.lock();
-        let taken = self.taken.swap(guard.len(), Ordering::SeqCst);
-        ItemPoolGuard { guard, start: taken }
+    /// the items not taken yet, to be read without holding the lock of the pool
+    pub fn take(&self) -> ItemSnapshot {
+        let chunks = self.chunks.lock();
+        let length = self.length.load(Ordering::SeqCst);
+        let taken = self.taken.swap(length, Ordering::SeqCst);
+        let head = self.head.load(Ordering::SeqCst);
+
+        ItemSnapshot {
+            chunks: chunks[(head + taken) / CHUNK_SIZE..].to_vec(),
+            head,
+            start: taken,
+            len: length - taken,
+            generation: self.generation(),
+            index: self.index,
+        }
     }
 
     pub fn reserved(&self) -> ItemPoolGuard<Arc<dyn SkimItem>> {
@@ -216,11 +692,105 @@ impl ItemPool {
     }
 }
 
+/// A chunk of a snapshot: the index in the pool of its first item, the items with their masks
+/// (empty without `--index`) and their keys of `--sort-key` (empty without it), and the trigram
+/// index of a full chunk if enabled
+#[cfg(feature = "tui")]
+pub type SnapshotChunk<'a> = (
+    usize,
+    &'a [Arc<dyn SkimItem>],
+    &'a [CharMask],
+    &'a [SortKey],
+    Option<ChunkIndex<'a>>,
+);
+
+/// The items of the pool at the time of `ItemPool::take`, unaffected by the later appends
+#[cfg(feature = "tui")]
+pub struct ItemSnapshot {
+    chunks: Vec<Arc<Chunk>>,
+    // the dropped items at the start of the first chunk of the pool
+    head: usize,
+    start: usize,
+    len: usize,
+    generation: usize,
+    index: bool,
+}
+
+#[cfg(feature = "tui")]
+impl ItemSnapshot {
+    /// the index of the first item in the pool
+    pub fn offset(&self) -> usize {
+        self.start
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// the generation of the pool the items belong to
+    pub fn generation(&self) -> usize {
+        self.generation
+    }
+
+    /// the chunks of the items
+    pub fn chunks(&self) -> impl Iterator<Item = SnapshotChunk<'_>> {
+        // the first chunk could be partly taken before, or partly dropped
+        let first = self.head + self.start;
+        let first_base = first - first % CHUNK_SIZE;
+        self.chunks.iter().enumerate().map(move |(idx, chunk)| {
+            let base = first_base + idx * CHUNK_SIZE;
+            let skip = first.saturating_sub(base);
+            // a full chunk is never changed, the last one is still growing
+            let index = Some(ChunkIndex { chunk, skip }).filter(|_| self.index && chunk.items.len() == CHUNK_SIZE);
+            (
+                base + skip - self.head,
+                &chunk.items[skip..],
+                chunk.masks.get(skip..).unwrap_or(&[]),
+                chunk.keys.get(skip..).unwrap_or(&[]),
+                index,
+            )
+        })
+    }
+}
+
+/// The trigram index of a full chunk of a snapshot
+#[cfg(feature = "tui")]
+pub struct ChunkIndex<'a> {
+    chunk: &'a Chunk,
+    // the items of the chunk before the ones of the snapshot
+    skip: usize,
+}
+
+#[cfg(feature = "tui")]
+impl ChunkIndex<'_> {
+    /// whether the items of the snapshot in the chunk, by their indices, could contain the
+    /// `required` substrings. `None` if all of them could.
+    pub fn candidates(&self, required: &Required) -> Option<impl Fn(usize) -> bool + Send + Sync> {
+        if *required == Required::Nothing {
+            return None;
+        }
+        let index = self
+            .chunk
+            .index
+            .lock()
+            .get_or_insert_with(|| Arc::new(TrigramIndex::of_texts(self.chunk.items.iter().map(|item| item.text()))))
+            .clone();
+        let bits = index.candidates(required)?;
+        let skip = self.skip;
+        Some(move |idx: usize| {
+            let idx = skip + idx;
+            bits[idx / 64] & (1 << (idx % 64)) != 0
+        })
+    }
+}
+
+#[cfg(feature = "tui")]
 pub struct ItemPoolGuard<'a, T: Sized + 'a> {
     guard: SpinLockGuard<'a, Vec<T>>,
     start: usize,
 }
 
+#[cfg(feature = "tui")]
 impl<'mutex, T: Sized> Deref for ItemPoolGuard<'mutex, T> {
     type Target = [T];
 
@@ -240,6 +810,11 @@ pub enum RankCriteria {
     NegEnd,
     Length,
     NegLength,
+    /// the field of `--sort-key`
+    Key,
+    NegKey,
+    /// the order of the input, the criteria after it are never reached
+    Index,
 }
 
 pub fn parse_criteria(text: &str) -> Option<RankCriteria> {
@@ -252,6 +827,291 @@ pub fn parse_criteria(text: &str) -> Option<RankCriteria> {
         "-end" => Some(RankCriteria::NegEnd),
         "length" => Some(RankCriteria::Length),
         "-length" => Some(RankCriteria::NegLength),
+        "key" => Some(RankCriteria::Key),
+        "-key" => Some(RankCriteria::NegKey),
+        "index" => Some(RankCriteria::Index),
         _ => None,
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    #[cfg(feature = "tui")]
+    use regex::Regex;
+
+    #[test]
+    fn test_rank_order() {
+        let builder = RankBuilder::new(vec![RankCriteria::Score, RankCriteria::NegBegin, RankCriteria::Length]);
+        let rank = |score, begin, length| builder.build_rank(score, begin, 0, length);
+
+        assert!(rank(10, 0, 0) < rank(5, 0, 0));
+        assert!(rank(5, 3, 9) < rank(5, 2, 0));
+        assert!(rank(5, 2, 1) < rank(5, 2, 9));
+        // the end isn't a criteria
+        assert_eq!(rank(5, 2, 1), builder.build_rank(5, 2, 7, 1));
+
+        let builder = RankBuilder::new(vec![RankCriteria::NegLength, RankCriteria::End]);
+        assert!(builder.build_rank(0, 0, 5, 9) < builder.build_rank(0, 0, 1, 3));
+        assert!(builder.build_rank(9, 0, 1, 3) < builder.build_rank(0, 0, 5, 3));
+
+        // the ties are left in the input order
+        let builder = RankBuilder::new(vec![RankCriteria::Score, RankCriteria::Index, RankCriteria::Length]);
+        assert_eq!(builder.build_rank(5, 0, 0, 9), builder.build_rank(5, 3, 0, 1));
+        assert!(builder.build_rank(6, 0, 0, 9) < builder.build_rank(5, 0, 0, 1));
+        assert_eq!(parse_criteria("index"), Some(RankCriteria::Index));
+    }
+
+    #[test]
+    fn test_score_value_order() {
+        let builder = RankBuilder::new(vec![RankCriteria::Score, RankCriteria::Begin]);
+        let matched = |score, begin, value| {
+            let item = MatchedItem::builder(Arc::new("a".to_string()))
+                .score(score)
+                .rank(builder.build_rank(score, begin, 0, 1));
+            match value {
+                Some(value) => item.score_detail(ScoreDetail {
+                    value: Some(value),
+                    terms: vec![],
+                }),
+                None => item,
+            }
+        };
+
+        // the value only breaks the ties of the score, before the next criteria
+        assert!(matched(5, 9, Some(0.7)) < matched(5, 0, Some(0.2)));
+        assert!(matched(6, 9, Some(0.2)) < matched(5, 0, Some(0.7)));
+        assert!(matched(5, 9, Some(-1e300)) < matched(5, 0, None));
+        assert!(matched(5, 0, None) < matched(5, 9, None));
+        assert!(matched(5, 0, Some(0.5)) == matched(5, 0, Some(0.5)));
+        assert!(matched(5, 0, Some(0.5)) != matched(5, 0, Some(0.2)));
+        assert_eq!(matched(5, 0, Some(0.5)).rank.primary_key(&SortKey::None), -5);
+
+        let builder = RankBuilder::new(vec![RankCriteria::NegScore]);
+        let rank = builder.build_rank(5, 0, 0, 1);
+        assert_eq!(
+            rank.cmp_with_keys(&SortKey::None, Some(0.2), &rank, &SortKey::None, Some(0.7)),
+            CmpOrd::Less
+        );
+    }
+
+    #[test]
+    fn test_byte_ranges() {
+        assert_eq!(MatchedRange::ByteRange(1, 3).byte_ranges("abcd"), vec![(1, 3)]);
+        assert_eq!(
+            MatchedRange::Chars(vec![0, 1, 3]).byte_ranges("abcd"),
+            vec![(0, 2), (3, 4)]
+        );
+        assert_eq!(MatchedRange::Chars(vec![1, 2]).byte_ranges("中文字"), vec![(3, 9)]);
+        assert_eq!(MatchedRange::Chars(vec![]).byte_ranges("abcd"), vec![]);
+
+        assert_eq!(MatchedRange::ByteRange(3, 6).char_indices("中文字"), vec![1]);
+        assert_eq!(MatchedRange::Chars(vec![0, 2]).char_indices("abc"), vec![0, 2]);
+    }
+
+    #[cfg(feature = "tui")]
+    #[test]
+    fn test_sort_key() {
+        let delimiter = Delimiter::from(Regex::new(r"\s+").unwrap());
+        let field = SortField::parse("2:num", delimiter.clone()).unwrap();
+        assert_eq!(field.key_of("a 12ms"), SortKey::Num(12.0));
+        assert_eq!(field.key_of("a -1.5"), SortKey::Num(-1.5));
+        assert_eq!(field.key_of("a b"), SortKey::None);
+        assert_eq!(field.key_of("a"), SortKey::None);
+        assert!(SortField::parse("2:number", delimiter.clone()).is_none());
+
+        let matched_item = |builder: &RankBuilder, field: &SortField, text: &str, score| {
+            MatchedItem::builder(Arc::new(text.to_string()))
+                .rank(builder.build_rank(score, 0, 0, 0))
+                .key(field.key_of(text))
+                .build()
+        };
+        let primary_key = |item: MatchedItem| item.rank.primary_key(&item.key);
+
+        let builder = RankBuilder::new(vec![RankCriteria::Key, RankCriteria::Score]);
+        let item = |text, score| matched_item(&builder, &field, text, score);
+        assert!(item("a 2", 0) < item("b 10", 0));
+        assert!(item("a 10", 9) < item("b 10", 0));
+        assert!(item("a 99", 0) < item("b -", 9));
+        assert!(primary_key(item("a 2", 0)) < primary_key(item("b 10", 0)));
+
+        let field = SortField::parse("1:desc", delimiter).unwrap();
+        assert!(field.descending);
+        let builder = RankBuilder::new(vec![RankCriteria::NegKey]);
+        let item = |text| matched_item(&builder, &field, text, 0);
+        assert!(item("bc x") < item("ab x"));
+        assert!(primary_key(item("bc x")) < primary_key(item("ab x")));
+
+        // the keys out of the range of i64 saturate, in either order
+        let num_field = SortField::parse("1:num:desc", Regex::new(r"\s+").unwrap().into()).unwrap();
+        let item = |text| matched_item(&builder, &num_field, text, 0);
+        assert!(item("1 x") < item("-1e300 x"));
+        assert!(primary_key(item("1 x")) <= primary_key(item("-1e300 x")));
+        assert!(item("-1e300 x") < item("- x"));
+        assert!(primary_key(item("-1e300 x")) <= primary_key(item("- x")));
+
+        // the keys are computed once, by the pool
+        let pool = ItemPool::new().lines_to_reserve(1).sort_field(Some(field));
+        pool.append(
+            ["h x", "b x", "a x"]
+                .iter()
+                .map(|text| Arc::new(text.to_string()) as Arc<dyn SkimItem>)
+                .collect(),
+        );
+        let snapshot = pool.take();
+        let (_, _, _, keys, _) = snapshot.chunks().next().unwrap();
+        assert_eq!(keys, &[SortKey::Str("b".into()), SortKey::Str("a".into())]);
+    }
+
+    #[cfg(feature = "tui")]
+    #[test]
+    fn test_chunks() {
+        let pool = ItemPool::new().index(true);
+        let items = |n: usize| (0..n).map(|i| Arc::new(i.to_string()) as Arc<dyn SkimItem>).collect();
+        let chunk_lens = |snapshot: &ItemSnapshot| {
+            snapshot
+                .chunks()
+                .map(|(base, items, masks, _, _)| (base, items.len(), masks.len()))
+                .collect::<Vec<_>>()
+        };
+
+        pool.append(items(CHUNK_SIZE + 10));
+        let first = pool.take();
+        // the last chunk is read by the snapshot, the items are appended to a copy of it
+        pool.append(items(CHUNK_SIZE));
+        assert_eq!(
+            chunk_lens(&first),
+            vec![(0, CHUNK_SIZE, CHUNK_SIZE), (CHUNK_SIZE, 10, 10)]
+        );
+
+        let second = pool.take();
+        assert_eq!(
+            chunk_lens(&second),
+            vec![
+                (CHUNK_SIZE + 10, CHUNK_SIZE - 10, CHUNK_SIZE - 10),
+                (2 * CHUNK_SIZE, 10, 10)
+            ]
+        );
+        assert_eq!(second.chunks().next().unwrap().1[0].text(), "0");
+        assert_eq!(pool.len(), 2 * CHUNK_SIZE + 10);
+
+        // the chars of the items are only kept with `--index`
+        let pool = ItemPool::new();
+        pool.append(items(10));
+        assert_eq!(chunk_lens(&pool.take()), vec![(0, 10, 0)]);
+    }
+
+    #[cfg(feature = "tui")]
+    #[test]
+    fn test_chunk_index() {
+        let pool = ItemPool::new().index(true);
+        let items = |texts: Vec<String>| {
+            texts
+                .into_iter()
+                .map(|text| Arc::new(text) as Arc<dyn SkimItem>)
+                .collect()
+        };
+        let matched = |items: &[Arc<dyn SkimItem>], index: Option<ChunkIndex>, substring: &str| {
+            let candidates = index
+                .unwrap()
+                .candidates(&Required::Substring(substring.to_string()))
+                .unwrap();
+            (0..items.len())
+                .filter(|&idx| candidates(idx))
+                .map(|idx| items[idx].text().to_string())
+                .collect::<Vec<_>>()
+        };
+
+        pool.append(items((0..10).map(|i| i.to_string()).collect()));
+        pool.take();
+        pool.append(items((0..CHUNK_SIZE).map(|i| format!("item-{}", i)).collect()));
+
+        // the index of the full chunk is looked up by the items of the snapshot in it
+        let snapshot = pool.take();
+        let mut chunks = snapshot.chunks();
+        let (base, items, _, _, index) = chunks.next().unwrap();
+        assert_eq!((base, items.len()), (10, CHUNK_SIZE - 10));
+        let expected: Vec<_> = (1230..1240).map(|i| format!("item-{}", i)).collect();
+        assert_eq!(
+            matched(items, index, "m-123"),
+            [vec!["item-123".to_string()], expected].concat()
+        );
+        // the last chunk is still growing
+        assert!(chunks.next().unwrap().4.is_none());
+    }
+
+    #[cfg(feature = "tui")]
+    #[test]
+    fn test_take_snapshot() {
+        let pool = ItemPool::new().lines_to_reserve(1);
+        let items = |texts: &[&'static str]| texts.iter().map(|&text| Arc::new(text) as Arc<dyn SkimItem>).collect();
+        let texts = |snapshot: &ItemSnapshot| {
+            snapshot
+                .chunks()
+                .flat_map(|(base, items, _, _, _)| {
+                    items.iter().enumerate().map(move |(i, item)| (base + i, item.text()))
+                })
+                .map(|(index, text)| format!("{}:{}", index, text))
+                .collect::<Vec<_>>()
+        };
+
+        pool.append(items(&["header", "a", "b"]));
+        let first = pool.take();
+        pool.append(items(&["c"]));
+        assert_eq!(texts(&first), vec!["0:a", "1:b"]);
+        assert_eq!(pool.reserved().len(), 1);
+
+        let second = pool.take();
+        assert_eq!((second.offset(), second.len()), (2, 1));
+        assert_eq!(texts(&second), vec!["2:c"]);
+        assert_eq!(pool.take().len(), 0);
+
+        pool.reset();
+        assert_eq!(texts(&pool.take()), vec!["0:a", "1:b", "2:c"]);
+
+        // the header is reserved again
+        pool.clear();
+        pool.append(items(&["header", "d"]));
+        let third = pool.take();
+        assert_eq!(third.generation(), first.generation() + 1);
+        assert_eq!(texts(&third), vec!["0:d"]);
+    }
+
+    #[cfg(feature = "tui")]
+    #[test]
+    fn test_tail() {
+        let pool = ItemPool::new().tail(Some(3));
+        let items =
+            |range: std::ops::Range<usize>| range.map(|i| Arc::new(i.to_string()) as Arc<dyn SkimItem>).collect();
+        let texts = |snapshot: &ItemSnapshot| {
+            snapshot
+                .chunks()
+                .flat_map(|(base, items, _, _, _)| {
+                    items.iter().enumerate().map(move |(i, item)| (base + i, item.text()))
+                })
+                .map(|(index, text)| format!("{}:{}", index, text))
+                .collect::<Vec<_>>()
+        };
+
+        pool.append(items(0..2));
+        let first = pool.take();
+        assert_eq!(texts(&first), vec!["0:0", "1:1"]);
+
+        // the oldest are dropped, the taken ones are not taken again, in a new generation
+        let dropped = pool.append(items(2..4));
+        assert_eq!(dropped.iter().map(|item| item.text()).collect::<Vec<_>>(), vec!["0"]);
+        let second = pool.take();
+        assert_eq!(second.generation(), first.generation() + 1);
+        assert_eq!(texts(&second), vec!["1:2", "2:3"]);
+
+        assert_eq!(pool.append(items(4..CHUNK_SIZE + 2)).len(), CHUNK_SIZE - 2);
+        assert_eq!(pool.len(), 3);
+        let n = CHUNK_SIZE - 1;
+        assert_eq!(
+            texts(&pool.take()),
+            vec![format!("0:{}", n), format!("1:{}", n + 1), format!("2:{}", n + 2)]
+        );
+        // the chunks of the dropped items are released
+        assert_eq!(pool.chunks.lock().len(), 2);
+    }
+}
@@ -5,45 +5,118 @@ extern crate log;
 
 use std::any::Any;
 use std::borrow::Cow;
+use std::cmp::{max, min};
 use std::fmt::Display;
+use std::sync::atomic::AtomicBool;
+#[cfg(feature = "tui")]
 use std::sync::mpsc::channel;
 use std::sync::Arc;
+#[cfg(feature = "tui")]
 use std::thread;
 
 use crossbeam::channel::{Receiver, Sender};
+#[cfg(feature = "tui")]
 use tuikit::prelude::{Event as TermEvent, *};
 
-pub use crate::ansi::AnsiString;
-pub use crate::engine::fuzzy::FuzzyAlgorithm;
+pub use crate::ansi::{ANSIParser, AnsiSpanIterator, AnsiString, AnsiStringIterator};
+use crate::attr::Attr;
+#[cfg(feature = "tui")]
+pub use crate::default_command::default_command;
+#[cfg(feature = "tui")]
+pub use crate::embed::{SkimEngine, SkimPoll};
+pub use crate::engine::command::CommandEngineFactory;
+pub use crate::engine::exact::{exact_match, ExactEngine, ExactMatchingParam};
+pub use crate::engine::fuzzy::{fuzzy_match, fuzzy_match_with, FuzzyAlgorithm, FuzzyEngine, FuzzyEngineBuilder};
+pub use crate::engine::index::Required;
+pub use crate::engine::mask::CharMask;
+pub use crate::engine::regexp::RegexEngine;
+#[cfg(feature = "tui")]
 use crate::event::{EventReceiver, EventSender};
-pub use crate::item::MatchedItem;
+#[cfg(feature = "tui")]
+pub use crate::handle::SkimHandle;
+pub use crate::helper::item::ItemWithPayload;
+pub use crate::item::{parse_criteria, MatchedItem, MatchedRange, Rank, RankBuilder, RankCriteria, ScoreDetail};
+#[cfg(feature = "tui")]
+use crate::listen::Listener;
+#[cfg(feature = "tui")]
 use crate::model::Model;
+#[cfg(feature = "tui")]
 pub use crate::options::SkimOptions;
-pub use crate::output::SkimOutput;
+pub use crate::output::{MatchedOutput, OutputFormat, SkimOutput};
+#[cfg(feature = "tui")]
 use crate::reader::Reader;
-
+#[cfg(feature = "tui")]
+use crate::record::{Recorder, Recording};
+#[cfg(feature = "tui")]
+pub use crate::rpc::{Framing, Json, RpcServer};
+#[cfg(feature = "tui")]
+use crate::signal::ReloadOnSignal;
+
+// items, fields and matching engines, always available
 mod ansi;
+pub mod attr;
 mod engine;
-mod event;
 /// provide default implementation
 pub mod field;
+mod helper;
+mod item;
+mod output;
+pub mod prelude;
+
+// the interactive finder, enabled by the `tui` feature
+#[cfg(feature = "tui")]
+mod browse;
+#[cfg(feature = "tui")]
+mod clipboard;
+#[cfg(feature = "tui")]
+mod decorator;
+#[cfg(feature = "tui")]
+mod default_command;
+#[cfg(feature = "tui")]
+mod embed;
+#[cfg(feature = "tui")]
+mod event;
+#[cfg(feature = "tui")]
 mod global;
+#[cfg(feature = "tui")]
+mod handle;
+#[cfg(feature = "tui")]
 mod header;
-mod helper;
+#[cfg(feature = "tui")]
+mod icons;
+#[cfg(feature = "tui")]
 mod input;
-mod item;
+#[cfg(feature = "tui")]
+mod listen;
+#[cfg(feature = "tui")]
 mod matcher;
+#[cfg(feature = "tui")]
 mod model;
+#[cfg(feature = "tui")]
 mod options;
+#[cfg(feature = "tui")]
 mod orderedvec;
-mod output;
-pub mod prelude;
+#[cfg(feature = "tui")]
+mod path_display;
+#[cfg(feature = "tui")]
 mod previewer;
+#[cfg(feature = "tui")]
 mod query;
+#[cfg(feature = "tui")]
 mod reader;
+#[cfg(feature = "tui")]
+mod record;
+#[cfg(feature = "tui")]
+mod rpc;
+#[cfg(feature = "tui")]
 mod selection;
+#[cfg(feature = "tui")]
+mod signal;
+#[cfg(feature = "tui")]
 mod spinlock;
+#[cfg(feature = "tui")]
 mod theme;
+#[cfg(feature = "tui")]
 mod util;
 
 //------------------------------------------------------------------------------
@@ -105,22 +178,36 @@ impl<T: Any> AsAny for T {
 pub trait SkimItem: AsAny + Send + Sync + 'static {
     /// The content to be displayed on the item list, could contain ANSI properties
     fn display<'a>(&'a self, context: DisplayContext<'a>) -> AnsiString<'a> {
-        AnsiString::from(context)
+        match self.display_text() {
+            Some(display_text) => display_text.render(context),
+            None => AnsiString::from(context),
+        }
+    }
+
+    /// The text to be displayed if it differs from the text to be matched, e.g. only the basename
+    /// with an icon while the full path is matched. The highlights of the matches are translated
+    /// to the displayed text. Only used by the default `display`.
+    fn display_text(&self) -> Option<DisplayText<'_>> {
+        None
     }
 
     /// The string to be used for matching (without color)
     fn text(&self) -> Cow<str>;
 
     /// Custom preview content, default to `ItemPreview::Global` which will use global preview
-    /// setting(i.e. the command set by `preview` option)
+    /// setting(i.e. the command set by `preview` option). It takes precedence over the `preview`
+    /// option, and the preview window is shown once an item previews itself even if the `preview`
+    /// option is not set.
     fn preview(&self, _context: PreviewContext) -> ItemPreview {
         ItemPreview::Global
     }
 
-    /// Get output text(after accept), default to `text()`
-    /// Note that this function is intended to be used by the caller of skim and will not be used by
-    /// skim. And since skim will return the item back in `SkimOutput`, if string is not what you
-    /// want, you could still use `downcast` to retain the pointer to the original struct.
+    /// Get output text(after accept), default to `text()`, could be anything independent of the
+    /// displayed and matched text, e.g. an ID.
+    /// It's what `SkimOutput::write_to` and `sk` print (`--accept-nth` picks fields of it), and what
+    /// `{}` in the preview and execute commands refers to. Since skim will return the item back in
+    /// `SkimOutput`, if string is not what you want, you could still use `downcast` to retain the
+    /// pointer to the original struct.
     fn output(&self) -> Cow<str> {
         self.text()
     }
@@ -130,6 +217,12 @@ pub trait SkimItem: AsAny + Send + Sync + 'static {
     fn get_matching_ranges(&self) -> Option<&[(usize, usize)]> {
         None
     }
+
+    /// The data attached to the item, retrieved from the selection with `SkimOutput::payloads`
+    /// so that there is no need to look up the object by the text. See `ItemWithPayload`.
+    fn payload(&self) -> Option<Arc<dyn Any + Send + Sync>> {
+        None
+    }
 }
 
 //------------------------------------------------------------------------------
@@ -143,6 +236,7 @@ impl<T: AsRef<str> + Send + Sync + 'static> SkimItem for T {
 
 //------------------------------------------------------------------------------
 // Display Context
+#[derive(Clone, Copy)]
 pub enum Matches<'a> {
     None,
     CharIndices(&'a [usize]),
@@ -175,6 +269,74 @@ impl<'a> From<DisplayContext<'a>> for AnsiString<'a> {
     }
 }
 
+/// The displayed text of an item: `prefix` followed by the `range` of `text()`, see
+/// `SkimItem::display_text`
+pub struct DisplayText<'a> {
+    /// shown before the text, e.g. an icon, never highlighted
+    pub prefix: Cow<'a, str>,
+    /// the byte range of `text()` to be shown, it's clamped to the text and its char boundaries
+    pub range: (usize, usize),
+}
+
+/// the byte range clamped to the text, its ends moved back to char boundaries
+fn clamp_byte_range(text: &str, (start, end): (usize, usize)) -> (usize, usize) {
+    let floor = |idx: usize| {
+        let mut idx = min(idx, text.len());
+        while !text.is_char_boundary(idx) {
+            idx -= 1;
+        }
+        idx
+    };
+    let end = floor(end);
+    (floor(min(start, end)), end)
+}
+
+impl DisplayText<'_> {
+    /// render the displayed text, with the matches of `context.text` translated to it
+    pub fn render(&self, context: DisplayContext) -> AnsiString<'static> {
+        let (begin, end) = clamp_byte_range(context.text, self.range);
+        let shown = &context.text[begin..end];
+        let begin_char = context.text[..begin].chars().count() as u32;
+        let end_char = begin_char + shown.chars().count() as u32;
+        let offset = self.prefix.chars().count() as u32;
+
+        // clip a char range of `text()` to the shown part and move it after the prefix
+        let translate = |start: u32, end: u32| {
+            let (start, end) = (max(start, begin_char), min(end, end_char));
+            if start < end {
+                Some((start - begin_char + offset, end - begin_char + offset))
+            } else {
+                None
+            }
+        };
+
+        let attr = context.highlight_attr;
+        let fragments = match context.matches {
+            Matches::CharIndices(indices) => indices
+                .iter()
+                .filter_map(|&idx| translate(idx as u32, idx as u32 + 1))
+                .map(|range| (attr, range))
+                .collect(),
+            Matches::CharRange(start, end) => translate(start as u32, end as u32)
+                .map(|range| (attr, range))
+                .into_iter()
+                .collect(),
+            Matches::ByteRange(start, end) => {
+                let (start, end) = clamp_byte_range(context.text, (start, end));
+                let start_char = context.text[..start].chars().count() as u32;
+                let end_char = start_char + context.text[start..end].chars().count() as u32;
+                translate(start_char, end_char)
+                    .map(|range| (attr, range))
+                    .into_iter()
+                    .collect()
+            }
+            Matches::None => vec![],
+        };
+
+        AnsiString::new_string(format!("{}{}", self.prefix, shown), fragments)
+    }
+}
+
 //------------------------------------------------------------------------------
 // Preview Context
 
@@ -220,8 +382,42 @@ impl Default for CaseMatching {
     }
 }
 
+/// The byte ranges of a text to be matched, e.g. the fields of `change-nth`
+pub type MatchingRanges<'a> = &'a dyn Fn(&str) -> Vec<(usize, usize)>;
+
 pub trait MatchEngine: Sync + Send + Display {
     fn match_item(&self, item: Arc<dyn SkimItem>) -> Option<MatchedItem>;
+
+    /// the chars that every item matched by the engine contains, the items without them are
+    /// skipped without calling `match_item` (`--index`). Default to none.
+    fn required_chars(&self) -> CharMask {
+        CharMask::default()
+    }
+
+    /// the substrings that every item matched by the engine contains, looked up in the trigram
+    /// index of the items (`--index`) to skip the ones without them. Default to nothing.
+    fn required_substrings(&self) -> Required {
+        Required::Nothing
+    }
+
+    /// called with all the items to match before `match_item`, for the engines that match them in
+    /// a batch, e.g. by an external process. Default to nothing.
+    fn prepare(&self, _items: &mut dyn Iterator<Item = &Arc<dyn SkimItem>>) {}
+
+    /// `prepare` for the items of the item pool from the index `offset` on, called by the matcher.
+    /// The items keep their indices, so an engine could keep them between the queries, e.g. send
+    /// them once to an external process. They are matched on the `matching_ranges` of their text
+    /// if set (`change-nth`), and `stopped` is set when the matching is abandoned. Default to
+    /// `prepare`.
+    fn prepare_pool(
+        &self,
+        _offset: usize,
+        items: &mut dyn Iterator<Item = &Arc<dyn SkimItem>>,
+        _matching_ranges: Option<MatchingRanges>,
+        _stopped: &AtomicBool,
+    ) {
+        self.prepare(items)
+    }
 }
 
 pub trait MatchEngineFactory {
@@ -235,10 +431,41 @@ pub trait MatchEngineFactory {
 pub type SkimItemSender = Sender<Arc<dyn SkimItem>>;
 pub type SkimItemReceiver = Receiver<Arc<dyn SkimItem>>;
 
+#[cfg(feature = "tui")]
 pub struct Skim {}
 
+#[cfg(feature = "tui")]
 impl Skim {
     pub fn run_with(options: &SkimOptions, source: Option<SkimItemReceiver>) -> Option<SkimOutput> {
+        Self::run_with_handle(options, source, &SkimHandle::new())
+    }
+
+    /// Same as `run_with`, but the running instance could be controlled through `handle`
+    pub fn run_with_handle(
+        options: &SkimOptions,
+        source: Option<SkimItemReceiver>,
+        handle: &SkimHandle,
+    ) -> Option<SkimOutput> {
+        Self::run(options, source, handle).map(|matched| matched.output)
+    }
+
+    /// Same as `run_with`, along with the ranges of the selected items matched by the final query,
+    /// e.g. for an editor to highlight them the way skim did
+    pub fn run_with_matches(options: &SkimOptions, source: Option<SkimItemReceiver>) -> Option<MatchedOutput> {
+        Self::run(options, source, &SkimHandle::new())
+    }
+
+    fn run(options: &SkimOptions, source: Option<SkimItemReceiver>, handle: &SkimHandle) -> Option<MatchedOutput> {
+        if let Some(path) = options.replay {
+            return match Recording::load(path) {
+                Ok(recording) => record::replay(options, source, recording),
+                Err(err) => {
+                    error!("failed to load recording {}: {}", path, err);
+                    None
+                }
+            };
+        }
+
         let min_height = options
             .min_height
             .map(Skim::parse_height_string)
@@ -265,9 +492,14 @@ impl Skim {
 
         //------------------------------------------------------------------------------
         // input
-        let mut input = input::Input::new();
-        input.parse_keymaps(&options.bind);
-        input.parse_expect_keys(options.expect.as_ref().map(String::as_str));
+        let input = input::Input::with_options(options);
+
+        let mut recorder = options.record.and_then(|path| {
+            let (width, height) = term.term_size().unwrap_or((80, 24));
+            Recorder::create(path, width, height)
+                .map_err(|err| error!("failed to create record file {}: {}", path, err))
+                .ok()
+        });
 
         let tx_clone = tx.clone();
         let term_clone = term.clone();
@@ -277,6 +509,10 @@ impl Skim {
                     break;
                 }
 
+                if let Some(recorder) = recorder.as_mut() {
+                    recorder.record(&key);
+                }
+
                 for ev in input.translate_event(key).into_iter() {
                     let _ = tx_clone.send(ev);
                 }
@@ -290,8 +526,24 @@ impl Skim {
 
         //------------------------------------------------------------------------------
         // model + previewer
-        let mut model = Model::new(rx, tx, reader, term.clone(), &options);
-        let ret = model.start();
+        handle.attach(tx.clone());
+        handle.attach_term(term.clone());
+        let _listener = options.listen_socket.and_then(|path| {
+            Listener::bind(path, handle.clone())
+                .map_err(|err| error!("failed to listen on socket {}: {}", path, err))
+                .ok()
+        });
+        let _reload_on_signal = if options.reload_on_signal {
+            ReloadOnSignal::install(handle.clone())
+                .map_err(|err| error!("failed to handle SIGUSR1: {}", err))
+                .ok()
+        } else {
+            None
+        };
+        let mut model = Model::new(rx, tx, reader, Some(term.clone()), options);
+        model.set_handle(handle.clone());
+        let ret = model.start().map(|output| model.matched_output(output));
+        handle.detach();
         let _ = term.send_event(TermEvent::User(())); // interrupt the input thread
         let _ = input_thread.join();
         ret
@@ -307,3 +559,57 @@ impl Skim {
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::attr::Effect;
+
+    /// the rendered text and its highlighted runs
+    fn render(text: &str, range: (usize, usize), matches: Matches) -> (String, Vec<String>) {
+        let display_text = DisplayText {
+            prefix: Cow::Borrowed("> "),
+            range,
+        };
+        let rendered = display_text.render(DisplayContext {
+            text,
+            score: 0,
+            matches,
+            container_width: 80,
+            highlight_attr: Attr::default().effect(Effect::BOLD),
+        });
+        let highlighted = rendered
+            .spans()
+            .filter(|(_, attr)| *attr != Attr::default())
+            .map(|(span, _)| span.to_string())
+            .collect();
+        (rendered.stripped().to_string(), highlighted)
+    }
+
+    #[test]
+    fn test_display_text_render() {
+        assert_eq!(
+            render("src/main.rs", (4, 11), Matches::CharIndices(&[0, 4, 5])),
+            ("> main.rs".to_string(), vec!["ma".to_string()])
+        );
+        assert_eq!(
+            render("src/main.rs", (4, 11), Matches::ByteRange(2, 6)),
+            ("> main.rs".to_string(), vec!["ma".to_string()])
+        );
+
+        // out of the text
+        assert_eq!(
+            render("abc", (1, 100), Matches::ByteRange(2, 50)),
+            ("> bc".to_string(), vec!["c".to_string()])
+        );
+        assert_eq!(
+            render("abc", (7, 9), Matches::CharRange(0, 3)),
+            ("> ".to_string(), vec![])
+        );
+        assert_eq!(render("abc", (2, 1), Matches::None), ("> ".to_string(), vec![]));
+
+        // not on the char boundaries
+        assert_eq!(render("中文字", (1, 7), Matches::ByteRange(4, 9)).0, "> 中文");
+        assert_eq!(render("中文字", (4, 5), Matches::ByteRange(5, 1)).0, "> ");
+    }
+}
@@ -0,0 +1,198 @@
+//! Remote control of a running skim through a unix socket, see `--listen-socket`.
+//!
+//! A client connects, writes a command on one line and reads the reply until the connection is
+//! closed. The commands are:
+//! - `reload [CMD]`: read the items again from CMD, or from the current command
+//! - `change-query QUERY`: replace the query
+//! - `selection`: reply with the output of the items that would be accepted, one per line
+//!
+//! The reply of the commands that don't report anything is `ok`, or `error: REASON`.
+use std::fs;
+use std::io::{self, BufRead, BufReader, Write};
+use std::os::unix::fs::FileTypeExt;
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use nix::sys::stat::{umask, Mode};
+
+use crate::handle::SkimHandle;
+
+// how often the listener checks whether skim has exited
+const ACCEPT_INTERVAL: Duration = Duration::from_millis(50);
+// how long to wait for the command of a client and for the selection reported by skim
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(1);
+
+#[derive(Debug, PartialEq, Eq)]
+enum Command {
+    Reload(Option<String>),
+    ChangeQuery(String),
+    Selection,
+}
+
+fn parse_command(line: &str) -> Result<Command, String> {
+    let line = line.trim_end_matches(['\n', '\r']);
+    let (name, arg) = match line.find(' ') {
+        Some(pos) => (&line[..pos], Some(&line[pos + 1..])),
+        None => (line, None),
+    };
+
+    match (name, arg) {
+        ("reload", arg) => Ok(Command::Reload(arg.filter(|cmd| !cmd.is_empty()).map(str::to_string))),
+        ("change-query", arg) => Ok(Command::ChangeQuery(arg.unwrap_or("").to_string())),
+        ("selection", None) => Ok(Command::Selection),
+        ("selection", Some(_)) => Err("selection takes no argument".to_string()),
+        (name, _) => Err(format!("unknown command '{}'", name)),
+    }
+}
+
+/// Listen on the socket while skim is running, the socket file is removed on drop
+pub struct Listener {
+    path: PathBuf,
+    stopped: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Listener {
+    /// a stale socket file at `path` is replaced, but not the socket of another running instance
+    /// nor a file that isn't a socket. Only the user can connect to the socket, it runs commands.
+    pub fn bind(path: &str, handle: SkimHandle) -> io::Result<Self> {
+        if UnixStream::connect(path).is_ok() {
+            return Err(io::Error::new(
+                io::ErrorKind::AddrInUse,
+                "another instance is listening",
+            ));
+        }
+        match fs::symlink_metadata(path) {
+            Ok(metadata) if metadata.file_type().is_socket() => fs::remove_file(path)?,
+            Ok(_) => {
+                return Err(io::Error::new(
+                    io::ErrorKind::AlreadyExists,
+                    "the path exists and isn't a socket",
+                ))
+            }
+            Err(_) => {}
+        }
+
+        // the socket is created with the permissions 0600 instead of setting them after the bind,
+        // so that no other user can connect in between
+        let old_mask = umask(Mode::from_bits_truncate(0o177));
+        let listener = UnixListener::bind(path);
+        umask(old_mask);
+        let listener = listener?;
+        listener.set_nonblocking(true)?;
+
+        let stopped = Arc::new(AtomicBool::new(false));
+        let stopped_clone = stopped.clone();
+        let thread = thread::spawn(move || {
+            while !stopped_clone.load(Ordering::SeqCst) {
+                match listener.accept() {
+                    Ok((stream, _)) => {
+                        if let Err(err) = serve(stream, &handle) {
+                            debug!("listen: failed to serve the client: {}", err);
+                        }
+                    }
+                    Err(ref err) if err.kind() == io::ErrorKind::WouldBlock => thread::sleep(ACCEPT_INTERVAL),
+                    Err(err) => {
+                        error!("listen: failed to accept: {}", err);
+                        break;
+                    }
+                }
+            }
+        });
+
+        Ok(Self {
+            path: PathBuf::from(path),
+            stopped,
+            thread: Some(thread),
+        })
+    }
+}
+
+impl Drop for Listener {
+    fn drop(&mut self) {
+        self.stopped.store(true, Ordering::SeqCst);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+fn serve(stream: UnixStream, handle: &SkimHandle) -> io::Result<()> {
+    stream.set_nonblocking(false)?;
+    stream.set_read_timeout(Some(REQUEST_TIMEOUT))?;
+
+    let mut line = String::new();
+    BufReader::new(&stream).read_line(&mut line)?;
+
+    let mut writer = &stream;
+    match parse_command(&line) {
+        Ok(Command::Reload(cmd)) => reply(&mut writer, handle.reload(cmd.as_deref())),
+        Ok(Command::ChangeQuery(query)) => reply(&mut writer, handle.set_query(&query)),
+        Ok(Command::Selection) => match handle.selection(REQUEST_TIMEOUT) {
+            Some(items) => items.iter().try_for_each(|item| writeln!(writer, "{}", item)),
+            None => writeln!(writer, "error: skim didn't report the selection"),
+        },
+        Err(err) => writeln!(writer, "error: {}", err),
+    }
+}
+
+fn reply(writer: &mut impl Write, sent: bool) -> io::Result<()> {
+    if sent {
+        writeln!(writer, "ok")
+    } else {
+        writeln!(writer, "error: skim isn't running")
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_command() {
+        assert_eq!(parse_command("reload\n"), Ok(Command::Reload(None)));
+        assert_eq!(
+            parse_command("reload find . -type f\n"),
+            Ok(Command::Reload(Some("find . -type f".to_string())))
+        );
+        assert_eq!(
+            parse_command("change-query a b"),
+            Ok(Command::ChangeQuery("a b".to_string()))
+        );
+        assert_eq!(parse_command("change-query"), Ok(Command::ChangeQuery(String::new())));
+        assert_eq!(parse_command("selection\r\n"), Ok(Command::Selection));
+        assert!(parse_command("selection x").is_err());
+        assert!(parse_command("quit").is_err());
+    }
+
+    #[test]
+    fn test_bind() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = std::env::temp_dir().join(format!("sk-listen-test-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("sk.sock");
+        let path_str = path.to_str().unwrap();
+
+        // a regular file isn't replaced
+        fs::write(&path, "data").unwrap();
+        assert!(Listener::bind(path_str, SkimHandle::new()).is_err());
+        assert_eq!(fs::read_to_string(&path).unwrap(), "data");
+        fs::remove_file(&path).unwrap();
+
+        // a stale socket is
+        drop(UnixListener::bind(&path).unwrap());
+        let listener = Listener::bind(path_str, SkimHandle::new()).unwrap();
+        let mode = fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+
+        drop(listener);
+        assert!(!path.exists());
+        fs::remove_dir(&dir).unwrap();
+    }
+}
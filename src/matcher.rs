@@ -1,13 +1,14 @@
-use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
 use std::sync::Arc;
 use std::thread;
 use std::thread::JoinHandle;
 
 use rayon::prelude::*;
 
-use crate::item::{ItemPool, MatchedItem};
+use crate::field::{parse_matching_fields_with_delimiter, Delimiter, FieldRange};
+use crate::item::{ItemPool, ItemWithFields, MatchedItem};
 use crate::spinlock::SpinLock;
-use crate::{CaseMatching, MatchEngineFactory};
+use crate::{CaseMatching, MatchEngine, MatchEngineFactory, MatchingRanges, SkimItem};
 use defer_drop::DeferDrop;
 use std::rc::Rc;
 
@@ -44,10 +45,73 @@ impl MatcherControl {
     }
 }
 
+//==============================================================================
+/// the number of queries to remember the failed items of
+const MAX_FAILED_HISTORY: usize = 8;
+
+/// The items that failed to match a query, by their indices in the item pool
+struct FailedItems {
+    query: String,
+    generation: usize,
+    bits: Vec<u64>,
+}
+
+impl FailedItems {
+    fn contains(&self, index: usize) -> bool {
+        self.bits
+            .get(index / 64)
+            .is_some_and(|bits| bits & (1 << (index % 64)) != 0)
+    }
+}
+
+/// whether every item matched by `query` is also matched by `prev`, so that the items failed
+/// `prev` could be skipped. e.g. "ab" => "abc", "ab" => "ab c", but not "!ab" => "!abc" or
+/// "ab" => "ab | c". It's about the extended search syntax and doesn't hold for regex.
+fn is_refinement(prev: &str, query: &str) -> bool {
+    // the escaped and quoted spaces join the terms, e.g. "a\" => "a\ b"
+    if !query.starts_with(prev) || query.contains(['|', '!', '\\', '"']) {
+        return false;
+    }
+
+    // the last term is extended unless the query starts a new one
+    let last_term = prev.rsplit(' ').next().unwrap_or("");
+    let extended = !query[prev.len()..].starts_with(' ');
+    // "ab$" => "ab$c" turns a suffix match into a fuzzy one, and a sole "'", "^" or "=" is a
+    // literal term but the prefix of the extended one
+    let sole_prefix = !last_term.is_empty() && last_term.chars().all(|ch| "'^=".contains(ch));
+    !extended || !(last_term.ends_with('$') || sole_prefix)
+}
+
+/// The fields of `change-nth` with the delimiter that splits them
+type MatchingFields = (Delimiter, Vec<FieldRange>);
+
+fn match_item(
+    engine: &dyn MatchEngine,
+    item: &Arc<dyn SkimItem>,
+    fields: Option<&MatchingFields>,
+) -> Option<MatchedItem> {
+    let (delimiter, fields) = match fields {
+        Some(fields) => fields,
+        None => return engine.match_item(item.clone()),
+    };
+    let ranges = parse_matching_fields_with_delimiter(delimiter, &item.text(), fields);
+    let mut matched = engine.match_item(Arc::new(ItemWithFields {
+        item: item.clone(),
+        ranges,
+    }))?;
+    matched.item = item.clone();
+    Some(matched)
+}
+
 //==============================================================================
 pub struct Matcher {
     engine_factory: Rc<dyn MatchEngineFactory>,
     case_matching: CaseMatching,
+    // skip the items that failed the previous queries if the new query refines them
+    prune: bool,
+    failed_history: Arc<SpinLock<Vec<Arc<FailedItems>>>>,
+    // the fields matched instead of the ones of the items, set by `change-nth`
+    matching_fields: Option<Arc<MatchingFields>>,
 }
 
 impl Matcher {
@@ -55,9 +119,18 @@ impl Matcher {
         Self {
             engine_factory,
             case_matching: CaseMatching::default(),
+            prune: false,
+            failed_history: Arc::new(SpinLock::new(Vec::new())),
+            matching_fields: None,
         }
     }
 
+    /// only for the engines that a longer query never matches more items, see `is_refinement`
+    pub fn prune(mut self, prune: bool) -> Self {
+        self.prune = prune;
+        self
+    }
+
     pub fn case(mut self, case_matching: CaseMatching) -> Self {
         self.case_matching = case_matching;
         self
@@ -67,7 +140,21 @@ impl Matcher {
         self
     }
 
-    pub fn run<C>(&self, query: &str, item_pool: Arc<DeferDrop<ItemPool>>, callback: C) -> MatcherControl
+    /// match the `fields` of the items instead of their own (`--nth`) ones, or them again if
+    /// `None`. The failed items of the previous queries don't hold for the new fields.
+    pub fn set_matching_fields(&mut self, fields: Option<MatchingFields>) {
+        self.matching_fields = fields.map(Arc::new);
+        self.failed_history = Arc::new(SpinLock::new(Vec::new()));
+    }
+
+    /// match the items not taken from the pool yet, stop after `max_matches` are found if set
+    pub fn run<C>(
+        &self,
+        query: &str,
+        item_pool: Arc<DeferDrop<ItemPool>>,
+        max_matches: Option<usize>,
+        callback: C,
+    ) -> MatcherControl
     where
         C: Fn(Arc<SpinLock<Vec<MatchedItem>>>) + Send + 'static,
     {
@@ -82,30 +169,109 @@ impl Matcher {
         let matched_items = Arc::new(SpinLock::new(Vec::new()));
         let matched_items_clone = matched_items.clone();
 
+        let prune = self.prune;
+        let matching_fields = self.matching_fields.clone();
+        let query = query.to_string();
+        let failed_history = self.failed_history.clone();
+
         let thread_matcher = thread::spawn(move || {
+            // the pool is not locked while matching, the reader keeps appending
             let items = item_pool.take();
+            let required_chars = matcher_engine.required_chars();
+            let required_substrings = matcher_engine.required_substrings();
+
+            // the latest failed items of the queries this one refines
+            let generation = items.generation();
+            let prev_failed = failed_history
+                .lock()
+                .iter()
+                .rev()
+                .find(|failed| prune && failed.generation == generation && is_refinement(&failed.query, &query))
+                .cloned();
+
+            // items before the offset are not matched again for the same query, keep their bits
+            let offset = items.offset();
+            let num_bits = (offset + items.len()).div_ceil(64);
+            let failed: Vec<AtomicU64> = (0..num_bits)
+                .map(
+                    |idx| match prev_failed.as_ref().filter(|failed| failed.query == query) {
+                        Some(prev) => AtomicU64::new(prev.bits.get(idx).copied().unwrap_or(0)),
+                        None => AtomicU64::new(0),
+                    },
+                )
+                .collect();
+            let mark_failed = |index: usize| {
+                failed[index / 64].fetch_or(1 << (index % 64), Ordering::Relaxed);
+                None
+            };
 
             // 1. use rayon for parallel
             // 2. return Err to skip iteration
             //    check https://doc.rust-lang.org/std/result/enum.Result.html#method.from_iter
 
             trace!("matcher start, total: {}", items.len());
-            let result: Result<Vec<_>, _> = items
-                .par_iter()
-                .filter_map(|item| {
+            let chunks: Vec<_> = items.chunks().collect();
+            let ranges_of = matching_fields
+                .as_ref()
+                .map(|fields| move |text: &str| parse_matching_fields_with_delimiter(&fields.0, text, &fields.1));
+            matcher_engine.prepare_pool(
+                items.offset(),
+                &mut chunks.iter().flat_map(|(_, items, _, _, _)| items.iter()),
+                ranges_of.as_ref().map(|ranges_of| ranges_of as MatchingRanges),
+                &stopped,
+            );
+            let result: Result<Vec<_>, _> = chunks
+                .into_par_iter()
+                .flat_map(|(base, items, masks, keys, chunk_index)| {
+                    let candidates = chunk_index.and_then(|chunk_index| chunk_index.candidates(&required_substrings));
+                    items.par_iter().enumerate().map(move |(idx, item)| {
+                        let candidate = masks.get(idx).map(|mask| mask.contains(required_chars)).unwrap_or(true)
+                            && candidates.as_ref().map(|candidates| candidates(idx)).unwrap_or(true);
+                        (base + idx, item, keys.get(idx), candidate)
+                    })
+                })
+                .filter_map(|(index, item, key, candidate)| {
                     processed.fetch_add(1, Ordering::Relaxed);
                     if stopped.load(Ordering::Relaxed) {
                         Some(Err("matcher killed"))
-                    } else if let Some(item) = matcher_engine.match_item(item.clone()) {
+                    } else if max_matches.is_some_and(|max| matched.load(Ordering::Relaxed) >= max) {
+                        // the rest are neither matched nor failed
+                        None
+                    } else if prev_failed.as_ref().is_some_and(|failed| failed.contains(index)) || !candidate {
+                        mark_failed(index)
+                    } else if let Some(item) = match_item(matcher_engine.as_ref(), item, matching_fields.as_deref()) {
                         matched.fetch_add(1, Ordering::Relaxed);
-                        Some(Ok(item))
+                        Some(Ok(item.key(key.cloned().unwrap_or_default())))
                     } else {
-                        None
+                        mark_failed(index)
                     }
                 })
                 .collect();
 
-            if let Ok(items) = result {
+            // even if killed, the items marked did fail the query
+            if prune {
+                let bits = failed.into_iter().map(AtomicU64::into_inner).collect();
+                let mut history = failed_history.lock();
+                // keep the ones that could still be refined to, i.e. before a deletion
+                history.retain(|prev| {
+                    prev.generation == generation && prev.query != query && is_refinement(&prev.query, &query)
+                });
+                if history.len() >= MAX_FAILED_HISTORY {
+                    history.remove(0);
+                }
+                history.push(Arc::new(FailedItems {
+                    query,
+                    generation,
+                    bits,
+                }));
+            }
+
+            if let Ok(mut items) = result {
+                // the threads could find a few more before they see the cap
+                if let Some(max) = max_matches {
+                    items.truncate(max);
+                    matched.store(items.len(), Ordering::Relaxed);
+                }
                 let mut pool = matched_items.lock();
                 *pool = items;
                 trace!("matcher stop, total matched: {}", pool.len());
@@ -124,3 +290,115 @@ impl Matcher {
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::engine::factory::ExactOrFuzzyEngineFactory;
+    use crate::item::MatchedRange;
+    use regex::Regex;
+
+    fn matcher(prune: bool) -> Matcher {
+        Matcher::builder(Rc::new(ExactOrFuzzyEngineFactory::builder().build()))
+            .prune(prune)
+            .build()
+    }
+
+    fn items(prefix: &str, num: usize) -> Vec<Arc<dyn SkimItem>> {
+        (0..num)
+            .map(|idx| Arc::new(format!("{}{}", prefix, idx)) as Arc<dyn SkimItem>)
+            .collect()
+    }
+
+    /// the texts matched by a run over the items not taken from `pool` yet
+    fn run(matcher: &Matcher, query: &str, pool: &Arc<DeferDrop<ItemPool>>, max_matches: Option<usize>) -> Vec<String> {
+        let items = matcher.run(query, pool.clone(), max_matches, |_| {}).into_items();
+        let items = items.lock();
+        items.iter().map(|matched| matched.item.text().to_string()).collect()
+    }
+
+    #[test]
+    fn test_is_refinement() {
+        assert!(is_refinement("", "a"));
+        assert!(is_refinement("ab", "abc"));
+        assert!(is_refinement("ab", "ab c"));
+        assert!(is_refinement("ab$", "ab$ c"));
+        assert!(is_refinement("'ab", "'abc"));
+        assert!(is_refinement("ab", "ab"));
+        assert!(!is_refinement("abc", "ab"));
+        assert!(!is_refinement("ab", "ab | c"));
+        assert!(!is_refinement("ab", "ab !c"));
+        assert!(!is_refinement("ab$", "ab$c"));
+        assert!(!is_refinement("ab '", "ab 'c"));
+        assert!(!is_refinement("=", "=a"));
+        assert!(!is_refinement("a\\", "a\\ b"));
+        assert!(!is_refinement("\"a", "\"a b"));
+    }
+
+    #[test]
+    fn test_max_matches() {
+        let pool = Arc::new(DeferDrop::new(ItemPool::new()));
+        pool.append(items("a", 1000));
+        let matched = run(&matcher(false), "a", &pool, Some(10));
+        assert_eq!(10, matched.len());
+        assert!(matched.iter().all(|text| text.starts_with('a')));
+
+        pool.reset();
+        assert_eq!(1000, run(&matcher(false), "a", &pool, None).len());
+    }
+
+    #[test]
+    fn test_max_matches_of_incremental_runs() {
+        let matcher = matcher(false);
+        let pool = Arc::new(DeferDrop::new(ItemPool::new()));
+        let max_matches = Some(10);
+        let mut num_matched = 0;
+
+        // the runs only match the new items, the matches of the previous ones count
+        let mut next_run = |pool: &Arc<DeferDrop<ItemPool>>| {
+            let max = max_matches.map(|max: usize| max.saturating_sub(num_matched));
+            let matched = run(&matcher, "a", pool, max).len();
+            num_matched += matched;
+            matched
+        };
+        pool.append(items("a", 4));
+        pool.append(items("b", 100));
+        assert_eq!(4, next_run(&pool));
+        pool.append(items("a", 100));
+        assert_eq!(6, next_run(&pool));
+        pool.append(items("a", 100));
+        assert_eq!(0, next_run(&pool));
+    }
+
+    #[test]
+    fn test_max_matches_not_failed() {
+        let pruning = matcher(true);
+        let pool = Arc::new(DeferDrop::new(ItemPool::new()));
+        pool.append(items("a", 1000));
+        pool.append(items("b", 1000));
+        assert_eq!(10, run(&pruning, "a", &pool, Some(10)).len());
+
+        // the items skipped for the cap didn't fail, they are matched by the same or a refined query
+        pool.reset();
+        assert_eq!(1000, run(&pruning, "a", &pool, None).len());
+        pool.reset();
+        let refined = run(&pruning, "a9", &pool, None);
+        pool.reset();
+        assert_eq!(run(&matcher(false), "a9", &pool, None), refined);
+    }
+
+    #[test]
+    fn test_match_item_fields() {
+        let engine = ExactOrFuzzyEngineFactory::builder().build().create_engine("'src");
+        let item: Arc<dyn SkimItem> = Arc::new("src/main.rs");
+        let fields = (Regex::new("/").unwrap().into(), vec![FieldRange::Single(-1)]);
+
+        assert!(match_item(engine.as_ref(), &item, None).is_some());
+        assert!(match_item(engine.as_ref(), &item, Some(&fields)).is_none());
+
+        let engine = ExactOrFuzzyEngineFactory::builder().build().create_engine("'main");
+        let matched = match_item(engine.as_ref(), &item, Some(&fields)).unwrap();
+        assert!(Arc::ptr_eq(&matched.item, &item));
+        assert_eq!(matched.matched_range, Some(MatchedRange::ByteRange(4, 8)));
+    }
+}
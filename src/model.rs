@@ -1,9 +1,15 @@
 use std::borrow::Cow;
-use std::env;
+use std::cell::Cell;
+use std::cmp::max;
+use std::collections::HashMap;
+use std::fs::OpenOptions;
+use std::io::Write;
 use std::mem;
-use std::process::Command;
+use std::path::PathBuf;
+use std::process::Stdio;
 use std::rc::Rc;
 use std::sync::Arc;
+use std::thread;
 use std::time::{Duration, Instant};
 
 use chrono::Duration as TimerDuration;
@@ -11,31 +17,48 @@ use defer_drop::DeferDrop;
 use regex::Regex;
 use timer::{Guard as TimerGuard, Timer};
 use tuikit::prelude::{Event as TermEvent, *};
-
-use crate::engine::factory::{AndOrEngineFactory, ExactOrFuzzyEngineFactory, RegexEngineFactory};
+use unicode_width::UnicodeWidthStr;
+
+use crate::browse::Browser;
+use crate::clipboard;
+use crate::decorator::Decorator;
+use crate::default_command::default_command;
+use crate::engine::command::CommandEngineFactory;
+use crate::engine::factory::{AndOrEngineFactory, ExactOrFuzzyEngineFactory, LiteralEngineFactory, RegexEngineFactory};
 use crate::event::{Event, EventHandler, EventReceiver, EventSender};
+use crate::field::{parse_delimiter, Delimiter, FieldRange};
+use crate::global::current_run_num;
+use crate::handle::SkimHandle;
 use crate::header::Header;
-use crate::input::parse_action_arg;
-use crate::item::{parse_criteria, ItemPool, RankBuilder, RankCriteria};
+use crate::input::{parse_action_arg, parse_triggers, ActionChain, Trigger};
+use crate::item::{parse_criteria, ItemPool, RankBuilder, RankCriteria, SortField};
 use crate::matcher::{Matcher, MatcherControl};
 use crate::options::SkimOptions;
-use crate::output::SkimOutput;
+use crate::output::{MatchedOutput, OutputFormat, SkimOutput};
 use crate::previewer::Previewer;
 use crate::query::Query;
 use crate::reader::{Reader, ReaderControl};
 use crate::selection::Selection;
 use crate::spinlock::SpinLock;
 use crate::theme::ColorTheme;
-use crate::util::{depends_on_items, inject_command, margin_string_to_size, parse_margin, InjectContext};
+use crate::util::{
+    depends_on_items, inject_command, margin_string_to_size, parse_margin, valid_env_vars, AsciiBorder, InjectContext,
+    Shell,
+};
 use crate::{FuzzyAlgorithm, MatchEngineFactory, SkimItem};
 
 const REFRESH_DURATION: i64 = 100;
 const SPINNER_DURATION: u32 = 200;
-// const SPINNERS: [char; 8] = ['-', '\\', '|', '/', '-', '\\', '|', '/'];
+// the shortest time between two frames while the items are still read, about 30 FPS
+const STREAMING_FRAME_DURATION: Duration = Duration::from_millis(33);
+const SPINNERS_ASCII: [char; 8] = ['-', '\\', '|', '/', '-', '\\', '|', '/'];
 const SPINNERS_INLINE: [char; 2] = ['-', '<'];
 const SPINNERS_UNICODE: [char; 10] = ['⠋', '⠙', '⠹', '⠸', '⠼', '⠴', '⠦', '⠧', '⠇', '⠏'];
 const DELIMITER_STR: &str = r"[\t\n ]+";
 
+// the size of the screen, whether the preview is shown and the lines of the header
+type Layout = ((usize, usize), bool, Option<usize>);
+
 lazy_static! {
     static ref RE_FIELDS: Regex = Regex::new(r"\\?(\{-?[0-9.,q]*?})").unwrap();
     static ref DEFAULT_CRITERION: Vec<RankCriteria> =
@@ -51,10 +74,20 @@ pub struct Model {
     exit0: bool,
 
     use_regex: bool,
+    nosort: bool,
+    // the fields matched instead of the ones of `--nth`, set by `change-nth`
+    nth: Option<String>,
+    max_matches: Option<usize>,
+    track: bool,
     regex_matcher: Matcher,
     matcher: Matcher,
 
-    term: Arc<Term>,
+    // `None` if the caller owns the terminal (e.g. `SkimEngine`)
+    term: Option<Arc<Term>>,
+    // size of the last drawn frame, used when there is no terminal
+    screen_size: Cell<(usize, usize)>,
+    // where the list and the preview were on the last frame drawn to the term
+    last_layout: Cell<Option<Layout>>,
 
     item_pool: Arc<DeferDrop<ItemPool>>,
 
@@ -73,30 +106,77 @@ pub struct Model {
     previewer: Option<Previewer>,
     preview_direction: Direction,
     preview_size: Size,
+    preview_wrap: bool,
+    preview_label: Option<String>,
+    preview_timeout: Option<Duration>,
 
     margin_top: Size,
     margin_right: Size,
     margin_bottom: Size,
     margin_left: Size,
+    border: bool,
+    border_label: Option<String>,
+    border_label_align: Align,
 
     layout: String,
-    delimiter: Regex,
+    delimiter: Delimiter,
     inline_info: bool,
+    info_format: Option<String>,
+    // no animation and a plain status line for screen readers
+    accessible: bool,
+    // ring the bell when the number of matches changes
+    bell: bool,
+    // the number of matches when all the items were last matched
+    final_matched: Option<usize>,
+    // draw the borders and spinners in ASCII
+    no_unicode: bool,
     theme: Arc<ColorTheme>,
+    // log the time spent on matching and rendering to stderr
+    debug_perf: bool,
+    // the handle of the host application, to report the selection to
+    handle: Option<SkimHandle>,
+    // the commands of `execute-bg`
+    jobs: Jobs,
+    // the actions bound to the events of skim, e.g. `focus`
+    triggers: HashMap<Trigger, ActionChain>,
+    // the current item when `focus` was last fired
+    focused_item: Option<Arc<dyn SkimItem>>,
+    // whether `load` is to be fired once the items are all read and matched
+    loading: bool,
 
     // timer thread for scheduled events
     timer: Timer,
     hb_timer_guard: Option<TimerGuard>,
+    // the shell to run the commands of the actions and the previews with
+    shell: Shell,
+    // the working directory and the extra environment variables of the preview commands
+    cmd_cwd: Option<PathBuf>,
+    cmd_env: Vec<(String, String)>,
+    // reload the items periodically, counted from the end of the last load
+    refresh_interval: Option<Duration>,
+    // the items are piped in rather than read from a command, which can't be read again
+    piped: bool,
+    refresh_timer_guard: Option<TimerGuard>,
+    // `--browse`, the items are the entries of its directory, shown in the prompt unless set
+    browser: Option<Browser>,
+    browse_prompt: bool,
+    // `--clipboard-cmd`, the command to copy with before OSC 52
+    clipboard_cmd: Option<String>,
+    // the fields of the items copied by `yank-item`, all of the output if empty
+    accept_nth: Vec<FieldRange>,
 
     next_idx_to_append: u32, // for AppendAndSelect action
 }
 
 impl Model {
-    pub fn new(rx: EventReceiver, tx: EventSender, reader: Reader, term: Arc<Term>, options: &SkimOptions) -> Self {
-        let default_command = match env::var("SKIM_DEFAULT_COMMAND").as_ref().map(String::as_ref) {
-            Ok("") | Err(_) => "find .".to_owned(),
-            Ok(val) => val.to_owned(),
-        };
+    pub fn new(
+        rx: EventReceiver,
+        tx: EventSender,
+        reader: Reader,
+        term: Option<Arc<Term>>,
+        options: &SkimOptions,
+    ) -> Self {
+        let default_command = default_command();
 
         let theme = Arc::new(ColorTheme::init_from_options(options));
         let query = Query::from_options(&options)
@@ -104,22 +184,71 @@ impl Model {
             .theme(theme.clone())
             .build();
 
-        let criterion = if let Some(ref tie_breaker) = options.tiebreak {
+        let mut criterion: Vec<RankCriteria> = if let Some(ref tie_breaker) = options.tiebreak {
             tie_breaker.split(',').filter_map(parse_criteria).collect()
         } else {
             DEFAULT_CRITERION.clone()
         };
 
+        // the field of `--sort-key` comes first, unless it's placed in the `--tiebreak`
+        let sort_field = options.sort_key.and_then(|spec| {
+            let delimiter = options.delimiter.and_then(|delimiter| parse_delimiter(delimiter).ok());
+            SortField::parse(
+                spec,
+                delimiter.unwrap_or_else(|| Regex::new(DELIMITER_STR).unwrap().into()),
+            )
+        });
+        if let Some(sort_field) = sort_field.as_ref() {
+            if !criterion
+                .iter()
+                .any(|c| matches!(c, RankCriteria::Key | RankCriteria::NegKey))
+            {
+                criterion.insert(0, RankCriteria::Key);
+            }
+            if sort_field.descending {
+                for criteria in criterion.iter_mut() {
+                    *criteria = match *criteria {
+                        RankCriteria::Key => RankCriteria::NegKey,
+                        RankCriteria::NegKey => RankCriteria::Key,
+                        criteria => criteria,
+                    };
+                }
+            }
+        }
+
         let rank_builder = Arc::new(RankBuilder::new(criterion));
 
-        let selection = Selection::with_options(options).theme(theme.clone());
+        let decorator = options.format_cmd.and_then(|cmd| {
+            Decorator::spawn(options.shell_command(cmd), tx.clone())
+                .map_err(|err| error!("failed to start the format command {:?}: {}", cmd, err))
+                .ok()
+        });
+        let selection = Selection::with_options(options)
+            .theme(theme.clone())
+            .decorator(decorator);
         let regex_engine: Rc<dyn MatchEngineFactory> =
             Rc::new(RegexEngineFactory::builder().rank_builder(rank_builder.clone()).build());
         let regex_matcher = Matcher::builder(regex_engine).build();
 
+        let command_engine_factory = options.matcher_cmd.and_then(|cmd| {
+            CommandEngineFactory::spawn(options.shell_command(cmd), options.read0)
+                .map_err(|err| error!("failed to start the matcher command {:?}: {}", cmd, err))
+                .ok()
+        });
         let matcher = if let Some(engine_factory) = options.engine_factory.as_ref() {
             // use provided engine
             Matcher::builder(engine_factory.clone()).case(options.case).build()
+        } else if let Some(command_engine_factory) = command_engine_factory {
+            let command_engine_factory: Rc<dyn MatchEngineFactory> =
+                Rc::new(command_engine_factory.rank_builder(rank_builder.clone()).build());
+            Matcher::builder(command_engine_factory).case(options.case).build()
+        } else if options.literal {
+            let literal_engine_factory: Rc<dyn MatchEngineFactory> =
+                Rc::new(LiteralEngineFactory::builder().rank_builder(rank_builder).build());
+            Matcher::builder(literal_engine_factory)
+                .case(options.case)
+                .prune(true)
+                .build()
         } else {
             let fuzzy_engine_factory: Rc<dyn MatchEngineFactory> = Rc::new(AndOrEngineFactory::new(
                 ExactOrFuzzyEngineFactory::builder()
@@ -127,10 +256,19 @@ impl Model {
                     .rank_builder(rank_builder)
                     .build(),
             ));
-            Matcher::builder(fuzzy_engine_factory).case(options.case).build()
+            Matcher::builder(fuzzy_engine_factory)
+                .case(options.case)
+                .prune(true)
+                .build()
         };
 
-        let item_pool = Arc::new(DeferDrop::new(ItemPool::new().lines_to_reserve(options.header_lines)));
+        let item_pool = Arc::new(DeferDrop::new(
+            ItemPool::new()
+                .lines_to_reserve(options.header_lines)
+                .tail(options.tail)
+                .index(options.index)
+                .sort_field(sort_field),
+        ));
         let header = Header::empty()
             .with_options(options)
             .item_pool(item_pool.clone())
@@ -142,6 +280,7 @@ impl Model {
             .expect("option margin is should be specified (by default)");
         let (margin_top, margin_right, margin_bottom, margin_left) = margins;
 
+        let piped = reader.has_source();
         let mut ret = Model {
             reader,
             query,
@@ -150,9 +289,15 @@ impl Model {
             select1: false,
             exit0: false,
             use_regex: options.regex,
+            nosort: options.nosort,
+            nth: None,
+            max_matches: options.max_matches,
+            track: options.track,
             regex_matcher,
             matcher,
             term,
+            screen_size: Cell::new((0, 0)),
+            last_layout: Cell::new(None),
             item_pool,
 
             rx,
@@ -168,18 +313,45 @@ impl Model {
             previewer: None,
             preview_direction: Direction::Right,
             preview_size: Size::Default,
+            preview_wrap: false,
+            preview_label: None,
+            preview_timeout: None,
 
             margin_top,
             margin_right,
             margin_bottom,
             margin_left,
+            border: false,
+            border_label: None,
+            border_label_align: Align::Left,
 
             layout: "default".to_string(),
-            delimiter: Regex::new(DELIMITER_STR).unwrap(),
+            delimiter: Regex::new(DELIMITER_STR).unwrap().into(),
             inline_info: false,
+            accessible: false,
+            bell: false,
+            final_matched: None,
+            no_unicode: false,
+            info_format: None,
+            debug_perf: options.debug_perf,
             theme,
+            handle: None,
+            jobs: Jobs::default(),
+            triggers: HashMap::new(),
+            focused_item: None,
+            loading: false,
             timer: Timer::new(),
             hb_timer_guard: None,
+            shell: Shell::default(),
+            cmd_cwd: None,
+            cmd_env: Vec::new(),
+            refresh_interval: None,
+            piped,
+            refresh_timer_guard: None,
+            browser: None,
+            browse_prompt: false,
+            clipboard_cmd: None,
+            accept_nth: Vec::new(),
 
             next_idx_to_append: 0,
         };
@@ -189,15 +361,28 @@ impl Model {
 
     fn parse_options(&mut self, options: &SkimOptions) {
         if let Some(delimiter) = options.delimiter {
-            self.delimiter = Regex::new(delimiter).unwrap_or_else(|_| Regex::new(DELIMITER_STR).unwrap());
+            self.delimiter = parse_delimiter(delimiter).unwrap_or_else(|_| Regex::new(DELIMITER_STR).unwrap().into());
         }
 
         self.layout = options.layout.to_string();
 
+        self.border = options.border;
+        self.border_label = options.border_label.map(str::to_string);
+        self.border_label_align = match options.border_label_pos {
+            "center" => Align::Center,
+            "right" => Align::Right,
+            _ => Align::Left,
+        };
+
         if options.inline_info {
             self.inline_info = true;
         }
 
+        self.info_format = options.info_format.map(str::to_string);
+        self.accessible = options.accessible;
+        self.bell = options.bell;
+        self.no_unicode = options.no_unicode;
+
         if options.regex {
             self.use_regex = true;
         }
@@ -211,21 +396,51 @@ impl Model {
             .expect("option 'preview-window' should be set (by default)");
         self.preview_direction = preview_direction;
         self.preview_size = preview_size;
+        self.preview_wrap = preview_wrap;
         self.preview_hidden = !preview_shown;
-
-        if let Some(preview_cmd) = options.preview {
-            let tx = Arc::new(SpinLock::new(self.tx.clone()));
-            self.previewer = Some(
-                Previewer::new(Some(preview_cmd.to_string()), move || {
-                    let _ = tx.lock().send(Event::EvHeartBeat);
-                })
-                .wrap(preview_wrap)
-                .delimiter(self.delimiter.clone()),
-            );
+        self.preview_label = options.preview_label.map(str::to_string);
+        self.preview_timeout = options.preview_timeout.map(Duration::from_millis);
+        if let Some(shell) = options.shell.and_then(Shell::parse) {
+            self.shell = shell;
         }
+        self.cmd_cwd = options.cmd_cwd.map(PathBuf::from);
+        self.cmd_env = valid_env_vars(&options.cmd_env);
+        self.refresh_interval = options
+            .refresh_interval
+            .filter(|secs| secs.is_finite() && *secs > 0.0)
+            .map(Duration::from_secs_f64);
+
+        // without `--preview`, the previewer is still needed for the items that preview themselves
+        self.previewer = Some(self.new_previewer(options.preview.map(str::to_string)));
+
+        self.triggers = parse_triggers(&options.bind);
 
         self.select1 = options.select1;
         self.exit0 = options.exit0;
+
+        self.browser = options.browse.and_then(|dir| {
+            Browser::new(dir)
+                .map_err(|err| error!("failed to browse {:?}: {}", dir, err))
+                .ok()
+        });
+        self.browse_prompt = options.browse_prompt;
+        self.clipboard_cmd = options.clipboard_cmd.map(str::to_string);
+        self.accept_nth = options
+            .accept_nth
+            .map(|nth| nth.split(',').filter_map(FieldRange::from_str).collect())
+            .unwrap_or_default();
+    }
+
+    fn new_previewer(&self, preview_cmd: Option<String>) -> Previewer {
+        let tx = Arc::new(SpinLock::new(self.tx.clone()));
+        Previewer::new(preview_cmd, move || {
+            let _ = tx.lock().send(Event::EvHeartBeat);
+        })
+        .wrap(self.preview_wrap)
+        .delimiter(self.delimiter.clone())
+        .label(self.preview_label.clone())
+        .timeout(self.preview_timeout)
+        .shell(self.shell.clone().cwd(self.cmd_cwd.clone()).envs(self.cmd_env.clone()))
     }
 
     // -> (direction, size, wrap, shown)
@@ -275,6 +490,9 @@ impl Model {
         if matcher_stopped {
             let reader_stopped = self.reader_control.as_ref().map(ReaderControl::is_done).unwrap_or(true);
             let ctrl = self.matcher_control.take().unwrap();
+            if self.debug_perf && ctrl.get_num_processed() > 0 {
+                self.log_match_perf(&ctrl);
+            }
             let lock = ctrl.into_items();
             let mut items = lock.lock();
             let matched = mem::replace(&mut *items, Vec::new());
@@ -294,6 +512,24 @@ impl Model {
             };
             self.num_options += matched.len();
             self.selection.append_sorted_items(matched);
+
+            // only the final count of a query, not the ones while the input is still read
+            if reader_stopped && self.item_pool.num_not_taken() == 0 {
+                let last_matched = self.final_matched.replace(self.num_options);
+                if self.bell && last_matched.is_some_and(|matched| matched != self.num_options) {
+                    ring_bell();
+                }
+
+                if self.loading {
+                    self.loading = false;
+                    self.fire(Trigger::Load);
+                    self.schedule_refresh();
+                }
+                if self.num_options == 0 && last_matched != Some(0) {
+                    self.fire(Trigger::Zero);
+                }
+                self.fire(Trigger::Result);
+            }
         }
 
         let items_consumed = self.item_pool.num_not_taken() == 0;
@@ -317,6 +553,21 @@ impl Model {
         }
     }
 
+    /// print the statistics of a finished matcher run, one `key=value` record per line
+    fn log_match_perf(&self, ctrl: &MatcherControl) {
+        let elapsed = self.matcher_timer.elapsed();
+        let processed = ctrl.get_num_processed();
+        let rate = processed as u128 * 1_000_000 / max(elapsed.as_micros(), 1);
+        eprintln!(
+            "[perf] event=match query={:?} processed={} matched={} time_ms={:.3} items_per_sec={}",
+            self.query.get_fz_query(),
+            processed,
+            ctrl.get_num_matched(),
+            elapsed.as_secs_f64() * 1000.0,
+            rate
+        );
+    }
+
     fn act_rotate_mode(&mut self, env: &mut ModelEnv) {
         self.use_regex = !self.use_regex;
 
@@ -331,6 +582,44 @@ impl Model {
         self.restart_matcher();
     }
 
+    fn act_toggle_sort(&mut self, env: &mut ModelEnv) {
+        self.nosort = !self.nosort;
+        self.selection.nosort(self.nosort);
+
+        // restart matcher
+        if let Some(ctrl) = self.matcher_control.take() {
+            ctrl.kill();
+        }
+
+        env.clear_selection = ClearStrategy::Clear;
+        self.item_pool.reset();
+        self.num_options = 0;
+        self.restart_matcher();
+    }
+
+    /// match the fields `nth` instead of the ones of `--nth`, or them again if empty
+    fn act_change_nth(&mut self, env: &mut ModelEnv, nth: &str) {
+        let fields: Vec<FieldRange> = nth
+            .split(',')
+            .filter(|field| !field.is_empty())
+            .filter_map(FieldRange::from_str)
+            .collect();
+        self.nth = Some(nth.to_string()).filter(|_| !fields.is_empty());
+        let matching_fields = Some((self.delimiter.clone(), fields)).filter(|(_, fields)| !fields.is_empty());
+        self.matcher.set_matching_fields(matching_fields.clone());
+        self.regex_matcher.set_matching_fields(matching_fields);
+
+        // restart matcher
+        if let Some(ctrl) = self.matcher_control.take() {
+            ctrl.kill();
+        }
+
+        env.clear_selection = ClearStrategy::Clear;
+        self.item_pool.reset();
+        self.num_options = 0;
+        self.restart_matcher();
+    }
+
     fn handle_select1_or_exit0(&mut self) {
         if !self.select1 && !self.exit0 {
             return;
@@ -349,12 +638,31 @@ impl Model {
             } else if num_matched == 0 && self.exit0 {
                 debug!("exit-0 triggered, accept");
                 let _ = self.tx.send(Event::EvActAbort);
-            } else {
-                let _ = self.term.restart();
+            } else if let Some(term) = self.term.as_ref() {
+                let _ = term.restart();
             }
         }
     }
 
+    /// reload the items once the refresh interval elapses, keeping the query and the selections
+    fn schedule_refresh(&mut self) {
+        if self.piped {
+            return;
+        }
+        let delay = match self
+            .refresh_interval
+            .and_then(|interval| TimerDuration::from_std(interval).ok())
+        {
+            Some(delay) => delay,
+            None => return,
+        };
+        let tx = self.tx.clone();
+        let guard = self.timer.schedule_with_delay(delay, move || {
+            let _ = tx.send(Event::EvActReload(None));
+        });
+        self.refresh_timer_guard.replace(guard);
+    }
+
     fn on_cmd_query_change(&mut self, env: &mut ModelEnv) {
         // stop matcher
         if let Some(ctrl) = self.reader_control.take() {
@@ -369,17 +677,118 @@ impl Model {
         self.num_options = 0;
 
         // restart reader
+        let last_run_num = current_run_num();
         self.reader_control.replace(self.reader.run(&env.cmd));
+        self.loading = true;
+        self.selection.prepare_reload(last_run_num, current_run_num());
         self.restart_matcher();
         self.reader_timer = Instant::now();
     }
 
+    /// list the directory browsed now, with an empty query
+    fn act_browse(&mut self, env: &mut ModelEnv) {
+        let browser = match self.browser.as_ref() {
+            Some(browser) => browser,
+            None => return,
+        };
+        if let Some(ctrl) = self.reader_control.take() {
+            ctrl.kill();
+        }
+        if let Some(ctrl) = self.matcher_control.take() {
+            ctrl.kill();
+        }
+
+        self.reader.set_source(browser.list());
+        if self.browse_prompt {
+            self.query.set_query_prompt(&browser.prompt());
+        }
+        self.query.act_change_query("");
+        env.query = self.query.get_fz_query();
+
+        env.clear_selection = ClearStrategy::Clear;
+        self.selection.handle(&Event::EvActFirst);
+        self.item_pool.clear();
+        self.num_options = 0;
+        self.reader_control.replace(self.reader.run(&env.cmd));
+        self.loading = true;
+        self.restart_matcher();
+        self.reader_timer = Instant::now();
+    }
+
+    fn act_parent_dir(&mut self, env: &mut ModelEnv) {
+        if self.browser.as_mut().is_some_and(Browser::parent) {
+            self.act_browse(env);
+        }
+    }
+
+    /// copy the selected items, or the current one, to the clipboard, as they would be printed
+    fn act_yank_item(&mut self) {
+        let (_, items) = self.selection.get_selected_indices_and_items();
+        if items.is_empty() {
+            return;
+        }
+        let format = OutputFormat {
+            accept_nth: Some((&self.delimiter, self.accept_nth.as_slice())).filter(|(_, fields)| !fields.is_empty()),
+            ..Default::default()
+        };
+        let outputs: Vec<Cow<str>> = items.iter().map(|item| format.output_of(item.as_ref())).collect();
+        self.copy_to_clipboard(&outputs.join("\n"));
+    }
+
+    fn act_yank_query(&mut self, env: &ModelEnv) {
+        let query = if env.in_query_mode { &env.query } else { &env.cmd_query };
+        self.copy_to_clipboard(query);
+    }
+
+    /// copy by `--clipboard-cmd` in the background, a clipboard tool could take a while (e.g.
+    /// xclip waits for the X server) and the UI shouldn't wait for it. Without the command, or if
+    /// it fails, copy by OSC 52, written here as the terminal is drawn by this thread.
+    fn copy_to_clipboard(&self, text: &str) {
+        match self.clipboard_cmd.as_ref() {
+            Some(cmd) => {
+                let command = self.shell.command(cmd);
+                let text = text.to_string();
+                let tx = self.tx.clone();
+                thread::spawn(move || {
+                    if let Err(err) = clipboard::copy(&text, Some(command)) {
+                        debug!("failed to copy by the clipboard command: {}", err);
+                        let _ = tx.send(Event::EvClipboardCmdFailed(text));
+                    }
+                });
+            }
+            None => {
+                if let Err(err) = clipboard::copy(text, None) {
+                    debug!("failed to copy to the clipboard: {}", err);
+                }
+            }
+        }
+    }
+
+    fn act_reload(&mut self, env: &mut ModelEnv, cmd: Option<&str>) {
+        match cmd {
+            Some(cmd) => {
+                self.query.set_base_cmd(cmd);
+                self.piped = false;
+            }
+            // the piped input can't be read again, and there's no command to run instead
+            None if self.piped => return,
+            None => {}
+        }
+        env.cmd = self.query.get_cmd();
+        self.on_cmd_query_change(env);
+    }
+
     fn on_query_change(&mut self, env: &mut ModelEnv) {
         // restart matcher
         if let Some(ctrl) = self.matcher_control.take() {
             ctrl.kill();
         }
         env.clear_selection = ClearStrategy::Clear;
+        if self.track {
+            self.selection.track_current_item();
+        } else {
+            self.selection.untrack_cursor();
+        }
         self.item_pool.reset();
         self.num_options = 0;
         self.restart_matcher();
@@ -393,20 +802,72 @@ impl Model {
             return;
         }
 
-        let _ = self.term.pause();
+        if let Some(term) = self.term.as_ref() {
+            let _ = term.pause();
+        }
         self.act_execute_silent(cmd);
-        let _ = self.term.restart();
+        if let Some(term) = self.term.as_ref() {
+            let _ = term.restart();
+        }
     }
 
     fn act_execute_silent(&mut self, cmd: &str) {
-        let current_index = self.selection.get_current_item_idx();
-        let current_item = self.selection.get_current_item();
-        if depends_on_items(cmd) && current_item.is_none() {
+        if depends_on_items(cmd) && self.selection.get_current_item().is_none() {
             debug!("act_execute_silent: command refers to items and there is no item for now");
             debug!("command to execute: [{}]", cmd);
             return;
         }
 
+        let cmd = self.injected_command(cmd);
+        let _ = self.shell.command(&cmd).status();
+    }
+
+    /// run the actions bound to the trigger, after the current event
+    fn fire(&self, trigger: Trigger) {
+        for ev in self.triggers.get(&trigger).into_iter().flatten() {
+            let _ = self.tx.send(ev.clone());
+        }
+    }
+
+    /// run the command in background, its exit status is shown in the info line when it's done
+    fn act_execute_bg(&mut self, cmd: &str) {
+        if depends_on_items(cmd) && self.selection.get_current_item().is_none() {
+            debug!("act_execute_bg: command refers to items and there is no item for now");
+            debug!("command to execute: [{}]", cmd);
+            return;
+        }
+
+        let mut command = self.shell.command(&self.injected_command(cmd));
+        let tx = self.tx.clone();
+        self.jobs.running += 1;
+        thread::spawn(move || {
+            let status = command
+                .stdin(Stdio::null())
+                .stdout(Stdio::null())
+                .stderr(Stdio::null())
+                .status();
+            let _ = tx.send(Event::EvExecuteBgDone(status.ok().and_then(|status| status.code())));
+        });
+    }
+
+    /// run `cmd` and use its output as the new query
+    fn act_transform_query(&mut self, cmd: &str) -> Option<String> {
+        if depends_on_items(cmd) && self.selection.get_current_item().is_none() {
+            debug!("act_transform_query: command refers to items and there is no item for now");
+            debug!("command to execute: [{}]", cmd);
+            return None;
+        }
+
+        let cmd = self.injected_command(cmd);
+        let output = self.shell.command(&cmd).stderr(Stdio::null()).output().ok()?;
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        Some(stdout.trim_end_matches(&['\n', '\r'][..]).to_string())
+    }
+
+    /// inject the placeholders(e.g. `{}`, `{q}`) in `cmd` with current states
+    fn injected_command(&self, cmd: &str) -> String {
+        let current_index = self.selection.get_current_item_idx();
+        let current_item = self.selection.get_current_item();
         let current_selection = current_item
             .as_ref()
             .map(|item| item.output())
@@ -428,9 +889,7 @@ impl Model {
             cmd_query: &cmd_query,
         };
 
-        let cmd = inject_command(cmd, context).to_string();
-        let shell = env::var("SHELL").unwrap_or_else(|_| "sh".to_string());
-        let _ = Command::new(shell).arg("-c").arg(cmd).status();
+        inject_command(cmd, context).to_string()
     }
 
     #[allow(clippy::trivial_regex)]
@@ -446,14 +905,60 @@ impl Model {
 
         self.next_idx_to_append += 1;
 
-        self.item_pool.append(vec![item.clone()]);
+        self.append_items(vec![item.clone()]);
         self.selection.act_select_item(item_index, item);
 
         self.act_heart_beat(env);
     }
 
+    /// report the selection to `handle` when it asks
+    pub fn set_handle(&mut self, handle: SkimHandle) {
+        self.handle = Some(handle);
+    }
+
     pub fn start(&mut self) -> Option<SkimOutput> {
-        let mut env = ModelEnv {
+        let mut env = self.prepare();
+
+        // In the event loop, there might need
+        let mut next_event = Some(Event::EvHeartBeat);
+        let mut last_render: Option<Instant> = None;
+        loop {
+            let ev = next_event.take().or_else(|| self.rx.recv().ok())?;
+            let is_heart_beat = matches!(ev, Event::EvHeartBeat);
+            match self.handle_event(ev, &mut env) {
+                Step::Chain(ev) => next_event = ev,
+                Step::Redraw(ev) => {
+                    next_event = ev;
+                    // the heart beats come as fast as the matcher runs while the items stream in,
+                    // coalesce them so the counters are repainted in batches; the keys are always
+                    // drawn at once and the heart beats go on until the reader is done
+                    let reading = self.reader_control.as_ref().map(|c| !c.is_done()).unwrap_or(false);
+                    if is_heart_beat && reading && last_render.is_some_and(|at| at.elapsed() < STREAMING_FRAME_DURATION)
+                    {
+                        continue;
+                    }
+                    if let Some(term) = self.term.as_ref() {
+                        let render_timer = Instant::now();
+                        // the screen buffer of the term keeps the rows of the list and the preview
+                        // between frames, only the changed ones are drawn unless they moved
+                        self.invalidate_moved();
+                        let _ = self.do_with_widget(|root| term.draw(&root));
+                        let _ = term.present();
+                        last_render = Some(Instant::now());
+                        if self.debug_perf {
+                            let elapsed = render_timer.elapsed();
+                            eprintln!("[perf] event=render time_ms={:.3}", elapsed.as_secs_f64() * 1000.0);
+                        }
+                    }
+                }
+                Step::Exit(output) => return output,
+            }
+        }
+    }
+
+    /// start the reader, return the initial environment for `handle_event`
+    pub fn prepare(&mut self) -> ModelEnv {
+        let env = ModelEnv {
             cmd: self.query.get_cmd(),
             query: self.query.get_fz_query(),
             cmd_query: self.query.get_cmd_query(),
@@ -461,55 +966,83 @@ impl Model {
             clear_selection: ClearStrategy::DontClear,
         };
 
+        if let Some(browser) = self.browser.as_ref() {
+            self.reader.set_source(browser.list());
+            if self.browse_prompt {
+                self.query.set_query_prompt(&browser.prompt());
+            }
+        }
         self.reader_control = Some(self.reader.run(&env.cmd));
+        self.loading = true;
+        env
+    }
 
-        // In the event loop, there might need
-        let mut next_event = Some(Event::EvHeartBeat);
-        loop {
-            let ev = next_event.take().or_else(|| self.rx.recv().ok())?;
-
-            debug!("handle event: {:?}", ev);
+    /// handle one event, the caller is responsible for drawing and feeding the next event
+    pub fn handle_event(&mut self, ev: Event, env: &mut ModelEnv) -> Step {
+        debug!("handle event: {:?}", ev);
+        let mut next_event = None;
+
+        match ev {
+            Event::EvHeartBeat => {
+                // consume following HeartBeat event
+                next_event = self.consume_additional_event(&Event::EvHeartBeat);
+                self.act_heart_beat(env);
+                self.handle_select1_or_exit0();
+            }
 
-            match ev {
-                Event::EvHeartBeat => {
-                    // consume following HeartBeat event
-                    next_event = self.consume_additional_event(&Event::EvHeartBeat);
-                    self.act_heart_beat(&mut env);
-                    self.handle_select1_or_exit0();
+            Event::EvActIfNonMatched(ref arg_str) => {
+                let matched =
+                    self.num_options + self.matcher_control.as_ref().map(|c| c.get_num_matched()).unwrap_or(0);
+                if matched == 0 {
+                    return Step::Chain(parse_action_arg(arg_str));
                 }
+            }
 
-                Event::EvActIfNonMatched(ref arg_str) => {
-                    let matched =
-                        self.num_options + self.matcher_control.as_ref().map(|c| c.get_num_matched()).unwrap_or(0);
-                    if matched == 0 {
-                        next_event = parse_action_arg(arg_str);
-                        continue;
-                    }
-                }
+            Event::EvActIfQueryEmpty(ref arg_str) if env.query.is_empty() => {
+                return Step::Chain(parse_action_arg(arg_str));
+            }
 
-                Event::EvActIfQueryEmpty(ref arg_str) => {
-                    if env.query.is_empty() {
-                        next_event = parse_action_arg(arg_str);
-                        continue;
-                    }
-                }
+            Event::EvActIfQueryNotEmpty(ref arg_str) if !env.query.is_empty() => {
+                return Step::Chain(parse_action_arg(arg_str));
+            }
 
-                Event::EvActIfQueryNotEmpty(ref arg_str) => {
-                    if !env.query.is_empty() {
-                        next_event = parse_action_arg(arg_str);
-                        continue;
-                    }
-                }
+            Event::EvActTogglePreview => {
+                self.preview_hidden = !self.preview_hidden;
+            }
 
-                Event::EvActTogglePreview => {
-                    self.preview_hidden = !self.preview_hidden;
-                }
+            Event::EvActPreview(_) => {
+                self.preview_hidden = false;
+            }
 
-                Event::EvActRotateMode => {
-                    self.act_rotate_mode(&mut env);
+            Event::EvActRotateMode => {
+                self.act_rotate_mode(env);
+            }
+
+            Event::EvActToggleSort => {
+                self.act_toggle_sort(env);
+            }
+
+            Event::EvActChangeNth(ref nth) => {
+                self.act_change_nth(env, nth);
+            }
+
+            Event::EvActToggleNth(ref nth) => {
+                if self.nth.as_deref() == Some(nth.as_str()) {
+                    self.act_change_nth(env, "");
+                } else {
+                    self.act_change_nth(env, nth);
                 }
+            }
 
-                Event::EvActAccept(accept_key) => {
+            Event::EvActAccept(ref accept_key) => {
+                // a directory is browsed instead, unless the items are selected to be output
+                let current_item = self.selection.get_current_item();
+                let entered = accept_key.is_none()
+                    && self.selection.get_num_selected() == 0
+                    && current_item.is_some_and(|item| self.browser.as_mut().is_some_and(|b| b.enter(&item)));
+                if entered {
+                    self.act_browse(env);
+                } else {
                     if let Some(ctrl) = self.reader_control.take() {
                         ctrl.kill();
                     }
@@ -517,112 +1050,232 @@ impl Model {
                         ctrl.kill();
                     }
 
-                    return Some(SkimOutput {
-                        accept_key,
+                    return Step::Exit(Some(SkimOutput {
+                        accept_key: accept_key.clone(),
                         query: self.query.get_fz_query(),
                         cmd: self.query.get_cmd_query(),
                         selected_items: self.selection.get_selected_indices_and_items().1,
-                    });
+                    }));
                 }
+            }
 
-                Event::EvActAbort => {
-                    if let Some(ctrl) = self.reader_control.take() {
-                        ctrl.kill();
-                    }
-                    if let Some(ctrl) = self.matcher_control.take() {
-                        ctrl.kill();
-                    }
+            Event::EvActParentDir => {
+                self.act_parent_dir(env);
+            }
 
-                    return None;
-                }
+            Event::EvActYankItem => {
+                self.act_yank_item();
+            }
 
-                Event::EvActDeleteCharEOF => {
-                    if env.in_query_mode && env.query.is_empty() || !env.in_query_mode && env.cmd_query.is_empty() {
-                        next_event = Some(Event::EvActAbort);
-                        continue;
-                    }
+            Event::EvActYankQuery => {
+                self.act_yank_query(env);
+            }
+
+            Event::EvActAbort => {
+                if let Some(ctrl) = self.reader_control.take() {
+                    ctrl.kill();
                 }
+                if let Some(ctrl) = self.matcher_control.take() {
+                    ctrl.kill();
+                }
+
+                return Step::Exit(None);
+            }
+
+            Event::EvActDeleteCharEOF
+                if env.in_query_mode && env.query.is_empty() || !env.in_query_mode && env.cmd_query.is_empty() =>
+            {
+                return Step::Chain(Some(Event::EvActAbort));
+            }
+
+            Event::EvActExecute(ref cmd) => {
+                self.act_execute(cmd);
+            }
+
+            Event::EvActExecuteSilent(ref cmd) => {
+                self.act_execute_silent(cmd);
+            }
+
+            Event::EvActExecuteBg(ref cmd) => {
+                self.act_execute_bg(cmd);
+            }
 
-                Event::EvActExecute(ref cmd) => {
-                    self.act_execute(cmd);
+            Event::EvExecuteBgDone(code) => {
+                self.jobs.running -= 1;
+                self.jobs.last_exit = Some(code);
+            }
+
+            Event::EvClipboardCmdFailed(ref text) => {
+                if let Err(err) = clipboard::copy(text, None) {
+                    debug!("failed to copy to the clipboard: {}", err);
                 }
+            }
 
-                Event::EvActExecuteSilent(ref cmd) => {
-                    self.act_execute_silent(cmd);
+            Event::EvActTransformQuery(ref cmd) => {
+                if let Some(query) = self.act_transform_query(cmd) {
+                    return Step::Chain(Some(Event::EvActChangeQuery(query)));
                 }
+            }
+
+            Event::EvActAppendAndSelect => {
+                self.act_append_and_select(env);
+            }
+
+            Event::EvActReload(ref cmd) => {
+                self.act_reload(env, cmd.as_deref());
+            }
 
-                Event::EvActAppendAndSelect => {
-                    self.act_append_and_select(&mut env);
+            Event::EvReportSelection => {
+                if let Some(handle) = self.handle.as_ref() {
+                    let (_, items) = self.selection.get_selected_indices_and_items();
+                    handle.report_selection(items.iter().map(|item| item.output().to_string()).collect());
                 }
+                return Step::Chain(next_event);
+            }
 
-                Event::EvInputKey(key) => {
-                    // dispatch key(normally the mouse keys) to sub-widgets
-                    self.do_with_widget(|root| {
-                        let (width, height) = self.term.term_size().unwrap();
-                        let rect = Rectangle {
-                            top: 0,
-                            left: 0,
-                            width,
-                            height,
-                        };
-                        let messages = root.on_event(TermEvent::Key(key), rect);
-                        for message in messages {
-                            let _ = self.tx.send(message);
-                        }
-                    })
+            Event::EvInputKey(key) => {
+                // a drag on the list ends on any other mouse event, or on the release even if it's
+                // outside of the list and isn't dispatched to it
+                let mouse_release = matches!(key, Key::MouseRelease(..));
+                if matches!(
+                    key,
+                    Key::MousePress(..)
+                        | Key::SingleClick(..)
+                        | Key::DoubleClick(..)
+                        | Key::WheelUp(..)
+                        | Key::WheelDown(..)
+                ) {
+                    self.selection.cancel_drag();
                 }
 
-                _ => {}
+                // dispatch key(normally the mouse keys) to sub-widgets
+                self.do_with_widget(|root| {
+                    let (width, height) = self.screen_size();
+                    let rect = Rectangle {
+                        top: 0,
+                        left: 0,
+                        width,
+                        height,
+                    };
+                    let messages = root.on_event(TermEvent::Key(key), rect);
+                    for message in messages {
+                        let _ = self.tx.send(message);
+                    }
+                });
+
+                if mouse_release {
+                    self.selection.cancel_drag();
+                }
             }
 
-            // dispatch events to sub-components
+            _ => {}
+        }
 
-            self.header.handle(&ev);
+        // dispatch events to sub-components
 
-            self.query.handle(&ev);
-            env.cmd_query = self.query.get_cmd_query();
+        self.header.handle(&ev);
 
-            let new_query = self.query.get_fz_query();
-            let new_cmd = self.query.get_cmd();
+        self.query.handle(&ev);
+        env.cmd_query = self.query.get_cmd_query();
 
-            // re-run reader & matcher if needed;
-            if new_cmd != env.cmd {
-                env.cmd = new_cmd;
-                self.on_cmd_query_change(&mut env);
-            } else if new_query != env.query {
-                env.query = new_query;
-                self.on_query_change(&mut env);
-            }
+        let new_query = self.query.get_fz_query();
+        let new_cmd = self.query.get_cmd();
 
-            self.selection.handle(&ev);
+        // re-run reader & matcher if needed;
+        if new_cmd != env.cmd {
+            env.cmd = new_cmd;
+            self.on_cmd_query_change(env);
+            self.fire(Trigger::Change);
+        } else if new_query != env.query {
+            env.query = new_query;
+            self.on_query_change(env);
+            self.fire(Trigger::Change);
+        }
 
+        self.selection.handle(&ev);
+
+        if let Some(previewer) = self.previewer.as_mut() {
+            previewer.handle(&ev);
+        }
+
+        let current_item = self.selection.get_current_item();
+        let focus_changed = match (self.focused_item.as_ref(), current_item.as_ref()) {
+            (Some(prev), Some(cur)) => !Arc::ptr_eq(prev, cur),
+            (None, Some(_)) => true,
+            (_, None) => false,
+        };
+        if focus_changed {
+            self.focused_item = current_item;
+            self.fire(Trigger::Focus);
+        }
+
+        // re-draw
+        if !self.preview_hidden {
+            let item_index = self.selection.get_current_item_idx();
+            let item = self.selection.get_current_item();
             if let Some(previewer) = self.previewer.as_mut() {
-                previewer.handle(&ev);
-            }
-
-            // re-draw
-            if !self.preview_hidden {
-                let item_index = self.selection.get_current_item_idx();
-                let item = self.selection.get_current_item();
-                if let Some(previewer) = self.previewer.as_mut() {
-                    let selections = &self.selection;
-                    let get_selected_items = || selections.get_selected_indices_and_items();
-                    previewer.on_item_change(
-                        item_index,
-                        item,
-                        env.query.to_string(),
-                        env.cmd_query.to_string(),
-                        selections.get_num_of_selected_exclude_current(),
-                        get_selected_items,
-                    );
-                }
+                let selections = &self.selection;
+                let get_selected_items = || selections.get_selected_indices_and_items();
+                previewer.on_item_change(
+                    item_index,
+                    item,
+                    env.query.to_string(),
+                    env.cmd_query.to_string(),
+                    selections.get_num_of_selected_exclude_current(),
+                    get_selected_items,
+                );
+            }
+        }
+
+        Step::Redraw(next_event)
+    }
+
+    /// draw the UI to the canvas, used when the caller owns the terminal
+    pub fn draw(&self, canvas: &mut dyn Canvas) -> Result<()> {
+        self.screen_size.set(canvas.size()?);
+        // the canvas of the caller may not keep what was drawn on it
+        self.selection.invalidate();
+        if let Some(previewer) = self.previewer.as_ref() {
+            previewer.invalidate();
+        }
+        self.do_with_widget(|root| root.draw(canvas))
+    }
+
+    /// forget the rows drawn by the list and the preview if they moved since the last frame
+    fn invalidate_moved(&self) {
+        let layout = self.layout();
+        if self.last_layout.replace(Some(layout)) != Some(layout) {
+            self.selection.invalidate();
+            if let Some(previewer) = self.previewer.as_ref() {
+                previewer.invalidate();
             }
+        }
+    }
 
-            let _ = self.do_with_widget(|root| self.term.draw(&root));
-            let _ = self.term.present();
+    /// what places the list and the preview on the screen
+    fn layout(&self) -> Layout {
+        let preview_shown = !self.preview_hidden && self.previewer.as_ref().is_some_and(Previewer::has_preview);
+        (self.screen_size(), preview_shown, self.header.size_hint().1)
+    }
+
+    fn screen_size(&self) -> (usize, usize) {
+        match self.term.as_ref() {
+            Some(term) => term.term_size().unwrap_or((0, 0)),
+            None => self.screen_size.get(),
         }
     }
 
+    /// take the next pending event without blocking
+    /// the output with the ranges of its selected items matched by the final query
+    pub fn matched_output(&self, output: SkimOutput) -> MatchedOutput {
+        let matched_ranges = self.selection.matched_ranges_of(&output.selected_items);
+        MatchedOutput { output, matched_ranges }
+    }
+
+    pub fn try_recv(&self) -> Option<Event> {
+        self.rx.try_recv().ok()
+    }
+
     fn consume_additional_event(&self, target_event: &Event) -> Option<Event> {
         // consume additional HeartBeat event
         let mut rx_try_iter = self.rx.try_iter().peekable();
@@ -637,6 +1290,14 @@ impl Model {
         rx_try_iter.next()
     }
 
+    /// put the items into the pool, the matched ones dropped by `--tail` are removed
+    fn append_items(&mut self, items: Vec<Arc<dyn SkimItem>>) {
+        let dropped = self.item_pool.append(items);
+        if !dropped.is_empty() {
+            self.num_options -= self.selection.drop_items(&dropped);
+        }
+    }
+
     fn restart_matcher(&mut self) {
         self.matcher_timer = Instant::now();
         let query = self.query.get_fz_query();
@@ -651,7 +1312,7 @@ impl Model {
         if !processed {
             // take out new items and put them into items
             let new_items = self.reader_control.as_ref().map(|c| c.take()).unwrap();
-            self.item_pool.append(new_items);
+            self.append_items(new_items);
         };
 
         // send heart beat (so that heartbeat/refresh is triggered)
@@ -664,7 +1325,9 @@ impl Model {
         };
 
         let tx = self.tx.clone();
-        let new_matcher_control = matcher.run(&query, self.item_pool.clone(), move |_| {
+        // the matches of the previous runs count, as the runs only match the new items
+        let max_matches = self.max_matches.map(|max| max.saturating_sub(self.num_options));
+        let new_matcher_control = matcher.run(&query, self.item_pool.clone(), max_matches, move |_| {
             // notify refresh immediately
             let _ = tx.send(Event::EvHeartBeat);
         });
@@ -675,14 +1338,19 @@ impl Model {
     /// construct the widget tree
     fn do_with_widget<R, F>(&'_ self, action: F) -> R
     where
-        F: Fn(Box<dyn Widget<Event> + '_>) -> R,
+        F: FnOnce(Box<dyn Widget<Event> + '_>) -> R,
     {
         let total = self.item_pool.len();
-        let matcher_mode = if self.use_regex {
-            "RE".to_string()
-        } else {
-            "".to_string()
-        };
+        let mut modes = vec![];
+        if self.use_regex {
+            modes.push("RE");
+        }
+        if self.nosort {
+            modes.push("NOSORT");
+        }
+        let nth_mode = self.nth.as_ref().map(|nth| format!("NTH:{}", nth));
+        modes.extend(nth_mode.as_deref());
+        let matcher_mode = modes.join("/");
 
         let matched = self.num_options + self.matcher_control.as_ref().map(|c| c.get_num_matched()).unwrap_or(0);
         let matcher_running = self.item_pool.num_not_taken() != 0 || matched != self.num_options;
@@ -692,6 +1360,24 @@ impl Model {
             .map(|c| c.get_num_processed())
             .unwrap_or(total);
 
+        let reading = !self.reader_control.as_ref().map(|c| c.is_done()).unwrap_or(true);
+        let num_read = self.reader_control.as_ref().map(|c| c.num_read()).unwrap_or(0);
+        let millis_since_read = self.reader_timer.elapsed().as_millis() as usize;
+        let read_rate = num_read * 1000 / max(millis_since_read, 1);
+        // only report the failures, after all the output is read
+        let exit_code = if reading {
+            None
+        } else {
+            self.reader.exit_code().filter(|&code| code != 0)
+        };
+
+        let truncated = self.reader_control.as_ref().is_some_and(ReaderControl::is_truncated);
+        let dropped = self
+            .reader_control
+            .as_ref()
+            .map(ReaderControl::num_dropped)
+            .unwrap_or(0);
+
         let status = Status {
             total,
             matched,
@@ -701,12 +1387,20 @@ impl Model {
             selected: self.selection.get_num_selected(),
             current_item_idx: self.selection.get_current_item_idx(),
             hscroll_offset: self.selection.get_hscroll_offset(),
-            reading: !self.reader_control.as_ref().map(|c| c.is_done()).unwrap_or(true),
+            reading,
+            read_rate,
+            exit_code,
+            truncated,
+            dropped,
+            jobs: self.jobs,
             time_since_read: self.reader_timer.elapsed(),
             time_since_match: self.matcher_timer.elapsed(),
             matcher_mode,
             theme: self.theme.clone(),
             inline_info: self.inline_info,
+            info_format: self.info_format.clone(),
+            accessible: self.accessible,
+            no_unicode: self.no_unicode,
         };
         let status_inline = status.clone();
 
@@ -749,20 +1443,38 @@ impl Model {
                 .split(win_query_status),
         };
 
-        let screen: Box<dyn Widget<Event>> = if !self.preview_hidden && self.previewer.is_some() {
-            let previewer = self.previewer.as_ref().unwrap();
+        let previewer = self.previewer.as_ref().filter(|previewer| previewer.has_preview());
+        let screen: Box<dyn Widget<Event>> = if let (false, Some(previewer)) = (self.preview_hidden, previewer) {
             let win = Win::new(previewer)
                 .basis(self.preview_size)
                 .grow(0)
                 .shrink(0)
                 .border_attr(self.theme.border());
 
+            let has_label = previewer.get_label().is_some();
+            let win = match previewer.get_label() {
+                Some(label) => {
+                    let label = label.to_string();
+                    let attr = self.theme.border();
+                    win.border_top(true).fn_draw_header(Box::new(move |canvas| {
+                        draw_border_label(canvas, &label, Align::Left, attr)
+                    }))
+                }
+                None => win,
+            };
+
             let win_preview = match self.preview_direction {
                 Direction::Up => win.border_bottom(true),
                 Direction::Right => win.border_left(true),
                 Direction::Down => win.border_top(true),
                 Direction::Left => win.border_right(true),
             };
+            let ascii = self.no_unicode;
+            let win_preview = AsciiBorder::new(win_preview)
+                .top(ascii && (has_label || self.preview_direction == Direction::Down))
+                .right(ascii && self.preview_direction == Direction::Left)
+                .bottom(ascii && self.preview_direction == Direction::Up)
+                .left(ascii && self.preview_direction == Direction::Right);
 
             match self.preview_direction {
                 Direction::Up => Box::new(VSplit::default().split(win_preview).split(win_main)),
@@ -774,7 +1486,28 @@ impl Model {
             Box::new(win_main)
         };
 
-        let root = Win::new(screen)
+        let win_border = Win::new(screen).border(self.border).border_attr(self.theme.border());
+
+        // the label needs the top border even without `--border`
+        let win_border = match self.border_label.as_ref() {
+            Some(label) => {
+                let label = label.clone();
+                let (align, attr) = (self.border_label_align, self.theme.border());
+                win_border
+                    .border_top(true)
+                    .fn_draw_header(Box::new(move |canvas| draw_border_label(canvas, &label, align, attr)))
+            }
+            None => win_border,
+        };
+        let ascii = self.no_unicode;
+        let win_border = AsciiBorder::new(win_border)
+            .top(ascii && (self.border || self.border_label.is_some()))
+            .right(ascii && self.border)
+            .bottom(ascii && self.border)
+            .left(ascii && self.border);
+
+        // the margins are out of the border
+        let root = Win::new(win_border)
             .margin_top(self.margin_top)
             .margin_right(self.margin_right)
             .margin_bottom(self.margin_bottom)
@@ -784,12 +1517,22 @@ impl Model {
     }
 }
 
-struct ModelEnv {
-    pub cmd: String,
-    pub query: String,
-    pub cmd_query: String,
-    pub clear_selection: ClearStrategy,
-    pub in_query_mode: bool,
+/// the result of handling one event
+pub enum Step {
+    /// the screen needs redrawing, carries the event to handle next if any
+    Redraw(Option<Event>),
+    /// the event is translated into another one, handle it without redrawing
+    Chain(Option<Event>),
+    /// skim should exit, `None` means aborted
+    Exit(Option<SkimOutput>),
+}
+
+pub struct ModelEnv {
+    cmd: String,
+    query: String,
+    cmd_query: String,
+    clear_selection: ClearStrategy,
+    in_query_mode: bool,
 }
 
 #[derive(Clone)]
@@ -803,11 +1546,114 @@ struct Status {
     current_item_idx: usize,
     hscroll_offset: i64,
     reading: bool,
+    // items read per second
+    read_rate: usize,
+    // exit code of the source command if it failed
+    exit_code: Option<i32>,
+    // the input stopped at `--max-items` or `--max-bytes`
+    truncated: bool,
+    // the items dropped as the read buffer was full
+    dropped: usize,
+    jobs: Jobs,
     time_since_read: Duration,
     time_since_match: Duration,
     matcher_mode: String,
     theme: Arc<ColorTheme>,
     inline_info: bool,
+    info_format: Option<String>,
+    accessible: bool,
+    no_unicode: bool,
+}
+
+impl Status {
+    /// the spinner character if the reader is still running
+    fn spinner(&self, spinner_set: &[char]) -> Option<char> {
+        if !self.accessible && self.reading && self.time_since_read > Duration::from_millis(50) {
+            let mills = (self.time_since_read.as_secs() * 1000) as u32 + self.time_since_read.subsec_millis();
+            let index = (mills / SPINNER_DURATION) % (spinner_set.len() as u32);
+            Some(spinner_set[index as usize])
+        } else {
+            None
+        }
+    }
+
+    /// expand the placeholders of `--info-format`
+    fn format_info(&self, template: &str, spinner: Option<char>) -> String {
+        let mode = if self.matcher_mode.is_empty() {
+            String::new()
+        } else {
+            format!("/{}", self.matcher_mode)
+        };
+        template
+            .replace("{matched}", &self.matched.to_string())
+            .replace("{total}", &self.total.to_string())
+            .replace("{processed}", &self.processed.to_string())
+            .replace("{selected}", &self.selected.to_string())
+            .replace("{current}", &self.current_item_idx.to_string())
+            .replace("{mode}", &mode)
+            .replace("{spinner}", &spinner.unwrap_or(' ').to_string())
+            .replace("{rate}", &self.read_rate.to_string())
+            .replace(
+                "{exit}",
+                &self.exit_code.map(|code| format!("exit {}", code)).unwrap_or_default(),
+            )
+            .replace("{truncated}", if self.truncated { "truncated" } else { "" })
+            .replace("{dropped}", &self.dropped.to_string())
+    }
+
+    /// the info line of the accessible mode, it only changes when the numbers or states change
+    fn accessible_info(&self) -> String {
+        let mut info = format!("{} of {} matches", self.matched, self.total);
+        if self.multi_selection && self.selected > 0 {
+            info.push_str(&format!(", {} selected", self.selected));
+        }
+        if self.reading {
+            info.push_str(", reading");
+        } else if self.matcher_running {
+            info.push_str(", matching");
+        }
+        if let Some(code) = self.exit_code {
+            info.push_str(&format!(", source exited with {}", code));
+        }
+        if self.truncated {
+            info.push_str(", input truncated");
+        }
+        if self.dropped > 0 {
+            info.push_str(&format!(", {} dropped", self.dropped));
+        }
+        if let Some(jobs) = self.jobs.describe() {
+            info.push_str(&format!(", background command {}", jobs));
+        }
+        info
+    }
+}
+
+/// the commands run by `execute-bg`
+#[derive(Clone, Copy, Default, Debug)]
+struct Jobs {
+    running: usize,
+    // the exit code of the last finished one, none if it's killed or failed to start
+    last_exit: Option<Option<i32>>,
+}
+
+impl Jobs {
+    fn describe(&self) -> Option<String> {
+        if self.running > 0 {
+            return Some(format!("{} running", self.running));
+        }
+        self.last_exit.map(|code| match code {
+            Some(0) => "done".to_string(),
+            Some(code) => format!("failed: exit {}", code),
+            None => "failed".to_string(),
+        })
+    }
+}
+
+/// ring the terminal bell, the screen is drawn on the terminal so is the bell
+fn ring_bell() {
+    if let Ok(mut tty) = OpenOptions::new().write(true).open("/dev/tty") {
+        let _ = tty.write_all(b"\x07");
+    }
 }
 
 #[allow(unused_assignments)]
@@ -832,12 +1678,13 @@ impl Draw for Status {
             ..self.theme.info()
         };
 
-        let a_while_since_read = self.time_since_read > Duration::from_millis(50);
         let a_while_since_match = self.time_since_match > Duration::from_millis(50);
 
         let mut col = 0;
         let spinner_set: &[char] = if self.inline_info {
             &SPINNERS_INLINE
+        } else if self.no_unicode {
+            &SPINNERS_ASCII
         } else {
             &SPINNERS_UNICODE
         };
@@ -846,12 +1693,22 @@ impl Draw for Status {
             col += canvas.put_char_with_attr(0, col, ' ', info_attr)?;
         }
 
+        let spinner = self.spinner(spinner_set);
+        if let Some(template) = self.info_format.as_ref() {
+            canvas.print_with_attr(0, col, &self.format_info(template, spinner), info_attr)?;
+            return Ok(());
+        }
+
+        if self.accessible {
+            canvas.print_with_attr(0, col, &self.accessible_info(), info_attr)?;
+            return Ok(());
+        }
+
         // draw the spinner
-        if self.reading && a_while_since_read {
-            let mills = (self.time_since_read.as_secs() * 1000) as u32 + self.time_since_read.subsec_millis();
-            let index = (mills / SPINNER_DURATION) % (spinner_set.len() as u32);
-            let ch = spinner_set[index as usize];
+        if let Some(ch) = spinner {
             col += canvas.put_char_with_attr(0, col, ch, self.theme.spinner())?;
+        } else if self.exit_code.is_some() {
+            col += canvas.put_char_with_attr(0, col, '!', self.theme.spinner())?;
         } else if self.inline_info {
             col += canvas.put_char_with_attr(0, col, '<', self.theme.prompt())?;
         } else {
@@ -866,6 +1723,29 @@ impl Draw for Status {
             col += canvas.print_with_attr(0, col, format!("/{}", &self.matcher_mode).as_ref(), info_attr)?;
         }
 
+        // display the reading speed while the source is still running
+        if self.reading && self.time_since_read > Duration::from_millis(50) {
+            col += canvas.print_with_attr(0, col, format!(" ({}/s)", self.read_rate).as_ref(), info_attr)?;
+        }
+
+        // the source command failed, the items may be incomplete
+        if let Some(code) = self.exit_code {
+            col += canvas.print_with_attr(0, col, format!(" [exit {}]", code).as_ref(), info_attr_bold)?;
+        }
+
+        // the rest of the input is dropped
+        if self.truncated {
+            col += canvas.print_with_attr(0, col, " [truncated]", info_attr_bold)?;
+        }
+        if self.dropped > 0 {
+            col += canvas.print_with_attr(0, col, format!(" [{} dropped]", self.dropped).as_ref(), info_attr_bold)?;
+        }
+
+        // the commands of `execute-bg`
+        if let Some(jobs) = self.jobs.describe() {
+            col += canvas.print_with_attr(0, col, format!(" [{}]", jobs).as_ref(), info_attr_bold)?;
+        }
+
         // display the percentage of the number of processed items
         if self.matcher_running && a_while_since_match {
             col += canvas.print_with_attr(
@@ -896,6 +1776,24 @@ impl Draw for Status {
 
 impl Widget<Event> for Status {}
 
+/// draw the label over the top border, e.g. `─ label ──────`
+fn draw_border_label(canvas: &mut dyn Canvas, label: &str, align: Align, attr: Attr) -> Result<()> {
+    let (width, _height) = canvas.size()?;
+    if label.is_empty() || width < 3 {
+        return Ok(());
+    }
+
+    let label = format!(" {} ", label);
+    let label_width = label.width_cjk();
+    let col = match align {
+        Align::Left => 1,
+        Align::Center => width.saturating_sub(label_width) / 2,
+        Align::Right => width.saturating_sub(label_width + 1),
+    };
+    let _ = canvas.print_with_attr(0, max(col, 1), &label, attr);
+    Ok(())
+}
+
 #[derive(PartialEq, Eq, Clone, Debug, Copy)]
 enum Direction {
     Up,
@@ -904,9 +1802,128 @@ enum Direction {
     Right,
 }
 
+#[derive(PartialEq, Eq, Clone, Debug, Copy)]
+enum Align {
+    Left,
+    Center,
+    Right,
+}
+
 #[derive(PartialEq, Eq, Clone, Debug, Copy)]
 enum ClearStrategy {
     DontClear,
     Clear,
     ClearIfNotNull,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::selection::tests::RowsCanvas;
+    use crate::theme::DEFAULT_THEME;
+    use std::sync::mpsc::channel;
+
+    fn model(options: &SkimOptions) -> Model {
+        let (tx, rx) = channel();
+        Model::new(rx, tx, Reader::with_options(options), None, options)
+    }
+
+    #[test]
+    fn test_browse_prompt() {
+        // the default prompt of the library is set, it doesn't hide the directory
+        let options = SkimOptions {
+            browse: Some("."),
+            ..Default::default()
+        };
+        assert!(options.prompt.is_some());
+        assert!(model(&options).browse_prompt);
+
+        let options = SkimOptions {
+            browse: Some("."),
+            browse_prompt: false,
+            ..Default::default()
+        };
+        assert!(!model(&options).browse_prompt);
+    }
+
+    #[test]
+    fn test_clipboard_cmd_fallback() {
+        let options = SkimOptions {
+            clipboard_cmd: Some("exit 1"),
+            ..Default::default()
+        };
+        let model = model(&options);
+        model.copy_to_clipboard("a b");
+        let event = std::iter::from_fn(|| model.rx.recv_timeout(Duration::from_secs(5)).ok())
+            .find(|event| *event != Event::EvHeartBeat);
+        assert_eq!(event, Some(Event::EvClipboardCmdFailed("a b".to_string())));
+    }
+
+    #[test]
+    fn test_invalidate_moved() {
+        let model = model(&SkimOptions::default());
+        model.screen_size.set((10, 5));
+        let mut canvas = RowsCanvas::new(10, 5);
+
+        model.invalidate_moved();
+        model.selection.draw(&mut canvas).unwrap();
+        assert_eq!(5, canvas.take_rows());
+        model.invalidate_moved();
+        model.selection.draw(&mut canvas).unwrap();
+        assert_eq!(0, canvas.take_rows());
+
+        // the screen grew, the list kept its size but moved on it
+        model.screen_size.set((10, 8));
+        model.invalidate_moved();
+        model.selection.draw(&mut canvas).unwrap();
+        assert_eq!(5, canvas.take_rows());
+    }
+
+    #[test]
+    fn test_format_info() {
+        let status = Status {
+            total: 100,
+            matched: 42,
+            processed: 100,
+            matcher_running: false,
+            multi_selection: true,
+            selected: 3,
+            current_item_idx: 7,
+            hscroll_offset: 0,
+            reading: false,
+            read_rate: 0,
+            exit_code: Some(2),
+            truncated: true,
+            dropped: 5,
+            jobs: Jobs {
+                running: 0,
+                last_exit: Some(Some(1)),
+            },
+            time_since_read: Duration::from_millis(0),
+            time_since_match: Duration::from_millis(0),
+            matcher_mode: "RE".to_string(),
+            theme: Arc::new(*DEFAULT_THEME),
+            inline_info: false,
+            info_format: None,
+            accessible: true,
+            no_unicode: false,
+        };
+
+        assert_eq!(
+            "42/100 (3 selected) #7/RE [ ]",
+            status.format_info(
+                "{matched}/{total} ({selected} selected) #{current}{mode} [{spinner}]",
+                None
+            )
+        );
+        assert_eq!("|", status.format_info("{spinner}", Some('|')));
+        assert_eq!(
+            "0/s exit 2 truncated 5",
+            status.format_info("{rate}/s {exit} {truncated} {dropped}", None)
+        );
+        assert_eq!(
+            "42 of 100 matches, 3 selected, source exited with 2, input truncated, 5 dropped, background command failed: exit 1",
+            status.accessible_info()
+        );
+    }
+}
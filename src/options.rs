@@ -1,9 +1,12 @@
+use std::path::PathBuf;
+use std::process::Command;
 use std::rc::Rc;
 
 use derive_builder::Builder;
 
 use crate::helper::item_reader::SkimItemReader;
 use crate::reader::CommandCollector;
+use crate::util::{valid_env_vars, Shell};
 use crate::{CaseMatching, FuzzyAlgorithm, MatchEngineFactory};
 use std::cell::RefCell;
 
@@ -15,11 +18,24 @@ pub struct SkimOptions<'a> {
     pub multi: bool,
     pub prompt: Option<&'a str>,
     pub cmd_prompt: Option<&'a str>,
+    pub pointer: Option<&'a str>,
+    pub marker: Option<&'a str>,
     pub expect: Option<String>,
     pub tac: bool,
     pub nosort: bool,
+    pub max_matches: Option<usize>,
+    pub max_items: Option<usize>,
+    pub tail: Option<usize>,
+    pub index: bool,
+    pub max_bytes: Option<usize>,
+    pub read_buffer: Option<usize>,
+    pub read_buffer_policy: &'a str,
     pub tiebreak: Option<String>,
+    pub sort_key: Option<&'a str>,
     pub exact: bool,
+    pub literal: bool,
+    pub matcher_cmd: Option<&'a str>,
+    pub read0: bool,
     pub cmd: Option<&'a str>,
     pub interactive: bool,
     pub query: Option<&'a str>,
@@ -29,17 +45,47 @@ pub struct SkimOptions<'a> {
     pub replstr: Option<&'a str>,
     pub color: Option<&'a str>,
     pub margin: Option<&'a str>,
+    pub border: bool,
+    pub border_label: Option<&'a str>,
+    pub border_label_pos: &'a str,
     pub no_height: bool,
     pub no_clear: bool,
     pub min_height: Option<&'a str>,
     pub height: Option<&'a str>,
     pub preview: Option<&'a str>,
     pub preview_window: Option<&'a str>,
+    pub preview_label: Option<&'a str>,
+    pub preview_timeout: Option<u64>,
     pub reverse: bool,
     pub tabstop: Option<&'a str>,
+    pub format_cmd: Option<&'a str>,
+    pub icons: Option<&'a str>,
+    pub shorten_paths: bool,
+    pub browse: Option<&'a str>,
+    /// show the browsed directory as the prompt of `browse` instead of `prompt`
+    pub browse_prompt: bool,
+    /// the command `yank-item` and `yank-query` pipe the text to (e.g. `pbcopy`), run by `shell`.
+    /// Copy by OSC 52 if it's `None` or the command fails.
+    pub clipboard_cmd: Option<&'a str>,
+    /// the fields of the items copied by `yank-item`, split by `delimiter`, e.g. `1,3..`. The
+    /// output of `SkimOutput` isn't changed, see `OutputFormat::accept_nth` for it.
+    pub accept_nth: Option<&'a str>,
     pub no_hscroll: bool,
     pub no_mouse: bool,
     pub inline_info: bool,
+    pub info_format: Option<&'a str>,
+    pub accessible: bool,
+    pub bell: bool,
+    pub no_unicode: bool,
+    pub debug_perf: bool,
+    pub record: Option<&'a str>,
+    pub replay: Option<&'a str>,
+    pub listen_socket: Option<&'a str>,
+    pub refresh_interval: Option<f64>,
+    pub shell: Option<&'a str>,
+    pub cmd_cwd: Option<&'a str>,
+    pub cmd_env: Vec<&'a str>,
+    pub reload_on_signal: bool,
     pub header: Option<&'a str>,
     pub header_lines: usize,
     pub layout: &'a str,
@@ -50,6 +96,12 @@ pub struct SkimOptions<'a> {
     pub cmd_history: &'a [String],
     pub cmd_collector: Rc<RefCell<dyn CommandCollector>>,
     pub keep_right: bool,
+    pub cycle: bool,
+    pub scroll_off: usize,
+    pub track: bool,
+    pub grid: bool,
+    pub wrap: bool,
+    pub multi_line: bool,
     pub skip_to_pattern: &'a str,
     pub select1: bool,
     pub exit0: bool,
@@ -62,11 +114,24 @@ impl<'a> Default for SkimOptions<'a> {
             multi: false,
             prompt: Some("> "),
             cmd_prompt: Some("c> "),
+            pointer: Some(">"),
+            marker: Some(">"),
             expect: None,
             tac: false,
             nosort: false,
+            max_matches: None,
+            max_items: None,
+            tail: None,
+            index: false,
+            max_bytes: None,
+            read_buffer: None,
+            read_buffer_policy: "block",
             tiebreak: None,
+            sort_key: None,
             exact: false,
+            literal: false,
+            matcher_cmd: None,
+            read0: false,
             cmd: None,
             interactive: false,
             query: None,
@@ -76,17 +141,42 @@ impl<'a> Default for SkimOptions<'a> {
             replstr: Some("{}"),
             color: None,
             margin: Some("0,0,0,0"),
+            border: false,
+            border_label: None,
+            border_label_pos: "left",
             no_height: false,
             no_clear: false,
             min_height: Some("10"),
             height: Some("100%"),
             preview: None,
             preview_window: Some("right:50%"),
+            preview_label: None,
+            preview_timeout: None,
             reverse: false,
             tabstop: None,
+            format_cmd: None,
+            icons: None,
+            shorten_paths: false,
+            browse: None,
+            browse_prompt: true,
+            clipboard_cmd: None,
+            accept_nth: None,
             no_hscroll: false,
             no_mouse: false,
             inline_info: false,
+            info_format: None,
+            accessible: false,
+            bell: false,
+            no_unicode: false,
+            debug_perf: false,
+            record: None,
+            replay: None,
+            listen_socket: None,
+            refresh_interval: None,
+            shell: None,
+            cmd_cwd: None,
+            cmd_env: vec![],
+            reload_on_signal: false,
             header: None,
             header_lines: 0,
             layout: "",
@@ -97,6 +187,12 @@ impl<'a> Default for SkimOptions<'a> {
             cmd_history: &[],
             cmd_collector: Rc::new(RefCell::new(SkimItemReader::new(Default::default()))),
             keep_right: false,
+            cycle: false,
+            scroll_off: 0,
+            track: false,
+            grid: false,
+            wrap: false,
+            multi_line: false,
             skip_to_pattern: "",
             select1: false,
             exit0: false,
@@ -104,6 +200,19 @@ impl<'a> Default for SkimOptions<'a> {
     }
 }
 
+impl SkimOptions<'_> {
+    /// the command to run `cmd` with the shell, in the working directory and with the environment
+    /// variables of the options, i.e. `--with-shell`, `--cmd-cwd` and `--cmd-env`
+    pub fn shell_command(&self, cmd: &str) -> Command {
+        self.shell
+            .and_then(Shell::parse)
+            .unwrap_or_default()
+            .cwd(self.cmd_cwd.map(PathBuf::from))
+            .envs(valid_env_vars(&self.cmd_env))
+            .command(cmd)
+    }
+}
+
 impl<'a> SkimOptionsBuilder<'a> {
     pub fn build(&mut self) -> Result<SkimOptions<'a>, String> {
         if let Some(true) = self.no_height {
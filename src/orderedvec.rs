@@ -1,29 +1,52 @@
 // ordered container
-// Normally, user will only care about the first several options. So we only keep several of them
-// in order. Other items are kept unordered and are sorted on demand.
+// Normally, user will only care about the first several options. So the items are put into buckets
+// by their primary sort key as they come, which is cheap, and a bucket is only sorted when one of
+// its items is asked for, i.e. the buckets of the visible items.
 
 use defer_drop::DeferDrop;
 use rayon::prelude::ParallelSliceMut;
 use std::cell::{Ref, RefCell};
-use std::cmp::Ordering;
+use std::collections::BTreeMap;
 
-const ORDERED_SIZE: usize = 300;
-const MAX_MOVEMENT: usize = 100;
+use crate::item::MatchedItem;
 
-pub struct OrderedVec<T: Send + Ord + 'static> {
-    // sorted vectors for merge, reverse ordered, last one is the smallest one
-    sub_vectors: RefCell<DeferDrop<Vec<Vec<T>>>>,
-    // globally sorted items, the first one is the smallest one.
-    sorted: RefCell<DeferDrop<Vec<T>>>,
+/// The key of the bucket of an item, it must agree with the order: `a < b` => `a.bucket() <= b.bucket()`
+pub trait Bucketed {
+    fn bucket(&self) -> i64;
+}
+
+impl Bucketed for MatchedItem {
+    fn bucket(&self) -> i64 {
+        self.rank.primary_key(&self.key)
+    }
+}
+
+struct Bucket<T> {
+    items: Vec<T>,
+    sorted: bool,
+}
+
+impl<T> Default for Bucket<T> {
+    fn default() -> Self {
+        Self {
+            items: Vec::new(),
+            sorted: true,
+        }
+    }
+}
+
+pub struct OrderedVec<T: Send + Ord + Bucketed + 'static> {
+    buckets: RefCell<BTreeMap<i64, Bucket<T>>>,
+    len: usize,
     tac: bool,
     nosort: bool,
 }
 
-impl<T: Send + Ord + 'static> OrderedVec<T> {
+impl<T: Send + Ord + Bucketed + 'static> OrderedVec<T> {
     pub fn new() -> Self {
         OrderedVec {
-            sub_vectors: RefCell::new(DeferDrop::new(Vec::new())),
-            sorted: RefCell::new(DeferDrop::new(Vec::with_capacity(ORDERED_SIZE))),
+            buckets: RefCell::new(BTreeMap::new()),
+            len: 0,
             tac: false,
             nosort: false,
         }
@@ -39,125 +62,115 @@ impl<T: Send + Ord + 'static> OrderedVec<T> {
         self
     }
 
-    pub fn append(&mut self, mut items: Vec<T>) {
+    pub fn append(&mut self, items: Vec<T>) {
         trace!("orderedvec append: new vec size: {}", items.len());
+        self.len += items.len();
+        let buckets = self.buckets.get_mut();
         if self.nosort {
-            self.sorted.borrow_mut().append(&mut items);
+            buckets.entry(0).or_default().items.extend(items);
             return;
         }
 
-        self.sort_vector(&mut items, false);
-        let mut sorted = self.sorted.borrow_mut();
-
-        let mut items_smaller = Vec::new();
-        if !sorted.is_empty() {
-            // move the ones <= sorted to sorted
-            while items_smaller.len() < MAX_MOVEMENT
-                && !items.is_empty()
-                && self.compare_item(items.last().unwrap(), sorted.last().unwrap()) == Ordering::Less
-            {
-                items_smaller.push(items.pop().unwrap());
-            }
+        for item in items {
+            let bucket = buckets.entry(item.bucket()).or_default();
+            bucket.items.push(item);
+            bucket.sorted = false;
         }
+        trace!("orderedvec done append: num of buckets: {}", buckets.len());
+    }
 
-        if !items.is_empty() {
-            self.sub_vectors.borrow_mut().push(items);
+    // the bucket of the item at the index in ascending order, and the index in the bucket
+    fn locate(buckets: &BTreeMap<i64, Bucket<T>>, mut index: usize) -> (i64, usize) {
+        for (&key, bucket) in buckets.iter() {
+            if index < bucket.items.len() {
+                return (key, index);
+            }
+            index -= bucket.items.len();
         }
+        unreachable!("orderedvec: index out of range")
+    }
 
-        let too_many_moved = items_smaller.len() >= ORDERED_SIZE;
-        trace!("append_ordered: num_moved: {}", items_smaller.len());
-
-        sorted.append(&mut items_smaller);
-        if too_many_moved {
-            // means the current sorted vector contains item that's large
-            // so we'll move the sorted vector to partially sorted candidates.
-            self.sort_vector(&mut sorted, false);
-            let old_vec = self.sorted.replace(DeferDrop::new(Vec::new()));
-            self.sub_vectors.borrow_mut().push(DeferDrop::into_inner(old_vec));
-        } else {
-            self.sort_vector(&mut sorted, true);
+    fn sort_bucket(bucket: &mut Bucket<T>) {
+        if !bucket.sorted {
+            // stable, the items of the same rank stay in the order they are matched
+            bucket.items.par_sort();
+            bucket.sorted = true;
         }
-
-        trace!(
-            "orderedvec done append: sub_vector size: {}",
-            self.sub_vectors.borrow().len()
-        );
     }
 
-    fn sort_vector(&self, vec: &mut Vec<T>, asc: bool) {
-        let asc = asc ^ self.tac;
-        vec.par_sort();
-        if !asc {
-            vec.reverse();
+    pub fn get(&self, index: usize) -> Option<Ref<T>> {
+        if index >= self.len {
+            return None;
         }
+
+        // the order of tac is the reverse of the ascending one, sorted or not
+        let index = if self.tac { self.len - index - 1 } else { index };
+        let (key, index) = Self::locate(&self.buckets.borrow(), index);
+        Self::sort_bucket(self.buckets.borrow_mut().get_mut(&key).unwrap());
+        Some(Ref::map(self.buckets.borrow(), |buckets| &buckets[&key].items[index]))
     }
 
-    #[inline]
-    fn compare_item(&self, a: &T, b: &T) -> Ordering {
-        if !self.tac {
-            a.cmp(b)
+    /// the index of the item `same` picks among the ones equal to `item`, only the bucket of
+    /// `item` is looked into
+    pub fn position(&self, item: &T, same: impl Fn(&T) -> bool) -> Option<usize> {
+        let key = if self.nosort { 0 } else { item.bucket() };
+        let mut buckets = self.buckets.borrow_mut();
+        let before: usize = buckets.range(..key).map(|(_, bucket)| bucket.items.len()).sum();
+        let bucket = buckets.get_mut(&key)?;
+        let index = if self.nosort {
+            // in the order they came, the ones looked for are usually the last ones
+            bucket.items.iter().rposition(same)?
         } else {
-            b.cmp(a)
-        }
+            Self::sort_bucket(bucket);
+            let start = bucket.items.partition_point(|other| other < item);
+            let mut equals = bucket.items[start..].iter().take_while(|other| *other == item);
+            start + equals.position(same)?
+        };
+
+        let index = before + index;
+        Some(if self.tac { self.len - index - 1 } else { index })
     }
 
-    fn merge_till(&self, index: usize) {
-        let mut sorted = self.sorted.borrow_mut();
-        let mut vectors = self.sub_vectors.borrow_mut();
-
-        if index >= sorted.len() {
-            trace!("merge_till: index: {}, num_sorted: {}", index, sorted.len());
-        }
-
-        while index >= sorted.len() {
-            let o_min_index = vectors
-                .iter()
-                .map(|v| v.last())
-                .enumerate()
-                .filter(|(_idx, item)| item.is_some())
-                .min_by(|(_, a), (_, b)| self.compare_item(a.unwrap(), b.unwrap()))
-                .map(|(idx, _)| idx);
-            if o_min_index.is_none() {
-                break;
-            }
-
-            let min_index = o_min_index.unwrap();
-            let min_item = vectors[min_index].pop();
-            if min_item.is_none() {
-                break;
+    /// remove the items `keep` rejects, returns their former indices in ascending order. Only the
+    /// buckets with such items are sorted.
+    pub fn retain(&mut self, keep: impl Fn(&T) -> bool) -> Vec<usize> {
+        let mut removed = Vec::new();
+        let mut before = 0;
+        for bucket in self.buckets.get_mut().values_mut() {
+            if bucket.items.iter().all(&keep) {
+                before += bucket.items.len();
+                continue;
             }
-
-            if vectors[min_index].is_empty() {
-                vectors.remove(min_index);
+            Self::sort_bucket(bucket);
+            let items = std::mem::take(&mut bucket.items);
+            let len = items.len();
+            for (index, item) in items.into_iter().enumerate() {
+                if keep(&item) {
+                    bucket.items.push(item);
+                } else {
+                    removed.push(before + index);
+                }
             }
-
-            sorted.push(min_item.unwrap());
+            before += len;
         }
-    }
+        self.buckets.get_mut().retain(|_, bucket| !bucket.items.is_empty());
 
-    pub fn get(&self, index: usize) -> Option<Ref<T>> {
-        self.merge_till(index);
-        if self.len() <= index {
-            None
-        } else {
-            let index = if self.tac && self.nosort {
-                self.len() - index - 1
-            } else {
-                index
-            };
-            Some(Ref::map(self.sorted.borrow(), |list| &list[index]))
+        if self.tac {
+            removed = removed.into_iter().rev().map(|index| self.len - index - 1).collect();
         }
+        self.len -= removed.len();
+        removed
     }
 
     pub fn len(&self) -> usize {
-        let sorted_len = self.sorted.borrow().len();
-        let unsorted_len: usize = self.sub_vectors.borrow().iter().map(|v| v.len()).sum();
-        sorted_len + unsorted_len
+        self.len
     }
 
     pub fn clear(&mut self) {
-        self.sub_vectors.replace(DeferDrop::new(Vec::new()));
-        self.sorted.replace(DeferDrop::new(Vec::new()));
+        let buckets = std::mem::take(self.buckets.get_mut());
+        // drop the items in the background, there might be millions of them
+        drop(DeferDrop::new(buckets));
+        self.len = 0;
     }
 
     pub fn is_empty(&self) -> bool {
@@ -165,26 +178,18 @@ impl<T: Send + Ord + 'static> OrderedVec<T> {
     }
 
     pub fn iter(&self) -> impl Iterator<Item = Ref<T>> {
-        self.merge_till(self.len());
-        OrderedVecIter {
-            ordered_vec: self,
-            index: 0,
+        let mut positions = Vec::with_capacity(self.len);
+        for (&key, bucket) in self.buckets.borrow_mut().iter_mut() {
+            Self::sort_bucket(bucket);
+            positions.extend((0..bucket.items.len()).map(|index| (key, index)));
+        }
+        if self.tac {
+            positions.reverse();
         }
-    }
-}
-
-struct OrderedVecIter<'a, T: Send + Ord + 'static> {
-    ordered_vec: &'a OrderedVec<T>,
-    index: usize,
-}
-
-impl<'a, T: Send + Ord + 'static> Iterator for OrderedVecIter<'a, T> {
-    type Item = Ref<'a, T>;
 
-    fn next(&mut self) -> Option<Self::Item> {
-        let ret = self.ordered_vec.get(self.index);
-        self.index += 1;
-        ret
+        positions
+            .into_iter()
+            .map(move |(key, index)| Ref::map(self.buckets.borrow(), |buckets| &buckets[&key].items[index]))
     }
 }
 
@@ -192,6 +197,12 @@ impl<'a, T: Send + Ord + 'static> Iterator for OrderedVecIter<'a, T> {
 mod tests {
     use super::*;
 
+    impl Bucketed for usize {
+        fn bucket(&self) -> i64 {
+            (*self / 3) as i64
+        }
+    }
+
     #[test]
     fn test() {
         let a = vec![1, 3, 5, 7];
@@ -213,6 +224,42 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_position() {
+        let mut ordered_vec = OrderedVec::new();
+        ordered_vec.append(vec![1, 3, 5, 7]);
+        ordered_vec.append(vec![4, 8, 9]);
+        assert_eq!(ordered_vec.position(&5, |&item| item == 5), Some(3));
+        assert_eq!(ordered_vec.position(&9, |&item| item == 9), Some(6));
+        assert_eq!(ordered_vec.position(&6, |&item| item == 6), None);
+
+        ordered_vec.tac(true);
+        assert_eq!(ordered_vec.position(&5, |&item| item == 5), Some(3));
+        assert_eq!(ordered_vec.position(&9, |&item| item == 9), Some(0));
+
+        let mut ordered_vec = OrderedVec::new();
+        ordered_vec.nosort(true);
+        ordered_vec.append(vec![7, 1, 5]);
+        assert_eq!(ordered_vec.position(&1, |&item| item == 1), Some(1));
+    }
+
+    #[test]
+    fn test_retain() {
+        let mut ordered_vec = OrderedVec::new();
+        ordered_vec.append(vec![1, 3, 5, 7]);
+        ordered_vec.append(vec![4, 8, 9]);
+        assert_eq!(ordered_vec.retain(|&item| item % 4 != 1), vec![0, 3, 6]);
+        assert_eq!(
+            ordered_vec.iter().map(|item| *item).collect::<Vec<_>>(),
+            vec![3, 4, 7, 8]
+        );
+        assert_eq!(ordered_vec.len(), 4);
+
+        ordered_vec.tac(true);
+        assert_eq!(ordered_vec.retain(|&item| item != 3), vec![3]);
+        assert_eq!(ordered_vec.iter().map(|item| *item).collect::<Vec<_>>(), vec![8, 7, 4]);
+    }
+
     #[test]
     fn test_tac() {
         let a = vec![1, 3, 5, 7];
@@ -279,4 +326,25 @@ mod tests {
             assert_eq!(*a, *b);
         }
     }
+
+    #[test]
+    fn test_sort_visible_buckets() {
+        let mut ordered_vec = OrderedVec::new();
+        ordered_vec.append(vec![8, 1, 7, 0, 2]);
+        assert_eq!(*ordered_vec.get(1).unwrap(), 1);
+
+        // only the bucket of [0, 1, 2] is sorted
+        let sorted: Vec<_> = ordered_vec
+            .buckets
+            .borrow()
+            .values()
+            .map(|bucket| bucket.sorted)
+            .collect();
+        assert_eq!(sorted, vec![true, false]);
+        assert_eq!(*ordered_vec.get(4).unwrap(), 8);
+
+        ordered_vec.clear();
+        assert!(ordered_vec.is_empty());
+        assert!(ordered_vec.get(0).is_none());
+    }
 }
@@ -1,4 +1,8 @@
-use crate::SkimItem;
+use crate::field::{parse_output_fields, Delimiter, FieldRange};
+use crate::{MatchEngine, MatchedItem, MatchedRange, SkimItem};
+use std::any::Any;
+use std::borrow::Cow;
+use std::io::{self, Write};
 use std::sync::Arc;
 
 pub struct SkimOutput {
@@ -7,3 +11,223 @@ pub struct SkimOutput {
     pub cmd: String,
     pub selected_items: Vec<Arc<dyn SkimItem>>,
 }
+
+/// The output of a run of skim along with the ranges of the selected items matched by the final
+/// query, to highlight them the way skim did, see `Skim::run_with_matches`
+pub struct MatchedOutput {
+    pub output: SkimOutput,
+    /// the ranges of the text of every selected item matched by `output.query`, in the same order.
+    /// `None` if the item is not matched by the final query, e.g. it was selected with another one.
+    pub matched_ranges: Vec<Option<MatchedRange>>,
+}
+
+/// Which lines to print for an output and how they are terminated, see `SkimOutput::write_to`
+pub struct OutputFormat<'a> {
+    pub print_query: bool,
+    pub print_cmd: bool,
+    /// the terminator of every line, e.g. "\n" or "\0" for `--print0`
+    pub ending: &'a str,
+    /// output only these fields of the items split by the delimiter, e.g. `--accept-nth`
+    pub accept_nth: Option<(&'a Delimiter, &'a [FieldRange])>,
+    /// print the score of every item by this engine before it, separated by a tab, or by NUL if
+    /// the `ending` is NUL, e.g. `--print-score`. Items not matched by the engine score 0.
+    pub scorer: Option<&'a dyn MatchEngine>,
+    /// print the byte ranges of the text matched by the `scorer` after the score, e.g. `0-2,4-5`,
+    /// separated the same way, e.g. `--print-ranges`. Empty for the items not matched.
+    pub print_ranges: bool,
+}
+
+impl Default for OutputFormat<'_> {
+    fn default() -> Self {
+        Self {
+            print_query: false,
+            print_cmd: false,
+            ending: "\n",
+            accept_nth: None,
+            scorer: None,
+            print_ranges: false,
+        }
+    }
+}
+
+impl OutputFormat<'_> {
+    /// write the `output` of an item, or the `accept_nth` fields of it, followed by the ending
+    pub fn write_item(&self, writer: &mut impl Write, item: &Arc<dyn SkimItem>) -> io::Result<()> {
+        let matched = self.scorer.and_then(|engine| engine.match_item(item.clone()));
+        self.write_match_info(writer, item.as_ref(), matched.as_ref())?;
+        write!(writer, "{}{}", self.output_of(item.as_ref()), self.ending)
+    }
+
+    /// same as `write_item`, but reuse the score of the match instead of matching it again
+    pub fn write_matched(&self, writer: &mut impl Write, matched: &MatchedItem) -> io::Result<()> {
+        self.write_match_info(writer, matched.item.as_ref(), Some(matched))?;
+        write!(writer, "{}{}", self.output_of(matched.item.as_ref()), self.ending)
+    }
+
+    /// the `output` of an item, or the `accept_nth` fields of it
+    pub fn output_of<'b>(&self, item: &'b dyn SkimItem) -> Cow<'b, str> {
+        match self.accept_nth {
+            Some((delimiter, fields)) => Cow::Owned(parse_output_fields(delimiter, &item.output(), fields)),
+            None => item.output(),
+        }
+    }
+
+    fn write_match_info(
+        &self,
+        writer: &mut impl Write,
+        item: &dyn SkimItem,
+        matched: Option<&MatchedItem>,
+    ) -> io::Result<()> {
+        let separator = if self.ending == "\0" { "\0" } else { "\t" };
+        if self.scorer.is_some() {
+            write!(writer, "{}{}", matched.map_or(0, |matched| matched.score), separator)?;
+        }
+        if self.print_ranges {
+            let ranges = matched
+                .and_then(|matched| matched.matched_range.as_ref())
+                .map(|range| range.byte_ranges(&item.text()))
+                .unwrap_or_default();
+            write!(writer, "{}{}", format_ranges(&ranges), separator)?;
+        }
+        Ok(())
+    }
+}
+
+/// the byte ranges as `start-end` separated by commas, e.g. `0-2,4-5`
+fn format_ranges(ranges: &[(usize, usize)]) -> String {
+    ranges
+        .iter()
+        .map(|(start, end)| format!("{}-{}", start, end))
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+impl SkimOutput {
+    /// the output of the selected items, without a query, a command or an accept key
+    pub fn new(selected_items: Vec<Arc<dyn SkimItem>>) -> Self {
+        Self {
+            accept_key: None,
+            query: String::new(),
+            cmd: String::new(),
+            selected_items,
+        }
+    }
+
+    /// the payloads of type `T` attached to the selected items, in order, see `SkimItem::payload`
+    pub fn payloads<T: Any + Send + Sync>(&self) -> impl Iterator<Item = Arc<T>> + '_ {
+        self.selected_items
+            .iter()
+            .filter_map(|item| item.payload())
+            .filter_map(|payload| payload.downcast::<T>().ok())
+    }
+
+    /// write the output the way `sk` prints it: the query, the command, the accept key and the
+    /// selected items, in order, every line is terminated by `format.ending`.
+    pub fn write_to(&self, writer: &mut impl Write, format: &OutputFormat) -> io::Result<()> {
+        if format.print_query {
+            write!(writer, "{}{}", self.query, format.ending)?;
+        }
+
+        if format.print_cmd {
+            write!(writer, "{}{}", self.cmd, format.ending)?;
+        }
+
+        if let Some(key) = self.accept_key.as_ref() {
+            write!(writer, "{}{}", key, format.ending)?;
+        }
+
+        if let Some(engine) = format.scorer {
+            engine.prepare(&mut self.selected_items.iter());
+        }
+        for item in self.selected_items.iter() {
+            format.write_item(writer, item)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl MatchedOutput {
+    /// the selected items with the byte ranges of their text matched by the query, empty for the
+    /// items not matched, e.g. `[(item "src/main.rs", [(4, 6)])]` for the query "ma"
+    pub fn highlights(&self) -> impl Iterator<Item = (&Arc<dyn SkimItem>, Vec<(usize, usize)>)> + '_ {
+        self.output.selected_items.iter().enumerate().map(move |(idx, item)| {
+            let ranges = match self.matched_ranges.get(idx) {
+                Some(Some(range)) => range.byte_ranges(&item.text()),
+                _ => Vec::new(),
+            };
+            (item, ranges)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use regex::Regex;
+
+    #[test]
+    fn test_write_to() {
+        let output = SkimOutput {
+            accept_key: None,
+            query: "q".to_string(),
+            cmd: "c".to_string(),
+            selected_items: vec![Arc::new("a") as Arc<dyn SkimItem>, Arc::new("b")],
+        };
+
+        let mut buffer = Vec::new();
+        output.write_to(&mut buffer, &OutputFormat::default()).unwrap();
+        assert_eq!(b"a\nb\n".to_vec(), buffer);
+
+        let format = OutputFormat {
+            print_query: true,
+            print_cmd: true,
+            ending: "\0",
+            accept_nth: None,
+            scorer: None,
+            print_ranges: false,
+        };
+        let mut buffer = Vec::new();
+        output.write_to(&mut buffer, &format).unwrap();
+        assert_eq!(b"q\0c\0a\0b\0".to_vec(), buffer);
+
+        // the accept key only if one of --expect was pressed
+        let output = SkimOutput {
+            accept_key: Some("ctrl-v".to_string()),
+            ..output
+        };
+        let mut buffer = Vec::new();
+        output.write_to(&mut buffer, &format).unwrap();
+        assert_eq!(b"q\0c\0ctrl-v\0a\0b\0".to_vec(), buffer);
+
+        let output = SkimOutput {
+            accept_key: None,
+            selected_items: vec![Arc::new("a,1") as Arc<dyn SkimItem>, Arc::new("b,2")],
+            ..output
+        };
+        let delimiter = Regex::new(",").unwrap().into();
+        let format = OutputFormat {
+            accept_nth: Some((&delimiter, &[FieldRange::Single(2)])),
+            ..Default::default()
+        };
+        let mut buffer = Vec::new();
+        output.write_to(&mut buffer, &format).unwrap();
+        assert_eq!(b"1\n2\n".to_vec(), buffer);
+    }
+
+    #[test]
+    fn test_highlights() {
+        let output = MatchedOutput {
+            output: SkimOutput::new(vec![Arc::new("xaxb") as Arc<dyn SkimItem>, Arc::new("c")]),
+            matched_ranges: vec![Some(MatchedRange::Chars(vec![1, 3])), None],
+        };
+        let highlights: Vec<_> = output
+            .highlights()
+            .map(|(item, ranges)| (item.text().to_string(), ranges))
+            .collect();
+        assert_eq!(
+            highlights,
+            vec![("xaxb".to_string(), vec![(1, 2), (3, 4)]), ("c".to_string(), vec![])]
+        );
+    }
+}
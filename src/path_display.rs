@@ -0,0 +1,166 @@
+//! Shorten the displayed paths, e.g. `--shorten-paths`: `$HOME` is shown as `~` and the middle
+//! directories of the paths too long for the screen are replaced by an ellipsis
+//! (`/very/…/deep/file.rs`). Only the display changes, the full path is matched and output.
+use unicode_width::UnicodeWidthStr;
+
+use crate::ansi::AnsiString;
+use crate::attr::Attr;
+use crate::Matches;
+
+enum Piece {
+    Literal(&'static str),
+    /// a byte range of the text
+    Text(usize, usize),
+}
+
+/// Shorten the paths under the `$HOME` of the user
+pub struct PathShortener {
+    home: Option<String>,
+    ellipsis: &'static str,
+}
+
+impl PathShortener {
+    pub fn new(no_unicode: bool) -> Self {
+        Self {
+            home: std::env::var("HOME").ok(),
+            ellipsis: if no_unicode { "..." } else { "…" },
+        }
+    }
+
+    pub fn shorten(&self, text: &str, max_width: usize) -> Option<ShortPath> {
+        shorten_path(text, self.home.as_deref(), max_width, self.ellipsis)
+    }
+}
+
+/// The displayed path, the pieces of the text shown and what replaces the others
+pub struct ShortPath {
+    pieces: Vec<Piece>,
+}
+
+/// shorten `text` if it's a path (contains a `/`) to be shown in `max_width` columns, `None` if
+/// it's shown as it is
+fn shorten_path(text: &str, home: Option<&str>, max_width: usize, ellipsis: &'static str) -> Option<ShortPath> {
+    if !text.contains('/') || text.chars().any(char::is_control) {
+        return None;
+    }
+
+    let mut pieces = Vec::new();
+    let mut start = 0;
+    if let Some(home) = home.filter(|home| !home.is_empty() && *home != "/") {
+        let home = home.trim_end_matches('/');
+        if text == home || (text.starts_with(home) && text[home.len()..].starts_with('/')) {
+            pieces.push(Piece::Literal("~"));
+            start = home.len();
+        }
+    }
+
+    let rest = &text[start..];
+    let prefix_width = if start > 0 { 1 } else { 0 };
+    if prefix_width + rest.width() > max_width {
+        // keep the first directory, and the longest tail that fits, the last component at least
+        let skip = if rest.starts_with('/') { 1 } else { 0 };
+        if let Some(head_end) = rest[skip..].find('/').map(|slash| skip + slash + 1) {
+            let head_width = prefix_width + rest[..head_end].width() + ellipsis.width();
+            let tails: Vec<usize> = rest[head_end..]
+                .match_indices('/')
+                .map(|(slash, _)| head_end + slash)
+                .collect();
+            let tail = tails
+                .iter()
+                .find(|&&tail| head_width + rest[tail..].width() <= max_width)
+                .or_else(|| tails.last())
+                .filter(|&&tail| head_width + rest[tail..].width() < prefix_width + rest.width());
+            if let Some(&tail) = tail {
+                pieces.push(Piece::Text(start, start + head_end));
+                pieces.push(Piece::Literal(ellipsis));
+                pieces.push(Piece::Text(start + tail, text.len()));
+                return Some(ShortPath { pieces });
+            }
+        }
+    }
+
+    if pieces.is_empty() {
+        return None;
+    }
+    pieces.push(Piece::Text(start, text.len()));
+    Some(ShortPath { pieces })
+}
+
+impl ShortPath {
+    /// the displayed path with the matches of `text` that are still shown highlighted
+    pub fn render(&self, text: &str, matches: &Matches, attr: Attr) -> AnsiString<'static> {
+        let is_matched = |char_idx: usize, byte: usize| match *matches {
+            Matches::CharIndices(indices) => indices.contains(&char_idx),
+            Matches::CharRange(start, end) => start <= char_idx && char_idx < end,
+            Matches::ByteRange(start, end) => start <= byte && byte < end,
+            Matches::None => false,
+        };
+
+        // the fragments are byte ranges of the shown text, as the parsed ANSI strings
+        let mut shown = String::new();
+        let mut fragments = Vec::new();
+        for piece in self.pieces.iter() {
+            match *piece {
+                Piece::Literal(literal) => shown.push_str(literal),
+                Piece::Text(begin, end) => {
+                    let begin_char = text[..begin].chars().count();
+                    for (idx, (byte, ch)) in text[begin..end].char_indices().enumerate() {
+                        if is_matched(begin_char + idx, begin + byte) {
+                            let start = shown.len() + byte;
+                            fragments.push((attr, (start as u32, (start + ch.len_utf8()) as u32)));
+                        }
+                    }
+                    shown.push_str(&text[begin..end]);
+                }
+            }
+        }
+        AnsiString::new_string(shown, fragments)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn shorten(text: &str, max_width: usize) -> Option<String> {
+        shorten_path(text, Some("/home/user"), max_width, "…").map(|short| {
+            short
+                .render(text, &Matches::None, Attr::default())
+                .stripped()
+                .to_string()
+        })
+    }
+
+    #[test]
+    fn test_shorten_path() {
+        assert_eq!(shorten("/home/user/src/main.rs", 80), Some("~/src/main.rs".to_string()));
+        assert_eq!(shorten("/home/user", 80), Some("~".to_string()));
+        assert_eq!(shorten("/home/username/a", 80), None);
+        assert_eq!(shorten("/etc/hosts", 80), None);
+        assert_eq!(shorten("not a path", 3), None);
+
+        let path = "/very/long/path/to/deep/file.rs";
+        assert_eq!(shorten(path, 20), Some("/very/…/deep/file.rs".to_string()));
+        assert_eq!(shorten(path, 5), Some("/very/…/file.rs".to_string()));
+        assert_eq!(shorten("/home/user/a/b/c/d.rs", 10), Some("~/a/…/d.rs".to_string()));
+        assert_eq!(shorten("src/a/b/file.rs", 12), Some("src/…/file.rs".to_string()));
+        // nothing shorter to show
+        assert_eq!(shorten("/a/b/c", 3), None);
+    }
+
+    #[test]
+    fn test_render_matches() {
+        let text = "/very/long/path/file.rs";
+        let short = shorten_path(text, None, 15, "…").unwrap();
+        let attr = Attr::default().fg(crate::attr::Color::RED);
+        // "v" shown, "long" elided, "file" shown
+        let rendered = short.render(text, &Matches::CharIndices(&[1, 6, 16]), attr);
+        assert_eq!(rendered.stripped(), "/very/…/file.rs");
+        let highlighted: Vec<&str> = rendered
+            .spans()
+            .filter(|(_, span_attr)| *span_attr == attr)
+            .map(|(text, _)| text)
+            .collect();
+        assert_eq!(highlighted, vec!["v", "f"]);
+    }
+}
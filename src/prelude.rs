@@ -1,8 +1,10 @@
 pub use crate::ansi::AnsiString;
 pub use crate::engine::{factory::*, fuzzy::FuzzyAlgorithm};
+#[cfg(feature = "tui")]
 pub use crate::helper::item_reader::{SkimItemReader, SkimItemReaderOption};
+#[cfg(feature = "tui")]
 pub use crate::options::{SkimOptions, SkimOptionsBuilder};
-pub use crate::output::SkimOutput;
+pub use crate::output::{MatchedOutput, OutputFormat, SkimOutput};
 pub use crate::*;
 pub use crossbeam::channel::{bounded, unbounded, Receiver, Sender};
 pub use std::borrow::Cow;
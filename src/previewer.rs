@@ -1,26 +1,35 @@
 use std::borrow::Cow;
 use std::cmp::{max, min};
-use std::env;
-use std::process::{Command, Stdio};
+use std::collections::VecDeque;
+use std::mem;
+use std::os::unix::process::CommandExt;
+use std::process::Stdio;
 use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
-use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError, Sender};
 use std::sync::Arc;
 use std::thread;
 use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
 
 use derive_builder::Builder;
 use nix::libc;
 use regex::Regex;
 use tuikit::prelude::{Event as TermEvent, *};
+use unicode_width::UnicodeWidthChar;
 
 use crate::ansi::{ANSIParser, AnsiString};
 use crate::event::{Event, EventHandler, UpdateScreen};
+use crate::field::Delimiter;
 use crate::spinlock::SpinLock;
-use crate::util::{depends_on_items, inject_command, InjectContext};
+use crate::util::{depends_on_items, inject_command, inject_label, InjectContext, Shell};
 use crate::{ItemPreview, PreviewContext, SkimItem};
 
 const TAB_STOP: usize = 8;
 const DELIMITER_STR: &str = r"[\t\n ]+";
+// the number of preview outputs to keep, for moving back and forth between items
+const PREVIEW_CACHE_SIZE: usize = 16;
+// the time for a superseded preview command to exit on SIGTERM before it's killed
+const TERM_TIMEOUT: Duration = Duration::from_millis(100);
 
 pub struct Previewer {
     tx_preview: Sender<PreviewEvent>,
@@ -38,8 +47,23 @@ pub struct Previewer {
     prev_num_selected: usize,
 
     preview_cmd: Option<String>,
-    delimiter: Regex,
+    // label shown in the border, fields are expanded for every item
+    label: Option<String>,
+    label_text: String,
+    // command set by the `preview(...)` action, only valid for the current item
+    alternate_cmd: Option<String>,
+    // whether any item has provided its own preview, see `SkimItem::preview`
+    item_preview: bool,
+    force_refresh: bool,
+    // run the preview command again even if its output is cached, see `refresh-preview`
+    refresh: bool,
+    // kill the preview command if it doesn't finish in time
+    timeout: Option<Duration>,
+    shell: Shell,
+    delimiter: Delimiter,
     thread_previewer: Option<JoinHandle<()>>,
+    // the frame drawn last, only the rows that changed since are drawn again
+    drawn: SpinLock<Frame>,
 }
 
 impl Previewer {
@@ -52,8 +76,13 @@ impl Previewer {
         let content_clone = content_lines.clone();
         let thread_previewer = thread::spawn(move || {
             run(rx_preview, move |lines| {
-                *content_clone.lock() = lines;
-                callback();
+                // the screen is diffed when presented, there's no need to redraw the same content
+                let mut content = content_clone.lock();
+                if *content != lines {
+                    *content = lines;
+                    drop(content);
+                    callback();
+                }
             })
         });
 
@@ -73,8 +102,17 @@ impl Previewer {
             prev_num_selected: 0,
 
             preview_cmd,
-            delimiter: Regex::new(DELIMITER_STR).unwrap(),
+            label: None,
+            label_text: String::new(),
+            alternate_cmd: None,
+            item_preview: false,
+            force_refresh: false,
+            refresh: false,
+            timeout: None,
+            shell: Shell::default(),
+            delimiter: Regex::new(DELIMITER_STR).unwrap().into(),
             thread_previewer: Some(thread_previewer),
+            drawn: SpinLock::new(Frame::default()),
         }
     }
 
@@ -83,11 +121,37 @@ impl Previewer {
         self
     }
 
-    pub fn delimiter(mut self, delimiter: Regex) -> Self {
+    pub fn delimiter(mut self, delimiter: Delimiter) -> Self {
         self.delimiter = delimiter;
         self
     }
 
+    pub fn timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    pub fn shell(mut self, shell: Shell) -> Self {
+        self.shell = shell;
+        self
+    }
+
+    pub fn label(mut self, label: Option<String>) -> Self {
+        self.label = label;
+        self
+    }
+
+    /// the label with the fields of current item expanded, `None` if no label is set
+    pub fn get_label(&self) -> Option<&str> {
+        self.label.as_ref().map(|_| self.label_text.as_str())
+    }
+
+    /// whether there is anything to preview: a preview command, or an item that previews itself.
+    /// Once an item previews itself, the preview stays so that the layout doesn't jump around.
+    pub fn has_preview(&self) -> bool {
+        self.preview_cmd.is_some() || self.alternate_cmd.is_some() || self.item_preview
+    }
+
     pub fn on_item_change(
         &mut self,
         new_item_index: usize,
@@ -124,10 +188,21 @@ impl Previewer {
 
         let selected_items_changed = self.prev_num_selected != num_selected;
 
-        if !item_changed && !query_changed && !cmd_query_changed && !selected_items_changed {
+        if !item_changed && !query_changed && !cmd_query_changed && !selected_items_changed && !self.force_refresh {
             return;
         }
 
+        if item_changed {
+            self.alternate_cmd = None;
+        }
+        self.force_refresh = false;
+        // a refresh of the same preview keeps the scroll position
+        let refresh = mem::replace(&mut self.refresh, false)
+            && !item_changed
+            && !query_changed
+            && !cmd_query_changed
+            && !selected_items_changed;
+
         self.prev_item = new_item.clone();
         self.prev_query = new_query;
         self.prev_cmd_query = new_cmd_query;
@@ -160,6 +235,10 @@ impl Previewer {
             cmd_query: &cmd_query,
         };
 
+        if let Some(label) = self.label.as_ref() {
+            self.label_text = inject_label(label, inject_context).to_string();
+        }
+
         let preview_context = PreviewContext {
             query,
             cmd_query,
@@ -171,36 +250,57 @@ impl Previewer {
             selections: &selected_texts,
         };
 
-        let preview_event = match new_item {
-            Some(item) => match item.preview(preview_context) {
-                ItemPreview::Text(text) => PreviewEvent::PreviewPlainText(text),
-                ItemPreview::AnsiText(text) => PreviewEvent::PreviewAnsiText(text),
-                preview => {
-                    let cmd = match preview {
-                        ItemPreview::Command(cmd) => cmd,
-                        ItemPreview::Global => self.preview_cmd.clone().expect("previewer: not provided"),
-                        ItemPreview::Text(_) | ItemPreview::AnsiText(_) => unreachable!(),
-                    };
-
-                    if depends_on_items(&cmd) && self.prev_item.is_none() {
-                        debug!("the command for preview refers to items and currently there is no item");
-                        debug!("command to execute: [{}]", cmd);
-                        PreviewEvent::PreviewPlainText("no item matched".to_string())
-                    } else {
-                        let cmd = inject_command(&cmd, inject_context).to_string();
-                        let preview_command = PreviewCommand { cmd, columns, lines };
-
-                        PreviewEvent::PreviewCommand(preview_command)
+        let preview = new_item.map(|item| item.preview(preview_context));
+        if let Some(ItemPreview::Text(_)) | Some(ItemPreview::AnsiText(_)) | Some(ItemPreview::Command(_)) = preview {
+            self.item_preview = true;
+        }
+
+        let preview_event = match preview {
+            Some(ItemPreview::Text(text)) if self.alternate_cmd.is_none() => PreviewEvent::PreviewPlainText(text),
+            Some(ItemPreview::AnsiText(text)) if self.alternate_cmd.is_none() => PreviewEvent::PreviewAnsiText(text),
+            Some(preview) => {
+                let cmd = match (self.alternate_cmd.as_ref(), preview) {
+                    (Some(cmd), _) => cmd.clone(),
+                    (None, ItemPreview::Command(cmd)) => cmd,
+                    (None, ItemPreview::Global) => self.preview_cmd.clone().unwrap_or_default(),
+                    (None, ItemPreview::Text(_)) | (None, ItemPreview::AnsiText(_)) => unreachable!(),
+                };
+
+                if cmd.is_empty() {
+                    PreviewEvent::PreviewPlainText("".to_string())
+                } else if depends_on_items(&cmd) && self.prev_item.is_none() {
+                    debug!("the command for preview refers to items and currently there is no item");
+                    debug!("command to execute: [{}]", cmd);
+                    PreviewEvent::PreviewPlainText("no item matched".to_string())
+                } else {
+                    // the output of a command that doesn't refer to the items may change at any time
+                    let cache = depends_on_items(&cmd);
+                    let cmd = inject_command(&cmd, inject_context).to_string();
+                    let preview_command = PreviewCommand { cmd, columns, lines };
+
+                    PreviewEvent::PreviewCommand {
+                        command: preview_command,
+                        cache,
+                        refresh,
+                        timeout: self.timeout,
+                        shell: self.shell.clone(),
                     }
                 }
-            },
+            }
             None => PreviewEvent::PreviewPlainText("".to_string()),
         };
 
         let _ = self.tx_preview.send(preview_event);
 
-        self.hscroll_offset = 0;
-        self.vscroll_offset = 0;
+        if !refresh {
+            self.hscroll_offset = 0;
+            self.vscroll_offset = 0;
+        }
+    }
+
+    /// forget the frame drawn, it's all drawn next time, e.g. the preview moved on the screen
+    pub fn invalidate(&self) {
+        *self.drawn.lock() = Frame::default();
     }
 
     fn act_scroll_down(&mut self, diff: i32) {
@@ -224,6 +324,18 @@ impl Previewer {
     fn act_toggle_wrap(&mut self) {
         self.wrap = !self.wrap;
     }
+
+    /// preview the current item with `cmd` instead, until the current item changes
+    fn act_preview(&mut self, cmd: &str) {
+        self.alternate_cmd = Some(cmd.to_string());
+        self.force_refresh = true;
+    }
+
+    /// run the preview command of the current item again, e.g. for the content changes over time
+    fn act_refresh_preview(&mut self) {
+        self.force_refresh = true;
+        self.refresh = true;
+    }
 }
 
 impl Drop for Previewer {
@@ -239,6 +351,8 @@ impl EventHandler for Previewer {
         let height = self.height.load(Ordering::Relaxed);
         match event {
             EvActTogglePreviewWrap => self.act_toggle_wrap(),
+            EvActPreview(cmd) => self.act_preview(cmd),
+            EvActRefreshPreview => self.act_refresh_preview(),
             EvActPreviewUp(diff) => self.act_scroll_down(-*diff),
             EvActPreviewDown(diff) => self.act_scroll_down(*diff),
             EvActPreviewLeft(diff) => self.act_scroll_right(-*diff),
@@ -253,7 +367,6 @@ impl EventHandler for Previewer {
 
 impl Draw for Previewer {
     fn draw(&self, canvas: &mut dyn Canvas) -> Result<()> {
-        canvas.clear()?;
         let (screen_width, screen_height) = canvas.size()?;
 
         if screen_width == 0 || screen_height == 0 {
@@ -264,6 +377,7 @@ impl Draw for Previewer {
         self.height.store(screen_height, Ordering::Relaxed);
 
         let content = self.content_lines.lock();
+        let mut frame = Frame::new(screen_width, screen_height);
 
         let mut printer = PrinterBuilder::default()
             .width(screen_width)
@@ -273,12 +387,12 @@ impl Draw for Previewer {
             .wrap(self.wrap)
             .build()
             .unwrap();
-        printer.print_lines(canvas, &content);
+        printer.print_lines(&mut frame, &content);
 
         // print the vscroll info
         let status = format!("{}/{}", self.vscroll_offset + 1, content.len());
         let col = max(status.len() + 1, self.width.load(Ordering::Relaxed)) - status.len() - 1;
-        canvas.print_with_attr(
+        frame.print_with_attr(
             0,
             col,
             &status,
@@ -288,6 +402,14 @@ impl Draw for Previewer {
             },
         )?;
 
+        let mut drawn = self.drawn.lock();
+        for row in 0..screen_height {
+            if drawn.size() != frame.size() || drawn.cells[row] != frame.cells[row] {
+                frame.draw_row(canvas, row)?;
+            }
+        }
+        *drawn = frame;
+
         Ok(())
     }
 }
@@ -304,6 +426,69 @@ impl Widget<Event> for Previewer {
     }
 }
 
+/// The cells of the preview, printed off the screen first to be compared with the last frame
+#[derive(Default)]
+struct Frame {
+    width: usize,
+    // by the row
+    cells: Vec<Vec<Cell>>,
+}
+
+impl Frame {
+    fn new(width: usize, height: usize) -> Self {
+        Self {
+            width,
+            cells: vec![vec![Cell::empty(); width]; height],
+        }
+    }
+
+    fn size(&self) -> (usize, usize) {
+        (self.width, self.cells.len())
+    }
+
+    /// put the cells of the row on `canvas`, the whole row is overwritten
+    fn draw_row(&self, canvas: &mut dyn Canvas, row: usize) -> Result<()> {
+        let mut col = 0;
+        while col < self.width {
+            let cell = self.cells[row][col];
+            canvas.put_cell(row, col, cell)?;
+            col += max(cell.ch.width().unwrap_or(2), 1);
+        }
+        Ok(())
+    }
+}
+
+impl Canvas for Frame {
+    fn size(&self) -> Result<(usize, usize)> {
+        Ok(Frame::size(self))
+    }
+
+    fn clear(&mut self) -> Result<()> {
+        *self = Frame::new(self.width, self.cells.len());
+        Ok(())
+    }
+
+    fn put_cell(&mut self, row: usize, col: usize, cell: Cell) -> Result<usize> {
+        // the same as the screen: a wide character takes the next cell as well
+        let ch_width = cell.ch.width().unwrap_or(2);
+        if row < self.cells.len() && col + max(ch_width, 1) <= self.width {
+            self.cells[row][col] = cell;
+            if ch_width > 1 {
+                self.cells[row][col + 1] = Cell::default();
+            }
+        }
+        Ok(ch_width)
+    }
+
+    fn set_cursor(&mut self, _row: usize, _col: usize) -> Result<()> {
+        Ok(())
+    }
+
+    fn show_cursor(&mut self, _show: bool) -> Result<()> {
+        Ok(())
+    }
+}
+
 #[derive(Debug, Ord, PartialOrd, PartialEq, Eq)]
 pub struct PreviewCommand {
     pub cmd: String,
@@ -313,7 +498,14 @@ pub struct PreviewCommand {
 
 #[derive(Debug)]
 enum PreviewEvent {
-    PreviewCommand(PreviewCommand),
+    PreviewCommand {
+        command: PreviewCommand,
+        cache: bool,
+        // don't use the cached output, but cache the new one
+        refresh: bool,
+        timeout: Option<Duration>,
+        shell: Shell,
+    },
     PreviewPlainText(String),
     PreviewAnsiText(String),
     Abort,
@@ -323,57 +515,134 @@ struct PreviewThread {
     pid: u32,
     thread: thread::JoinHandle<()>,
     stopped: Arc<AtomicBool>,
+    // the output of a killed command is not shown, the last preview stays until the next one
+    killed: Arc<AtomicBool>,
 }
 
 impl PreviewThread {
+    /// terminate the command along with the processes it spawned, which are in its process group
     fn kill(self) {
+        self.killed.store(true, Ordering::SeqCst);
+        let pgid = -(self.pid as i32);
+        if !self.stopped.load(Ordering::Relaxed) {
+            unsafe { libc::kill(pgid, libc::SIGTERM) };
+            let deadline = Instant::now() + TERM_TIMEOUT;
+            while !self.stopped.load(Ordering::Relaxed) && Instant::now() < deadline {
+                thread::sleep(Duration::from_millis(5));
+            }
+        }
         if !self.stopped.load(Ordering::Relaxed) {
-            unsafe { libc::kill(self.pid as i32, libc::SIGKILL) };
+            unsafe { libc::kill(pgid, libc::SIGKILL) };
         }
         self.thread.join().expect("Failed to join Preview process");
     }
 }
 
+/// The least recently used outputs of the preview commands, which contain the items injected
+#[derive(Default)]
+struct PreviewCache {
+    entries: VecDeque<(PreviewCommand, Vec<AnsiString<'static>>)>,
+}
+
+impl PreviewCache {
+    fn get(&mut self, command: &PreviewCommand) -> Option<Vec<AnsiString<'static>>> {
+        let idx = self.entries.iter().position(|(cmd, _)| cmd == command)?;
+        let entry = self.entries.remove(idx)?;
+        let lines = entry.1.clone();
+        self.entries.push_back(entry);
+        Some(lines)
+    }
+
+    fn insert(&mut self, command: PreviewCommand, lines: Vec<AnsiString<'static>>) {
+        self.entries.retain(|(cmd, _)| *cmd != command);
+        if self.entries.len() >= PREVIEW_CACHE_SIZE {
+            self.entries.pop_front();
+        }
+        self.entries.push_back((command, lines));
+    }
+}
+
 fn run<C>(rx_preview: Receiver<PreviewEvent>, on_return: C)
 where
     C: Fn(Vec<AnsiString<'static>>) + Send + Sync + 'static,
 {
     let callback = Arc::new(on_return);
+    let cache = Arc::new(SpinLock::new(PreviewCache::default()));
     let mut preview_thread: Option<PreviewThread> = None;
-    while let Ok(_event) = rx_preview.recv() {
+    // when the running preview command times out
+    let mut deadline: Option<(Instant, Duration)> = None;
+    loop {
+        let received = match deadline {
+            Some((instant, _)) => rx_preview.recv_timeout(instant.saturating_duration_since(Instant::now())),
+            None => rx_preview.recv().map_err(|_| RecvTimeoutError::Disconnected),
+        };
+        let _event = match received {
+            Ok(event) => event,
+            Err(RecvTimeoutError::Timeout) => {
+                let (_, timeout) = deadline.take().unwrap();
+                if let Some(thread) = preview_thread.take() {
+                    let timed_out = !thread.stopped.load(Ordering::Relaxed);
+                    thread.kill();
+                    if timed_out {
+                        let message = format!("preview timed out after {} ms", timeout.as_millis());
+                        callback(vec![message.into()]);
+                    }
+                }
+                continue;
+            }
+            Err(RecvTimeoutError::Disconnected) => {
+                // the previewer is gone, so is whoever would read the output
+                if let Some(thread) = preview_thread.take() {
+                    thread.kill();
+                }
+                break;
+            }
+        };
+        deadline = None;
+
         if preview_thread.is_some() {
             preview_thread.unwrap().kill();
             preview_thread = None;
         }
 
-        let mut event = match _event {
-            PreviewEvent::Abort => return,
-            _ => _event,
-        };
+        let mut event = _event;
 
         // Try to empty the channel. Happens when spamming up/down or typing fast.
         while let Ok(_event) = rx_preview.try_recv() {
-            event = match _event {
-                PreviewEvent::Abort => return,
-                _ => _event,
+            event = _event;
+            if let PreviewEvent::Abort = event {
+                break;
             }
         }
 
         match event {
-            PreviewEvent::PreviewCommand(preview_cmd) => {
+            PreviewEvent::PreviewCommand {
+                command: preview_cmd,
+                cache: cacheable,
+                refresh,
+                timeout,
+                shell,
+            } => {
                 let cmd = &preview_cmd.cmd;
                 if cmd == "" {
                     continue;
                 }
 
-                let shell = env::var("SHELL").unwrap_or_else(|_| "sh".to_string());
-                let spawned = Command::new(shell)
+                if let Some(lines) = (cacheable && !refresh)
+                    .then(|| cache.lock().get(&preview_cmd))
+                    .flatten()
+                {
+                    callback(lines);
+                    continue;
+                }
+
+                let spawned = shell
+                    .command(cmd)
                     .env("LINES", preview_cmd.lines.to_string())
                     .env("COLUMNS", preview_cmd.columns.to_string())
-                    .arg("-c")
-                    .arg(&cmd)
                     .stdout(Stdio::piped())
                     .stderr(Stdio::piped())
+                    .process_group(0)
                     .spawn();
 
                 match spawned {
@@ -386,14 +655,29 @@ where
                         let pid = spawned.id();
                         let stopped = Arc::new(AtomicBool::new(false));
                         let stopped_clone = stopped.clone();
+                        let killed = Arc::new(AtomicBool::new(false));
+                        let killed_clone = killed.clone();
                         let callback_clone = callback.clone();
+                        let cache_clone = cache.clone();
                         let thread = thread::spawn(move || {
-                            wait(spawned, move |lines| {
+                            wait(spawned, move |lines, success| {
                                 stopped_clone.store(true, Ordering::SeqCst);
+                                if killed_clone.load(Ordering::SeqCst) {
+                                    return;
+                                }
+                                if cacheable && success {
+                                    cache_clone.lock().insert(preview_cmd, lines.clone());
+                                }
                                 callback_clone(lines);
                             })
                         });
-                        preview_thread = Some(PreviewThread { pid, thread, stopped });
+                        preview_thread = Some(PreviewThread {
+                            pid,
+                            thread,
+                            stopped,
+                            killed,
+                        });
+                        deadline = timeout.map(|timeout| (Instant::now() + timeout, timeout));
                     }
                 }
             }
@@ -412,7 +696,7 @@ where
 
 fn wait<C>(spawned: std::process::Child, callback: C)
 where
-    C: Fn(Vec<AnsiString<'static>>),
+    C: FnOnce(Vec<AnsiString<'static>>, bool),
 {
     let output = spawned.wait_with_output();
 
@@ -430,7 +714,7 @@ where
     });
 
     let lines = out_str.lines().map(AnsiString::parse).collect();
-    callback(lines);
+    callback(lines, output.status.success());
 }
 
 #[derive(Builder, Default, Debug)]
@@ -530,3 +814,124 @@ impl Printer {
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn command(cmd: &str) -> PreviewCommand {
+        PreviewCommand {
+            cmd: cmd.to_string(),
+            lines: 10,
+            columns: 80,
+        }
+    }
+
+    #[test]
+    fn test_preview_cache() {
+        let mut cache = PreviewCache::default();
+        for idx in 0..PREVIEW_CACHE_SIZE {
+            cache.insert(command(&format!("cat {}", idx)), vec![idx.to_string().into()]);
+        }
+
+        // the recently used one is kept over the older ones
+        assert!(cache.get(&command("cat 0")).is_some());
+        cache.insert(command("cat new"), vec![]);
+        assert!(cache.get(&command("cat 0")).is_some());
+        assert!(cache.get(&command("cat 1")).is_none());
+
+        // the size of the preview window counts
+        let mut resized = command("cat 2");
+        resized.lines = 20;
+        assert!(cache.get(&resized).is_none());
+        assert_eq!("2", cache.get(&command("cat 2")).unwrap()[0].stripped());
+    }
+
+    #[test]
+    fn test_kill_on_disconnect() {
+        let pid_file = std::env::temp_dir().join(format!("skim-preview-pid-{}", std::process::id()));
+        let _ = std::fs::remove_file(&pid_file);
+        let (tx, rx) = channel();
+        let thread = thread::spawn(move || run(rx, |_| {}));
+        tx.send(PreviewEvent::PreviewCommand {
+            command: command(&format!("echo $$ > {}; exec sleep 10", pid_file.display())),
+            cache: false,
+            refresh: false,
+            timeout: None,
+            shell: Shell::default(),
+        })
+        .unwrap();
+
+        let deadline = Instant::now() + Duration::from_secs(5);
+        let pid = loop {
+            let pid = std::fs::read_to_string(&pid_file)
+                .ok()
+                .and_then(|pid| pid.trim().parse().ok());
+            match pid {
+                Some(pid) => break pid,
+                None if Instant::now() < deadline => thread::sleep(Duration::from_millis(10)),
+                None => panic!("the preview command didn't start"),
+            }
+        };
+        let _ = std::fs::remove_file(&pid_file);
+
+        drop(tx);
+        thread.join().unwrap();
+        assert_eq!(-1, unsafe { libc::kill(pid, 0) });
+    }
+
+    /// counts the cells put on it by the row
+    struct RowsCanvas {
+        width: usize,
+        height: usize,
+        rows: Vec<usize>,
+    }
+
+    impl Canvas for RowsCanvas {
+        fn size(&self) -> Result<(usize, usize)> {
+            Ok((self.width, self.height))
+        }
+        fn clear(&mut self) -> Result<()> {
+            Ok(())
+        }
+        fn put_cell(&mut self, row: usize, _col: usize, _cell: Cell) -> Result<usize> {
+            self.rows.push(row);
+            Ok(1)
+        }
+        fn set_cursor(&mut self, _row: usize, _col: usize) -> Result<()> {
+            Ok(())
+        }
+        fn show_cursor(&mut self, _show: bool) -> Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_draw_changed_rows() {
+        let previewer = Previewer::new(None, || {});
+        let set_content = |lines: &[&str]| {
+            *previewer.content_lines.lock() = lines.iter().map(|line| AnsiString::parse(line)).collect();
+        };
+        let mut canvas = RowsCanvas {
+            width: 10,
+            height: 4,
+            rows: vec![],
+        };
+        let mut drawn_rows = |previewer: &Previewer| {
+            previewer.draw(&mut canvas).unwrap();
+            let mut rows = mem::take(&mut canvas.rows);
+            rows.dedup();
+            rows
+        };
+
+        set_content(&["a", "b", "c"]);
+        assert_eq!(vec![0, 1, 2, 3], drawn_rows(&previewer));
+        assert!(drawn_rows(&previewer).is_empty());
+
+        set_content(&["a", "bb", "c"]);
+        assert_eq!(vec![1], drawn_rows(&previewer));
+
+        previewer.invalidate();
+        assert_eq!(vec![0, 1, 2, 3], drawn_rows(&previewer));
+    }
+}
@@ -4,6 +4,7 @@ use std::sync::Arc;
 use tuikit::prelude::*;
 use unicode_width::UnicodeWidthStr;
 
+use crate::ansi::AnsiString;
 use crate::event::{Event, EventHandler, UpdateScreen};
 use crate::options::SkimOptions;
 use crate::theme::{ColorTheme, DEFAULT_THEME};
@@ -24,13 +25,16 @@ pub struct Query {
     mode: QueryMode,
     base_cmd: String,
     replstr: String,
-    query_prompt: String,
-    cmd_prompt: String,
+    query_prompt: AnsiString<'static>,
+    cmd_prompt: AnsiString<'static>,
 
     cmd_history_before: Vec<String>,
     cmd_history_after: Vec<String>,
     fz_query_history_before: Vec<String>,
     fz_query_history_after: Vec<String>,
+    // only the history entries starting with it are visited, i.e. the text before the cursor when
+    // the navigation started
+    history_prefix: Option<String>,
 
     theme: Arc<ColorTheme>,
 }
@@ -47,13 +51,14 @@ impl Query {
             mode: QueryMode::QUERY,
             base_cmd: String::new(),
             replstr: "{}".to_string(),
-            query_prompt: "> ".to_string(),
-            cmd_prompt: "c> ".to_string(),
+            query_prompt: AnsiString::parse("> "),
+            cmd_prompt: AnsiString::parse("c> "),
 
             cmd_history_before: Vec::new(),
             cmd_history_after: Vec::new(),
             fz_query_history_before: Vec::new(),
             fz_query_history_after: Vec::new(),
+            history_prefix: None,
 
             theme: Arc::new(*DEFAULT_THEME),
         }
@@ -72,6 +77,11 @@ impl Query {
         self
     }
 
+    /// replace the command, e.g. to reload the items with another command
+    pub fn set_base_cmd(&mut self, base_cmd: &str) {
+        self.base_cmd = base_cmd.to_owned();
+    }
+
     pub fn fz_query(mut self, query: &str) -> Self {
         self.fz_query_before = query.chars().collect();
         self
@@ -120,11 +130,11 @@ impl Query {
         }
 
         if let Some(query_prompt) = options.prompt {
-            self.query_prompt = query_prompt.to_string();
+            self.query_prompt = AnsiString::parse(query_prompt);
         }
 
         if let Some(cmd_prompt) = options.cmd_prompt {
-            self.cmd_prompt = cmd_prompt.to_string();
+            self.cmd_prompt = AnsiString::parse(cmd_prompt);
         }
 
         self.fz_query_history_before = options.query_history.to_vec();
@@ -185,7 +195,7 @@ impl Query {
         }
     }
 
-    fn get_prompt(&self) -> &str {
+    fn get_prompt(&self) -> &AnsiString<'static> {
         match self.mode {
             QueryMode::CMD => &self.cmd_prompt,
             QueryMode::QUERY => &self.query_prompt,
@@ -235,6 +245,12 @@ impl Query {
         before.push(ch);
     }
 
+    /// insert the text at the cursor
+    pub fn act_put(&mut self, text: &str) {
+        let (before, _) = self.get_query_ref();
+        before.extend(text.chars());
+    }
+
     pub fn act_backward_delete_char(&mut self) {
         let (before, _) = self.get_query_ref();
         let _ = before.pop();
@@ -380,6 +396,29 @@ impl Query {
         self.save_yank(before, false);
     }
 
+    pub fn set_query_prompt(&mut self, prompt: &str) {
+        self.query_prompt = AnsiString::parse(prompt);
+    }
+
+    /// replace the query of current mode, leave the cursor at the end
+    pub fn act_change_query(&mut self, query: &str) {
+        let (before, after) = self.get_query_ref();
+        before.clear();
+        after.clear();
+        before.extend(query.chars());
+    }
+
+    /// move the cursor to `position`(in chars), or to the end if out of range
+    pub fn act_set_cursor(&mut self, position: usize) {
+        self.act_end_of_line();
+        let (before, after) = self.get_query_ref();
+        while before.len() > position {
+            if let Some(ch) = before.pop() {
+                after.push(ch);
+            }
+        }
+    }
+
     pub fn act_yank(&mut self) {
         let yank = mem::replace(&mut self.yank, Vec::new());
         for &c in &yank {
@@ -388,32 +427,39 @@ impl Query {
         let _ = mem::replace(&mut self.yank, yank);
     }
 
+    /// go to the previous history entry that starts with the history prefix
     pub fn previous_history(&mut self) {
+        let prefix = self.history_prefix();
         let current_query = self.get_query();
         let (history_before, history_after) = self.get_history_ref();
-        if let Some(history) = history_before.pop() {
-            history_after.push(current_query);
-
-            // store history into current query
-            let (query_before, _) = self.get_query_ref();
-            query_before.clear();
-            let mut new_query_chars = history.chars().collect();
-            query_before.append(&mut new_query_chars);
+        if let Some(history) = step_history(history_before, history_after, current_query, &prefix) {
+            self.set_history_query(&history);
         }
     }
 
+    /// go to the next history entry that starts with the history prefix
     pub fn next_history(&mut self) {
+        let prefix = self.history_prefix();
         let current_query = self.get_query();
         let (history_before, history_after) = self.get_history_ref();
-        if let Some(history) = history_after.pop() {
-            history_before.push(current_query);
+        if let Some(history) = step_history(history_after, history_before, current_query, &prefix) {
+            self.set_history_query(&history);
+        }
+    }
 
-            // store history into current query
+    fn history_prefix(&mut self) -> String {
+        if self.history_prefix.is_none() {
             let (query_before, _) = self.get_query_ref();
-            query_before.clear();
-            let mut new_query_chars = history.chars().collect();
-            query_before.append(&mut new_query_chars);
+            self.history_prefix = Some(query_before.iter().collect());
         }
+        self.history_prefix.clone().unwrap_or_default()
+    }
+
+    fn set_history_query(&mut self, history: &str) {
+        let (query_before, query_after) = self.get_query_ref();
+        query_before.clear();
+        query_after.clear();
+        query_before.extend(history.chars());
     }
 
     fn query_changed(
@@ -432,6 +478,15 @@ impl Query {
     }
 }
 
+/// move the current query to the stack of `to` and go to the last entry of `from` that starts with
+/// `prefix`, the entries skipped are moved along, so going back reaches them in order
+fn step_history(from: &mut Vec<String>, to: &mut Vec<String>, current: String, prefix: &str) -> Option<String> {
+    let pos = from.iter().rposition(|history| history.starts_with(prefix))?;
+    to.push(current);
+    to.extend(from.drain(pos + 1..).rev());
+    from.pop()
+}
+
 impl EventHandler for Query {
     fn handle(&mut self, event: &Event) -> UpdateScreen {
         use crate::event::Event::*;
@@ -447,6 +502,10 @@ impl EventHandler for Query {
                 self.act_add_char(*ch);
             }
 
+            EvActPut(text) => {
+                self.act_put(text);
+            }
+
             EvActDeleteChar | EvActDeleteCharEOF => {
                 self.act_delete_char();
             }
@@ -491,10 +550,14 @@ impl EventHandler for Query {
                 self.act_kill_word();
             }
 
-            EvActPreviousHistory => self.previous_history(),
+            EvActPreviousHistory => {
+                self.previous_history();
+                return UpdateScreen::REDRAW;
+            }
 
             EvActNextHistory => {
                 self.next_history();
+                return UpdateScreen::REDRAW;
             }
 
             EvActUnixLineDiscard => {
@@ -513,10 +576,20 @@ impl EventHandler for Query {
                 self.act_query_toggle_interactive();
             }
 
+            EvActChangeQuery(query) => {
+                self.act_change_query(query);
+            }
+
+            EvActSetQueryCursor(position) => {
+                self.act_set_cursor(*position);
+            }
+
             _ => {}
         }
 
         if self.query_changed(mode, query_before_len, query_after_len, cmd_before_len, cmd_after_len) {
+            // the query is edited, the next history navigation starts over
+            self.history_prefix = None;
             UpdateScreen::REDRAW
         } else {
             UpdateScreen::DONT_REDRAW
@@ -531,7 +604,10 @@ impl Draw for Query {
         let after = self.get_after();
         let prompt = self.get_prompt();
 
-        let prompt_width = canvas.print_with_attr(0, 0, prompt, self.theme.prompt())?;
+        let mut prompt_width = 0;
+        for (ch, attr) in prompt.iter() {
+            prompt_width += canvas.put_char_with_attr(0, prompt_width, ch, self.theme.prompt().extend(attr))?;
+        }
         let before_width = canvas.print_with_attr(0, prompt_width, &before, self.theme.query())?;
         let col = prompt_width + before_width;
         canvas.print_with_attr(0, col, &after, self.theme.query())?;
@@ -546,13 +622,17 @@ impl Widget<Event> for Query {
         let before = self.get_before();
         let after = self.get_after();
         let prompt = self.get_prompt();
-        (Some(prompt.width() + before.width() + after.width() + 1), None)
+        (
+            Some(prompt.stripped().width() + before.width() + after.width() + 1),
+            None,
+        )
     }
 }
 
 #[cfg(test)]
 mod test {
     use super::Query;
+    use crate::event::{Event, EventHandler};
 
     #[test]
     fn test_new_query() {
@@ -574,6 +654,44 @@ mod test {
         assert_eq!(query1.get_fz_query(), "ab中");
     }
 
+    #[test]
+    fn test_put() {
+        let mut query = Query::builder().fz_query("src").build();
+        query.act_put(".rs$");
+        assert_eq!(query.get_fz_query(), "src.rs$");
+
+        query.act_set_cursor(0);
+        query.act_put("^中 ");
+        assert_eq!(query.get_fz_query(), "^中 src.rs$");
+    }
+
+    #[test]
+    fn test_history_search() {
+        let history = ["git log", "ls", "git status", "cargo"];
+        let mut query = Query::builder()
+            .fz_query_history(history.iter().map(|h| h.to_string()).collect())
+            .fz_query("git")
+            .build();
+
+        query.previous_history();
+        assert_eq!(query.get_fz_query(), "git status");
+        query.previous_history();
+        assert_eq!(query.get_fz_query(), "git log");
+        query.previous_history();
+        assert_eq!(query.get_fz_query(), "git log");
+        query.next_history();
+        assert_eq!(query.get_fz_query(), "git status");
+        query.next_history();
+        assert_eq!(query.get_fz_query(), "git");
+
+        // without a prefix, every entry is visited
+        query.handle(&Event::EvActChangeQuery(String::new()));
+        query.handle(&Event::EvActPreviousHistory);
+        assert_eq!(query.get_fz_query(), "cargo");
+        query.previous_history();
+        assert_eq!(query.get_fz_query(), "git status");
+    }
+
     #[test]
     fn test_backward_delete_char() {
         let mut query = Query::builder().fz_query("AB中c").build();
@@ -594,4 +712,45 @@ mod test {
         query.act_backward_delete_char();
         assert_eq!(query.get_fz_query(), "");
     }
+
+    #[test]
+    fn test_change_query() {
+        let mut query = Query::builder().fz_query("abc").build();
+        query.act_backward_char();
+        query.act_change_query("中d");
+        assert_eq!(query.get_fz_query(), "中d");
+
+        query.act_add_char('e');
+        assert_eq!(query.get_fz_query(), "中de");
+
+        query.act_change_query("");
+        assert_eq!(query.get_fz_query(), "");
+    }
+
+    #[test]
+    fn test_set_cursor() {
+        let mut query = Query::builder().fz_query("ab中d").build();
+        query.act_set_cursor(2);
+        query.act_add_char('x');
+        assert_eq!(query.get_fz_query(), "abx中d");
+
+        query.act_set_cursor(0);
+        query.act_add_char('y');
+        assert_eq!(query.get_fz_query(), "yabx中d");
+
+        query.act_set_cursor(100);
+        query.act_add_char('z');
+        assert_eq!(query.get_fz_query(), "yabx中dz");
+    }
+
+    #[test]
+    fn test_ansi_prompt() {
+        let options = crate::SkimOptions {
+            prompt: Some("\x1b[31mfind\x1b[0m> "),
+            ..Default::default()
+        };
+        let query = Query::from_options(&options);
+        assert_eq!("find> ", query.get_prompt().stripped());
+        assert!(query.get_prompt().has_attrs());
+    }
 }
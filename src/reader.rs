@@ -5,14 +5,17 @@ use crate::global::mark_new_run;
 use crate::options::SkimOptions;
 use crate::spinlock::SpinLock;
 use crate::{SkimItem, SkimItemReceiver};
-use crossbeam::channel::{bounded, select, Sender};
+use crossbeam::channel::{bounded, select, RecvTimeoutError, Sender};
 use std::cell::RefCell;
 use std::rc::Rc;
 use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 use std::sync::Arc;
 use std::thread;
+use std::time::Duration;
 
 const CHANNEL_SIZE: usize = 1024;
+// how often a blocked reader checks whether the buffer is taken
+const BLOCK_INTERVAL: Duration = Duration::from_millis(10);
 
 pub trait CommandCollector {
     /// execute the `cmd` and produce a
@@ -23,6 +26,12 @@ pub trait CommandCollector {
     /// should add `1` on every thread creation and sub `1` on thread termination. reader would use
     /// this information to determine whether the collector had stopped or not.
     fn invoke(&mut self, cmd: &str, components_to_stop: Arc<AtomicUsize>) -> (SkimItemReceiver, Sender<i32>);
+
+    /// the exit code of the command of last `invoke`, `None` if it is still running, was killed
+    /// or the collector doesn't know about it.
+    fn exit_code(&self) -> Option<i32> {
+        None
+    }
 }
 
 pub struct ReaderControl {
@@ -30,6 +39,7 @@ pub struct ReaderControl {
     tx_interrupt_cmd: Option<Sender<i32>>,
     components_to_stop: Arc<AtomicUsize>,
     items: Arc<SpinLock<Vec<Arc<dyn SkimItem>>>>,
+    stats: Arc<ReadStats>,
 }
 
 impl ReaderControl {
@@ -51,15 +61,78 @@ impl ReaderControl {
         ret
     }
 
+    /// number of items read so far
+    pub fn num_read(&self) -> usize {
+        self.stats.num_read.load(Ordering::Relaxed)
+    }
+
+    /// number of items dropped because the buffer was full, see `--read-buffer`
+    pub fn num_dropped(&self) -> usize {
+        self.stats.num_dropped.load(Ordering::Relaxed)
+    }
+
+    /// whether the reading stopped at `--max-items` or `--max-bytes`, the rest of the input is dropped
+    pub fn is_truncated(&self) -> bool {
+        self.stats.truncated.load(Ordering::Relaxed)
+    }
+
     pub fn is_done(&self) -> bool {
         let items = self.items.lock();
         self.components_to_stop.load(Ordering::SeqCst) == 0 && items.is_empty()
     }
 }
 
+#[derive(Default)]
+struct ReadStats {
+    num_read: AtomicUsize,
+    num_dropped: AtomicUsize,
+    truncated: AtomicBool,
+}
+
+/// what to do with the new items when the buffer is full
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum Overflow {
+    /// stop reading until the matcher takes the buffered items, so the producer is blocked
+    Block,
+    /// drop the new items and count them
+    Drop,
+}
+
+/// the limits of the input and of the items read but not yet taken by the matcher
+#[derive(Clone, Copy)]
+struct ReadLimits {
+    max_items: Option<usize>,
+    max_bytes: Option<usize>,
+    max_buffered: Option<usize>,
+    overflow: Overflow,
+}
+
+impl Default for ReadLimits {
+    fn default() -> Self {
+        Self {
+            max_items: None,
+            max_bytes: None,
+            max_buffered: None,
+            overflow: Overflow::Block,
+        }
+    }
+}
+
+impl ReadLimits {
+    /// stop reading before the input exceeds either limit
+    fn exceeded(&self, num_items: usize, num_bytes: usize) -> bool {
+        self.max_items.is_some_and(|max| num_items > max) || self.max_bytes.is_some_and(|max| num_bytes > max)
+    }
+
+    fn buffer_full(&self, num_buffered: usize) -> bool {
+        self.max_buffered.is_some_and(|max| num_buffered >= max)
+    }
+}
+
 pub struct Reader {
     cmd_collector: Rc<RefCell<dyn CommandCollector>>,
     rx_item: Option<SkimItemReceiver>,
+    limits: ReadLimits,
 }
 
 impl Reader {
@@ -67,6 +140,15 @@ impl Reader {
         Self {
             cmd_collector: options.cmd_collector.clone(),
             rx_item: None,
+            limits: ReadLimits {
+                max_items: options.max_items,
+                max_bytes: options.max_bytes,
+                max_buffered: options.read_buffer.filter(|&size| size > 0),
+                overflow: match options.read_buffer_policy {
+                    "drop" => Overflow::Drop,
+                    _ => Overflow::Block,
+                },
+            },
         }
     }
 
@@ -75,6 +157,21 @@ impl Reader {
         self
     }
 
+    /// read the items of the next `run` from `rx_item` instead of the command
+    pub fn set_source(&mut self, rx_item: SkimItemReceiver) {
+        self.rx_item = Some(rx_item);
+    }
+
+    /// whether the next `run` reads the items from a source instead of the command
+    pub fn has_source(&self) -> bool {
+        self.rx_item.is_some()
+    }
+
+    /// the exit code of the last command, see `CommandCollector::exit_code`
+    pub fn exit_code(&self) -> Option<i32> {
+        self.cmd_collector.borrow().exit_code()
+    }
+
     pub fn run(&mut self, cmd: &str) -> ReaderControl {
         mark_new_run(cmd);
 
@@ -89,13 +186,22 @@ impl Reader {
         });
 
         let components_to_stop_clone = components_to_stop.clone();
-        let tx_interrupt = collect_item(components_to_stop_clone, rx_item, items_clone);
+        let stats = Arc::new(ReadStats::default());
+        let tx_interrupt = collect_item(
+            components_to_stop_clone,
+            rx_item,
+            items_clone,
+            self.limits,
+            stats.clone(),
+            tx_interrupt_cmd.clone(),
+        );
 
         ReaderControl {
             tx_interrupt,
             tx_interrupt_cmd,
             components_to_stop,
             items,
+            stats,
         }
     }
 }
@@ -104,6 +210,10 @@ fn collect_item(
     components_to_stop: Arc<AtomicUsize>,
     rx_item: SkimItemReceiver,
     items: Arc<SpinLock<Vec<Arc<dyn SkimItem>>>>,
+    limits: ReadLimits,
+    stats: Arc<ReadStats>,
+    // to stop the command once the input is truncated
+    tx_interrupt_cmd: Option<Sender<i32>>,
 ) -> Sender<i32> {
     let (tx_interrupt, rx_interrupt) = bounded(CHANNEL_SIZE);
 
@@ -114,12 +224,39 @@ fn collect_item(
         components_to_stop.fetch_add(1, Ordering::SeqCst);
         started_clone.store(true, Ordering::SeqCst); // notify parent that it is started
 
-        loop {
+        let mut num_bytes = 0;
+        'read: loop {
             select! {
                 recv(rx_item) -> new_item => match new_item {
                     Ok(item) => {
+                        if limits.buffer_full(items.lock().len()) {
+                            match limits.overflow {
+                                Overflow::Drop => {
+                                    stats.num_dropped.fetch_add(1, Ordering::Relaxed);
+                                    continue;
+                                }
+                                Overflow::Block => {
+                                    while limits.buffer_full(items.lock().len()) {
+                                        match rx_interrupt.recv_timeout(BLOCK_INTERVAL) {
+                                            Err(RecvTimeoutError::Timeout) => {}
+                                            _ => break 'read,
+                                        }
+                                    }
+                                }
+                            }
+                        }
+
+                        num_bytes += item.text().len() + 1;
+                        if limits.exceeded(stats.num_read.load(Ordering::Relaxed) + 1, num_bytes) {
+                            debug!("reader: the input is truncated");
+                            stats.truncated.store(true, Ordering::Relaxed);
+                            // the command may not write anything more to notice that it's not read
+                            let _ = tx_interrupt_cmd.map(|tx| tx.send(1));
+                            break;
+                        }
                         let mut vec = items.lock();
                         vec.push(item);
+                        stats.num_read.fetch_add(1, Ordering::Relaxed);
                     }
                     Err(_) => break,
                 },
@@ -137,3 +274,62 @@ fn collect_item(
 
     tx_interrupt
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crossbeam::channel::unbounded;
+
+    #[test]
+    fn test_read_limits() {
+        let limits = ReadLimits {
+            max_items: Some(2),
+            max_bytes: Some(10),
+            max_buffered: Some(4),
+            ..Default::default()
+        };
+        assert!(!limits.exceeded(2, 10));
+        assert!(limits.exceeded(3, 6));
+        assert!(limits.exceeded(1, 11));
+        assert!(!limits.buffer_full(3));
+        assert!(limits.buffer_full(4));
+
+        let unlimited = ReadLimits::default();
+        assert!(!unlimited.exceeded(usize::MAX, usize::MAX));
+        assert!(!unlimited.buffer_full(usize::MAX));
+    }
+
+    #[test]
+    fn test_drop_on_full_buffer() {
+        let (tx_item, rx_item) = unbounded();
+        for idx in 0..10 {
+            let item: Arc<dyn SkimItem> = Arc::new(idx.to_string());
+            tx_item.send(item).unwrap();
+        }
+        drop(tx_item);
+
+        let components_to_stop = Arc::new(AtomicUsize::new(0));
+        let items = Arc::new(SpinLock::new(Vec::new()));
+        let stats = Arc::new(ReadStats::default());
+        let limits = ReadLimits {
+            max_buffered: Some(3),
+            overflow: Overflow::Drop,
+            ..Default::default()
+        };
+        let _tx_interrupt = collect_item(
+            components_to_stop.clone(),
+            rx_item,
+            items.clone(),
+            limits,
+            stats.clone(),
+            None,
+        );
+        while components_to_stop.load(Ordering::SeqCst) != 0 {
+            thread::yield_now();
+        }
+
+        assert_eq!(items.lock().len(), 3);
+        assert_eq!(stats.num_read.load(Ordering::Relaxed), 3);
+        assert_eq!(stats.num_dropped.load(Ordering::Relaxed), 7);
+    }
+}
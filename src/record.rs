@@ -0,0 +1,311 @@
+//! Record the terminal events of a session into a file and replay them later
+//!
+//! The file is plain text, one event per line: the milliseconds since the session started, the
+//! kind of the event and its arguments, separated by tabs (shown as spaces below). The first line
+//! records the size of the terminal. e.g.
+//!
+//! ```text
+//! 0  size  80  24
+//! 512  key  char  a
+//! 873  key  ctrl  n
+//! 1024  resize  100  30
+//! 1650  key  enter
+//! ```
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use tuikit::prelude::{Event as TermEvent, Key, MouseButton};
+use tuikit::screen::Screen;
+
+use crate::embed::{SkimEngine, SkimPoll};
+use crate::options::SkimOptions;
+use crate::output::MatchedOutput;
+use crate::SkimItemReceiver;
+
+// how long to wait for skim to finish after all the recorded events are replayed
+const REPLAY_GRACE_PERIOD: Duration = Duration::from_millis(500);
+const REPLAY_TICK: Duration = Duration::from_millis(5);
+
+#[rustfmt::skip]
+const NAMED_KEYS: &[(&str, Key)] = &[
+    ("null",             Key::Null),
+    ("esc",              Key::ESC),
+    ("tab",              Key::Tab),
+    ("enter",            Key::Enter),
+    ("btab",             Key::BackTab),
+    ("bspace",           Key::Backspace),
+    ("alt-btab",         Key::AltBackTab),
+    ("up",               Key::Up),
+    ("down",             Key::Down),
+    ("left",             Key::Left),
+    ("right",            Key::Right),
+    ("home",             Key::Home),
+    ("end",              Key::End),
+    ("insert",           Key::Insert),
+    ("del",              Key::Delete),
+    ("pgup",             Key::PageUp),
+    ("pgdn",             Key::PageDown),
+    ("ctrl-up",          Key::CtrlUp),
+    ("ctrl-down",        Key::CtrlDown),
+    ("ctrl-left",        Key::CtrlLeft),
+    ("ctrl-right",       Key::CtrlRight),
+    ("shift-up",         Key::ShiftUp),
+    ("shift-down",       Key::ShiftDown),
+    ("shift-left",       Key::ShiftLeft),
+    ("shift-right",      Key::ShiftRight),
+    ("alt-up",           Key::AltUp),
+    ("alt-down",         Key::AltDown),
+    ("alt-left",         Key::AltLeft),
+    ("alt-right",        Key::AltRight),
+    ("alt-home",         Key::AltHome),
+    ("alt-end",          Key::AltEnd),
+    ("alt-pgup",         Key::AltPageUp),
+    ("alt-pgdn",         Key::AltPageDown),
+    ("alt-shift-up",     Key::AltShiftUp),
+    ("alt-shift-down",   Key::AltShiftDown),
+    ("alt-shift-left",   Key::AltShiftLeft),
+    ("alt-shift-right",  Key::AltShiftRight),
+    ("alt-enter",        Key::AltEnter),
+    ("alt-bspace",       Key::AltBackspace),
+    ("alt-tab",          Key::AltTab),
+];
+
+/// A recorded session: the initial size of the terminal and the events with their timing
+pub struct Recording {
+    pub width: usize,
+    pub height: usize,
+    pub events: Vec<(Duration, TermEvent)>,
+}
+
+impl Recording {
+    pub fn load(path: &str) -> std::io::Result<Self> {
+        let file = File::open(path)?;
+        let mut recording = Recording {
+            width: 80,
+            height: 24,
+            events: Vec::new(),
+        };
+
+        for line in BufReader::new(file).lines() {
+            let line = line?;
+            let (millis, rest) = match line.split_once('\t') {
+                Some((millis, rest)) => match millis.parse::<u64>() {
+                    Ok(millis) => (millis, rest),
+                    Err(_) => continue,
+                },
+                None => continue,
+            };
+
+            match parse_event(rest) {
+                Some(TermEvent::Resize { width, height }) if rest.starts_with("size\t") => {
+                    recording.width = width;
+                    recording.height = height;
+                }
+                Some(event) => recording.events.push((Duration::from_millis(millis), event)),
+                None => debug!("record: ignore unknown event: {}", line),
+            }
+        }
+
+        Ok(recording)
+    }
+}
+
+/// Append the events to a file, along with the time they happen
+pub struct Recorder {
+    writer: BufWriter<File>,
+    start: Instant,
+}
+
+impl Recorder {
+    pub fn create(path: &str, width: usize, height: usize) -> std::io::Result<Self> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        writeln!(writer, "0\tsize\t{}\t{}", width, height)?;
+        writer.flush()?;
+        Ok(Self {
+            writer,
+            start: Instant::now(),
+        })
+    }
+
+    pub fn record(&mut self, event: &TermEvent) {
+        if let Some(text) = format_event(event) {
+            let millis = self.start.elapsed().as_millis();
+            let _ = writeln!(self.writer, "{}\t{}", millis, text);
+            // flush so that the record survives a crash, which is what it's for
+            let _ = self.writer.flush();
+        }
+    }
+}
+
+/// Replay the recorded events with the same timing, against a fake terminal of the recorded size
+/// instead of the real one. Returns `None` (aborted) if the recording doesn't finish skim.
+pub fn replay(options: &SkimOptions, source: Option<SkimItemReceiver>, recording: Recording) -> Option<MatchedOutput> {
+    let mut screen = Screen::new(recording.width, recording.height);
+    let mut engine = SkimEngine::new(options, source);
+    let mut events = recording.events.into_iter().peekable();
+    let start = Instant::now();
+    let mut idle_since = None;
+
+    loop {
+        while let Some((_, event)) = events.next_if(|(time, _)| *time <= start.elapsed()) {
+            if let TermEvent::Resize { width, height } = event {
+                screen.resize(width, height);
+            }
+            engine.feed_event(event);
+        }
+
+        match engine.poll() {
+            SkimPoll::Done(output) => return output.map(|output| engine.matched_output(output)),
+            SkimPoll::Redraw => {
+                let _ = engine.draw(&mut screen);
+                idle_since = None;
+            }
+            SkimPoll::Idle if events.peek().is_none() => {
+                let idle_since = *idle_since.get_or_insert_with(Instant::now);
+                if idle_since.elapsed() > REPLAY_GRACE_PERIOD {
+                    debug!("replay: the recording ends before skim finishes");
+                    return None;
+                }
+            }
+            SkimPoll::Idle => {}
+        }
+
+        thread::sleep(REPLAY_TICK);
+    }
+}
+
+fn format_button(button: MouseButton) -> &'static str {
+    match button {
+        MouseButton::Left => "left",
+        MouseButton::Right => "right",
+        MouseButton::Middle => "middle",
+        MouseButton::WheelUp => "wheel-up",
+        MouseButton::WheelDown => "wheel-down",
+    }
+}
+
+fn parse_button(button: &str) -> Option<MouseButton> {
+    match button {
+        "left" => Some(MouseButton::Left),
+        "right" => Some(MouseButton::Right),
+        "middle" => Some(MouseButton::Middle),
+        "wheel-up" => Some(MouseButton::WheelUp),
+        "wheel-down" => Some(MouseButton::WheelDown),
+        _ => None,
+    }
+}
+
+fn format_key(key: &Key) -> Option<String> {
+    if let Some((name, _)) = NAMED_KEYS.iter().find(|(_, named)| named == key) {
+        return Some(name.to_string());
+    }
+
+    let text = match *key {
+        Key::Char(ch) => format!("char\t{}", ch),
+        Key::Ctrl(ch) => format!("ctrl\t{}", ch),
+        Key::Alt(ch) => format!("alt\t{}", ch),
+        Key::CtrlAlt(ch) => format!("ctrl-alt\t{}", ch),
+        Key::F(n) => format!("f\t{}", n),
+        Key::MousePress(button, row, col) => format!("press\t{}\t{}\t{}", format_button(button), row, col),
+        Key::MouseRelease(row, col) => format!("release\t{}\t{}", row, col),
+        Key::MouseHold(row, col) => format!("hold\t{}\t{}", row, col),
+        Key::SingleClick(button, row, col) => format!("click\t{}\t{}\t{}", format_button(button), row, col),
+        Key::DoubleClick(button, row, col) => format!("double-click\t{}\t{}\t{}", format_button(button), row, col),
+        Key::WheelUp(row, col, count) => format!("wheel-up\t{}\t{}\t{}", row, col, count),
+        Key::WheelDown(row, col, count) => format!("wheel-down\t{}\t{}\t{}", row, col, count),
+        _ => return None,
+    };
+    Some(text)
+}
+
+fn parse_key(text: &str) -> Option<Key> {
+    let mut args = text.split('\t');
+    let kind = args.next()?;
+    if let Some((_, key)) = NAMED_KEYS.iter().find(|(name, _)| *name == kind) {
+        return Some(*key);
+    }
+
+    // the char could be a tab itself, thus not split
+    let ch = || text.split_once('\t').and_then(|(_, arg)| arg.chars().next());
+    let mut num = || args.next().and_then(|arg| arg.parse::<u16>().ok());
+
+    let key = match kind {
+        "char" => Key::Char(ch()?),
+        "ctrl" => Key::Ctrl(ch()?),
+        "alt" => Key::Alt(ch()?),
+        "ctrl-alt" => Key::CtrlAlt(ch()?),
+        "f" => Key::F(num()? as u8),
+        "release" => Key::MouseRelease(num()?, num()?),
+        "hold" => Key::MouseHold(num()?, num()?),
+        "wheel-up" => Key::WheelUp(num()?, num()?, num()?),
+        "wheel-down" => Key::WheelDown(num()?, num()?, num()?),
+        "press" | "click" | "double-click" => {
+            let button = parse_button(args.next()?)?;
+            let mut num = || args.next().and_then(|arg| arg.parse::<u16>().ok());
+            let (row, col) = (num()?, num()?);
+            match kind {
+                "press" => Key::MousePress(button, row, col),
+                "click" => Key::SingleClick(button, row, col),
+                _ => Key::DoubleClick(button, row, col),
+            }
+        }
+        _ => return None,
+    };
+    Some(key)
+}
+
+fn format_event(event: &TermEvent) -> Option<String> {
+    match event {
+        TermEvent::Key(key) => format_key(key).map(|key| format!("key\t{}", key)),
+        TermEvent::Resize { width, height } => Some(format!("resize\t{}\t{}", width, height)),
+        _ => None,
+    }
+}
+
+/// parse an event, the size in the header is returned as a resize event
+fn parse_event(text: &str) -> Option<TermEvent> {
+    match text.split_once('\t').unwrap_or((text, "")) {
+        ("key", key) => parse_key(key).map(TermEvent::Key),
+        ("resize", size) | ("size", size) => {
+            let mut size = size.split('\t').map(|num| num.parse::<usize>().ok());
+            let width = size.next()??;
+            let height = size.next()??;
+            Some(TermEvent::Resize { width, height })
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_event_round_trip() {
+        let events = vec![
+            TermEvent::Key(Key::Char('a')),
+            TermEvent::Key(Key::Char(' ')),
+            TermEvent::Key(Key::Char('\t')),
+            TermEvent::Key(Key::Ctrl('n')),
+            TermEvent::Key(Key::Alt('B')),
+            TermEvent::Key(Key::CtrlAlt('x')),
+            TermEvent::Key(Key::Enter),
+            TermEvent::Key(Key::AltShiftLeft),
+            TermEvent::Key(Key::F(12)),
+            TermEvent::Key(Key::SingleClick(MouseButton::Right, 3, 14)),
+            TermEvent::Key(Key::WheelDown(1, 2, 3)),
+            TermEvent::Key(Key::MouseRelease(5, 6)),
+            TermEvent::Resize { width: 100, height: 30 },
+        ];
+
+        for event in events.into_iter() {
+            let text = format_event(&event).unwrap();
+            assert_eq!(Some(event), parse_event(&text), "{}", text);
+        }
+
+        assert_eq!(None, format_event(&TermEvent::Restarted));
+        assert_eq!(None, parse_event("key\tunknown"));
+    }
+}
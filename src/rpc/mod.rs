@@ -0,0 +1,445 @@
+//! The RPC mode: skim runs as a co-process of an editor and speaks JSON-RPC 2.0 over stdio, one
+//! message per line, instead of drawing on the terminal. The editor pushes the items, sets the
+//! query, asks for the results and draws them itself. The messages could be MessagePack values
+//! instead, see `Framing`.
+//!
+//! Methods:
+//! - `push {"items": [string]}`: add the items, the result is `{"total": n}`
+//! - `set_query {"query": string}`: match the items against the query, the result is
+//!   `{"matched": n}`
+//! - `results {"offset": n, "limit": n}`: the matched items in order, the result is
+//!   `{"query", "total", "matched", "items": [{"index", "text", "score", "indices"}]}` where
+//!   `index` is the position of the item in the pushed ones and `indices` the matched chars
+//! - `accept {"indices": [n]}`: accept the items at these positions, the top result if omitted,
+//!   the result is `{"query", "items": [string]}` and the session ends
+//! - `abort`: end the session without accepting
+mod json;
+mod msgpack;
+
+use std::io::{self, BufRead, Write};
+use std::sync::Arc;
+
+use crate::helper::item_reader::SkimItemReader;
+use crate::output::OutputFormat;
+use crate::{CaseMatching, MatchEngineFactory, MatchedItem, SkimItem};
+
+pub use self::json::Json;
+
+const PARSE_ERROR: i32 = -32700;
+const INVALID_REQUEST: i32 = -32600;
+const METHOD_NOT_FOUND: i32 = -32601;
+const INVALID_PARAMS: i32 = -32602;
+
+const DEFAULT_LIMIT: usize = 100;
+
+/// how the messages are encoded and delimited
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Framing {
+    /// a JSON text per line
+    #[default]
+    JsonLines,
+    /// MessagePack values one after another, the same messages encoded as maps and arrays
+    MessagePack,
+}
+
+/// whether the session goes on after a request
+enum Flow {
+    Continue,
+    Exit(i32),
+}
+
+type RpcResult = Result<(Json, Flow), (i32, String)>;
+
+pub struct RpcServer<'a> {
+    engine_factory: &'a dyn MatchEngineFactory,
+    item_reader: &'a SkimItemReader,
+    case: CaseMatching,
+    format: OutputFormat<'a>,
+    framing: Framing,
+    items: Vec<Arc<dyn SkimItem>>,
+    query: String,
+    /// the matched items in order, with their positions in `items`
+    matched: Vec<(usize, MatchedItem)>,
+}
+
+impl<'a> RpcServer<'a> {
+    /// the pushed items are read by `item_reader`, so they are split and transformed the same way
+    /// as the input of the finder
+    pub fn new(engine_factory: &'a dyn MatchEngineFactory, item_reader: &'a SkimItemReader) -> Self {
+        Self {
+            engine_factory,
+            item_reader,
+            case: CaseMatching::default(),
+            format: OutputFormat {
+                ending: "",
+                ..Default::default()
+            },
+            framing: Framing::default(),
+            items: Vec::new(),
+            query: String::new(),
+            matched: Vec::new(),
+        }
+    }
+
+    pub fn case(mut self, case: CaseMatching) -> Self {
+        self.case = case;
+        self
+    }
+
+    /// how the accepted items are output, e.g. `accept_nth`, the ending is ignored
+    pub fn format(mut self, format: OutputFormat<'a>) -> Self {
+        self.format = OutputFormat { ending: "", ..format };
+        self
+    }
+
+    pub fn framing(mut self, framing: Framing) -> Self {
+        self.framing = framing;
+        self
+    }
+
+    /// serve the requests of `input` until it's closed or the session ends, returns the exit code:
+    /// 0 if items are accepted, 1 if there's nothing to accept, 2 if a MessagePack message is
+    /// invalid (the next one can't be found then) and 130 if aborted or closed
+    pub fn serve(mut self, mut input: impl BufRead, mut output: impl Write) -> io::Result<i32> {
+        while let Some(request) = self.read_request(&mut input)? {
+            let (response, flow) = match request {
+                Ok(request) => self.handle(&request),
+                Err(err) => {
+                    let flow = match self.framing {
+                        Framing::JsonLines => Flow::Continue,
+                        Framing::MessagePack => Flow::Exit(2),
+                    };
+                    (Some(error_response(Json::Null, PARSE_ERROR, err)), flow)
+                }
+            };
+
+            if let Some(response) = response {
+                match self.framing {
+                    Framing::JsonLines => writeln!(output, "{}", response)?,
+                    Framing::MessagePack => {
+                        let mut bytes = Vec::new();
+                        msgpack::write(&response, &mut bytes);
+                        output.write_all(&bytes)?;
+                    }
+                }
+                output.flush()?;
+            }
+
+            if let Flow::Exit(code) = flow {
+                return Ok(code);
+            }
+        }
+        Ok(130)
+    }
+
+    /// the next request, `None` if the input is closed
+    fn read_request(&self, input: &mut impl BufRead) -> io::Result<Option<Result<Json, String>>> {
+        match self.framing {
+            Framing::JsonLines => {
+                let mut line = String::new();
+                loop {
+                    line.clear();
+                    if input.read_line(&mut line)? == 0 {
+                        return Ok(None);
+                    }
+                    if !line.trim().is_empty() {
+                        return Ok(Some(Json::parse(&line)));
+                    }
+                }
+            }
+            Framing::MessagePack => msgpack::read(input),
+        }
+    }
+
+    /// the response of a request, none for notifications
+    fn handle(&mut self, request: &Json) -> (Option<Json>, Flow) {
+        let id = request.get("id").cloned();
+        let params = request.get("params").cloned().unwrap_or(Json::Null);
+        let result = match request.get("method").and_then(Json::as_str) {
+            Some("push") => self.push(&params),
+            Some("set_query") => self.set_query(&params),
+            Some("results") => self.results(&params),
+            Some("accept") => self.accept(&params),
+            Some("abort") => Ok((Json::Null, Flow::Exit(130))),
+            Some(method) => Err((METHOD_NOT_FOUND, format!("method not found: {}", method))),
+            None => Err((INVALID_REQUEST, "the request has no method".to_string())),
+        };
+
+        match (id, result) {
+            (None, Ok((_, flow))) => (None, flow),
+            (None, Err(_)) => (None, Flow::Continue),
+            (Some(id), Ok((result, flow))) => {
+                let response = Json::object(vec![("jsonrpc", "2.0".into()), ("id", id), ("result", result)]);
+                (Some(response), flow)
+            }
+            (Some(id), Err((code, message))) => (Some(error_response(id, code, message)), Flow::Continue),
+        }
+    }
+
+    fn push(&mut self, params: &Json) -> RpcResult {
+        let texts = params
+            .get("items")
+            .and_then(Json::as_array)
+            .and_then(|items| items.iter().map(Json::as_str).collect::<Option<Vec<_>>>())
+            .ok_or((INVALID_PARAMS, "expect the items as an array of strings".to_string()))?;
+
+        // an item per text, the texts could have line endings
+        let start = self.items.len();
+        let item_reader = self.item_reader;
+        self.items
+            .extend(texts.into_iter().map(|text| item_reader.of_text(text)));
+
+        let engine = self.engine_factory.create_engine_with_case(&self.query, self.case);
+        engine.prepare(&mut self.items[start..].iter());
+        let new_matches = self.items[start..]
+            .iter()
+            .enumerate()
+            .filter_map(|(idx, item)| engine.match_item(item.clone()).map(|matched| (start + idx, matched)));
+        self.matched.extend(new_matches);
+        // stable, the items of the same rank stay in the order they are pushed
+        self.matched.sort_by_key(|(_, matched)| matched.rank);
+
+        Ok((Json::object(vec![("total", self.items.len().into())]), Flow::Continue))
+    }
+
+    fn set_query(&mut self, params: &Json) -> RpcResult {
+        let query = params
+            .get("query")
+            .and_then(Json::as_str)
+            .ok_or((INVALID_PARAMS, "expect the query as a string".to_string()))?;
+        self.query = query.to_string();
+
+        let engine = self.engine_factory.create_engine_with_case(&self.query, self.case);
+        engine.prepare(&mut self.items.iter());
+        self.matched = self
+            .items
+            .iter()
+            .enumerate()
+            .filter_map(|(idx, item)| engine.match_item(item.clone()).map(|matched| (idx, matched)))
+            .collect();
+        self.matched.sort_by_key(|(_, matched)| matched.rank);
+
+        Ok((
+            Json::object(vec![("matched", self.matched.len().into())]),
+            Flow::Continue,
+        ))
+    }
+
+    fn results(&mut self, params: &Json) -> RpcResult {
+        let offset = params.get("offset").and_then(Json::as_usize).unwrap_or(0);
+        let limit = params.get("limit").and_then(Json::as_usize).unwrap_or(DEFAULT_LIMIT);
+
+        let items = self
+            .matched
+            .iter()
+            .skip(offset)
+            .take(limit)
+            .map(|(idx, matched)| {
+                let text = matched.item.text();
+                let indices = matched
+                    .matched_range
+                    .as_ref()
+                    .map(|range| range.char_indices(&text))
+                    .unwrap_or_default();
+                let mut fields = vec![
+                    ("index", (*idx).into()),
+                    ("text", text.as_ref().into()),
+                    ("score", matched.score.into()),
+                    ("indices", indices.into()),
+                ];
+                if let Some(ref detail) = matched.score_detail {
+                    fields.push(("terms", detail.terms.clone().into()));
+                    if let Some(value) = detail.value {
+                        fields.push(("score_value", Json::Number(value)));
+                    }
+                }
+                Json::object(fields)
+            })
+            .collect();
+
+        let result = Json::object(vec![
+            ("query", self.query.as_str().into()),
+            ("total", self.items.len().into()),
+            ("matched", self.matched.len().into()),
+            ("items", Json::Array(items)),
+        ]);
+        Ok((result, Flow::Continue))
+    }
+
+    fn accept(&mut self, params: &Json) -> RpcResult {
+        let indices = match params.get("indices") {
+            Some(indices) => indices
+                .as_array()
+                .and_then(|indices| indices.iter().map(Json::as_usize).collect::<Option<Vec<_>>>())
+                .ok_or((INVALID_PARAMS, "expect the indices as an array of integers".to_string()))?,
+            None => self.matched.first().map(|(idx, _)| *idx).into_iter().collect(),
+        };
+
+        let mut outputs = Vec::new();
+        for idx in indices {
+            let item = self
+                .items
+                .get(idx)
+                .ok_or((INVALID_PARAMS, format!("no item at index {}", idx)))?;
+            let mut output = Vec::new();
+            self.format
+                .write_item(&mut output, item)
+                .map_err(|err| (INVALID_PARAMS, err.to_string()))?;
+            outputs.push(String::from_utf8_lossy(&output).into_owned());
+        }
+
+        let code = if outputs.is_empty() { 1 } else { 0 };
+        let result = Json::object(vec![("query", self.query.as_str().into()), ("items", outputs.into())]);
+        Ok((result, Flow::Exit(code)))
+    }
+}
+
+fn error_response(id: Json, code: i32, message: String) -> Json {
+    let error = Json::object(vec![("code", code.into()), ("message", message.into())]);
+    Json::object(vec![("jsonrpc", "2.0".into()), ("id", id), ("error", error)])
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::engine::factory::{AndOrEngineFactory, ExactOrFuzzyEngineFactory};
+    use crate::helper::item_reader::SkimItemReaderOption;
+    use std::io::Cursor;
+
+    fn serve(requests: &[&str]) -> (i32, Vec<Json>) {
+        serve_with(SkimItemReader::default(), requests)
+    }
+
+    fn serve_with(item_reader: SkimItemReader, requests: &[&str]) -> (i32, Vec<Json>) {
+        let engine_factory = AndOrEngineFactory::new(ExactOrFuzzyEngineFactory::builder().build());
+        let mut output = Vec::new();
+        let code = RpcServer::new(&engine_factory, &item_reader)
+            .serve(Cursor::new(requests.join("\n")), &mut output)
+            .unwrap();
+        let responses = String::from_utf8(output)
+            .unwrap()
+            .lines()
+            .map(|line| Json::parse(line).unwrap())
+            .collect();
+        (code, responses)
+    }
+
+    #[test]
+    fn test_session() {
+        let (code, responses) = serve(&[
+            r#"{"jsonrpc":"2.0","id":1,"method":"push","params":{"items":["src/main.rs","README.md"]}}"#,
+            r#"{"jsonrpc":"2.0","method":"push","params":{"items":["src/lib.rs"]}}"#,
+            r#"{"jsonrpc":"2.0","id":2,"method":"set_query","params":{"query":"lib"}}"#,
+            r#"{"jsonrpc":"2.0","id":3,"method":"results","params":{"limit":1}}"#,
+            r#"{"jsonrpc":"2.0","id":4,"method":"nothing"}"#,
+            r#"not json"#,
+            r#"{"jsonrpc":"2.0","id":5,"method":"accept","params":{"indices":[2]}}"#,
+            r#"{"jsonrpc":"2.0","id":6,"method":"abort"}"#,
+        ]);
+
+        assert_eq!(code, 0);
+        assert_eq!(responses.len(), 6);
+        assert_eq!(
+            responses[0].get("result").and_then(|r| r.get("total")),
+            Some(&Json::from(2))
+        );
+        assert_eq!(
+            responses[1].get("result").and_then(|r| r.get("matched")),
+            Some(&Json::from(1))
+        );
+
+        let results = responses[2].get("result").unwrap();
+        assert_eq!(results.get("total"), Some(&Json::from(3)));
+        let items = results.get("items").and_then(Json::as_array).unwrap();
+        assert_eq!(items.len(), 1);
+        assert_eq!(items[0].get("text").and_then(Json::as_str), Some("src/lib.rs"));
+        assert_eq!(items[0].get("index"), Some(&Json::from(2)));
+        assert_eq!(items[0].get("indices"), Some(&Json::from(vec![4, 5, 6])));
+
+        let error_code = |response: &Json| response.get("error").and_then(|e| e.get("code")).cloned();
+        assert_eq!(error_code(&responses[3]), Some(Json::from(METHOD_NOT_FOUND)));
+        assert_eq!(error_code(&responses[4]), Some(Json::from(PARSE_ERROR)));
+
+        let accepted = responses[5].get("result").unwrap();
+        assert_eq!(accepted.get("items"), Some(&Json::from(vec!["src/lib.rs"])));
+    }
+
+    #[test]
+    fn test_push_multiline() {
+        let requests = [
+            r#"{"jsonrpc":"2.0","id":1,"method":"push","params":{"items":["a\nb","c d","e"]}}"#,
+            r#"{"jsonrpc":"2.0","id":2,"method":"set_query","params":{"query":"a"}}"#,
+            r#"{"jsonrpc":"2.0","id":3,"method":"accept","params":{}}"#,
+        ];
+        let read0 = SkimItemReader::new(SkimItemReaderOption::default().read0(true).build());
+        let nth = SkimItemReader::new(SkimItemReaderOption::default().nth("1").build());
+
+        for item_reader in [SkimItemReader::default(), read0, nth] {
+            let (code, responses) = serve_with(item_reader, &requests);
+            assert_eq!(code, 0);
+            assert_eq!(
+                responses[0].get("result").and_then(|r| r.get("total")),
+                Some(&Json::from(3))
+            );
+            let accepted = responses[2].get("result").unwrap();
+            assert_eq!(accepted.get("items"), Some(&Json::from(vec!["a\nb"])));
+        }
+    }
+
+    #[test]
+    fn test_msgpack_framing() {
+        let requests = [
+            r#"{"jsonrpc":"2.0","id":1,"method":"push","params":{"items":["src/main.rs","README.md"]}}"#,
+            r#"{"jsonrpc":"2.0","id":2,"method":"set_query","params":{"query":"read"}}"#,
+            r#"{"jsonrpc":"2.0","id":3,"method":"accept"}"#,
+        ];
+        let mut input = Vec::new();
+        for request in requests.iter() {
+            msgpack::write(&Json::parse(request).unwrap(), &mut input);
+        }
+
+        let engine_factory = AndOrEngineFactory::new(ExactOrFuzzyEngineFactory::builder().build());
+        let item_reader = SkimItemReader::default();
+        let serve = |input: Vec<u8>| {
+            let mut output = Vec::new();
+            let code = RpcServer::new(&engine_factory, &item_reader)
+                .framing(Framing::MessagePack)
+                .serve(Cursor::new(input), &mut output)
+                .unwrap();
+            let mut output = Cursor::new(output);
+            let mut responses = Vec::new();
+            while let Some(response) = msgpack::read(&mut output).unwrap() {
+                responses.push(response.unwrap());
+            }
+            (code, responses)
+        };
+
+        let (code, responses) = serve(input);
+        assert_eq!(code, 0);
+        assert_eq!(responses.len(), 3);
+        let accepted = responses[2].get("result").unwrap();
+        assert_eq!(accepted.get("items"), Some(&Json::from(vec!["README.md"])));
+
+        // the session ends at an invalid message
+        let (code, responses) = serve(vec![0xc1, 0x90]);
+        assert_eq!(code, 2);
+        assert_eq!(responses.len(), 1);
+        assert!(responses[0].get("error").is_some());
+    }
+
+    #[test]
+    fn test_term_scores() {
+        let (_, responses) = serve(&[
+            r#"{"jsonrpc":"2.0","id":1,"method":"push","params":{"items":["src/main.rs","README.md"]}}"#,
+            r#"{"jsonrpc":"2.0","id":2,"method":"set_query","params":{"query":"src main"}}"#,
+            r#"{"jsonrpc":"2.0","id":3,"method":"results","params":{}}"#,
+            r#"{"jsonrpc":"2.0","id":4,"method":"abort"}"#,
+        ]);
+
+        let results = responses[2].get("result").unwrap();
+        let items = results.get("items").and_then(Json::as_array).unwrap();
+        let terms = items[0].get("terms").and_then(Json::as_array).unwrap();
+        assert_eq!(terms.len(), 2);
+        assert_eq!(items[0].get("score"), Some(&terms[0]));
+    }
+}
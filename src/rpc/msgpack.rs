@@ -0,0 +1,241 @@
+//! The MessagePack encoding of the JSON values, the framing of `--rpc=msgpack`: the messages are
+//! MessagePack values one after another, without delimiters.
+use std::io::{self, BufRead, Read};
+
+use super::json::{Json, MAX_DEPTH};
+
+/// read the next message, `None` if the input ends before it, `Some(Err)` if it isn't valid
+pub fn read(input: &mut impl BufRead) -> io::Result<Option<Result<Json, String>>> {
+    if input.fill_buf()?.is_empty() {
+        return Ok(None);
+    }
+    match read_value(input, 0) {
+        Ok(value) => Ok(Some(Ok(value))),
+        Err(err) if err.kind() == io::ErrorKind::InvalidData => Ok(Some(Err(err.to_string()))),
+        Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => Ok(Some(Err("unexpected end of input".to_string()))),
+        Err(err) => Err(err),
+    }
+}
+
+fn invalid(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+fn read_bytes<const N: usize>(input: &mut impl Read) -> io::Result<[u8; N]> {
+    let mut bytes = [0; N];
+    input.read_exact(&mut bytes)?;
+    Ok(bytes)
+}
+
+fn read_len(input: &mut impl Read, size: usize) -> io::Result<usize> {
+    Ok(match size {
+        1 => u8::from_be_bytes(read_bytes(input)?) as usize,
+        2 => u16::from_be_bytes(read_bytes(input)?) as usize,
+        _ => u32::from_be_bytes(read_bytes(input)?) as usize,
+    })
+}
+
+fn read_string(input: &mut impl Read, len: usize) -> io::Result<String> {
+    // not allocated up front, the length could be anything
+    let mut bytes = Vec::new();
+    input.take(len as u64).read_to_end(&mut bytes)?;
+    if bytes.len() < len {
+        return Err(io::ErrorKind::UnexpectedEof.into());
+    }
+    String::from_utf8(bytes).map_err(|_| invalid("invalid UTF-8 in a string".to_string()))
+}
+
+fn read_array(input: &mut impl Read, len: usize, depth: usize) -> io::Result<Json> {
+    if depth >= MAX_DEPTH {
+        return Err(invalid("the value is nested too deep".to_string()));
+    }
+    let mut values = Vec::with_capacity(len.min(1024));
+    for _ in 0..len {
+        values.push(read_value(input, depth + 1)?);
+    }
+    Ok(Json::Array(values))
+}
+
+fn read_map(input: &mut impl Read, len: usize, depth: usize) -> io::Result<Json> {
+    if depth >= MAX_DEPTH {
+        return Err(invalid("the value is nested too deep".to_string()));
+    }
+    let mut members = Vec::with_capacity(len.min(1024));
+    for _ in 0..len {
+        let key = match read_value(input, depth + 1)? {
+            Json::String(key) => key,
+            _ => return Err(invalid("expected a string as the key of a member".to_string())),
+        };
+        members.push((key, read_value(input, depth + 1)?));
+    }
+    Ok(Json::Object(members))
+}
+
+fn read_value(input: &mut impl Read, depth: usize) -> io::Result<Json> {
+    let [marker] = read_bytes(input)?;
+    Ok(match marker {
+        0x00..=0x7f => Json::Number(f64::from(marker)),
+        0x80..=0x8f => read_map(input, usize::from(marker & 0x0f), depth)?,
+        0x90..=0x9f => read_array(input, usize::from(marker & 0x0f), depth)?,
+        0xa0..=0xbf => Json::String(read_string(input, usize::from(marker & 0x1f))?),
+        0xc0 => Json::Null,
+        0xc2 => Json::Bool(false),
+        0xc3 => Json::Bool(true),
+        // bin 8/16/32, the bytes of a string that isn't UTF-8 are read as a string
+        0xc4..=0xc6 => {
+            let len = read_len(input, 1 << (marker - 0xc4))?;
+            let mut bytes = Vec::new();
+            input.take(len as u64).read_to_end(&mut bytes)?;
+            if bytes.len() < len {
+                return Err(io::ErrorKind::UnexpectedEof.into());
+            }
+            Json::String(String::from_utf8_lossy(&bytes).into_owned())
+        }
+        0xca => Json::Number(f32::from_be_bytes(read_bytes(input)?).into()),
+        0xcb => Json::Number(f64::from_be_bytes(read_bytes(input)?)),
+        0xcc => Json::Number(u8::from_be_bytes(read_bytes(input)?).into()),
+        0xcd => Json::Number(u16::from_be_bytes(read_bytes(input)?).into()),
+        0xce => Json::Number(u32::from_be_bytes(read_bytes(input)?).into()),
+        0xcf => Json::Number(u64::from_be_bytes(read_bytes(input)?) as f64),
+        0xd0 => Json::Number(i8::from_be_bytes(read_bytes(input)?).into()),
+        0xd1 => Json::Number(i16::from_be_bytes(read_bytes(input)?).into()),
+        0xd2 => Json::Number(i32::from_be_bytes(read_bytes(input)?).into()),
+        0xd3 => Json::Number(i64::from_be_bytes(read_bytes(input)?) as f64),
+        0xd9..=0xdb => {
+            let len = read_len(input, 1 << (marker - 0xd9))?;
+            Json::String(read_string(input, len)?)
+        }
+        0xdc | 0xdd => {
+            let len = read_len(input, 2 << (marker - 0xdc))?;
+            read_array(input, len, depth)?
+        }
+        0xde | 0xdf => {
+            let len = read_len(input, 2 << (marker - 0xde))?;
+            read_map(input, len, depth)?
+        }
+        0xe0..=0xff => Json::Number(f64::from(marker as i8)),
+        // the extension types have no JSON value
+        _ => return Err(invalid(format!("unsupported MessagePack type 0x{:02x}", marker))),
+    })
+}
+
+/// append the encoding of the value, the integers are encoded as integers
+pub fn write(value: &Json, out: &mut Vec<u8>) {
+    match value {
+        Json::Null => out.push(0xc0),
+        Json::Bool(false) => out.push(0xc2),
+        Json::Bool(true) => out.push(0xc3),
+        Json::Number(number) if number.fract() == 0.0 && number.abs() < 1e18 => write_int(*number as i64, out),
+        Json::Number(number) => {
+            out.push(0xcb);
+            out.extend_from_slice(&number.to_be_bytes());
+        }
+        Json::String(string) => {
+            write_len(string.len(), 0xa0, 0x1f, &[0xd9, 0xda, 0xdb], out);
+            out.extend_from_slice(string.as_bytes());
+        }
+        Json::Array(values) => {
+            write_len(values.len(), 0x90, 0x0f, &[0xdc, 0xdc, 0xdd], out);
+            values.iter().for_each(|value| write(value, out));
+        }
+        Json::Object(members) => {
+            write_len(members.len(), 0x80, 0x0f, &[0xde, 0xde, 0xdf], out);
+            for (key, value) in members {
+                write(&Json::String(key.clone()), out);
+                write(value, out);
+            }
+        }
+    }
+}
+
+fn write_int(number: i64, out: &mut Vec<u8>) {
+    match number {
+        0..=0x7f => out.push(number as u8),
+        -32..=-1 => out.push(number as i8 as u8),
+        0x80..=0xffff_ffff => {
+            out.push(0xce);
+            out.extend_from_slice(&(number as u32).to_be_bytes());
+        }
+        _ => {
+            out.push(0xd3);
+            out.extend_from_slice(&number.to_be_bytes());
+        }
+    }
+}
+
+/// the length in the fix marker if it fits the mask, otherwise after the marker of 8, 16 or 32
+/// bits, the arrays and maps have no 8 bit form
+fn write_len(len: usize, fix_marker: u8, fix_mask: usize, markers: &[u8; 3], out: &mut Vec<u8>) {
+    if len <= fix_mask {
+        out.push(fix_marker | len as u8);
+    } else if len <= 0xff && markers[0] != markers[1] {
+        out.push(markers[0]);
+        out.push(len as u8);
+    } else if len <= 0xffff {
+        out.push(markers[1]);
+        out.extend_from_slice(&(len as u16).to_be_bytes());
+    } else {
+        out.push(markers[2]);
+        out.extend_from_slice(&(len as u32).to_be_bytes());
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::io::Cursor;
+
+    fn encode(value: &Json) -> Vec<u8> {
+        let mut out = Vec::new();
+        write(value, &mut out);
+        out
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let value = Json::parse(
+            r#"{"a": [1, -2, -200, 70000, -2.5, true, false, null], "b": "x\"é😀", "c": {}, "d": [[], ""]}"#,
+        )
+        .unwrap();
+        let long = Json::Array(vec![Json::String("x".repeat(300)); 20]);
+
+        let mut input = Cursor::new([encode(&value), encode(&long)].concat());
+        assert_eq!(read(&mut input).unwrap(), Some(Ok(value)));
+        assert_eq!(read(&mut input).unwrap(), Some(Ok(long)));
+        assert_eq!(read(&mut input).unwrap(), None);
+    }
+
+    #[test]
+    fn test_encoding() {
+        assert_eq!(encode(&Json::from(5)), vec![0x05]);
+        assert_eq!(encode(&Json::from(-1)), vec![0xff]);
+        assert_eq!(encode(&Json::from("ab")), vec![0xa2, b'a', b'b']);
+        assert_eq!(encode(&Json::from(vec![true])), vec![0x91, 0xc3]);
+        assert_eq!(
+            encode(&Json::object(vec![("a", Json::Null)])),
+            vec![0x81, 0xa1, b'a', 0xc0]
+        );
+        assert_eq!(
+            encode(&Json::Number(0.5)),
+            [&[0xcb][..], &0.5f64.to_be_bytes()].concat()
+        );
+    }
+
+    #[test]
+    fn test_invalid() {
+        let read_bytes = |bytes: &[u8]| read(&mut Cursor::new(bytes.to_vec())).unwrap();
+        // an ext type
+        assert!(matches!(read_bytes(&[0xd4, 0x01, 0x00]), Some(Err(_))));
+        // a key that isn't a string
+        assert!(matches!(read_bytes(&[0x81, 0x01, 0x01]), Some(Err(_))));
+        // a string longer than the input
+        assert!(matches!(
+            read_bytes(&[0xdb, 0xff, 0xff, 0xff, 0xff, b'a']),
+            Some(Err(_))
+        ));
+        // nested too deep
+        assert!(matches!(read_bytes(&[0x91; 100_000]), Some(Err(_))));
+        let nested: Vec<u8> = [0x91; MAX_DEPTH - 1].iter().chain(&[0x90]).copied().collect();
+        assert!(matches!(read_bytes(&nested), Some(Ok(_))));
+    }
+}
@@ -1,28 +1,98 @@
 use std::cmp::max;
 use std::cmp::min;
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::mem;
 use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::Arc;
 
+use tuikit::canvas::BoundedCanvas;
 use tuikit::prelude::{Event as TermEvent, *};
 
 ///! Handle the selections of items
+use crate::ansi::AnsiString;
+use crate::decorator::Decorator;
 use crate::event::{Event, EventHandler, UpdateScreen};
 use crate::global::current_run_num;
+use crate::icons::{icon_of, DirCache, IconStyle};
 use crate::item::ItemIndex;
 use crate::item::{MatchedItem, MatchedRange};
 use crate::orderedvec::OrderedVec;
+use crate::path_display::PathShortener;
+use crate::spinlock::SpinLock;
 use crate::theme::{ColorTheme, DEFAULT_THEME};
-use crate::util::{print_item, reshape_string, LinePrinter};
+use crate::util::{accumulate_text_width, print_item, reshape_widths, text_width, CharIndex, LinePrinter};
 use crate::{DisplayContext, Matches, SkimItem, SkimOptions};
 use regex::Regex;
-use unicode_width::UnicodeWidthStr;
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
+
+// the spaces between the columns in grid mode
+const GRID_GAP: usize = 1;
+// the indentation of the continuation rows of a wrapped item
+const WRAP_INDENT: usize = 2;
+// the number of items whose `CharIndex` is kept, a few screens of them
+const CHAR_INDEX_CACHE_SIZE: usize = 256;
+// the columns of the icon of --icons, the icon and a space
+const ICON_WIDTH: usize = 2;
+
+// the address of an item -> the item, kept alive so the address isn't reused, and its char table
+type CharIndexCache = HashMap<usize, (Arc<dyn SkimItem>, Arc<CharIndex>)>;
+
+/// What a row of the list shows, the row is drawn again only if it changes
+#[derive(Clone, PartialEq)]
+struct RowKey {
+    // the address of the item
+    item: usize,
+    item_index: usize,
+    matched_range: Option<MatchedRange>,
+    is_current: bool,
+    is_selected: bool,
+    hscroll_offset: i64,
+    // the version of the decorations of --format-cmd
+    decorations: usize,
+}
+
+/// The rows of the list drawn lately and the size of the canvas they were drawn on
+#[derive(Default)]
+struct DrawnRows {
+    size: (usize, usize),
+    // by the line number, `None` for the blank rows
+    rows: Vec<Option<RowKey>>,
+}
+
+/// The item the cursor should be moved to once it shows up
+enum CursorAnchor {
+    /// an item with the same text (the items are re-created on reload)
+    Text(String),
+    /// the very same item
+    Item(Arc<dyn SkimItem>),
+    /// the item found for one of the above, it's only looked up by its rank from now on
+    Found(MatchedItem),
+}
+
+impl CursorAnchor {
+    fn matches(&self, item: &Arc<dyn SkimItem>) -> bool {
+        match self {
+            CursorAnchor::Text(text) => item.text() == text.as_str(),
+            CursorAnchor::Item(anchor) => same_item(anchor, item),
+            CursorAnchor::Found(found) => same_item(&found.item, item),
+        }
+    }
+}
+
+fn same_item(a: &Arc<dyn SkimItem>, b: &Arc<dyn SkimItem>) -> bool {
+    Arc::as_ptr(a) as *const u8 == Arc::as_ptr(b) as *const u8
+}
 
 pub struct Selection {
     // all items
     items: OrderedVec<MatchedItem>,
     selected: BTreeMap<ItemIndex, Arc<dyn SkimItem>>,
 
+    // selections(item text -> old index) and cursor to be restored after the items are reloaded
+    sticky_selected: HashMap<String, Option<ItemIndex>>,
+    sticky_cursor: Option<CursorAnchor>,
+    sticky_run_num: u32,
+
     //
     // |>------ items[items.len()-1]
     // |
@@ -42,13 +112,38 @@ pub struct Selection {
     keep_right: bool,
     skip_to_pattern: Option<Regex>,
     height: AtomicUsize,
+    width: AtomicUsize,
     tabstop: usize,
+    // number of columns in grid mode, 1 otherwise
+    columns: AtomicUsize,
+    // max display width of the items, used as the width of grid cells
+    max_item_width: usize,
 
     // Options
+    pointer: AnsiString<'static>,
+    marker: AnsiString<'static>,
     multi_selection: bool,
     reverse: bool,
+    cycle: bool,
+    scroll_off: usize,
+    grid: bool,
+    wrap: bool,
+    multi_line: bool,
     no_hscroll: bool,
     theme: Arc<ColorTheme>,
+    // the char tables of the items drawn lately
+    char_indices: SpinLock<CharIndexCache>,
+    // the display of the items by --format-cmd
+    decorator: Option<Decorator>,
+    icons: Option<IconStyle>,
+    // whether the items of --icons are directories
+    dir_cache: DirCache,
+    path_shortener: Option<PathShortener>,
+    no_unicode: bool,
+    // the row the left button was pressed on, to toggle the rows dragged over when it's released
+    drag_start: SpinLock<Option<usize>>,
+    // the rows drawn by the last frame, only the ones that changed since are drawn again
+    drawn_rows: SpinLock<DrawnRows>,
 }
 
 impl Selection {
@@ -56,17 +151,38 @@ impl Selection {
         Selection {
             items: OrderedVec::new(),
             selected: BTreeMap::new(),
+            sticky_selected: HashMap::new(),
+            sticky_cursor: None,
+            sticky_run_num: 0,
             item_cursor: 0,
             line_cursor: 0,
             hscroll_offset: 0,
             keep_right: false,
             skip_to_pattern: None,
             height: AtomicUsize::new(0),
+            width: AtomicUsize::new(0),
             tabstop: 8,
+            columns: AtomicUsize::new(1),
+            max_item_width: 0,
+            pointer: AnsiString::parse(">"),
+            marker: AnsiString::parse(">"),
             multi_selection: false,
             reverse: false,
+            cycle: false,
+            scroll_off: 0,
+            grid: false,
+            wrap: false,
+            multi_line: false,
             no_hscroll: false,
             theme: Arc::new(*DEFAULT_THEME),
+            char_indices: SpinLock::new(HashMap::new()),
+            decorator: None,
+            icons: None,
+            dir_cache: DirCache::default(),
+            path_shortener: None,
+            no_unicode: false,
+            drag_start: SpinLock::new(None),
+            drawn_rows: SpinLock::new(DrawnRows::default()),
         }
     }
 
@@ -81,6 +197,14 @@ impl Selection {
             self.multi_selection = true;
         }
 
+        if let Some(pointer) = options.pointer {
+            self.pointer = AnsiString::parse(pointer);
+        }
+
+        if let Some(marker) = options.marker {
+            self.marker = AnsiString::parse(marker);
+        }
+
         if options.layout.starts_with("reverse") {
             self.reverse = true;
         }
@@ -107,6 +231,26 @@ impl Selection {
         }
 
         self.keep_right = options.keep_right;
+        self.cycle = options.cycle;
+        self.scroll_off = options.scroll_off;
+        self.grid = options.grid;
+        self.wrap = options.wrap;
+        self.multi_line = options.multi_line;
+        self.icons = options.icons.map(|style| match style {
+            "nerd" => IconStyle::Nerd,
+            "ascii" => IconStyle::Ascii,
+            _ if options.no_unicode => IconStyle::Ascii,
+            _ => IconStyle::Nerd,
+        });
+        self.no_unicode = options.no_unicode;
+        if options.shorten_paths {
+            self.path_shortener = Some(PathShortener::new(options.no_unicode));
+        }
+    }
+
+    /// show items in the order they are read instead of by rank, takes effect on next clear
+    pub fn nosort(&mut self, nosort: bool) {
+        self.items.nosort(nosort);
     }
 
     pub fn theme(mut self, theme: Arc<ColorTheme>) -> Self {
@@ -114,7 +258,41 @@ impl Selection {
         self
     }
 
+    pub fn decorator(mut self, decorator: Option<Decorator>) -> Self {
+        self.decorator = decorator;
+        self
+    }
+
     pub fn append_sorted_items(&mut self, items: Vec<MatchedItem>) {
+        // the anchor only moves when items are appended, it is not looked up on every heartbeat
+        let appended = !items.is_empty();
+        // only the new items are looked into, the ones that are found are looked up by their rank
+        // once appended
+        match self.sticky_cursor.as_ref() {
+            Some(CursorAnchor::Found(_)) | None => {}
+            Some(anchor) => {
+                if let Some(found) = items.iter().find(|matched| anchor.matches(&matched.item)) {
+                    self.sticky_cursor = Some(CursorAnchor::Found(found.clone()));
+                }
+            }
+        }
+        let sticky_items: Vec<MatchedItem> = if self.sticky_selected.is_empty() {
+            Vec::new()
+        } else {
+            items
+                .iter()
+                .filter(|matched| self.is_sticky(&matched.item))
+                .cloned()
+                .collect()
+        };
+
+        if self.grid {
+            for matched in items.iter() {
+                let width = text_width(&matched.item.text(), self.tabstop);
+                self.max_item_width = max(self.max_item_width, width);
+            }
+        }
+
         self.items.append(items);
 
         let height = self.height.load(Ordering::Relaxed);
@@ -127,20 +305,179 @@ impl Selection {
             // if not enough items, scroll the cursor a page down
             self.item_cursor = max(self.items.len(), height) - height;
         }
+
+        if self.grid {
+            // re-align the cursor to the rows of the grid
+            self.act_move_grid_cursor(0);
+        }
+
+        self.restore_sticky(sticky_items, appended);
+    }
+
+    /// The items are dropped from the pool (`--tail`), remove them from the matched ones and shift
+    /// the indices of the selections and the cursor over them, returns the number removed
+    pub fn drop_items(&mut self, dropped: &[Arc<dyn SkimItem>]) -> usize {
+        let addresses: HashSet<*const u8> = dropped.iter().map(|item| Arc::as_ptr(item) as *const u8).collect();
+        let is_dropped = |item: &Arc<dyn SkimItem>| addresses.contains(&(Arc::as_ptr(item) as *const u8));
+        let removed = self.items.retain(|matched| !is_dropped(&matched.item));
+        // the removed ones before the index
+        let shift = |idx: usize| idx - removed.partition_point(|&removed| removed < idx);
+
+        let run_num = current_run_num();
+        let selected = mem::take(&mut self.selected);
+        for ((run, idx), item) in selected {
+            if is_dropped(&item) {
+                continue;
+            }
+            let idx = if run == run_num {
+                shift(idx as usize) as u32
+            } else {
+                idx
+            };
+            self.selected.insert((run, idx), item);
+        }
+
+        let current = shift(self.get_current_item_idx());
+        self.item_cursor = shift(self.item_cursor);
+        self.line_cursor = current - self.item_cursor;
+        if self.items.len() <= self.get_current_item_idx() {
+            let height = self.height.load(Ordering::Relaxed);
+            self.line_cursor = max(min(self.items.len(), height), 1) - 1;
+            self.item_cursor = max(self.items.len(), height) - height;
+        }
+        if self.grid {
+            self.act_move_grid_cursor(0);
+        }
+        removed.len()
     }
 
     pub fn clear(&mut self) {
         self.items.clear();
+        self.max_item_width = 0;
+    }
+
+    /// The items of run `last_run_num` are going to be replaced by the ones of `run_num`, remember
+    /// the selections and the cursor so that they could be re-applied (by item text) to the new
+    /// items once they arrive.
+    pub fn prepare_reload(&mut self, last_run_num: u32, run_num: u32) {
+        self.sticky_selected.clear();
+        self.sticky_cursor = self
+            .get_current_item()
+            .map(|item| CursorAnchor::Text(item.text().to_string()));
+        self.sticky_run_num = run_num;
+
+        let indices: Vec<ItemIndex> = self
+            .selected
+            .keys()
+            .filter(|(run, _)| *run == last_run_num)
+            .cloned()
+            .collect();
+
+        for index in indices {
+            if run_num == last_run_num {
+                // the old indices will be taken by the new items, drop them now
+                let item = self.selected.remove(&index).unwrap();
+                self.sticky_selected.insert(item.text().to_string(), None);
+            } else {
+                let text = self.selected[&index].text().to_string();
+                self.sticky_selected.insert(text, Some(index));
+            }
+        }
+    }
+
+    /// The items are going to be re-matched, keep the cursor on the current item once it shows up
+    /// again (if it still matches).
+    pub fn track_current_item(&mut self) {
+        self.sticky_cursor = self.get_current_item().map(CursorAnchor::Item);
+    }
+
+    /// The items are going to be re-matched and the cursor goes back to the first one
+    pub fn untrack_cursor(&mut self) {
+        self.sticky_cursor = None;
+    }
+
+    fn is_sticky(&self, item: &Arc<dyn SkimItem>) -> bool {
+        self.sticky_selected.contains_key(item.text().as_ref())
+    }
+
+    fn position(&self, matched: &MatchedItem) -> Option<usize> {
+        self.items
+            .position(matched, |other| same_item(&other.item, &matched.item))
+    }
+
+    /// re-apply the selections found in the new items, and keep the cursor on the anchor, it's
+    /// kept until the user moves the cursor as the items sorted before it could arrive later
+    fn restore_sticky(&mut self, sticky_items: Vec<MatchedItem>, appended: bool) {
+        for matched in sticky_items {
+            let old_index = match self.sticky_selected.remove(matched.item.text().as_ref()) {
+                Some(old_index) => old_index,
+                None => continue,
+            };
+            if let Some(old_index) = old_index {
+                self.selected.remove(&old_index);
+            }
+            if let (true, Some(idx)) = (self.multi_selection, self.position(&matched)) {
+                self.selected.insert((self.sticky_run_num, idx as u32), matched.item);
+            }
+        }
+
+        let found = match self.sticky_cursor.as_ref() {
+            Some(CursorAnchor::Found(found)) if appended => found,
+            _ => return,
+        };
+        let on_anchor = self
+            .get_current_item()
+            .map(|item| same_item(&item, &found.item))
+            .unwrap_or(false);
+        if !on_anchor {
+            if let Some(idx) = self.position(found) {
+                self.act_move_to_item(idx);
+            }
+        }
+    }
+
+    /// move the cursor to the matched item at `idx`
+    fn act_move_to_item(&mut self, idx: usize) {
+        let diff = idx as i32 - self.get_current_item_idx() as i32;
+        if self.grid {
+            // the line cursor moves by rows in grid mode
+            self.act_move_grid_cursor(diff);
+        } else {
+            self.act_move_line_cursor(if self.reverse { -diff } else { diff });
+        }
     }
 
     // > 0 means move up, < 0 means move down
     pub fn act_move_line_cursor(&mut self, diff: i32) {
-        let diff = if self.reverse { -diff } else { diff };
+        if self.grid {
+            let diff = if self.reverse { -diff } else { diff };
+            let columns = self.columns.load(Ordering::Relaxed) as i32;
+            self.act_move_grid_cursor(diff * columns);
+            return;
+        }
+
+        if self.wrap || self.multi_line {
+            let diff = if self.reverse { -diff } else { diff };
+            self.act_move_wrapped_cursor(diff);
+            return;
+        }
+
+        let mut diff = if self.reverse { -diff } else { diff };
 
         let mut line_cursor = self.line_cursor as i32;
         let mut item_cursor = self.item_cursor as i32;
         let item_len = self.items.len() as i32;
 
+        if self.cycle && item_len > 0 {
+            // jump to the other end if moving out of the items
+            let target = item_cursor + line_cursor + diff;
+            if target < 0 {
+                diff = item_len - 1 - item_cursor - line_cursor;
+            } else if target >= item_len {
+                diff = -item_cursor - line_cursor;
+            }
+        }
+
         let height = self.height.load(Ordering::Relaxed) as i32;
 
         line_cursor += diff;
@@ -158,12 +495,141 @@ impl Selection {
 
         line_cursor = max(0, line_cursor);
 
+        // keep `scroll_off` lines of context above/below the cursor
+        let scroll_off = min(self.scroll_off as i32, (height - 1) / 2);
+        if scroll_off > 0 {
+            let cursor = item_cursor + line_cursor;
+            if line_cursor < scroll_off {
+                item_cursor = max(0, cursor - scroll_off);
+            } else if line_cursor > height - 1 - scroll_off {
+                item_cursor = max(0, min(cursor + scroll_off - height + 1, item_len - height));
+            }
+            line_cursor = cursor - item_cursor;
+        }
+
         self.item_cursor = item_cursor as usize;
         self.line_cursor = line_cursor as usize;
     }
 
+    /// move the cursor by `diff` items in grid mode, `item_cursor` is always the first item of the
+    /// first visible row.
+    fn act_move_grid_cursor(&mut self, diff: i32) {
+        let item_len = self.items.len() as i32;
+        let height = max(self.height.load(Ordering::Relaxed) as i32, 1);
+        let columns = max(self.columns.load(Ordering::Relaxed) as i32, 1);
+        if item_len == 0 {
+            self.item_cursor = 0;
+            self.line_cursor = 0;
+            return;
+        }
+
+        let current = (self.item_cursor + self.line_cursor) as i32;
+        let mut cursor = current + diff;
+        if self.cycle && (cursor < 0 || cursor >= item_len) && diff != 0 {
+            cursor = if cursor < 0 { item_len - 1 } else { 0 };
+        }
+        let cursor = max(0, min(cursor, item_len - 1));
+
+        let row = cursor / columns;
+        let mut top_row = self.item_cursor as i32 / columns;
+        if row < top_row {
+            top_row = row;
+        } else if row >= top_row + height {
+            top_row = row - height + 1;
+        }
+
+        self.item_cursor = (top_row * columns) as usize;
+        self.line_cursor = (cursor - top_row * columns) as usize;
+    }
+
+    /// the number of screen rows the item takes, more than one only if it is wrapped or has
+    /// multiple lines
+    fn item_rows(&self, item: &MatchedItem) -> usize {
+        if !self.wrap && !self.multi_line {
+            return 1;
+        }
+
+        let text = item.item.text();
+        let container_width = self.width.load(Ordering::Relaxed).saturating_sub(self.prefix_width());
+        if !self.wrap || container_width <= WRAP_INDENT {
+            return self.lines(&text).count();
+        }
+
+        self.lines(&text)
+            .map(|line| {
+                let width = text_width(line, self.tabstop);
+                if width <= container_width {
+                    1
+                } else {
+                    let row_width = container_width - WRAP_INDENT;
+                    1 + (width - container_width).div_ceil(row_width)
+                }
+            })
+            .sum()
+    }
+
+    /// the lines of the text that are displayed on their own rows
+    fn lines<'a>(&self, text: &'a str) -> Box<dyn Iterator<Item = &'a str> + 'a> {
+        if self.multi_line {
+            Box::new(text.split('\n'))
+        } else {
+            Box::new(std::iter::once(text))
+        }
+    }
+
+    /// move the cursor by `diff` items in wrap mode, scroll so that all the rows of the items
+    /// between `item_cursor` and the cursor fit in the screen.
+    fn act_move_wrapped_cursor(&mut self, diff: i32) {
+        let item_len = self.items.len() as i32;
+        let height = max(self.height.load(Ordering::Relaxed), 1);
+        if item_len == 0 {
+            self.item_cursor = 0;
+            self.line_cursor = 0;
+            return;
+        }
+
+        let current = (self.item_cursor + self.line_cursor) as i32;
+        let mut cursor = current + diff;
+        if self.cycle && (cursor < 0 || cursor >= item_len) && diff != 0 {
+            cursor = if cursor < 0 { item_len - 1 } else { 0 };
+        }
+        let cursor = max(0, min(cursor, item_len - 1)) as usize;
+
+        let mut item_cursor = min(self.item_cursor, cursor);
+        let rows_of = |idx: usize| self.items.get(idx).map(|item| self.item_rows(&item)).unwrap_or(1);
+        let mut rows: usize = (item_cursor..=cursor).map(rows_of).sum();
+        while item_cursor < cursor && rows > height {
+            rows -= rows_of(item_cursor);
+            item_cursor += 1;
+        }
+
+        self.item_cursor = item_cursor;
+        self.line_cursor = cursor - item_cursor;
+    }
+
     pub fn act_select_screen_row(&mut self, rows_to_top: usize) {
         let height = self.height.load(Ordering::Relaxed);
+        if self.wrap || self.multi_line {
+            // find the item that covers the row
+            let mut row = if self.reverse {
+                rows_to_top
+            } else {
+                height.saturating_sub(rows_to_top + 1)
+            };
+            let mut item_idx = self.item_cursor;
+            while let Some(item) = self.items.get(item_idx) {
+                let rows = self.item_rows(&item);
+                if row < rows {
+                    break;
+                }
+                row -= rows;
+                item_idx += 1;
+            }
+            let diff = item_idx as i32 - (self.item_cursor + self.line_cursor) as i32;
+            self.act_move_wrapped_cursor(diff);
+            return;
+        }
+
         let diff = if self.reverse {
             self.line_cursor as i32 - rows_to_top as i32
         } else {
@@ -182,12 +648,52 @@ impl Selection {
         let current_item = self
             .items
             .get(cursor)
-            .unwrap_or_else(|| panic!("model:act_toggle: failed to get item {}", cursor));
+            .unwrap_or_else(|| panic!("model:act_toggle: failed to get item {}", cursor))
+            .item
+            .clone();
         let index = (current_run_num(), cursor as u32);
         if !self.selected.contains_key(&index) {
-            self.selected.insert(index, current_item.item.clone());
+            self.selected.insert(index, current_item);
         } else {
-            self.selected.remove(&index);
+            self.deselect(&index);
+        }
+    }
+
+    /// deselect the item at `index`, it's no longer restored on reload either
+    fn deselect(&mut self, index: &ItemIndex) -> Option<Arc<dyn SkimItem>> {
+        let item = self.selected.remove(index)?;
+        if !self.sticky_selected.is_empty() {
+            self.sticky_selected.remove(item.text().as_ref());
+        }
+        Some(item)
+    }
+
+    /// forget the row a drag started on, e.g. it's released outside of the list
+    pub fn cancel_drag(&self) {
+        *self.drag_start.lock() = None;
+    }
+
+    /// toggle the items from the screen row `start_row` to `end_row`, the cursor ends on the latter
+    pub fn act_toggle_screen_rows(&mut self, start_row: usize, end_row: usize) {
+        if !self.multi_selection || self.items.is_empty() {
+            return;
+        }
+
+        self.act_select_screen_row(start_row);
+        let start = self.item_cursor + self.line_cursor;
+        self.act_select_screen_row(end_row);
+        let end = self.item_cursor + self.line_cursor;
+
+        let run_num = current_run_num();
+        for idx in min(start, end)..=max(start, end) {
+            let index = (run_num, idx as u32);
+            if self.deselect(&index).is_none() {
+                let item = self
+                    .items
+                    .get(idx)
+                    .unwrap_or_else(|| panic!("model:act_toggle_screen_rows: failed to get item {}", idx));
+                self.selected.insert(index, item.item.clone());
+            }
         }
     }
 
@@ -198,14 +704,18 @@ impl Selection {
         }
 
         let run_num = current_run_num();
+        let mut deselected = Vec::new();
         for (idx, current_item) in self.items.iter().enumerate() {
             let index = (run_num, idx as u32);
             if !self.selected.contains_key(&index) {
                 self.selected.insert(index, current_item.item.clone());
             } else {
-                self.selected.remove(&index);
+                deselected.push(index);
             }
         }
+        for index in deselected {
+            self.deselect(&index);
+        }
     }
 
     pub fn act_select_item(&mut self, item_index: ItemIndex, item: Arc<dyn SkimItem>) {
@@ -230,6 +740,7 @@ impl Selection {
 
     pub fn act_deselect_all(&mut self) {
         self.selected.clear();
+        self.sticky_selected.clear();
     }
 
     pub fn act_scroll(&mut self, offset: i32) {
@@ -256,6 +767,29 @@ impl Selection {
         (item_indices, selected)
     }
 
+    /// the ranges matched by the current query in the items, looked up in the matched list instead
+    /// of matching them again, `None` for the items not in it
+    pub fn matched_ranges_of(&self, items: &[Arc<dyn SkimItem>]) -> Vec<Option<MatchedRange>> {
+        let key = |item: &Arc<dyn SkimItem>| Arc::as_ptr(item) as *const u8 as usize;
+        let mut pending: HashMap<usize, Vec<usize>> = HashMap::new();
+        for (idx, item) in items.iter().enumerate() {
+            pending.entry(key(item)).or_default().push(idx);
+        }
+
+        let mut ranges = vec![None; items.len()];
+        for matched in self.items.iter() {
+            if pending.is_empty() {
+                break;
+            }
+            if let Some(indices) = pending.remove(&key(&matched.item)) {
+                for idx in indices {
+                    ranges[idx] = matched.matched_range.clone();
+                }
+            }
+        }
+        ranges
+    }
+
     pub fn get_num_of_selected_exclude_current(&self) -> usize {
         self.selected.len()
     }
@@ -285,19 +819,64 @@ impl Selection {
         self.items.len()
     }
 
+    /// return (shift, full_width): the display width to be skipped so that the matched part is
+    /// visible, and the display width of the whole text
+    fn calc_shift(
+        &self,
+        text: &str,
+        acc_width: &[usize],
+        container_width: usize,
+        match_start_char: usize,
+        match_end_char: usize,
+    ) -> (usize, usize) {
+        let (shift, full_width) = reshape_widths(acc_width, container_width, match_start_char, match_end_char);
+
+        let shift = if self.no_hscroll {
+            // truncate the long lines instead, keep the columns aligned
+            0
+        } else if match_start_char == 0 && match_end_char == 0 {
+            // no match
+            if self.keep_right {
+                max(full_width, container_width) - container_width
+            } else {
+                self.calc_skip_width(text)
+            }
+        } else {
+            shift
+        };
+
+        (shift, full_width)
+    }
+
     fn calc_skip_width(&self, text: &str) -> usize {
         let skip = if self.skip_to_pattern.is_none() {
             0
         } else {
             let regex = self.skip_to_pattern.as_ref().unwrap();
             if let Some(mat) = regex.find(text) {
-                text[..mat.start()].width_cjk()
+                text_width(&text[..mat.start()], self.tabstop)
             } else {
                 0
             }
         };
         max(2, skip) - 2
     }
+
+    /// the char table of the text of the item, built on its first draw
+    fn char_index(&self, item: &Arc<dyn SkimItem>) -> Arc<CharIndex> {
+        let key = Arc::as_ptr(item) as *const u8 as usize;
+        let mut char_indices = self.char_indices.lock();
+        if let Some((_, index)) = char_indices.get(&key) {
+            return index.clone();
+        }
+
+        if char_indices.len() >= CHAR_INDEX_CACHE_SIZE {
+            char_indices.clear();
+        }
+        let index = Arc::new(CharIndex::new(&item.text(), self.tabstop));
+        char_indices.insert(key, (item.clone(), index.clone()));
+        index
+    }
 }
 
 impl EventHandler for Selection {
@@ -310,12 +889,24 @@ impl EventHandler for Selection {
             EvActDown(diff) => {
                 self.act_move_line_cursor(-*diff);
             }
+            EvActFirst => {
+                // the cursor isn't going back to the tracked item
+                self.sticky_cursor = None;
+                self.act_move_to_item(0);
+            }
+            EvActLast => {
+                self.sticky_cursor = None;
+                self.act_move_to_item(self.items.len().saturating_sub(1));
+            }
             EvActToggle => {
                 self.act_toggle();
             }
             EvActToggleAll => {
                 self.act_toggle_all();
             }
+            EvActToggleRows(start_row, end_row) => {
+                self.act_toggle_screen_rows(*start_row, *end_row);
+            }
             EvActSelectAll => {
                 self.act_select_all();
             }
@@ -341,6 +932,12 @@ impl EventHandler for Selection {
             EvActSelectRow(row) => {
                 self.act_select_screen_row(*row);
             }
+            EvActLeft(diff) if self.grid => {
+                self.act_move_grid_cursor(-*diff);
+            }
+            EvActRight(diff) if self.grid => {
+                self.act_move_grid_cursor(*diff);
+            }
             EvActScrollLeft(diff) => {
                 self.act_scroll(-*diff);
             }
@@ -349,11 +946,40 @@ impl EventHandler for Selection {
             }
             _ => return UpdateScreen::DONT_REDRAW,
         }
+        // the user had moved on, don't restore the cursor of last reload
+        self.sticky_cursor = None;
         UpdateScreen::REDRAW
     }
 }
 
 impl Selection {
+    /// the number of columns before the item text, taken by the pointer, the marker and the icon
+    fn prefix_width(&self) -> usize {
+        let icon_width = if self.icons.is_some() { ICON_WIDTH } else { 0 };
+        self.pointer.stripped().width_cjk() + self.marker.stripped().width_cjk() + icon_width
+    }
+
+    /// print `glyph` (pointer or marker) if `shown`, otherwise blanks of the same width
+    fn draw_glyph(
+        canvas: &mut dyn Canvas,
+        row: usize,
+        col: usize,
+        glyph: &AnsiString,
+        shown: bool,
+        attr: Attr,
+    ) -> Result<usize> {
+        let mut width = 0;
+        for (ch, glyph_attr) in glyph.iter() {
+            if shown {
+                width += canvas.put_char_with_attr(row, col + width, ch, attr.extend(glyph_attr))?;
+            } else {
+                let blank = " ".repeat(ch.width_cjk().unwrap_or(2));
+                width += canvas.print_with_attr(row, col + width, &blank, attr)?;
+            }
+        }
+        Ok(width)
+    }
+
     fn draw_item(
         &self,
         canvas: &mut dyn Canvas,
@@ -362,12 +988,11 @@ impl Selection {
         item_index: usize,
         is_current: bool,
     ) -> Result<()> {
-        let (screen_width, screen_height) = canvas.size()?;
-
-        // update item heights
-        self.height.store(screen_height, Ordering::Relaxed);
+        let (screen_width, _screen_height) = canvas.size()?;
 
-        if screen_width < 3 {
+        let pointer_width = self.pointer.stripped().width_cjk();
+        let prefix_width = self.prefix_width();
+        if screen_width < prefix_width + 1 {
             return Err("screen width is too small".into());
         }
 
@@ -385,15 +1010,31 @@ impl Selection {
 
         // print selection cursor
         let index = (current_run_num(), item_index as u32);
-        if self.selected.contains_key(&index) {
-            let _ = canvas.print_with_attr(row, 1, ">", default_attr.extend(self.theme.selected()));
+        let _ = if self.selected.contains_key(&index) {
+            Self::draw_glyph(
+                canvas,
+                row,
+                pointer_width,
+                &self.marker,
+                true,
+                default_attr.extend(self.theme.selected()),
+            )
         } else {
-            let _ = canvas.print_with_attr(row, 1, " ", default_attr);
-        }
+            Self::draw_glyph(canvas, row, pointer_width, &self.marker, false, default_attr)
+        };
 
         let item = &matched_item.item;
         let item_text = item.text();
-        let container_width = screen_width - 2;
+        let container_width = screen_width - prefix_width;
+
+        if let Some(style) = self.icons {
+            // the blanks first, the width of the glyphs of the icon fonts varies
+            let col = prefix_width - ICON_WIDTH;
+            let _ = canvas.print_with_attr(row, col, &" ".repeat(ICON_WIDTH), default_attr);
+            if let Some(icon) = icon_of(&item_text, style, |text| self.dir_cache.is_dir(text)) {
+                let _ = canvas.put_char_with_attr(row, col, icon, default_attr);
+            }
+        }
 
         let matches = match matched_item.matched_range {
             Some(MatchedRange::Chars(ref matched_indices)) => Matches::CharIndices(matched_indices),
@@ -401,72 +1042,80 @@ impl Selection {
             _ => Matches::None,
         };
 
-        let context = DisplayContext {
+        let display_content = item.display(DisplayContext {
             text: &item_text,
             score: 0,
             matches,
             container_width,
             highlight_attr: matched_attr,
+        });
+        let display_content = match self.decorator.as_ref() {
+            Some(decorator) => decorator
+                .decorate(&item_text, &display_content)
+                .unwrap_or(display_content),
+            None => display_content,
+        };
+
+        let display_content = match self.path_shortener {
+            Some(ref shortener) if display_content.stripped() == item_text => {
+                // the rows of wrapped items are not truncated, only the home is abbreviated
+                let max_width = if self.wrap || self.multi_line {
+                    usize::MAX
+                } else {
+                    container_width
+                };
+                shortener
+                    .shorten(&item_text, max_width)
+                    .map_or(display_content, |short| {
+                        short.render(&item_text, &matches, matched_attr)
+                    })
+            }
+            _ => display_content,
         };
 
-        let display_content = item.display(context);
+        if self.wrap || self.multi_line {
+            let matched_chars = if display_content.stripped() == item_text {
+                let char_index = self.char_index(item);
+                Some(matched_chars(&char_index, matched_item.matched_range.as_ref()))
+            } else {
+                None
+            };
+            let chars: Vec<(char, Attr)> = display_content.iter().collect();
+            return self.draw_rows(canvas, row, container_width, &chars, matched_chars, default_attr);
+        }
 
         let mut printer = if display_content.stripped() == item_text {
             // need to display the match content
-            let (match_start_char, match_end_char) = match matched_item.matched_range {
-                Some(MatchedRange::Chars(ref matched_indices)) => {
-                    if !matched_indices.is_empty() {
-                        (matched_indices[0], matched_indices[matched_indices.len() - 1] + 1)
-                    } else {
-                        (0, 0)
-                    }
-                }
-                Some(MatchedRange::ByteRange(match_start, match_end)) => {
-                    let match_start_char = item_text[..match_start].chars().count();
-                    let diff = item_text[match_start..match_end].chars().count();
-                    (match_start_char, match_start_char + diff)
-                }
-                None => (0, 0),
-            };
+            let char_index = self.char_index(item);
+            let (match_start_char, match_end_char) = matched_chars(&char_index, matched_item.matched_range.as_ref());
 
-            let (shift, full_width) = reshape_string(
+            let (shift, full_width) = self.calc_shift(
                 &item_text,
+                char_index.widths(),
                 container_width,
                 match_start_char,
                 match_end_char,
-                self.tabstop,
             );
 
-            let shift = if self.no_hscroll {
-                0
-            } else if match_start_char == 0 && match_end_char == 0 {
-                // no match
-                if self.keep_right {
-                    max(full_width, container_width) - container_width
-                } else {
-                    self.calc_skip_width(&item_text)
-                }
-            } else {
-                shift
-            };
-
             LinePrinter::builder()
                 .row(row)
-                .col(2)
+                .col(prefix_width)
                 .tabstop(self.tabstop)
                 .container_width(container_width)
                 .shift(shift)
                 .text_width(full_width)
                 .hscroll_offset(self.hscroll_offset)
+                .no_unicode(self.no_unicode)
                 .build()
         } else {
             LinePrinter::builder()
                 .row(row)
-                .col(2)
+                .col(prefix_width)
                 .tabstop(self.tabstop)
                 .container_width(container_width)
-                .text_width(display_content.stripped().width_cjk())
+                .text_width(text_width(display_content.stripped(), self.tabstop))
                 .hscroll_offset(self.hscroll_offset)
+                .no_unicode(self.no_unicode)
                 .build()
         };
 
@@ -475,19 +1124,210 @@ impl Selection {
 
         Ok(())
     }
+
+    /// print the (highlighted) chars of an item on the rows it takes, see `item_rows`.
+    /// `matched_chars` is the range of the match in chars, if the chars are the text of the item
+    fn draw_rows(
+        &self,
+        canvas: &mut dyn Canvas,
+        row: usize,
+        container_width: usize,
+        chars: &[(char, Attr)],
+        matched_chars: Option<(usize, usize)>,
+        default_attr: Attr,
+    ) -> Result<()> {
+        let (_, screen_height) = canvas.size()?;
+        let prefix_width = self.prefix_width();
+
+        let lines: Vec<&[(char, Attr)]> = if self.multi_line {
+            chars.split(|&(ch, _)| ch == '\n').collect()
+        } else {
+            vec![chars]
+        };
+
+        let mut row = row;
+        let mut line_start = 0;
+        for line in lines {
+            if row >= screen_height {
+                break;
+            }
+
+            let line_text: String = line.iter().map(|&(ch, _)| ch).collect();
+            let print_line = |printer: &mut LinePrinter, canvas: &mut dyn Canvas| {
+                for &(ch, attr) in line {
+                    printer.print_char(canvas, ch, default_attr.extend(attr), false);
+                }
+            };
+
+            if self.wrap {
+                let full_width = text_width(&line_text, self.tabstop);
+                let mut shift = 0;
+                for wrapped_row in 0..screen_height - row {
+                    if wrapped_row > 0 && (shift >= full_width || container_width <= WRAP_INDENT) {
+                        break;
+                    }
+
+                    let indent = if wrapped_row > 0 { WRAP_INDENT } else { 0 };
+                    let mut printer = LinePrinter::builder()
+                        .row(row)
+                        .col(prefix_width + indent)
+                        .tabstop(self.tabstop)
+                        .container_width(container_width - indent)
+                        .shift(shift)
+                        .text_width(full_width)
+                        .overflow_hint(false)
+                        .build();
+                    print_line(&mut printer, canvas);
+                    shift += container_width - indent;
+                    row += 1;
+                }
+            } else {
+                // scroll each line to show its own part of the match
+                let (shift, full_width) = match matched_chars {
+                    Some((match_start, match_end)) => {
+                        let line_end = line_start + line.len();
+                        let (start, end) = (max(match_start, line_start), min(match_end, line_end));
+                        let (start, end) = if start < end {
+                            (start - line_start, end - line_start)
+                        } else {
+                            (0, 0)
+                        };
+                        let acc_width = accumulate_text_width(&line_text, self.tabstop);
+                        self.calc_shift(&line_text, &acc_width, container_width, start, end)
+                    }
+                    None => (0, text_width(&line_text, self.tabstop)),
+                };
+                let mut printer = LinePrinter::builder()
+                    .row(row)
+                    .col(prefix_width)
+                    .tabstop(self.tabstop)
+                    .container_width(container_width)
+                    .shift(shift)
+                    .text_width(full_width)
+                    .hscroll_offset(self.hscroll_offset)
+                    .build();
+                print_line(&mut printer, canvas);
+                row += 1;
+            }
+
+            // skip the newline
+            line_start += line.len() + 1;
+        }
+
+        Ok(())
+    }
 }
 
-impl Draw for Selection {
-    fn draw(&self, canvas: &mut dyn Canvas) -> Result<()> {
-        let (_screen_width, screen_height) = canvas.size()?;
-        canvas.clear()?;
+/// the range of the match in chars, (0, 0) if there is none
+fn matched_chars(char_index: &CharIndex, matched_range: Option<&MatchedRange>) -> (usize, usize) {
+    match matched_range {
+        Some(MatchedRange::Chars(matched_indices)) if !matched_indices.is_empty() => {
+            (matched_indices[0], matched_indices[matched_indices.len() - 1] + 1)
+        }
+        Some(&MatchedRange::ByteRange(match_start, match_end)) => {
+            (char_index.char_at(match_start), char_index.char_at(match_end))
+        }
+        _ => (0, 0),
+    }
+}
+
+impl Selection {
+    fn draw_grid(&self, canvas: &mut dyn Canvas) -> Result<()> {
+        let (screen_width, screen_height) = canvas.size()?;
+        let cell_width = min(self.prefix_width() + max(self.max_item_width, 1), screen_width);
+        let columns = max(1, (screen_width + GRID_GAP) / (cell_width + GRID_GAP));
+        self.columns.store(columns, Ordering::Relaxed);
+
+        let cursor = self.get_current_item_idx();
+        let item_idx_upper = min(self.item_cursor + screen_height * columns, self.items.len());
+        for item_idx in self.item_cursor..item_idx_upper {
+            let row = (item_idx - self.item_cursor) / columns;
+            let column = (item_idx - self.item_cursor) % columns;
+            let line_no = if self.reverse { row } else { screen_height - 1 - row };
 
-        let item_idx_lower = self.item_cursor;
-        let max_upper = self.item_cursor + screen_height;
-        let item_idx_upper = min(max_upper, self.items.len());
+            let item = self
+                .items
+                .get(item_idx)
+                .unwrap_or_else(|| panic!("model:draw_grid: failed to get item at {}", item_idx));
+
+            let left = column * (cell_width + GRID_GAP);
+            let mut cell = BoundedCanvas::new(line_no, left, cell_width, 1, canvas);
+            let is_current = item_idx == cursor;
+            let _ = Self::draw_glyph(&mut cell, 0, 0, &self.pointer, is_current, self.theme.cursor());
+            let _ = self.draw_item(&mut cell, 0, &item, item_idx, is_current);
+        }
+
+        Ok(())
+    }
+}
+
+impl Selection {
+    fn draw_wrapped(&self, canvas: &mut dyn Canvas) -> Result<()> {
+        let (screen_width, screen_height) = canvas.size()?;
+
+        let cursor = self.get_current_item_idx();
+        let mut used_rows = 0;
+        let mut item_idx = self.item_cursor;
+        while used_rows < screen_height {
+            let item = match self.items.get(item_idx) {
+                Some(item) => item,
+                None => break,
+            };
+
+            // the first row of an item is always on the top of its block
+            let rows = min(self.item_rows(&item), screen_height - used_rows);
+            let top = if self.reverse {
+                used_rows
+            } else {
+                screen_height - used_rows - rows
+            };
+
+            let mut block = BoundedCanvas::new(top, 0, screen_width, rows, canvas);
+            let is_current = item_idx == cursor;
+            let _ = Self::draw_glyph(&mut block, 0, 0, &self.pointer, is_current, self.theme.cursor());
+            let _ = self.draw_item(&mut block, 0, &item, item_idx, is_current);
+
+            used_rows += rows;
+            item_idx += 1;
+        }
+
+        Ok(())
+    }
+}
+
+impl Selection {
+    /// forget the rows drawn, they are all drawn next time, e.g. the list moved on the screen
+    pub fn invalidate(&self) {
+        *self.drawn_rows.lock() = DrawnRows::default();
+    }
+
+    fn row_key(&self, matched_item: &MatchedItem, item_index: usize, is_current: bool) -> RowKey {
+        RowKey {
+            item: Arc::as_ptr(&matched_item.item) as *const u8 as usize,
+            item_index,
+            matched_range: matched_item.matched_range.clone(),
+            is_current,
+            is_selected: self.selected.contains_key(&(current_run_num(), item_index as u32)),
+            hscroll_offset: self.hscroll_offset,
+            decorations: self.decorator.as_ref().map_or(0, Decorator::version),
+        }
+    }
+
+    /// draw the rows that changed since the last frame, all of them if the size changed
+    fn draw_list(&self, canvas: &mut dyn Canvas) -> Result<()> {
+        let (screen_width, screen_height) = canvas.size()?;
+
+        let mut drawn = self.drawn_rows.lock();
+        if drawn.size != (screen_width, screen_height) {
+            canvas.clear()?;
+            *drawn = DrawnRows {
+                size: (screen_width, screen_height),
+                rows: vec![None; screen_height],
+            };
+        }
 
-        for item_idx in item_idx_lower..item_idx_upper {
-            let line_cursor = item_idx - item_idx_lower;
+        for line_cursor in 0..screen_height {
+            let item_idx = self.item_cursor + line_cursor;
             let line_no = if self.reverse {
                 // top down
                 line_cursor
@@ -496,22 +1336,51 @@ impl Draw for Selection {
                 screen_height - 1 - line_cursor
             };
 
-            // print the cursor label
-            let label = if line_cursor == self.line_cursor { ">" } else { " " };
-            let _next_col = canvas.print_with_attr(line_no, 0, label, self.theme.cursor()).unwrap();
-
-            let item = self
-                .items
-                .get(item_idx)
-                .unwrap_or_else(|| panic!("model:draw_items: failed to get item at {}", item_idx));
+            let is_current = line_cursor == self.line_cursor;
+            let item = self.items.get(item_idx);
+            let key = item.as_ref().map(|item| self.row_key(item, item_idx, is_current));
+            if drawn.rows[line_no] == key {
+                continue;
+            }
 
-            let _ = self.draw_item(canvas, line_no, &item, item_idx, line_cursor == self.line_cursor);
+            for col in 0..screen_width {
+                canvas.put_cell(line_no, col, Cell::default())?;
+            }
+            if let Some(item) = item {
+                // print the cursor label
+                let _ = Self::draw_glyph(canvas, line_no, 0, &self.pointer, is_current, self.theme.cursor());
+                let _ = self.draw_item(canvas, line_no, &item, item_idx, is_current);
+            }
+            drawn.rows[line_no] = key;
         }
 
         Ok(())
     }
 }
 
+impl Draw for Selection {
+    fn draw(&self, canvas: &mut dyn Canvas) -> Result<()> {
+        let (screen_width, screen_height) = canvas.size()?;
+
+        // update item heights
+        self.height.store(screen_height, Ordering::Relaxed);
+        self.width.store(screen_width, Ordering::Relaxed);
+
+        if self.grid || self.wrap || self.multi_line {
+            // the items don't take a row each, they are all drawn again
+            self.invalidate();
+            canvas.clear()?;
+            return if self.grid {
+                self.draw_grid(canvas)
+            } else {
+                self.draw_wrapped(canvas)
+            };
+        }
+
+        self.draw_list(canvas)
+    }
+}
+
 impl Widget<Event> for Selection {
     fn on_event(&self, event: TermEvent, _rect: Rectangle) -> Vec<Event> {
         let mut ret = vec![];
@@ -519,6 +1388,7 @@ impl Widget<Event> for Selection {
             TermEvent::Key(Key::WheelUp(.., count)) => ret.push(Event::EvActUp(count as i32)),
             TermEvent::Key(Key::WheelDown(.., count)) => ret.push(Event::EvActDown(count as i32)),
             TermEvent::Key(Key::SingleClick(MouseButton::Left, row, _)) => {
+                *self.drag_start.lock() = Some(row as usize);
                 ret.push(Event::EvActSelectRow(row as usize))
             }
             TermEvent::Key(Key::DoubleClick(MouseButton::Left, ..)) => ret.push(Event::EvActAccept(None)),
@@ -526,8 +1396,497 @@ impl Widget<Event> for Selection {
                 ret.push(Event::EvActSelectRow(row as usize));
                 ret.push(Event::EvActToggle);
             }
+            // released on another row than pressed: a drag, toggle the rows swept over
+            TermEvent::Key(Key::MouseRelease(row, _)) => match self.drag_start.lock().take() {
+                Some(start_row) if self.multi_selection && start_row != row as usize => {
+                    ret.push(Event::EvActToggleRows(start_row, row as usize))
+                }
+                _ => {}
+            },
             _ => {}
         }
         ret
     }
 }
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::item::{ItemPool, ItemSnapshot, RankBuilder};
+
+    fn matched_items(texts: &[&'static str]) -> Vec<MatchedItem> {
+        texts
+            .iter()
+            .map(|&text| MatchedItem::builder(Arc::new(text)).build())
+            .collect()
+    }
+
+    fn selected_texts(selection: &Selection) -> Vec<String> {
+        let (_, items) = selection.get_selected_indices_and_items();
+        items.iter().map(|item| item.text().to_string()).collect()
+    }
+
+    // records the rows drawn on
+    pub(crate) struct RowsCanvas {
+        width: usize,
+        height: usize,
+        rows: HashSet<usize>,
+    }
+
+    impl RowsCanvas {
+        pub(crate) fn new(width: usize, height: usize) -> Self {
+            Self {
+                width,
+                height,
+                rows: HashSet::new(),
+            }
+        }
+
+        /// the number of rows drawn since the last call
+        pub(crate) fn take_rows(&mut self) -> usize {
+            mem::take(&mut self.rows).len()
+        }
+    }
+
+    impl Canvas for RowsCanvas {
+        fn size(&self) -> Result<(usize, usize)> {
+            Ok((self.width, self.height))
+        }
+        fn clear(&mut self) -> Result<()> {
+            self.rows.extend(0..self.height);
+            Ok(())
+        }
+        fn put_cell(&mut self, row: usize, _col: usize, _cell: Cell) -> Result<usize> {
+            self.rows.insert(row);
+            Ok(1)
+        }
+        fn set_cursor(&mut self, _row: usize, _col: usize) -> Result<()> {
+            Ok(())
+        }
+        fn show_cursor(&mut self, _show: bool) -> Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_draw_changed_rows() {
+        let mut selection = Selection::new();
+        selection.multi_selection = true;
+        selection.items.nosort(true);
+        selection.append_sorted_items(matched_items(&["a", "b", "c"]));
+        let mut canvas = RowsCanvas::new(10, 5);
+
+        selection.draw(&mut canvas).unwrap();
+        assert_eq!(5, canvas.take_rows());
+        selection.draw(&mut canvas).unwrap();
+        assert_eq!(0, canvas.take_rows());
+
+        // the rows the cursor left and moved to
+        selection.act_move_line_cursor(1);
+        selection.draw(&mut canvas).unwrap();
+        assert_eq!(2, canvas.take_rows());
+
+        selection.act_toggle();
+        selection.draw(&mut canvas).unwrap();
+        assert_eq!(1, canvas.take_rows());
+
+        // a new item on the blank row
+        selection.append_sorted_items(matched_items(&["d"]));
+        selection.draw(&mut canvas).unwrap();
+        assert_eq!(1, canvas.take_rows());
+
+        selection.invalidate();
+        selection.draw(&mut canvas).unwrap();
+        assert_eq!(5, canvas.take_rows());
+    }
+
+    #[test]
+    fn test_toggle_screen_rows() {
+        let mut selection = Selection::new();
+        selection.multi_selection = true;
+        selection.height.store(5, Ordering::Relaxed);
+        selection.items.nosort(true);
+        selection.append_sorted_items(matched_items(&["a", "b", "c", "d"]));
+
+        // the first item is on the bottom row
+        selection.act_toggle_screen_rows(4, 2);
+        assert_eq!(vec!["a", "b", "c"], selected_texts(&selection));
+        assert_eq!("c", selection.get_current_item().unwrap().text());
+
+        // dragged upwards from "d" to "b", the selected ones are toggled off
+        selection.act_toggle_screen_rows(1, 3);
+        let mut selected = selected_texts(&selection);
+        selected.sort();
+        assert_eq!(vec!["a", "d"], selected);
+        assert_eq!("b", selection.get_current_item().unwrap().text());
+    }
+
+    #[test]
+    fn test_drag() {
+        let mut selection = Selection::new();
+        selection.multi_selection = true;
+        let rect = Rectangle {
+            top: 0,
+            left: 0,
+            width: 10,
+            height: 5,
+        };
+        let click = |row| TermEvent::Key(Key::SingleClick(MouseButton::Left, row, 0));
+        let release = |row| TermEvent::Key(Key::MouseRelease(row, 0));
+
+        selection.on_event(click(1), rect);
+        let events = selection.on_event(release(3), rect);
+        assert!(matches!(events[..], [Event::EvActToggleRows(1, 3)]));
+        // released on the same row, a click
+        selection.on_event(click(1), rect);
+        assert!(selection.on_event(release(1), rect).is_empty());
+
+        // released outside of the list, the next release doesn't end the drag
+        selection.on_event(click(1), rect);
+        selection.cancel_drag();
+        assert!(selection.on_event(release(3), rect).is_empty());
+    }
+
+    #[test]
+    fn test_sticky_selection_on_reload() {
+        let mut selection = Selection::new();
+        selection.multi_selection = true;
+        selection.items.nosort(true);
+        selection.append_sorted_items(matched_items(&["a", "b", "c"]));
+        selection.act_select_item((1, 0), Arc::new("a"));
+        selection.act_select_item((1, 2), Arc::new("c"));
+
+        // reload with the same run number, old indices should not mark the new items
+        selection.prepare_reload(1, 1);
+        selection.clear();
+        selection.append_sorted_items(matched_items(&["x", "c", "y", "a"]));
+
+        let mut selected: Vec<(ItemIndex, String)> = selection
+            .selected
+            .iter()
+            .map(|(&index, item)| (index, item.text().to_string()))
+            .collect();
+        selected.sort();
+        assert_eq!(vec![((1, 1), "c".to_string()), ((1, 3), "a".to_string())], selected);
+    }
+
+    #[test]
+    fn test_sticky_selection_keeps_missing_items_of_other_runs() {
+        let mut selection = Selection::new();
+        selection.multi_selection = true;
+        selection.items.nosort(true);
+        selection.append_sorted_items(matched_items(&["a", "b"]));
+        selection.act_select_item((1, 0), Arc::new("a"));
+        selection.act_select_item((1, 1), Arc::new("b"));
+
+        selection.prepare_reload(1, 2);
+        selection.clear();
+        selection.append_sorted_items(matched_items(&["b", "c"]));
+
+        assert_eq!(vec!["a".to_string(), "b".to_string()], selected_texts(&selection));
+        assert!(selection.selected.contains_key(&(2, 0)));
+        assert!(!selection.selected.contains_key(&(1, 1)));
+    }
+
+    #[test]
+    fn test_sticky_selection_deselected_before_reloaded() {
+        let mut selection = Selection::new();
+        selection.multi_selection = true;
+        selection.items.nosort(true);
+        selection.append_sorted_items(matched_items(&["a", "b"]));
+        selection.act_select_item((1, 0), Arc::new("a"));
+        selection.act_select_item((1, 1), Arc::new("b"));
+
+        selection.prepare_reload(1, 2);
+        selection.deselect(&(1, 0));
+        selection.clear();
+        selection.append_sorted_items(matched_items(&["a", "b"]));
+
+        assert_eq!(vec!["b".to_string()], selected_texts(&selection));
+    }
+
+    #[test]
+    fn test_sticky_cursor_on_reload() {
+        let mut selection = Selection::new();
+        selection.height.store(10, Ordering::Relaxed);
+        selection.items.nosort(true);
+        selection.append_sorted_items(matched_items(&["a", "b", "c"]));
+        selection.act_move_line_cursor(2);
+        assert_eq!("c", selection.get_current_item().unwrap().text());
+
+        selection.prepare_reload(1, 1);
+        selection.clear();
+        selection.append_sorted_items(matched_items(&["c", "b", "a"]));
+        assert_eq!("c", selection.get_current_item().unwrap().text());
+
+        selection.prepare_reload(1, 1);
+        selection.clear();
+        selection.append_sorted_items(matched_items(&["x", "y"]));
+        selection.append_sorted_items(matched_items(&["z", "c"]));
+        assert_eq!(3, selection.get_current_item_idx());
+
+        // the anchor is kept for the items that arrive later, until the cursor is moved
+        assert!(selection.sticky_cursor.is_some());
+        selection.handle(&Event::EvActUp(1));
+        assert!(selection.sticky_cursor.is_none());
+    }
+
+    #[test]
+    fn test_sticky_cursor_follows_the_anchor() {
+        let ranked = |text: &'static str, score| {
+            MatchedItem::builder(Arc::new(text))
+                .rank(RankBuilder::default().build_rank(score, 0, 0, text.len()))
+                .build()
+        };
+        let mut selection = Selection::new();
+        selection.height.store(10, Ordering::Relaxed);
+        selection.sticky_cursor = Some(CursorAnchor::Text("c".to_string()));
+        selection.append_sorted_items(vec![ranked("b", 10), ranked("c", 5)]);
+        assert_eq!("c", selection.get_current_item().unwrap().text());
+        assert!(matches!(selection.sticky_cursor, Some(CursorAnchor::Found(_))));
+
+        // the items sorted before the anchor move the cursor along with it
+        selection.append_sorted_items(vec![ranked("a", 20), ranked("d", 1)]);
+        assert_eq!(2, selection.get_current_item_idx());
+        assert_eq!("c", selection.get_current_item().unwrap().text());
+
+        // the anchor isn't looked up again until new items arrive
+        selection.act_move_to_item(0);
+        selection.append_sorted_items(vec![]);
+        assert_eq!(0, selection.get_current_item_idx());
+        selection.append_sorted_items(vec![ranked("e", 0)]);
+        assert_eq!("c", selection.get_current_item().unwrap().text());
+    }
+
+    #[test]
+    fn test_cycle() {
+        for &reverse in [false, true].iter() {
+            let mut selection = Selection::new();
+            selection.cycle = true;
+            selection.reverse = reverse;
+            selection.height.store(3, Ordering::Relaxed);
+            selection.items.nosort(true);
+            selection.append_sorted_items(matched_items(&["a", "b", "c", "d", "e"]));
+            // the diff that increases the item index
+            let forward = if reverse { -1 } else { 1 };
+
+            // moving backward from the first item wraps to the last one
+            selection.act_move_line_cursor(-forward);
+            assert_eq!(4, selection.get_current_item_idx());
+            assert_eq!((2, 2), (selection.item_cursor, selection.line_cursor));
+
+            // moving forward from the last item wraps to the first one
+            selection.act_move_line_cursor(forward);
+            assert_eq!(0, selection.get_current_item_idx());
+            assert_eq!((0, 0), (selection.item_cursor, selection.line_cursor));
+
+            selection.act_move_line_cursor(forward * 2);
+            assert_eq!(2, selection.get_current_item_idx());
+        }
+    }
+
+    #[test]
+    fn test_scroll_off() {
+        let mut selection = Selection::new();
+        selection.scroll_off = 2;
+        selection.height.store(6, Ordering::Relaxed);
+        selection.items.nosort(true);
+        selection.append_sorted_items(matched_items(&["0", "1", "2", "3", "4", "5", "6", "7", "8", "9"]));
+
+        // the window starts scrolling when the cursor is 2 lines away from the edge
+        selection.act_move_line_cursor(3);
+        assert_eq!((0, 3), (selection.item_cursor, selection.line_cursor));
+        selection.act_move_line_cursor(1);
+        assert_eq!((1, 3), (selection.item_cursor, selection.line_cursor));
+
+        // the context is kept at the other edge as well
+        selection.act_move_line_cursor(-2);
+        assert_eq!((0, 2), (selection.item_cursor, selection.line_cursor));
+
+        // but not beyond the items
+        selection.act_move_line_cursor(100);
+        assert_eq!((4, 5), (selection.item_cursor, selection.line_cursor));
+        selection.act_move_line_cursor(-100);
+        assert_eq!((0, 0), (selection.item_cursor, selection.line_cursor));
+    }
+
+    #[test]
+    fn test_track_current_item() {
+        let items = matched_items(&["a", "b", "b", "c"]);
+        let tracked = items[2].clone();
+
+        let mut selection = Selection::new();
+        selection.height.store(10, Ordering::Relaxed);
+        selection.items.nosort(true);
+        selection.append_sorted_items(items);
+        selection.act_move_line_cursor(2);
+
+        // the tracked item is identified by itself, not by its text
+        selection.track_current_item();
+        selection.clear();
+        selection.append_sorted_items(vec![matched_items(&["b"]).remove(0), tracked]);
+        assert_eq!(1, selection.get_current_item_idx());
+    }
+
+    #[test]
+    fn test_drop_items_past_tail() {
+        let pool = ItemPool::new().tail(Some(4));
+        let items = |texts: &[&'static str]| texts.iter().map(|&text| Arc::new(text) as Arc<dyn SkimItem>).collect();
+        let matched = |snapshot: ItemSnapshot| {
+            snapshot
+                .chunks()
+                .flat_map(|(_, items, _, _, _)| items.iter().map(|item| MatchedItem::builder(item.clone()).build()))
+                .collect::<Vec<_>>()
+        };
+
+        let mut selection = Selection::new();
+        selection.multi_selection = true;
+        selection.height.store(10, Ordering::Relaxed);
+        selection.items.nosort(true);
+        assert!(pool.append(items(&["a", "b", "c", "d"])).is_empty());
+        selection.append_sorted_items(matched(pool.take()));
+        selection.act_move_line_cursor(2);
+        selection.act_toggle();
+        selection.act_move_line_cursor(-2);
+        selection.act_toggle();
+        selection.act_move_line_cursor(1);
+        assert_eq!("b", selection.get_current_item().unwrap().text());
+
+        // past the capacity, "a" and "b" are dropped, only the new items are matched
+        let dropped = pool.append(items(&["e", "f"]));
+        assert_eq!(2, selection.drop_items(&dropped));
+        selection.append_sorted_items(matched(pool.take()));
+
+        let texts: Vec<String> = selection
+            .items
+            .iter()
+            .map(|item| item.item.text().to_string())
+            .collect();
+        assert_eq!(vec!["c", "d", "e", "f"], texts);
+        let run_num = current_run_num();
+        let selected: Vec<(ItemIndex, String)> = selection
+            .selected
+            .iter()
+            .map(|(&index, item)| (index, item.text().to_string()))
+            .collect();
+        assert_eq!(vec![((run_num, 0), "c".to_string())], selected);
+        // the cursor was on a dropped item, it's on the next one
+        assert_eq!("c", selection.get_current_item().unwrap().text());
+
+        selection.act_move_line_cursor(1);
+        let dropped = pool.append(items(&["g"]));
+        assert_eq!(1, selection.drop_items(&dropped));
+        assert_eq!("d", selection.get_current_item().unwrap().text());
+        assert_eq!(0, selection.get_num_selected());
+    }
+
+    #[test]
+    fn test_first_and_last() {
+        let mut selection = Selection::new();
+        selection.height.store(2, Ordering::Relaxed);
+        selection.items.nosort(true);
+        selection.append_sorted_items(matched_items(&["a", "b", "c", "d"]));
+
+        selection.handle(&Event::EvActLast);
+        assert_eq!((2, 1), (selection.item_cursor, selection.line_cursor));
+        selection.handle(&Event::EvActFirst);
+        assert_eq!((0, 0), (selection.item_cursor, selection.line_cursor));
+
+        selection.reverse = true;
+        selection.handle(&Event::EvActLast);
+        assert_eq!(3, selection.get_current_item_idx());
+    }
+
+    #[test]
+    fn test_no_hscroll() {
+        let text = "0123456789abcdefghijklmnopqrstuvwxyz";
+        let widths = accumulate_text_width(text, 8);
+        let mut selection = Selection::new();
+        assert!(selection.calc_shift(text, &widths, 10, 30, 31).0 > 0);
+        selection.keep_right = true;
+        assert!(selection.calc_shift(text, &widths, 10, 0, 0).0 > 0);
+
+        selection.no_hscroll = true;
+        assert_eq!((0, 36), selection.calc_shift(text, &widths, 10, 30, 31));
+        assert_eq!((0, 36), selection.calc_shift(text, &widths, 10, 0, 0));
+    }
+
+    #[test]
+    fn test_grid_cursor() {
+        let mut selection = Selection::new();
+        selection.grid = true;
+        selection.height.store(2, Ordering::Relaxed);
+        selection.columns.store(3, Ordering::Relaxed);
+        selection.items.nosort(true);
+        selection.append_sorted_items(matched_items(&["0", "1", "2", "3", "4", "5", "6", "7"]));
+
+        selection.handle(&Event::EvActRight(1));
+        assert_eq!(1, selection.get_current_item_idx());
+
+        // up/down move across the rows
+        selection.handle(&Event::EvActUp(1));
+        assert_eq!((0, 4), (selection.item_cursor, selection.line_cursor));
+
+        // the window scrolls by rows
+        selection.handle(&Event::EvActUp(1));
+        assert_eq!((3, 4), (selection.item_cursor, selection.line_cursor));
+
+        // stop at the last item
+        selection.handle(&Event::EvActRight(5));
+        assert_eq!(7, selection.get_current_item_idx());
+
+        selection.handle(&Event::EvActLeft(7));
+        assert_eq!((0, 0), (selection.item_cursor, selection.line_cursor));
+    }
+
+    #[test]
+    fn test_wrapped_cursor() {
+        let mut selection = Selection::new();
+        selection.wrap = true;
+        selection.height.store(4, Ordering::Relaxed);
+        // 10 columns for the text, 8 for the continuation rows
+        selection.width.store(12, Ordering::Relaxed);
+        selection.items.nosort(true);
+        let long = "0123456789abcdefghijklmnopqrst";
+        selection.append_sorted_items(matched_items(&["a", long, "b", "c"]));
+
+        assert_eq!(1, selection.item_rows(&selection.items.get(0).unwrap()));
+        assert_eq!(4, selection.item_rows(&selection.items.get(1).unwrap()));
+
+        // scroll until all the rows of the items up to the cursor fit in the screen
+        selection.handle(&Event::EvActUp(1));
+        assert_eq!((1, 0), (selection.item_cursor, selection.line_cursor));
+
+        // the wrapped item counts as one entry
+        selection.handle(&Event::EvActUp(2));
+        assert_eq!((2, 1), (selection.item_cursor, selection.line_cursor));
+
+        // the row 2 is a continuation row of the long item
+        selection.handle(&Event::EvActDown(3));
+        selection.act_select_screen_row(4 - 1 - 2);
+        assert_eq!(1, selection.get_current_item_idx());
+    }
+
+    #[test]
+    fn test_multi_line_rows() {
+        let mut selection = Selection::new();
+        selection.multi_line = true;
+        selection.height.store(4, Ordering::Relaxed);
+        selection.width.store(12, Ordering::Relaxed);
+        selection.items.nosort(true);
+        let long = "0123456789abcdefghijklmnopqrst";
+        selection.append_sorted_items(matched_items(&["a\nb\nc", long, "d"]));
+
+        assert_eq!(3, selection.item_rows(&selection.items.get(0).unwrap()));
+        assert_eq!(1, selection.item_rows(&selection.items.get(1).unwrap()));
+
+        // the long line of the multi-line item is wrapped as well
+        selection.wrap = true;
+        selection.append_sorted_items(matched_items(&["a\n0123456789abcdefghijklmnopqrst"]));
+        assert_eq!(5, selection.item_rows(&selection.items.get(3).unwrap()));
+
+        // the row 1 is the second line of the first item
+        selection.act_select_screen_row(4 - 1 - 1);
+        assert_eq!(0, selection.get_current_item_idx());
+    }
+}
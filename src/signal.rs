@@ -0,0 +1,68 @@
+//! Reload the items of a running skim on SIGUSR1, e.g. `kill -USR1 $pid`, for the shell setups
+//! that don't need the socket of `--listen-socket`.
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use nix::libc::c_int;
+use nix::sys::signal::{self, SaFlags, SigAction, SigHandler, SigSet, Signal};
+
+use crate::handle::SkimHandle;
+
+// how often the pending signal is checked, the handler itself could only set a flag
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+static RELOAD_REQUESTED: AtomicBool = AtomicBool::new(false);
+
+extern "C" fn request_reload(_: c_int) {
+    RELOAD_REQUESTED.store(true, Ordering::SeqCst);
+}
+
+/// Reload on SIGUSR1 while skim is running, the previous handler is restored on drop
+pub struct ReloadOnSignal {
+    previous: SigAction,
+    stopped: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl ReloadOnSignal {
+    pub fn install(handle: SkimHandle) -> nix::Result<Self> {
+        let action = SigAction::new(
+            SigHandler::Handler(request_reload),
+            SaFlags::SA_RESTART,
+            SigSet::empty(),
+        );
+        // the handler only touches an atomic, which is async-signal-safe
+        let previous = unsafe { signal::sigaction(Signal::SIGUSR1, &action)? };
+        RELOAD_REQUESTED.store(false, Ordering::SeqCst);
+
+        let stopped = Arc::new(AtomicBool::new(false));
+        let stopped_clone = stopped.clone();
+        let thread = thread::spawn(move || {
+            while !stopped_clone.load(Ordering::SeqCst) {
+                if RELOAD_REQUESTED.swap(false, Ordering::SeqCst) {
+                    debug!("signal: reload on SIGUSR1");
+                    handle.reload(None);
+                }
+                thread::sleep(POLL_INTERVAL);
+            }
+        });
+
+        Ok(Self {
+            previous,
+            stopped,
+            thread: Some(thread),
+        })
+    }
+}
+
+impl Drop for ReloadOnSignal {
+    fn drop(&mut self) {
+        self.stopped.store(true, Ordering::SeqCst);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+        let _ = unsafe { signal::sigaction(Signal::SIGUSR1, &self.previous) };
+    }
+}
@@ -1,12 +1,15 @@
 use std::borrow::Cow;
 use std::cmp::{max, min};
+use std::env;
+use std::path::PathBuf;
 use std::prelude::v1::*;
+use std::process::Command;
 
 use regex::{Captures, Regex};
 use tuikit::prelude::*;
 use unicode_width::UnicodeWidthChar;
 
-use crate::field::get_string_by_range;
+use crate::field::{get_string_by_range_with_delimiter, Delimiter};
 use crate::AnsiString;
 
 lazy_static! {
@@ -50,6 +53,8 @@ pub struct LinePrinter {
     text_width: usize,
     container_width: usize,
     hscroll_offset: i64,
+    overflow_hint: bool,
+    no_unicode: bool,
 }
 
 impl LinePrinter {
@@ -68,6 +73,8 @@ impl LinePrinter {
             text_width: 0,
             container_width: 0,
             hscroll_offset: 0,
+            overflow_hint: true,
+            no_unicode: false,
         }
     }
 
@@ -106,6 +113,18 @@ impl LinePrinter {
         self
     }
 
+    /// whether to show `..` in place of the hidden content, default to true
+    pub fn overflow_hint(mut self, hint: bool) -> Self {
+        self.overflow_hint = hint;
+        self
+    }
+
+    /// mark the newlines with `$` instead of `␊`
+    pub fn no_unicode(mut self, no_unicode: bool) -> Self {
+        self.no_unicode = no_unicode;
+        self
+    }
+
     pub fn build(mut self) -> Self {
         self.reset();
         self
@@ -140,6 +159,8 @@ impl LinePrinter {
 
         if current < self.start || current >= self.end {
             // pass if it is hidden
+        } else if !self.overflow_hint {
+            self.print_ch_to_canvas(canvas, ch, attr, skip);
         } else if current < self.start + 2 && self.start > 0 {
             // print left ".."
             for _ in 0..min(w, current - self.start + 1) {
@@ -162,6 +183,11 @@ impl LinePrinter {
             '\u{08}' => {
                 // ignore \b character
             }
+            '\n' => {
+                // a newline of the multi-line items that are printed on a single row
+                let marker = if self.no_unicode { '$' } else { '\u{240A}' };
+                self.print_char_raw(canvas, marker, attr, skip);
+            }
             '\t' => {
                 // handle tabstop
                 let rest = if self.current_pos < 0 {
@@ -184,21 +210,194 @@ pub fn print_item(canvas: &mut dyn Canvas, printer: &mut LinePrinter, content: A
     }
 }
 
+/// A bordered widget whose box-drawing chars on the given edges are drawn with ASCII ones of the
+/// same width, for `--no-unicode`. Only the border cells are replaced, the content is drawn as it
+/// is.
+pub struct AsciiBorder<W> {
+    inner: W,
+    top: bool,
+    right: bool,
+    bottom: bool,
+    left: bool,
+}
+
+impl<W> AsciiBorder<W> {
+    pub fn new(inner: W) -> Self {
+        Self {
+            inner,
+            top: false,
+            right: false,
+            bottom: false,
+            left: false,
+        }
+    }
+
+    pub fn top(mut self, top: bool) -> Self {
+        self.top = top;
+        self
+    }
+
+    pub fn right(mut self, right: bool) -> Self {
+        self.right = right;
+        self
+    }
+
+    pub fn bottom(mut self, bottom: bool) -> Self {
+        self.bottom = bottom;
+        self
+    }
+
+    pub fn left(mut self, left: bool) -> Self {
+        self.left = left;
+        self
+    }
+
+    fn is_border(&self, row: usize, col: usize, width: usize, height: usize) -> bool {
+        (self.top && row == 0)
+            || (self.bottom && row + 1 == height)
+            || (self.left && col == 0)
+            || (self.right && col + 1 == width)
+    }
+}
+
+impl<W: Draw> Draw for AsciiBorder<W> {
+    fn draw(&self, canvas: &mut dyn Canvas) -> tuikit::Result<()> {
+        if !(self.top || self.right || self.bottom || self.left) {
+            return self.inner.draw(canvas);
+        }
+        let (width, height) = canvas.size()?;
+        self.inner.draw(&mut AsciiCanvas {
+            canvas,
+            border: self,
+            width,
+            height,
+        })
+    }
+}
+
+impl<Message, W: Widget<Message>> Widget<Message> for AsciiBorder<W> {
+    fn size_hint(&self) -> (Option<usize>, Option<usize>) {
+        self.inner.size_hint()
+    }
+
+    fn on_event(&self, event: Event, rect: Rectangle) -> Vec<Message> {
+        self.inner.on_event(event, rect)
+    }
+}
+
+impl<Message, W: Split<Message>> Split<Message> for AsciiBorder<W> {
+    fn get_basis(&self) -> Size {
+        self.inner.get_basis()
+    }
+
+    fn get_grow(&self) -> usize {
+        self.inner.get_grow()
+    }
+
+    fn get_shrink(&self) -> usize {
+        self.inner.get_shrink()
+    }
+}
+
+struct AsciiCanvas<'a, W> {
+    canvas: &'a mut dyn Canvas,
+    border: &'a AsciiBorder<W>,
+    width: usize,
+    height: usize,
+}
+
+impl<W> Canvas for AsciiCanvas<'_, W> {
+    fn size(&self) -> tuikit::Result<(usize, usize)> {
+        self.canvas.size()
+    }
+
+    fn clear(&mut self) -> tuikit::Result<()> {
+        self.canvas.clear()
+    }
+
+    fn put_cell(&mut self, row: usize, col: usize, cell: Cell) -> tuikit::Result<usize> {
+        let cell = if self.border.is_border(row, col, self.width, self.height) {
+            Cell {
+                ch: ascii_glyph(cell.ch),
+                ..cell
+            }
+        } else {
+            cell
+        };
+        self.canvas.put_cell(row, col, cell)
+    }
+
+    fn set_cursor(&mut self, row: usize, col: usize) -> tuikit::Result<()> {
+        self.canvas.set_cursor(row, col)
+    }
+
+    fn show_cursor(&mut self, show: bool) -> tuikit::Result<()> {
+        self.canvas.show_cursor(show)
+    }
+}
+
+fn ascii_glyph(ch: char) -> char {
+    match ch {
+        '─' | '━' | '═' | '┄' | '┅' | '┈' | '┉' | '╌' | '╍' | '╴' | '╶' | '╸' | '╺' => '-',
+        '│' | '┃' | '║' | '┆' | '┇' | '┊' | '┋' | '╎' | '╏' | '╵' | '╷' | '╹' | '╻' => '|',
+        '\u{2500}'..='\u{257F}' => '+',
+        ch => ch,
+    }
+}
+
 /// return an array, arr[i] store the display width till char[i]
 pub fn accumulate_text_width(text: &str, tabstop: usize) -> Vec<usize> {
     let mut ret = Vec::new();
     let mut w = 0;
     for ch in text.chars() {
-        w += if ch == '\t' {
-            tabstop - (w % tabstop)
-        } else {
-            ch.width().unwrap_or(2)
-        };
+        w += char_width(ch, w, tabstop);
         ret.push(w);
     }
     ret
 }
 
+/// The char boundaries and the accumulated display widths of a text, built once for the long lines
+/// that are otherwise scanned again in every frame to highlight the match and to scroll them
+pub struct CharIndex {
+    // the byte offset of every char
+    offsets: Vec<u32>,
+    // the display width up to and including every char, see `accumulate_text_width`
+    widths: Vec<usize>,
+}
+
+impl CharIndex {
+    pub fn new(text: &str, tabstop: usize) -> Self {
+        Self {
+            offsets: text.char_indices().map(|(offset, _)| offset as u32).collect(),
+            widths: accumulate_text_width(text, tabstop),
+        }
+    }
+
+    /// the char index of `byte`, a char boundary of the text or its length
+    pub fn char_at(&self, byte: usize) -> usize {
+        self.offsets.partition_point(|&offset| (offset as usize) < byte)
+    }
+
+    pub fn widths(&self) -> &[usize] {
+        &self.widths
+    }
+}
+
+/// the display width of the text, with tabs expanded the same way as `LinePrinter`
+pub fn text_width(text: &str, tabstop: usize) -> usize {
+    text.chars().fold(0, |w, ch| w + char_width(ch, w, tabstop))
+}
+
+/// the display width of `ch` printed at (display) position `pos`
+fn char_width(ch: char, pos: usize, tabstop: usize) -> usize {
+    match ch {
+        '\u{08}' => 0,
+        '\n' => 1,
+        '\t' => tabstop - (pos % tabstop),
+        ch => ch.width().unwrap_or(2),
+    }
+}
+
 /// "smartly" calculate the "start" position of the string in order to show the matched contents
 /// for example, if the match appear in the end of a long string, we need to show the right part.
 /// ```text
@@ -206,19 +405,18 @@ pub fn accumulate_text_width(text: &str, tabstop: usize) -> Vec<usize> {
 ///               shift ->|               |
 /// ```
 ///
-/// return (left_shift, full_print_width)
-pub fn reshape_string(
-    text: &str,
+/// `acc_width` is the accumulated display width of every char, see `accumulate_text_width` and
+/// `CharIndex`, return (left_shift, full_print_width)
+pub fn reshape_widths(
+    acc_width: &[usize],
     container_width: usize,
     match_start: usize,
     match_end: usize,
-    tabstop: usize,
 ) -> (usize, usize) {
-    if text.is_empty() {
+    if acc_width.is_empty() {
         return (0, 0);
     }
 
-    let acc_width = accumulate_text_width(text, tabstop);
     let full_width = acc_width[acc_width.len() - 1];
     if full_width <= container_width {
         return (0, full_width);
@@ -299,7 +497,7 @@ pub fn parse_margin(margin_option: &str) -> (Size, Size, Size, Size) {
 /// The context for injecting command.
 #[derive(Copy, Clone)]
 pub struct InjectContext<'a> {
-    pub delimiter: &'a Regex,
+    pub delimiter: &'a Delimiter,
     pub current_index: usize,
     pub current_selection: &'a str,
     pub indices: &'a [usize],
@@ -329,6 +527,16 @@ pub fn depends_on_items(cmd: &str) -> bool {
 /// * `{q}` for query
 /// * `{cq}` for command query
 pub fn inject_command<'a>(cmd: &'a str, context: InjectContext<'a>) -> Cow<'a, str> {
+    inject_fields(cmd, context, |text| format!("'{}'", escape_single_quote(text)))
+}
+
+/// inject the fields into a label to be displayed, same as `inject_command` except that the
+/// replacements are not quoted
+pub fn inject_label<'a>(label: &'a str, context: InjectContext<'a>) -> Cow<'a, str> {
+    inject_fields(label, context, |text| text.to_string())
+}
+
+fn inject_fields<'a>(cmd: &'a str, context: InjectContext<'a>, quote: impl Fn(&str) -> String) -> Cow<'a, str> {
     RE_FIELDS.replace_all(cmd, |caps: &Captures| {
         // \{...
         if &caps[0][0..1] == "\\" {
@@ -364,9 +572,9 @@ pub fn inject_command<'a>(cmd: &'a str, context: InjectContext<'a>) -> Cow<'a, s
                     let replacement = match rest {
                         "" => s,
                         "n" => &index_str,
-                        _ => get_string_by_range(context.delimiter, s, rest).unwrap_or(""),
+                        _ => get_string_by_range_with_delimiter(context.delimiter, s, rest).unwrap_or(""),
                     };
-                    format!("'{}'", escape_single_quote(replacement))
+                    quote(replacement)
                 })
                 .collect::<Vec<_>>()
                 .join(" ");
@@ -379,10 +587,10 @@ pub fn inject_command<'a>(cmd: &'a str, context: InjectContext<'a>) -> Cow<'a, s
             "n" => &index_str,
             "q" => context.query,
             "cq" => context.cmd_query,
-            _ => get_string_by_range(context.delimiter, context.current_selection, range).unwrap_or(""),
+            _ => get_string_by_range_with_delimiter(context.delimiter, context.current_selection, range).unwrap_or(""),
         };
 
-        format!("'{}'", escape_single_quote(replacement))
+        quote(replacement)
     })
 }
 
@@ -390,9 +598,190 @@ pub fn str_lines(string: &str) -> Vec<&str> {
     string.trim_end().split("\n").collect()
 }
 
+/// The shell (and its flags) to run the commands with, the command is passed as the last argument,
+/// e.g. `bash -eo pipefail -c`. `$SHELL -c` by default. The commands could also be given a working
+/// directory and extra environment variables.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Shell {
+    words: Vec<String>,
+    cwd: Option<PathBuf>,
+    envs: Vec<(String, String)>,
+}
+
+impl Default for Shell {
+    fn default() -> Self {
+        let shell = env::var("SHELL").unwrap_or_else(|_| "sh".to_string());
+        Self {
+            words: vec![shell, "-c".to_string()],
+            cwd: None,
+            envs: Vec::new(),
+        }
+    }
+}
+
+impl Shell {
+    /// split the words as the shell does, `None` if there is no word or the quotes don't match
+    pub fn parse(shell: &str) -> Option<Self> {
+        shlex::split(shell).filter(|words| !words.is_empty()).map(|words| Self {
+            words,
+            ..Default::default()
+        })
+    }
+
+    /// run the commands in `dir` instead of the current directory
+    pub fn cwd(mut self, dir: Option<PathBuf>) -> Self {
+        self.cwd = dir;
+        self
+    }
+
+    /// set the environment variables for the commands, in addition to the inherited ones
+    pub fn envs(mut self, envs: Vec<(String, String)>) -> Self {
+        self.envs = envs;
+        self
+    }
+
+    pub fn command(&self, cmd: &str) -> Command {
+        let mut command = Command::new(&self.words[0]);
+        command.args(&self.words[1..]).arg(cmd);
+        if let Some(dir) = self.cwd.as_ref() {
+            command.current_dir(dir);
+        }
+        command.envs(self.envs.iter().map(|(key, value)| (key, value)));
+        command
+    }
+}
+
+/// parse the `KEY=VALUE` assignments of the environment variables, `None` if any has no `=`
+pub fn parse_env_vars(vars: &[&str]) -> Option<Vec<(String, String)>> {
+    vars.iter()
+        .map(|var| {
+            let (key, value) = var.split_once('=')?;
+            (!key.is_empty()).then(|| (key.to_string(), value.to_string()))
+        })
+        .collect()
+}
+
+/// the valid `KEY=VALUE` assignments of the environment variables, the others are ignored
+pub fn valid_env_vars(vars: &[&str]) -> Vec<(String, String)> {
+    vars.iter().filter_map(|var| parse_env_vars(&[var])).flatten().collect()
+}
+
 #[cfg(test)]
-mod tests {
+pub(crate) mod tests {
     use super::*;
+    use std::path::Path;
+
+    /// a canvas of chars to check what's drawn
+    pub(crate) struct Grid(pub Vec<Vec<char>>);
+
+    impl Grid {
+        pub fn new(width: usize, height: usize) -> Self {
+            Grid(vec![vec![' '; width]; height])
+        }
+
+        pub fn row(&self, row: usize) -> String {
+            self.0[row].iter().collect()
+        }
+    }
+
+    impl Canvas for Grid {
+        fn size(&self) -> tuikit::Result<(usize, usize)> {
+            Ok((self.0[0].len(), self.0.len()))
+        }
+        fn clear(&mut self) -> tuikit::Result<()> {
+            Ok(())
+        }
+        fn put_cell(&mut self, row: usize, col: usize, cell: Cell) -> tuikit::Result<usize> {
+            self.0[row][col] = cell.ch;
+            Ok(1)
+        }
+        fn set_cursor(&mut self, _row: usize, _col: usize) -> tuikit::Result<()> {
+            Ok(())
+        }
+        fn show_cursor(&mut self, _show: bool) -> tuikit::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_shell() {
+        let shell = Shell::parse("bash -eo 'pipefail' -c").unwrap();
+        assert_eq!(shell.words, vec!["bash", "-eo", "pipefail", "-c"]);
+        let command = shell
+            .cwd(Some(PathBuf::from("/tmp")))
+            .envs(vec![("A".to_string(), "1".to_string())])
+            .command("ls | wc");
+        assert_eq!(command.get_program(), "bash");
+        assert_eq!(command.get_args().last().unwrap(), "ls | wc");
+        assert_eq!(command.get_current_dir(), Some(Path::new("/tmp")));
+        assert_eq!(command.get_envs().count(), 1);
+
+        assert_eq!(Shell::parse(" "), None);
+        assert_eq!(Shell::parse("sh -c 'x"), None);
+    }
+
+    #[test]
+    fn test_parse_env_vars() {
+        assert_eq!(
+            parse_env_vars(&["A=1", "B=x=y", "C="]),
+            Some(vec![
+                ("A".to_string(), "1".to_string()),
+                ("B".to_string(), "x=y".to_string()),
+                ("C".to_string(), String::new()),
+            ])
+        );
+        assert_eq!(parse_env_vars(&["A=1", "B"]), None);
+        assert_eq!(parse_env_vars(&["=1"]), None);
+
+        assert_eq!(
+            valid_env_vars(&["A=1", "B", "=1", "C=2"]),
+            vec![("A".to_string(), "1".to_string()), ("C".to_string(), "2".to_string())]
+        );
+    }
+
+    #[test]
+    fn test_ascii_glyph() {
+        assert_eq!(ascii_glyph('─'), '-');
+        assert_eq!(ascii_glyph('│'), '|');
+        assert_eq!(ascii_glyph('┌'), '+');
+        assert_eq!(ascii_glyph('╯'), '+');
+        assert_eq!(ascii_glyph('中'), '中');
+    }
+
+    #[test]
+    fn test_ascii_border() {
+        struct Content;
+        impl Draw for Content {
+            fn draw(&self, canvas: &mut dyn Canvas) -> tuikit::Result<()> {
+                canvas.print(0, 0, "─│")?;
+                Ok(())
+            }
+        }
+        impl Widget for Content {}
+
+        let win = Win::new(Content).border_top(true).border_left(true);
+        let mut grid = Grid::new(4, 2);
+        AsciiBorder::new(win).top(true).left(true).draw(&mut grid).unwrap();
+        // the borders are drawn in ASCII, the content as it is
+        assert_eq!(grid.row(0), "+---");
+        assert_eq!(grid.row(1), "|─│ ");
+    }
+
+    #[test]
+    fn test_newline_marker() {
+        for (no_unicode, expected) in [(false, "a␊b "), (true, "a$b ")].iter() {
+            let mut grid = Grid::new(4, 1);
+            let mut printer = LinePrinter::builder()
+                .container_width(4)
+                .text_width(3)
+                .no_unicode(*no_unicode)
+                .build();
+            for ch in "a\nb".chars() {
+                printer.print_char(&mut grid, ch, Attr::default(), false);
+            }
+            assert_eq!(&grid.row(0), expected);
+        }
+    }
 
     #[test]
     fn test_accumulate_text_width() {
@@ -400,10 +789,38 @@ mod tests {
         assert_eq!(accumulate_text_width("ab中de国g", 8), vec![1, 2, 4, 5, 6, 8, 9]);
         assert_eq!(accumulate_text_width("ab\tdefg", 8), vec![1, 2, 8, 9, 10, 11, 12]);
         assert_eq!(accumulate_text_width("ab中\te国g", 8), vec![1, 2, 4, 8, 9, 11, 12]);
+        assert_eq!(accumulate_text_width("a\tb\tc", 4), vec![1, 4, 5, 8, 9]);
+    }
+
+    #[test]
+    fn test_text_width() {
+        assert_eq!(text_width("", 8), 0);
+        assert_eq!(text_width("ab中de国g", 8), 9);
+        assert_eq!(text_width("ab\tdefg", 8), 12);
+        assert_eq!(text_width("ab\tdefg", 3), 7);
+        assert_eq!(text_width("\tab\u{08}", 2), 4);
+    }
+
+    #[test]
+    fn test_char_index() {
+        let index = CharIndex::new("a中\tb", 4);
+        assert_eq!(index.char_at(0), 0);
+        assert_eq!(index.char_at(1), 1);
+        assert_eq!(index.char_at(4), 2);
+        assert_eq!(index.char_at(6), 4);
+        assert_eq!(index.widths(), &[1, 3, 4, 5]);
     }
 
     #[test]
     fn test_reshape_string() {
+        let reshape_string = |text, container_width, match_start, match_end, tabstop| {
+            reshape_widths(
+                &accumulate_text_width(text, tabstop),
+                container_width,
+                match_start,
+                match_end,
+            )
+        };
         // no match, left fixed to 0
         assert_eq!(reshape_string("abc", 10, 0, 0, 8), (0, 3));
         assert_eq!(reshape_string("a\tbc", 8, 0, 0, 8), (0, 10));
@@ -414,7 +831,7 @@ mod tests {
 
     #[test]
     fn test_inject_command() {
-        let delimiter = Regex::new(r",").unwrap();
+        let delimiter = Regex::new(r",").unwrap().into();
         let current_selection = "a,b,c";
         let selections = vec!["a,b,c", "x,y,z"];
         let query = "query";
@@ -453,6 +870,24 @@ mod tests {
         assert_eq!("'0' '1'", inject_command("{+n}", default_context));
     }
 
+    #[test]
+    fn test_inject_label() {
+        let delimiter = Regex::new(r",").unwrap().into();
+        let context = InjectContext {
+            current_index: 3,
+            delimiter: &delimiter,
+            current_selection: "src/main.rs,it's",
+            selections: &[],
+            indices: &[],
+            query: "main",
+            cmd_query: "",
+        };
+
+        assert_eq!("src/main.rs", inject_label("{1}", context));
+        assert_eq!("[3] it's for main", inject_label("[{n}] {2} for {q}", context));
+        assert_eq!("no fields", inject_label("no fields", context));
+    }
+
     #[test]
     fn test_escape_single_quote() {
         assert_eq!("'\\''a'\\''\\0", escape_single_quote("'a'\0"));